@@ -19,6 +19,8 @@ from ._minllm import (
     AsyncFlow,
     AsyncBatchFlow,
     AsyncParallelBatchFlow,
+    # Runtime
+    configure_runtime,
 )
 
 __all__ = [
@@ -33,6 +35,7 @@ __all__ = [
     "AsyncFlow",
     "AsyncBatchFlow",
     "AsyncParallelBatchFlow",
+    "configure_runtime",
 ]
 
 __version__ = "0.1.0" 
\ No newline at end of file
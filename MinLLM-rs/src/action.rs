@@ -0,0 +1,313 @@
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::sync::Arc;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::base::{DEFAULT_ACTION, ERROR_ACTION};
+
+/// The name of an action returned by `post`, cheap to clone and compare
+///
+/// Compares, hashes and orders like the string it holds, so it can be looked up by `&str`.
+/// Names known at compile time are held without allocating, which is how `new` builds them
+/// in constants; names made at runtime share one allocation between their clones.
+#[derive(Clone)]
+pub struct ActionName(Repr);
+
+/// Where the string of an `ActionName` lives
+#[derive(Clone)]
+enum Repr {
+    /// A name known at compile time
+    Static(&'static str),
+    
+    /// A name made at runtime, shared by the clones
+    Shared(Arc<str>),
+}
+
+impl ActionName {
+    /// The action followed when a node returns no action
+    pub const DEFAULT: ActionName = ActionName::new(DEFAULT_ACTION);
+    
+    /// The action reserved for routing a failed step to an error handler
+    pub const ERROR: ActionName = ActionName::new(ERROR_ACTION);
+    
+    /// The action named `name`; use `ActionName::from` for names made at runtime
+    pub const fn new(name: &'static str) -> Self {
+        Self(Repr::Static(name))
+    }
+    
+    /// The action followed when a node returns no action
+    pub fn default_action() -> Self {
+        Self::DEFAULT
+    }
+    
+    /// The action reserved for routing a failed step to an error handler
+    pub fn error() -> Self {
+        Self::ERROR
+    }
+    
+    /// The action as a string slice
+    pub fn as_str(&self) -> &str {
+        match &self.0 {
+            Repr::Static(name) => name,
+            Repr::Shared(name) => name,
+        }
+    }
+}
+
+impl PartialEq for ActionName {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for ActionName {}
+
+impl Hash for ActionName {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state);
+    }
+}
+
+impl PartialOrd for ActionName {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ActionName {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl Deref for ActionName {
+    type Target = str;
+    
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for ActionName {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl Borrow<str> for ActionName {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl fmt::Debug for ActionName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for ActionName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// "default" and "__error__" come back as the constants, so they never allocate
+impl From<&str> for ActionName {
+    fn from(name: &str) -> Self {
+        match name {
+            DEFAULT_ACTION => Self::DEFAULT,
+            ERROR_ACTION => Self::ERROR,
+            name => Self(Repr::Shared(Arc::from(name))),
+        }
+    }
+}
+
+impl From<&String> for ActionName {
+    fn from(name: &String) -> Self {
+        Self::from(name.as_str())
+    }
+}
+
+impl From<String> for ActionName {
+    fn from(name: String) -> Self {
+        Self::from(name.as_str())
+    }
+}
+
+impl From<ActionName> for String {
+    fn from(name: ActionName) -> Self {
+        name.as_str().to_string()
+    }
+}
+
+impl PartialEq<str> for ActionName {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for ActionName {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<String> for ActionName {
+    fn eq(&self, other: &String) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Serialize for ActionName {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for ActionName {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Self::from)
+    }
+}
+
+/// A value that names an action, converted to the string flows route on
+pub trait IntoAction {
+    /// The canonical string form of the action
+    fn into_action(self) -> String;
+}
+
+impl IntoAction for &str {
+    fn into_action(self) -> String {
+        self.to_string()
+    }
+}
+
+impl IntoAction for String {
+    fn into_action(self) -> String {
+        self
+    }
+}
+
+impl IntoAction for &String {
+    fn into_action(self) -> String {
+        self.clone()
+    }
+}
+
+/// An action type with a fixed set of values, such as an enum declared with `actions!`
+pub trait ActionSet: IntoAction + Sized {
+    /// Canonical string forms of every value
+    fn all_actions() -> Vec<String>;
+}
+
+/// Declare an enum of actions with their canonical string forms, as `Variant => "action"` pairs
+///
+/// The enum implements `IntoAction`, `ActionSet` and `Into<Action>`, so it can be returned from
+/// `post` as `Ok(Review::Approve.into())` and used in `add_action_successor`.
+#[macro_export]
+macro_rules! actions {
+    ($(#[$meta:meta])* $vis:vis enum $name:ident { $($(#[$variant_meta:meta])* $variant:ident => $action:literal),+ $(,)? }) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+        $vis enum $name {
+            $($(#[$variant_meta])* $variant),+
+        }
+        
+        impl $name {
+            /// The canonical string form of the action
+            pub fn as_str(&self) -> &'static str {
+                match self {
+                    $($name::$variant => $action),+
+                }
+            }
+        }
+        
+        impl $crate::IntoAction for $name {
+            fn into_action(self) -> String {
+                self.as_str().to_string()
+            }
+        }
+        
+        impl $crate::ActionSet for $name {
+            fn all_actions() -> Vec<String> {
+                vec![$($action.to_string()),+]
+            }
+        }
+        
+        impl From<$name> for $crate::Action {
+            fn from(action: $name) -> Self {
+                Some($crate::ActionName::new(action.as_str()))
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::hash_map::DefaultHasher;
+    use std::collections::HashMap;
+    use std::hash::{Hash, Hasher};
+    
+    use super::*;
+    
+    fn hash_of(value: &(impl Hash + ?Sized)) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+    
+    #[test]
+    fn compares_like_the_string_it_holds() {
+        let action = ActionName::new("approve");
+        assert_eq!(action, ActionName::from("approve".to_string()));
+        assert_eq!(action, "approve");
+        assert_eq!(action, *"approve");
+        assert_eq!(action, "approve".to_string());
+        assert_ne!(action, ActionName::new("reject"));
+        assert!(ActionName::new("a") < ActionName::new("b"));
+    }
+    
+    #[test]
+    fn hashes_like_the_string_it_holds() {
+        assert_eq!(hash_of(&ActionName::new("approve")), hash_of("approve"));
+        
+        let mut routes = HashMap::new();
+        routes.insert(ActionName::new("approve"), 1);
+        routes.insert(ActionName::default_action(), 2);
+        assert_eq!(routes.get("approve"), Some(&1));
+        assert_eq!(routes.get(DEFAULT_ACTION), Some(&2));
+        assert_eq!(routes.get("reject"), None);
+    }
+    
+    /// A constant built with `new`, as a route table would declare one
+    const APPROVE: ActionName = ActionName::new("approve");
+    
+    #[test]
+    fn builds_constants_and_reuses_them_for_the_builtin_actions() {
+        assert_eq!(ActionName::DEFAULT, DEFAULT_ACTION);
+        assert_eq!(ActionName::ERROR, ERROR_ACTION);
+        assert_eq!(APPROVE, ActionName::from("approve".to_string()));
+        assert!(matches!(ActionName::from(DEFAULT_ACTION.to_string()).0, Repr::Static(name) if name == DEFAULT_ACTION));
+        assert!(matches!(ActionName::from(ERROR_ACTION).0, Repr::Static(name) if name == ERROR_ACTION));
+        
+        let other = ActionName::from("other".to_string());
+        let (Repr::Shared(a), Repr::Shared(b)) = (&other.0, &other.clone().0) else {
+            panic!("runtime names are shared");
+        };
+        assert!(Arc::ptr_eq(a, b), "clones share the name");
+    }
+    
+    #[test]
+    fn serializes_as_a_string() {
+        let action = ActionName::new("approve");
+        assert_eq!(serde_json::to_string(&action).unwrap(), "\"approve\"");
+        assert_eq!(serde_json::from_str::<ActionName>("\"approve\"").unwrap(), action);
+        assert_eq!(String::from(action.clone()), "approve");
+        assert_eq!(action.to_string(), "approve");
+        assert_eq!(format!("{:?}", action), "\"approve\"");
+    }
+}
\ No newline at end of file
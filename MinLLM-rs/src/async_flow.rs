@@ -1,14 +1,39 @@
 use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
-use std::any::Any;
+use std::pin::pin;
+use std::sync::Arc;
+use std::time::Duration;
+use parking_lot::RwLock;
+use tokio::time::Instant;
 use async_trait::async_trait;
-use futures::future;
+use futures::future::{join_all, BoxFuture};
+use futures::FutureExt;
+use futures::stream::{BoxStream, StreamExt};
 use serde_json::Value;
-use log::warn;
 
-use crate::base::{BaseNode, Node, SharedState, Action};
-use crate::flow::{Flow, BatchFlow};
+use crate::base::{BaseNode, short_type_name, Node, SharedState, Action, ParamMap};
+use crate::successors::{Successors, GraphNode};
+use crate::hooks::NodeHooks;
+use crate::cancel::{self, CancellationToken};
+use crate::deadline;
+use crate::step_guard;
+use crate::shutdown::{self, ShutdownHandle, RunOutcome};
+use crate::heartbeat::{self, HeartbeatCallback, HeartbeatConfig};
+use crate::dry_run::{self, DryRunStub, DryRunReport};
+use crate::trace::{self, FlowTrace};
+use crate::streaming::{self, FlowEvent};
+use crate::dataflow::DataflowReport;
+use crate::dot;
+use crate::validation::{self, ValidationReport};
+use crate::param_spec::{ParamSpec, resolve_params};
+use crate::flow::{Flow, Route, merge_params};
+use crate::compiled_flow::CompiledFlow;
 use crate::async_node::AsyncNodeTrait;
+use crate::nodes::join;
+use crate::nodes::fn_node::PrepFn;
+use crate::cow_state::{MergePolicy, merge_overlays};
+use crate::determinism::Determinism;
+use crate::history::{StateEvent, EntryMeta};
+use crate::state_limit::StateLimit;
 use crate::error::{Error, Result};
 
 /// A workflow with asynchronous execution
@@ -19,82 +44,361 @@ pub struct AsyncFlow {
     
     /// Base node implementation
     base: BaseNode,
+    
+    /// Resolution of conflicting writes when fan-out branches run concurrently
+    fan_out_merge: Option<MergePolicy>,
+    
+    /// Heartbeat of every async node without its own
+    heartbeat: Option<HeartbeatConfig>,
 }
 
 impl AsyncFlow {
     /// Create a new async flow with a starting node
     pub fn new(start: Arc<dyn Node>) -> Self {
+        Self::wrap(Flow::new(start))
+    }
+    
+    /// Run `flow` asynchronously, keeping its hooks, step limit and state observers
+    pub(crate) fn wrap(flow: Flow) -> Self {
         Self {
-            flow: Flow::new(start),
+            flow,
             base: BaseNode::new(),
+            fan_out_merge: None,
+            heartbeat: None,
         }
     }
     
-    /// Check if a node is an async node
-    fn is_async(&self, node: &Arc<dyn Node>) -> bool {
-        // Try to cast to the trait object, just to check if it's possible
-        // We can't use the result directly, we just want to know if it's possible
-        let type_id = node.type_id();
-        // Check against the type IDs of our async node types
-        let async_node_ids = [
-            std::any::TypeId::of::<dyn AsyncNodeTrait>(),
-            // Add other async node type IDs if needed
-        ];
-        async_node_ids.contains(&type_id)
+    /// The underlying flow
+    pub(crate) fn inner(&self) -> &Flow {
+        &self.flow
+    }
+    
+    /// Run the branches of a `FanOutNode` or of an action with several successors concurrently instead of in order
+    ///
+    /// Each branch runs against a copy-on-write view of the shared state; once all of them end,
+    /// their writes are merged back in successor order under `policy` and the flow continues.
+    pub fn with_concurrent_fan_out(mut self, policy: MergePolicy) -> Self {
+        self.fan_out_merge = Some(policy);
+        self
+    }
+    
+    /// Name the flow in logs, errors and traces
+    pub fn with_name(self, name: &str) -> Self {
+        self.set_name(name);
+        self
+    }
+    
+    /// Apply `hooks` to every node the flow runs, including the nodes of nested flows
+    pub fn with_default_hooks(mut self, hooks: NodeHooks) -> Self {
+        self.flow = self.flow.with_default_hooks(hooks);
+        self
+    }
+    
+    /// Call `callback` every `interval` while an exec attempt of any async node runs, including in nested flows
+    ///
+    /// Nodes with a heartbeat of their own keep it, and synchronous nodes never fire one.
+    pub fn with_heartbeat(mut self, interval: Duration, callback: HeartbeatCallback) -> Self {
+        self.heartbeat = Some(HeartbeatConfig::new(interval, callback));
+        self
+    }
+    
+    /// Stop a run with an error once it has taken `max_steps` node steps, or never with `None`
+    ///
+    /// Defaults to `DEFAULT_MAX_STEPS`. Steps of nested flows count toward their own limit,
+    /// and each step of a fan-out branch counts, concurrent or not.
+    pub fn with_max_steps(mut self, max_steps: Option<usize>) -> Self {
+        self.flow = self.flow.with_max_steps(max_steps);
+        self
+    }
+    
+    /// Validate the flow before every run, failing with `Error::InvalidFlow` when `validate` finds errors
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.flow = self.flow.with_strict(strict);
+        self
+    }
+    
+    /// Groups of reachable nodes that can loop back to each other, each in graph order
+    pub fn detect_cycles(&self) -> Vec<Vec<String>> {
+        self.flow.detect_cycles()
+    }
+    
+    /// Keep the shared state within `limit` after every node step
+    pub fn with_state_limit(mut self, limit: StateLimit) -> Self {
+        self.flow = self.flow.with_state_limit(limit);
+        self
+    }
+    
+    /// Record every change node steps make to the shared state, keeping the last `capacity` events
+    pub fn with_history(mut self, capacity: usize) -> Self {
+        self.flow = self.flow.with_history(capacity);
+        self
+    }
+    
+    /// Recorded state changes, oldest first; empty unless `with_history` was used
+    pub fn history(&self) -> Vec<StateEvent> {
+        self.flow.history()
+    }
+    
+    /// Recorded changes to one key, oldest first
+    pub fn history_for(&self, key: &str) -> Vec<StateEvent> {
+        self.flow.history_for(key)
+    }
+    
+    /// When a key was written and by which step; `None` unless `with_history` was used
+    pub fn metadata(&self, key: &str) -> Option<EntryMeta> {
+        self.flow.metadata(key)
+    }
+    
+    /// Subscribe to changes of a shared state key made while the flow runs
+    pub fn watch(&self, key: &str) -> tokio::sync::watch::Receiver<Option<Value>> {
+        self.flow.watch(key)
+    }
+    
+    /// Replace exec with `stub` in dry runs, instead of echoing the prep result
+    pub fn with_dry_run_stub(mut self, stub: DryRunStub) -> Self {
+        self.flow = self.flow.with_dry_run_stub(stub);
+        self
+    }
+    
+    /// Run the flow with every node's exec replaced by its `exec_dry_run`, recording each step
+    pub async fn dry_run_async(&self, shared: &mut SharedState) -> Result<DryRunReport> {
+        dry_run::run_async(self.flow.dry_run_stub.clone(), self.run_async(shared)).await
+    }
+    
+    /// Run the flow, recording every node step and batch iteration with its action and timing
+    ///
+    /// The trace is returned even when the run fails, ending with the steps that failed.
+    pub async fn run_async_traced(&self, shared: &mut SharedState) -> (Result<Action>, FlowTrace) {
+        trace::run_async(self.run_async(shared)).await
+    }
+    
+    /// Check the graph reachable from the start node without running it, as `Flow::validate` does
+    ///
+    /// Async-only nodes are fine here, so they aren't reported.
+    pub fn validate(&self) -> Result<ValidationReport> {
+        self.flow.validate_with(self.base.params(), false)
+    }
+    
+    /// Snapshot the flow's current topology into an index-based execution plan, as `Flow::compile` does
+    ///
+    /// Async-only nodes pass validation here, so the plan is meant to be run with `run_async`.
+    pub fn compile(&self) -> Result<CompiledFlow> {
+        validation::enforce(self.validate()?)?;
+        Ok(CompiledFlow::new(self.clone(), self.base.params()))
+    }
+    
+    /// Check that every key a reachable node reads is written by a node that can run before it
+    pub fn check_dataflow(&self, initial_keys: &[&str]) -> DataflowReport {
+        self.flow.check_dataflow(initial_keys)
+    }
+    
+    /// Every node reachable from the start node, breadth-first, with its outgoing edges
+    pub fn nodes(&self) -> Vec<GraphNode> {
+        self.flow.nodes()
+    }
+    
+    /// Describe the flow in Graphviz DOT, as `Flow::to_dot` does
+    pub fn to_dot(&self) -> String {
+        dot::render(&self.name(), self.flow.start.clone())
+    }
+    
+    /// Run the flow until it ends or `token` is cancelled
+    ///
+    /// Cancellation is checked before every node and between batch items, and aborts the exec
+    /// attempts of async nodes in flight; the run then fails with `Error::Cancelled`.
+    /// Execs can also watch `CancellationToken::current()` to stop early on their own.
+    pub async fn run_cancellable(&self, shared: &mut SharedState, token: CancellationToken) -> Result<Action> {
+        cancel::scope(token, self.run_async(shared)).await
+    }
+    
+    /// Run the flow, failing with `Error::DeadlineExceeded` once `deadline` has passed
+    ///
+    /// The deadline is checked before every node and between batch items, and the exec attempts
+    /// of async nodes are cut short when it passes; the error then lists the nodes the run could
+    /// still have reached. Per-attempt timeouts are clamped to the time left, and execs can read
+    /// the deadline through `current_deadline()`.
+    pub async fn run_async_with_deadline(&self, shared: &mut SharedState, deadline: Instant) -> Result<Action> {
+        deadline::scope(deadline, self.run_async(shared)).await
+    }
+    
+    /// Run the flow until it ends or `handle` is triggered
+    ///
+    /// Shutdown is checked before every node and between batch items, so the node in flight
+    /// finishes and keeps its writes to the shared state while the rest is skipped. The run then
+    /// returns `RunOutcome::Interrupted` with the last node that finished, rather than an error.
+    /// Execs can watch `ShutdownHandle::current()` to stop early on their own.
+    pub async fn run_with_shutdown(&self, shared: &mut SharedState, handle: ShutdownHandle) -> Result<RunOutcome> {
+        shutdown::scope(handle, self.run_async(shared)).await
+    }
+    
+    /// Run the flow as a stream of events: node boundaries, the chunks of every async exec, then the end
+    ///
+    /// The flow only advances while the stream is polled, so each event is seen before the node
+    /// that produced it moves on. Nodes return the combined chunks to `post_async` as usual.
+    pub fn run_async_streaming<'a>(&'a self, shared: &'a mut SharedState) -> BoxStream<'a, Result<FlowEvent>> {
+        streaming::run(self.run_async(shared))
+    }
+    
+    /// Run the flow to completion on a caller-provided runtime
+    ///
+    /// Blocks the calling thread, so it must not be called from within an async context.
+    pub fn run_on(&self, handle: &tokio::runtime::Handle, shared: &mut SharedState) -> Result<Action> {
+        handle.block_on(self.run_async(shared))
     }
     
     /// Orchestrate flow through nodes asynchronously
-    pub async fn _orch_async(&self, shared: &mut SharedState, params: Option<HashMap<String, Value>>) -> Result<()> {
-        let mut curr = self.flow.start.clone();
+    pub async fn _orch_async(&self, shared: &mut SharedState, params: Option<Arc<ParamMap>>) -> Result<()> {
         let params = params.unwrap_or_else(|| {
-            self.base.params().read().unwrap().clone()
+            self.base.params()
         });
         
-        curr.set_params(params);
-        
-        while let Some(node) = curr.clone().into() {
-            let action = if self.is_async(&node) {
-                // This is an async node, use dynamic dispatch to call the async method
-                // For simplicity, we'll just implement a mock here
-                // In a real implementation, you'd need to handle this more robustly
-                Err(Error::InvalidOperation("Dynamic dispatch for async nodes not implemented".into()))?
-            } else {
-                // Not an async node, use the synchronous method
-                node._run(shared)?
+        self.orch_async(&self.flow, self.flow.start.clone(), params, shared).await
+    }
+    
+    /// Orchestrate a walk from `start` along `route`, handing `params` to the start node
+    pub(crate) async fn orch_async<R: Route>(&self, route: &R, start: R::At, params: Arc<ParamMap>, shared: &mut SharedState) -> Result<()> {
+        let curr = route.node(&start);
+        curr.set_params(resolve_params(curr.as_ref(), params)?);
+        self.flow.begin_steps(shared);
+        let run = join::scope(async {
+            self.walk_async(route, start, shared, false).await?;
+            while let Some(join) = join::next_pending().await {
+                self.walk_async(route, route.locate(join), shared, false).await?;
+            }
+            Ok(())
+        });
+        heartbeat::scope(self.heartbeat.clone(), step_guard::scope(self.flow.max_steps, run)).await
+    }
+    
+    /// Run the flow with `params` handed to the start node in place of the flow's own
+    pub(crate) async fn run_async_with_params(&self, params: Arc<ParamMap>, shared: &mut SharedState) -> Result<Action> {
+        if self.flow.strict {
+            validation::enforce(self.flow.validate_with(params.clone(), false)?)?;
+        }
+        self.run_route_async(&self.flow, self.flow.start.clone(), params, shared).await
+    }
+    
+    /// Run the flow's prep, a walk from `start` along `route`, then its post
+    pub(crate) async fn run_route_async<R: Route>(&self, route: &R, start: R::At, params: Arc<ParamMap>, shared: &mut SharedState) -> Result<Action> {
+        let prep_res = self.prep_async(shared).await?;
+        self.orch_async(route, start, params, shared).await?;
+        self.post_async(shared, prep_res, Value::Null).await
+    }
+    
+    /// Run nodes from `start`, following `route`, until no successor matches the returned action
+    ///
+    /// A `detached` walk is a branch of a concurrent fan-out, which leaves joins to the merged state.
+    fn walk_async<'a, R: Route>(&'a self, route: &'a R, start: R::At, shared: &'a mut SharedState, detached: bool) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            // State accesses between steps are the flow's own, so none are recorded for the step around it
+            let outer = shared.attribute(None);
+            let walked = self.walk_steps_async(route, start, shared, detached).await;
+            shared.attribute(outer);
+            walked
+        })
+    }
+    
+    /// The steps of `walk_async`, recording each step's state accesses for its node
+    async fn walk_steps_async<R: Route>(&self, route: &R, start: R::At, shared: &mut SharedState, detached: bool) -> Result<()> {
+        let mut at = start;
+        loop {
+            let node = route.node(&at);
+            cancel::checkpoint()?;
+            shutdown::checkpoint()?;
+            deadline::checkpoint(&node)?;
+            step_guard::step(&node)?;
+            let before = self.flow.before_step(shared);
+            streaming::emit(|| FlowEvent::NodeStarted { node: node.name() }).await;
+            let started = trace::start(shared);
+            let armed = route.has_conditional(&at);
+            shared.attribute(trace::accessor(|| node.name()));
+            let step = match node.as_async() {
+                Some(async_node) => self.flow.run_step_async(&node, async_node, armed, shared).await,
+                None => self.flow.run_step(&node, armed, shared),
             };
+            shared.attribute(None);
+            let step = step.map_err(|e| deadline::cut_short(e, &node));
+            trace::record(started, || node.name(), step.as_ref().map(|(action, _)| action), None, shared);
+            let (action, exec_res) = step?;
+            shutdown::completed(|| (node.name(), action.clone()));
+            streaming::emit(|| FlowEvent::NodeFinished { node: node.name(), action: action.clone() }).await;
+            self.run_fan_out(route, self.flow.branch_starts(route, &at), shared, detached).await?;
+            self.flow.after_step(&node, before, shared)?;
             
-            curr = match self.flow.get_next_node(node, action) {
-                Some(next) => next,
+            let mut from = None;
+            let mut next = route.next(&at, action.as_deref(), shared, &exec_res);
+            next.retain(|succ| {
+                let succ = route.node(succ);
+                match succ.as_join() {
+                    Some(join) => join::arrive(&succ, join, from.get_or_insert_with(|| node.name()), detached),
+                    None => true,
+                }
+            });
+            at = match next.last {
+                Some(last) => last,
                 None => break,
             };
+            self.run_fan_out(route, next.branches, shared, detached).await?;
         }
         
         Ok(())
     }
+    
+    /// Run the branches of a fan-out, in order or concurrently
+    async fn run_fan_out<R: Route>(&self, route: &R, branches: Vec<R::At>, shared: &mut SharedState, detached: bool) -> Result<()> {
+        let policy = match &self.fan_out_merge {
+            Some(policy) if branches.len() > 1 => policy,
+            _ => {
+                for branch in branches {
+                    self.walk_async(route, branch, shared, detached).await?;
+                }
+                return Ok(());
+            },
+        };
+        
+        let forks = shared.racing_forks(branches.len());
+        let futures = branches.into_iter().zip(forks).map(|(branch, mut state)| {
+            async move {
+                self.walk_async(route, branch, &mut state, true).await?;
+                Ok::<_, Error>(state)
+            }
+        });
+        let overlays = join_all(futures).await.into_iter().collect::<Result<Vec<_>>>()?;
+        merge_overlays(shared, overlays, policy)
+    }
 }
 
 impl Node for AsyncFlow {
-    fn params(&self) -> Arc<RwLock<HashMap<String, Value>>> {
+    fn params(&self) -> Arc<ParamMap> {
         self.base.params()
     }
     
-    fn successors(&self) -> Arc<RwLock<HashMap<String, Arc<dyn Node>>>> {
+    fn name(&self) -> String {
+        self.base.assigned_name().unwrap_or_else(short_type_name::<Self>)
+    }
+    
+    fn set_name(&self, name: &str) {
+        self.base.set_name(name);
+    }
+    
+    fn required_params(&self) -> &[ParamSpec] {
+        self.flow.start.required_params()
+    }
+    
+    fn nested_start(&self) -> Option<Arc<dyn Node>> {
+        Some(self.flow.start.clone())
+    }
+    
+    fn successors(&self) -> Arc<RwLock<Successors>> {
         self.base.successors()
     }
     
-    fn set_params(&self, params: HashMap<String, Value>) {
-        let params_lock = self.params();
-        let mut p = params_lock.write().unwrap();
-        *p = params;
+    fn set_params(&self, params: Arc<ParamMap>) {
+        self.base.set_params(params);
     }
     
     fn add_successor(&self, node: Arc<dyn Node>, action: &str) -> Result<Arc<dyn Node>> {
-        let successors_lock = self.successors();
-        let mut successors = successors_lock.write().unwrap();
-        if successors.contains_key(action) {
-            warn!("Overwriting successor for action '{}'", action);
-        }
-        successors.insert(action.to_string(), node.clone());
+        self.successors().write().push(action.to_string(), node.clone());
         Ok(node)
     }
     
@@ -102,7 +406,7 @@ impl Node for AsyncFlow {
         Err(Error::InvalidOperation("Use prep_async".into()))
     }
     
-    fn exec(&self, _prep_res: Value) -> Result<Value> {
+    fn exec(&self, _prep_res: &Value) -> Result<Value> {
         Err(Error::InvalidOperation("AsyncFlow can't exec".into()))
     }
     
@@ -113,18 +417,20 @@ impl Node for AsyncFlow {
     fn _run(&self, _shared: &mut SharedState) -> Result<Action> {
         Err(Error::InvalidOperation("Use run_async".into()))
     }
+    
+    fn as_async(&self) -> Option<&dyn AsyncNodeTrait> {
+        Some(self)
+    }
 }
 
 #[async_trait]
 impl AsyncNodeTrait for AsyncFlow {
-    async fn _exec_async(&self, _prep_res: Value) -> Result<Value> {
+    async fn _exec_async(&self, _prep_res: &Value) -> Result<Value> {
         Err(Error::InvalidOperation("AsyncFlow can't exec".into()))
     }
     
     async fn _run_async(&self, shared: &mut SharedState) -> Result<Action> {
-        let prep_res = self.prep_async(shared).await?;
-        self._orch_async(shared, None).await?;
-        self.post_async(shared, prep_res, Value::Null).await
+        self.run_async_with_params(self.base.params(), shared).await
     }
 }
 
@@ -134,30 +440,60 @@ pub struct AsyncBatchFlow {
     /// Underlying async flow
     flow: AsyncFlow,
     
-    /// Underlying batch flow
-    batch_flow: BatchFlow,
+    /// Prep closure returning the batch params, when set instead of the default prep
+    prep: Option<PrepFn>,
 }
 
 impl AsyncBatchFlow {
     /// Create a new async batch flow with a starting node
     pub fn new(start: Arc<dyn Node>) -> Self {
         Self {
-            flow: AsyncFlow::new(start.clone()),
-            batch_flow: BatchFlow::new(start),
+            flow: AsyncFlow::new(start),
+            prep: None,
         }
     }
+    
+    /// Compute the batch params from the shared state, as an array of objects with one run per object
+    pub fn with_prep(mut self, f: impl Fn(&SharedState) -> Result<Value> + Send + Sync + 'static) -> Self {
+        self.prep = Some(Arc::new(f));
+        self
+    }
+    
+    /// Run the flow once for each of `items`, with its entries overlaid on the flow params
+    pub fn with_items(self, items: Vec<ParamMap>) -> Self {
+        let items = Value::Array(items.into_iter().map(|item| Value::Object(item.into_iter().collect())).collect());
+        self.with_prep(move |_| Ok(items.clone()))
+    }
+    
+    /// Name the flow in logs, errors and traces
+    pub fn with_name(self, name: &str) -> Self {
+        self.set_name(name);
+        self
+    }
 }
 
 impl Node for AsyncBatchFlow {
-    fn params(&self) -> Arc<RwLock<HashMap<String, Value>>> {
+    fn params(&self) -> Arc<ParamMap> {
         self.flow.params()
     }
     
-    fn successors(&self) -> Arc<RwLock<HashMap<String, Arc<dyn Node>>>> {
+    fn name(&self) -> String {
+        self.flow.name()
+    }
+    
+    fn set_name(&self, name: &str) {
+        self.flow.set_name(name);
+    }
+    
+    fn nested_start(&self) -> Option<Arc<dyn Node>> {
+        self.flow.nested_start()
+    }
+    
+    fn successors(&self) -> Arc<RwLock<Successors>> {
         self.flow.successors()
     }
     
-    fn set_params(&self, params: HashMap<String, Value>) {
+    fn set_params(&self, params: Arc<ParamMap>) {
         self.flow.set_params(params);
     }
     
@@ -169,7 +505,14 @@ impl Node for AsyncBatchFlow {
         Err(Error::InvalidOperation("Use prep_async".into()))
     }
     
-    fn exec(&self, _prep_res: Value) -> Result<Value> {
+    fn prep_readonly(&self, shared: &SharedState) -> Result<Value> {
+        match &self.prep {
+            Some(f) => f(shared),
+            None => Ok(Value::Null),
+        }
+    }
+    
+    fn exec(&self, _prep_res: &Value) -> Result<Value> {
         Err(Error::InvalidOperation("AsyncBatchFlow can't exec".into()))
     }
     
@@ -180,11 +523,15 @@ impl Node for AsyncBatchFlow {
     fn _run(&self, _shared: &mut SharedState) -> Result<Action> {
         Err(Error::InvalidOperation("Use run_async".into()))
     }
+    
+    fn as_async(&self) -> Option<&dyn AsyncNodeTrait> {
+        Some(self)
+    }
 }
 
 #[async_trait]
 impl AsyncNodeTrait for AsyncBatchFlow {
-    async fn _exec_async(&self, _prep_res: Value) -> Result<Value> {
+    async fn _exec_async(&self, _prep_res: &Value) -> Result<Value> {
         Err(Error::InvalidOperation("AsyncBatchFlow can't exec".into()))
     }
     
@@ -210,15 +557,15 @@ impl AsyncNodeTrait for AsyncBatchFlow {
             _ => return Err(Error::NodeExecution("AsyncBatchFlow prep should return array or null".into())),
         };
         
-        let flow_params = self.flow.params().read().unwrap().clone();
+        let flow_params = self.flow.params();
         
-        for mut bp in batch_params {
-            // Merge batch params with flow params
-            for (k, v) in flow_params.clone() {
-                bp.entry(k).or_insert(v);
-            }
-            
-            self.flow._orch_async(shared, Some(bp)).await?;
+        for bp in batch_params {
+            shutdown::checkpoint()?;
+            let params = merge_params(&flow_params, bp);
+            let started = trace::start(shared);
+            let result = self.flow._orch_async(shared, Some(params.clone())).await;
+            trace::record(started, || self.name(), result.as_ref().map(|_| &None), Some(params.as_ref()), shared);
+            result?;
         }
         
         self.post_async(shared, prep_res, Value::Null).await
@@ -226,10 +573,23 @@ impl AsyncNodeTrait for AsyncBatchFlow {
 }
 
 /// An async flow that processes batches of items in parallel
+///
+/// Each item runs against a fork of the shared state, reading the values it doesn't write
+/// without copying them. Once every item has finished, what each item wrote or removed is
+/// merged back in batch order. The first failing item drops the items still running, and
+/// those not started never start; the changes of the items that were already done are still
+/// merged and the flow fails with `Error::BatchFailed`, listing their failures and the
+/// positions of the items that succeeded.
 #[derive(Clone)]
 pub struct AsyncParallelBatchFlow {
     /// Underlying async batch flow
     batch_flow: AsyncBatchFlow,
+    
+    /// Resolution of conflicting writes between items
+    merge_policy: MergePolicy,
+    
+    /// Scheduling of the batch items
+    determinism: Determinism,
 }
 
 impl AsyncParallelBatchFlow {
@@ -237,20 +597,64 @@ impl AsyncParallelBatchFlow {
     pub fn new(start: Arc<dyn Node>) -> Self {
         Self {
             batch_flow: AsyncBatchFlow::new(start),
+            merge_policy: MergePolicy::default(),
+            determinism: Determinism::default(),
         }
     }
+    
+    /// Name the flow in logs, errors and traces
+    pub fn with_name(self, name: &str) -> Self {
+        self.set_name(name);
+        self
+    }
+    
+    /// Set how batch items are scheduled
+    pub fn with_determinism(mut self, determinism: Determinism) -> Self {
+        self.determinism = determinism;
+        self
+    }
+    
+    /// Set how conflicting writes between items are resolved
+    pub fn with_merge_policy(mut self, policy: MergePolicy) -> Self {
+        self.merge_policy = policy;
+        self
+    }
+    
+    /// Compute the batch params from the shared state, as an array of objects with one run per object
+    pub fn with_prep(mut self, f: impl Fn(&SharedState) -> Result<Value> + Send + Sync + 'static) -> Self {
+        self.batch_flow = self.batch_flow.with_prep(f);
+        self
+    }
+    
+    /// Run the flow once for each of `items`, with its entries overlaid on the flow params
+    pub fn with_items(mut self, items: Vec<ParamMap>) -> Self {
+        self.batch_flow = self.batch_flow.with_items(items);
+        self
+    }
 }
 
 impl Node for AsyncParallelBatchFlow {
-    fn params(&self) -> Arc<RwLock<HashMap<String, Value>>> {
+    fn params(&self) -> Arc<ParamMap> {
         self.batch_flow.params()
     }
     
-    fn successors(&self) -> Arc<RwLock<HashMap<String, Arc<dyn Node>>>> {
+    fn name(&self) -> String {
+        self.batch_flow.name()
+    }
+    
+    fn set_name(&self, name: &str) {
+        self.batch_flow.set_name(name);
+    }
+    
+    fn nested_start(&self) -> Option<Arc<dyn Node>> {
+        self.batch_flow.nested_start()
+    }
+    
+    fn successors(&self) -> Arc<RwLock<Successors>> {
         self.batch_flow.successors()
     }
     
-    fn set_params(&self, params: HashMap<String, Value>) {
+    fn set_params(&self, params: Arc<ParamMap>) {
         self.batch_flow.set_params(params);
     }
     
@@ -262,7 +666,7 @@ impl Node for AsyncParallelBatchFlow {
         Err(Error::InvalidOperation("Use prep_async".into()))
     }
     
-    fn exec(&self, _prep_res: Value) -> Result<Value> {
+    fn exec(&self, _prep_res: &Value) -> Result<Value> {
         Err(Error::InvalidOperation("AsyncParallelBatchFlow can't exec".into()))
     }
     
@@ -273,6 +677,10 @@ impl Node for AsyncParallelBatchFlow {
     fn _run(&self, _shared: &mut SharedState) -> Result<Action> {
         Err(Error::InvalidOperation("Use run_async".into()))
     }
+    
+    fn as_async(&self) -> Option<&dyn AsyncNodeTrait> {
+        Some(self)
+    }
 }
 
 #[async_trait]
@@ -285,7 +693,7 @@ impl AsyncNodeTrait for AsyncParallelBatchFlow {
         self.batch_flow.post_async(shared, prep_res, exec_res).await
     }
     
-    async fn _exec_async(&self, _prep_res: Value) -> Result<Value> {
+    async fn _exec_async(&self, _prep_res: &Value) -> Result<Value> {
         Err(Error::InvalidOperation("AsyncParallelBatchFlow can't exec".into()))
     }
     
@@ -315,34 +723,54 @@ impl AsyncNodeTrait for AsyncParallelBatchFlow {
             return self.post_async(shared, prep_res, Value::Null).await;
         }
         
-        let flow_params = self.batch_flow.params().read().unwrap().clone();
+        let flow_params = self.batch_flow.params();
         
-        // Create a future for each batch item
-        let futures = batch_params
-            .into_iter()
-            .map(|mut bp| {
-                // Clone what we need for the future
-                let flow = self.batch_flow.flow.clone();
-                let mut shared_clone = shared.clone();
-                let flow_params = flow_params.clone();
-                
-                // Merge batch params with flow params
-                for (k, v) in flow_params {
-                    bp.entry(k).or_insert(v);
-                }
-                
-                async move { flow._orch_async(&mut shared_clone, Some(bp)).await }
-            })
-            .collect::<Vec<_>>();
-        
-        // Execute all futures concurrently
-        let results = future::join_all(futures).await;
+        // Create a future for each batch item, each forking the state as the batch starts
+        let forks = shared.racing_forks(batch_params.len());
+        let futures = batch_params.into_iter().zip(forks).map(|(bp, mut state)| {
+            // Clone what we need for the future
+            let flow = self.batch_flow.flow.clone();
+            let bp = merge_params(&flow_params, bp);
+            
+            async move {
+                let started = trace::start(&state);
+                let result = flow._orch_async(&mut state, Some(bp.clone())).await;
+                trace::record(started, || flow.name(), result.as_ref().map(|_| &None), Some(bp.as_ref()), &state);
+                result?;
+                Ok::<_, Error>(state)
+            }
+        });
         
-        // Check for errors
-        for result in results {
-            result?;
+        // Execute all futures, concurrently unless a seeded order was requested, until one fails;
+        // after that only the items that are already done are taken
+        let mut finished = Vec::new();
+        let mut failures = Vec::new();
+        {
+            let mut outcomes = pin!(self.determinism.unordered(futures));
+            loop {
+                let next = if failures.is_empty() {
+                    outcomes.next().await
+                } else {
+                    outcomes.next().now_or_never().flatten()
+                };
+                let Some((i, result)) = next else {
+                    break;
+                };
+                match result {
+                    Ok(overlay) => finished.push((i, overlay)),
+                    Err(e) if e.is_cancelled() => return Err(e),
+                    Err(e) => failures.push((i, e)),
+                }
+            }
         }
         
+        finished.sort_by_key(|(i, _)| *i);
+        let succeeded = finished.iter().map(|(i, _)| (*i, Value::Null)).collect();
+        merge_overlays(shared, finished.into_iter().map(|(_, overlay)| overlay).collect(), &self.merge_policy)?;
+        if !failures.is_empty() {
+            failures.sort_by_key(|(i, _)| *i);
+            return Err(Error::BatchFailed { failures, results: succeeded });
+        }
         self.post_async(shared, prep_res, Value::Null).await
     }
 } 
\ No newline at end of file
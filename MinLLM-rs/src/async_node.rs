@@ -1,25 +1,44 @@
-use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
+use std::future::Future;
+use std::sync::Arc;
 use std::time::Duration;
+use parking_lot::RwLock;
 use async_trait::async_trait;
-use futures::future::{self};
-use tokio::time::sleep;
+use futures::FutureExt;
+use futures::stream::{self, BoxStream, StreamExt};
 use serde_json::Value;
 use log::warn;
 
-use crate::base::{BaseNode, Node as NodeTrait, SharedState, Action};
+use crate::base::{BaseNode, short_type_name, Node as NodeTrait, SharedState, Action, ParamMap};
+use crate::successors::Successors;
+use crate::node::batch_items;
+use crate::hooks::{NodeHooks, NodeHook, ErrorHook};
+use crate::dry_run;
+use crate::streaming;
+use crate::sleeper::{self, Sleeper};
+use crate::backoff::Backoff;
+use crate::rate_limit::RateLimiter;
+use crate::cancel;
+use crate::deadline;
+use crate::shutdown;
+use crate::heartbeat::{HeartbeatCallback, HeartbeatConfig};
+use crate::retry::{self, RetryPolicy, FixedRetry, BackoffRetry, FallbackContext};
+use crate::determinism::Determinism;
+use crate::batch_policy::{BatchCollector, ErrorPolicy, ResultOrder, ProgressCallback, ProgressTracker};
+use crate::nodes::fn_node::{AsyncExecFn, AsyncFallbackFn};
 use crate::error::{Error, Result};
 
 /// Trait for asynchronous node operations
 #[async_trait]
 pub trait AsyncNodeTrait: NodeTrait {
     /// Asynchronous preparation step before execution
-    async fn prep_async(&self, _shared: &mut SharedState) -> Result<Value> {
-        Ok(Value::Null)
+    ///
+    /// Delegates to `prep_readonly` unless overridden.
+    async fn prep_async(&self, shared: &mut SharedState) -> Result<Value> {
+        self.prep_readonly(shared)
     }
     
     /// Asynchronous execution of node logic
-    async fn exec_async(&self, _prep_res: Value) -> Result<Value> {
+    async fn exec_async(&self, _prep_res: &Value) -> Result<Value> {
         Ok(Value::Null)
     }
     
@@ -29,17 +48,53 @@ pub trait AsyncNodeTrait: NodeTrait {
     }
     
     /// Asynchronous fallback for execution failures
-    async fn exec_fallback_async(&self, _prep_res: Value, error: Error) -> Result<Value> {
+    async fn exec_fallback_async(&self, _prep_res: &Value, error: Error) -> Result<Value> {
         Err(error)
     }
     
+    /// Asynchronous fallback told which attempt failed and, in a batch, for which item
+    ///
+    /// Delegates to `exec_fallback_async` unless overridden.
+    async fn exec_fallback_async_ctx(&self, prep_res: &Value, error: Error, _ctx: FallbackContext) -> Result<Value> {
+        self.exec_fallback_async(prep_res, error).await
+    }
+    
     /// Internal asynchronous execution method
-    async fn _exec_async(&self, prep_res: Value) -> Result<Value>;
+    async fn _exec_async(&self, prep_res: &Value) -> Result<Value>;
+    
+    /// Exec as a stream of chunks, surfaced to callers of `AsyncFlow::run_async_streaming`
+    ///
+    /// Defaults to a single chunk holding the result of `_exec_async`, retries included;
+    /// nodes that override it handle their own retries. The chunks are combined with
+    /// `collect_chunks` into the exec result `post_async` receives.
+    fn exec_stream<'a>(&'a self, prep_res: &'a Value) -> BoxStream<'a, Result<Value>> {
+        Box::pin(stream::once(self._exec_async(prep_res)))
+    }
+    
+    /// Combine the chunks of `exec_stream` into one exec result
+    ///
+    /// Defaults to the lone chunk as is, strings concatenated, or anything else as an array.
+    fn collect_chunks(&self, chunks: Vec<Value>) -> Value {
+        streaming::concat(chunks)
+    }
     
     /// Run the node asynchronously
     async fn _run_async(&self, shared: &mut SharedState) -> Result<Action> {
         let prep_res = self.prep_async(shared).await?;
-        let exec_res = self._exec_async(prep_res.clone()).await?;
+        let hooks = NodeHooks::effective(self.hooks());
+        if dry_run::is_active() {
+            let exec_res = hooks.observe(|| self.name(), &prep_res, || self.exec_dry_run(&prep_res))?;
+            let action = self.post_async(shared, prep_res.clone(), exec_res).await?;
+            dry_run::record(self.name(), prep_res, &action);
+            return Ok(action);
+        }
+        let exec_res = if streaming::is_active() {
+            let chunks = self.exec_stream(&prep_res);
+            let exec = streaming::collect(self.name(), chunks, |chunks| self.collect_chunks(chunks));
+            hooks.observe_async(|| self.name(), &prep_res, exec).await?
+        } else {
+            hooks.observe_async(|| self.name(), &prep_res, self._exec_async(&prep_res)).await?
+        };
         self.post_async(shared, prep_res, exec_res).await
     }
     
@@ -47,9 +102,9 @@ pub trait AsyncNodeTrait: NodeTrait {
     async fn run_async(&self, shared: &mut SharedState) -> Result<Action> {
         {
             let successors_lock = self.successors();
-            let successors = successors_lock.read().unwrap();
+            let successors = successors_lock.read();
             if !successors.is_empty() {
-                warn!("AsyncNode won't run successors. Use AsyncFlow.");
+                warn!(target: "minllm::node", "AsyncNode won't run successors. Use AsyncFlow.");
             }
         }
         self._run_async(shared).await
@@ -65,11 +120,26 @@ pub struct AsyncNode {
     /// Maximum number of retries
     max_retries: usize,
     
-    /// Wait time between retries in milliseconds
-    wait: u64,
+    /// Decides which failures are retried and the wait before each retry
+    retry: Arc<dyn RetryPolicy>,
+    
+    /// Longest a single exec attempt may take
+    timeout: Option<Duration>,
     
-    /// Current retry count
-    cur_retry: Arc<RwLock<usize>>,
+    /// Source of the waits between retries
+    sleeper: Arc<dyn Sleeper>,
+    
+    /// Limiter every exec attempt acquires a permit from, when set
+    limiter: Option<Arc<RateLimiter>>,
+    
+    /// Heartbeat fired while an exec attempt runs, when set
+    heartbeat: Arc<RwLock<Option<HeartbeatConfig>>>,
+    
+    /// Exec closure, when set instead of the default exec
+    exec: Option<AsyncExecFn>,
+    
+    /// Fallback closure, when set instead of the default fallback
+    fallback: Option<AsyncFallbackFn>,
 }
 
 impl AsyncNode {
@@ -78,10 +148,104 @@ impl AsyncNode {
         Self {
             base: BaseNode::new(),
             max_retries,
-            wait,
-            cur_retry: Arc::new(RwLock::new(0)),
+            retry: Arc::new(FixedRetry::new(max_retries, Duration::from_millis(wait))),
+            timeout: None,
+            sleeper: sleeper::real(),
+            limiter: None,
+            heartbeat: Arc::new(RwLock::new(None)),
+            exec: None,
+            fallback: None,
         }
     }
+    
+    /// Compute each exec attempt's result from the prep result
+    pub fn with_exec<F, Fut>(mut self, f: F) -> Self
+    where
+        F: Fn(Value) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Value>> + Send + 'static,
+    {
+        self.exec = Some(Arc::new(move |prep_res| Box::pin(f(prep_res))));
+        self
+    }
+    
+    /// Recover from the last failed exec attempt, given the prep result and the error
+    pub fn with_fallback<F, Fut>(mut self, f: F) -> Self
+    where
+        F: Fn(Value, Error) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Value>> + Send + 'static,
+    {
+        self.fallback = Some(Arc::new(move |prep_res, error| Box::pin(f(prep_res, error))));
+        self
+    }
+    
+    /// Route the waits between retries through `sleeper`
+    pub fn with_sleeper(mut self, sleeper: Arc<dyn Sleeper>) -> Self {
+        self.sleeper = sleeper;
+        self
+    }
+    
+    /// Replace the fixed wait between retries with `backoff`, keeping the number of attempts
+    ///
+    /// This replaces any policy set with `with_retry_policy`.
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.retry = Arc::new(BackoffRetry::new(self.max_retries, backoff));
+        self
+    }
+    
+    /// Let `policy` decide which failures are retried and how long to wait before each retry
+    pub fn with_retry_policy(mut self, policy: Arc<dyn RetryPolicy>) -> Self {
+        self.retry = policy;
+        self
+    }
+    
+    /// Fail an exec attempt with `Error::Timeout` once it runs longer than `timeout`
+    ///
+    /// A timed-out attempt counts as a failure, so it is retried and finally handed to the fallback.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+    
+    /// Space the starts of consecutive exec attempts at least `interval` apart
+    ///
+    /// Clones of the node share the spacing, so it holds across batch items and flows.
+    pub fn with_min_interval(self, interval: Duration) -> Self {
+        self.with_rate_limiter(Arc::new(RateLimiter::new(1, interval)))
+    }
+    
+    /// Acquire a permit from `limiter` before every exec attempt, sharing its budget with other nodes
+    pub fn with_rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.limiter = Some(limiter);
+        self
+    }
+    
+    /// Name the node in logs, errors and traces
+    pub fn with_name(self, name: &str) -> Self {
+        self.set_name(name);
+        self
+    }
+    
+    /// Observe the prep result before exec
+    pub fn set_before_hook(&self, hook: NodeHook) {
+        self.base.set_before_hook(hook);
+    }
+    
+    /// Observe the exec result after a successful exec
+    pub fn set_after_hook(&self, hook: NodeHook) {
+        self.base.set_after_hook(hook);
+    }
+    
+    /// Observe the error of a failed exec
+    pub fn set_error_hook(&self, hook: ErrorHook) {
+        self.base.set_error_hook(hook);
+    }
+    
+    /// Call `callback` every `interval` while an exec attempt runs, in place of the flow's default heartbeat
+    ///
+    /// The ticker starts with each attempt and stops as soon as the attempt ends.
+    pub fn set_heartbeat(&self, interval: Duration, callback: HeartbeatCallback) {
+        *self.heartbeat.write() = Some(HeartbeatConfig::new(interval, callback));
+    }
 }
 
 impl Default for AsyncNode {
@@ -91,11 +255,27 @@ impl Default for AsyncNode {
 }
 
 impl NodeTrait for AsyncNode {
-    fn params(&self) -> Arc<RwLock<HashMap<String, Value>>> {
+    fn params(&self) -> Arc<ParamMap> {
         self.base.params()
     }
     
-    fn successors(&self) -> Arc<RwLock<HashMap<String, Arc<dyn NodeTrait>>>> {
+    fn name(&self) -> String {
+        self.base.assigned_name().unwrap_or_else(short_type_name::<Self>)
+    }
+    
+    fn set_name(&self, name: &str) {
+        self.base.set_name(name);
+    }
+    
+    fn hooks(&self) -> NodeHooks {
+        self.base.hooks()
+    }
+    
+    fn set_hooks(&self, hooks: NodeHooks) {
+        self.base.set_hooks(hooks);
+    }
+    
+    fn successors(&self) -> Arc<RwLock<Successors>> {
         self.base.successors()
     }
     
@@ -103,7 +283,7 @@ impl NodeTrait for AsyncNode {
         Err(Error::InvalidOperation("Use prep_async".into()))
     }
     
-    fn exec(&self, _prep_res: Value) -> Result<Value> {
+    fn exec(&self, _prep_res: &Value) -> Result<Value> {
         Err(Error::InvalidOperation("Use exec_async".into()))
     }
     
@@ -115,48 +295,47 @@ impl NodeTrait for AsyncNode {
         Err(Error::InvalidOperation("Use run_async".into()))
     }
     
-    fn set_params(&self, params: HashMap<String, Value>) {
-        let params_lock = self.params();
-        let mut p = params_lock.write().unwrap();
-        *p = params;
+    fn as_async(&self) -> Option<&dyn AsyncNodeTrait> {
+        Some(self)
+    }
+    
+    fn set_params(&self, params: Arc<ParamMap>) {
+        self.base.set_params(params);
     }
     
     fn add_successor(&self, node: Arc<dyn NodeTrait>, action: &str) -> Result<Arc<dyn NodeTrait>> {
-        let successors_lock = self.successors();
-        let mut successors = successors_lock.write().unwrap();
-        if successors.contains_key(action) {
-            warn!("Overwriting successor for action '{}'", action);
-        }
-        successors.insert(action.to_string(), node.clone());
+        self.successors().write().push(action.to_string(), node.clone());
         Ok(node)
     }
 }
 
 #[async_trait]
 impl AsyncNodeTrait for AsyncNode {
-    async fn _exec_async(&self, prep_res: Value) -> Result<Value> {
-        for retry in 0..self.max_retries {
-            {
-                let mut cur_retry = self.cur_retry.write().unwrap();
-                *cur_retry = retry;
-            }
-            
-            match self.exec_async(prep_res.clone()).await {
-                Ok(res) => return Ok(res),
-                Err(e) => {
-                    if retry == self.max_retries - 1 {
-                        return self.exec_fallback_async(prep_res, e).await;
-                    }
-                    
-                    if self.wait > 0 {
-                        sleep(Duration::from_millis(self.wait)).await;
-                    }
-                }
-            }
+    async fn exec_async(&self, prep_res: &Value) -> Result<Value> {
+        match &self.exec {
+            Some(f) => f(prep_res.clone()).await,
+            None => Ok(Value::Null),
+        }
+    }
+    
+    async fn exec_fallback_async(&self, prep_res: &Value, error: Error) -> Result<Value> {
+        match &self.fallback {
+            Some(f) => f(prep_res.clone(), error).await,
+            None => Err(error),
         }
-        
-        // This should never happen if max_retries > 0
-        Err(Error::NodeExecution("Max retries exceeded".into()))
+    }
+    
+    async fn _exec_async(&self, prep_res: &Value) -> Result<Value> {
+        let attempts = retry::Attempts::new(self.retry.as_ref(), self.sleeper.as_ref())
+            .limiter(self.limiter.as_deref())
+            .timeout(self.timeout)
+            .heartbeat(self.heartbeat.read().clone());
+        retry::drive_async(
+            attempts,
+            || self.name(),
+            |_| self.exec_async(prep_res),
+            |e, ctx| self.exec_fallback_async_ctx(prep_res, e, ctx),
+        ).await
     }
 }
 
@@ -165,6 +344,15 @@ impl AsyncNodeTrait for AsyncNode {
 pub struct AsyncBatchNode {
     /// Underlying async node
     node: AsyncNode,
+    
+    /// What happens when an item fails
+    error_policy: ErrorPolicy,
+    
+    /// Number of items passed to each exec call, when batching in chunks
+    chunk_size: Option<usize>,
+    
+    /// Called after every item or chunk completes
+    progress: Arc<RwLock<Option<ProgressCallback>>>,
 }
 
 impl AsyncBatchNode {
@@ -172,8 +360,104 @@ impl AsyncBatchNode {
     pub fn new(max_retries: usize, wait: u64) -> Self {
         Self {
             node: AsyncNode::new(max_retries, wait),
+            error_policy: ErrorPolicy::default(),
+            chunk_size: None,
+            progress: Arc::new(RwLock::new(None)),
         }
     }
+    
+    /// Compute the result of one item, or of one chunk with `with_chunk_size`
+    pub fn with_exec<F, Fut>(mut self, f: F) -> Self
+    where
+        F: Fn(Value) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Value>> + Send + 'static,
+    {
+        self.node = self.node.with_exec(f);
+        self
+    }
+    
+    /// Recover an item, or a chunk, from its last failed attempt
+    pub fn with_fallback<F, Fut>(mut self, f: F) -> Self
+    where
+        F: Fn(Value, Error) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Value>> + Send + 'static,
+    {
+        self.node = self.node.with_fallback(f);
+        self
+    }
+    
+    /// Route the waits between retries through `sleeper`
+    pub fn with_sleeper(mut self, sleeper: Arc<dyn Sleeper>) -> Self {
+        self.node = self.node.with_sleeper(sleeper);
+        self
+    }
+    
+    /// Replace the fixed wait between retries with `backoff`, keeping the number of attempts
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.node = self.node.with_backoff(backoff);
+        self
+    }
+    
+    /// Let `policy` decide which failures are retried and how long to wait before each retry
+    pub fn with_retry_policy(mut self, policy: Arc<dyn RetryPolicy>) -> Self {
+        self.node = self.node.with_retry_policy(policy);
+        self
+    }
+    
+    /// Fail an exec attempt with `Error::Timeout` once it runs longer than `timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.node = self.node.with_timeout(timeout);
+        self
+    }
+    
+    /// Space the starts of consecutive exec attempts at least `interval` apart, across all items
+    pub fn with_min_interval(mut self, interval: Duration) -> Self {
+        self.node = self.node.with_min_interval(interval);
+        self
+    }
+    
+    /// Acquire a permit from `limiter` before every exec attempt, sharing its budget with other nodes
+    pub fn with_rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.node = self.node.with_rate_limiter(limiter);
+        self
+    }
+    
+    /// Name the node in logs, errors and traces
+    pub fn with_name(self, name: &str) -> Self {
+        self.set_name(name);
+        self
+    }
+    
+    /// Decide what happens when an item fails; see `BatchReport` for the exec result it produces
+    pub fn with_error_policy(mut self, policy: ErrorPolicy) -> Self {
+        self.error_policy = policy;
+        self
+    }
+    
+    /// Call exec once per chunk of `size` items, with the chunk as an array
+    ///
+    /// Exec must return an array for each chunk; the arrays are flattened back in item order.
+    /// Retries and the error policy apply to whole chunks.
+    pub fn with_chunk_size(mut self, size: usize) -> Self {
+        self.chunk_size = Some(size.max(1));
+        self
+    }
+    
+    /// Call `callback` after every item completes, or every chunk when batching in chunks
+    pub fn set_progress_callback(&self, callback: ProgressCallback) {
+        *self.progress.write() = Some(callback);
+    }
+    
+    /// Call `callback` every `interval` while an exec attempt of an item or chunk runs
+    pub fn set_heartbeat(&self, interval: Duration, callback: HeartbeatCallback) {
+        self.node.set_heartbeat(interval, callback);
+    }
+    
+    /// Start reporting the progress of a batch of `total` items, if a callback is set
+    fn progress_tracker(&self, total: usize) -> Option<Arc<ProgressTracker>> {
+        let callback = self.progress.read().clone()?;
+        Some(Arc::new(ProgressTracker::new(callback, total)))
+    }
 }
 
 impl Default for AsyncBatchNode {
@@ -183,11 +467,27 @@ impl Default for AsyncBatchNode {
 }
 
 impl NodeTrait for AsyncBatchNode {
-    fn params(&self) -> Arc<RwLock<HashMap<String, Value>>> {
+    fn params(&self) -> Arc<ParamMap> {
         self.node.params()
     }
     
-    fn successors(&self) -> Arc<RwLock<HashMap<String, Arc<dyn NodeTrait>>>> {
+    fn name(&self) -> String {
+        self.node.name()
+    }
+    
+    fn set_name(&self, name: &str) {
+        self.node.set_name(name);
+    }
+    
+    fn hooks(&self) -> NodeHooks {
+        self.node.hooks()
+    }
+    
+    fn set_hooks(&self, hooks: NodeHooks) {
+        self.node.set_hooks(hooks);
+    }
+    
+    fn successors(&self) -> Arc<RwLock<Successors>> {
         self.node.successors()
     }
     
@@ -195,7 +495,7 @@ impl NodeTrait for AsyncBatchNode {
         Err(Error::InvalidOperation("Use prep_async".into()))
     }
     
-    fn exec(&self, _prep_res: Value) -> Result<Value> {
+    fn exec(&self, _prep_res: &Value) -> Result<Value> {
         Err(Error::InvalidOperation("Use exec_async".into()))
     }
     
@@ -207,7 +507,11 @@ impl NodeTrait for AsyncBatchNode {
         Err(Error::InvalidOperation("Use run_async".into()))
     }
     
-    fn set_params(&self, params: HashMap<String, Value>) {
+    fn as_async(&self) -> Option<&dyn AsyncNodeTrait> {
+        Some(self)
+    }
+    
+    fn set_params(&self, params: Arc<ParamMap>) {
         self.node.set_params(params);
     }
     
@@ -222,7 +526,7 @@ impl AsyncNodeTrait for AsyncBatchNode {
         self.node.prep_async(shared).await
     }
     
-    async fn exec_async(&self, prep_res: Value) -> Result<Value> {
+    async fn exec_async(&self, prep_res: &Value) -> Result<Value> {
         self.node.exec_async(prep_res).await
     }
     
@@ -230,30 +534,49 @@ impl AsyncNodeTrait for AsyncBatchNode {
         self.node.post_async(shared, prep_res, exec_res).await
     }
     
-    async fn exec_fallback_async(&self, prep_res: Value, error: Error) -> Result<Value> {
+    async fn exec_fallback_async(&self, prep_res: &Value, error: Error) -> Result<Value> {
         self.node.exec_fallback_async(prep_res, error).await
     }
     
-    async fn _exec_async(&self, items: Value) -> Result<Value> {
-        // Handle empty batches
-        if items.is_null() {
-            return Ok(Value::Array(vec![]));
-        }
-        
-        // Ensure we have an array
-        let items = match items {
-            Value::Array(items) => items,
-            _ => return Err(Error::NodeExecution("AsyncBatchNode requires an array".into())),
-        };
+    async fn exec_fallback_async_ctx(&self, prep_res: &Value, error: Error, ctx: FallbackContext) -> Result<Value> {
+        self.node.exec_fallback_async_ctx(prep_res, error, ctx).await
+    }
+    
+    async fn _exec_async(&self, items: &Value) -> Result<Value> {
+        let items = batch_items(items);
         
-        // Process each item sequentially
-        let mut results = Vec::with_capacity(items.len());
-        for item in items {
-            let result = self.node._exec_async(item).await?;
-            results.push(result);
+        let mut collector = BatchCollector::new(self.error_policy, items.len());
+        let progress = self.progress_tracker(items.len());
+        match self.chunk_size {
+            // Process each chunk sequentially as one exec call
+            Some(size) => {
+                for (i, chunk) in items.chunks(size).enumerate() {
+                    cancel::checkpoint()?;
+                    shutdown::checkpoint()?;
+                    deadline::check_item(|| self.name())?;
+                    let result = retry::with_item_async(i * size, self.node._exec_async(&Value::Array(chunk.to_vec()))).await;
+                    if let Some(progress) = &progress {
+                        progress.record(i * size, chunk.len(), &result);
+                    }
+                    collector.push_chunk(i * size, chunk.len(), result)?;
+                }
+            },
+            // Process each item sequentially
+            None => {
+                for (i, item) in items.iter().enumerate() {
+                    cancel::checkpoint()?;
+                    shutdown::checkpoint()?;
+                    deadline::check_item(|| self.name())?;
+                    let result = retry::with_item_async(i, self.node._exec_async(item)).await;
+                    if let Some(progress) = &progress {
+                        progress.record(i, 1, &result);
+                    }
+                    collector.push(i, result)?;
+                }
+            },
         }
         
-        Ok(Value::Array(results))
+        collector.finish()
     }
 }
 
@@ -262,6 +585,24 @@ impl AsyncNodeTrait for AsyncBatchNode {
 pub struct AsyncParallelBatchNode {
     /// Underlying async node
     node: AsyncNode,
+    
+    /// Scheduling of the batch items
+    determinism: Determinism,
+    
+    /// Order the results are collected in
+    result_order: ResultOrder,
+    
+    /// Most items, or chunks, in flight at once
+    concurrency: Option<usize>,
+    
+    /// What happens when an item fails
+    error_policy: ErrorPolicy,
+    
+    /// Number of items passed to each exec call, when batching in chunks
+    chunk_size: Option<usize>,
+    
+    /// Called after every item or chunk completes
+    progress: Arc<RwLock<Option<ProgressCallback>>>,
 }
 
 impl AsyncParallelBatchNode {
@@ -269,7 +610,166 @@ impl AsyncParallelBatchNode {
     pub fn new(max_retries: usize, wait: u64) -> Self {
         Self {
             node: AsyncNode::new(max_retries, wait),
+            determinism: Determinism::default(),
+            result_order: ResultOrder::default(),
+            concurrency: None,
+            error_policy: ErrorPolicy::default(),
+            chunk_size: None,
+            progress: Arc::new(RwLock::new(None)),
+        }
+    }
+    
+    /// Set how batch items are scheduled
+    pub fn with_determinism(mut self, determinism: Determinism) -> Self {
+        self.determinism = determinism;
+        self
+    }
+    
+    /// Collect results in batch order, or as the items finish with each tagged by its position
+    ///
+    /// Under `CompletionOrder` every result is `{"index": i, "value": ...}`, including the items of chunks.
+    pub fn with_result_order(mut self, order: ResultOrder) -> Self {
+        self.result_order = order;
+        self
+    }
+    
+    /// Run at most `limit` items, or chunks, at a time
+    ///
+    /// Items are only started as earlier ones finish, so memory grows with the limit
+    /// rather than with the batch. Unlimited by default.
+    pub fn with_concurrency(mut self, limit: usize) -> Self {
+        self.concurrency = Some(limit.max(1));
+        self
+    }
+    
+    /// Drive the batch futures lazily, yielding their results with their batch positions
+    ///
+    /// Concurrent batches yield results as they finish, so a failure is seen as soon as it happens;
+    /// the collector puts them back in batch order. Seeded batches run one future at a time anyway,
+    /// so in batch order they are gathered up front.
+    fn drive<'a, F>(&self, futures: impl Iterator<Item = F> + Send + 'a) -> BoxStream<'a, (usize, Result<Value>)>
+    where
+        F: Future<Output = (usize, Result<Value>)> + Send + 'a,
+    {
+        let limit = self.concurrency.unwrap_or(usize::MAX);
+        match (self.determinism, self.result_order) {
+            (Determinism::Concurrent, _) => stream::iter(futures).buffer_unordered(limit).boxed(),
+            (determinism, ResultOrder::Preserve) => {
+                let futures: Vec<F> = futures.collect();
+                stream::once(async move { determinism.run_all(futures).await }).flat_map(stream::iter).boxed()
+            },
+            (determinism, ResultOrder::CompletionOrder) => determinism.unordered(futures).map(|(_, output)| output).boxed(),
+        }
+    }
+    
+    /// The next result of the batch, or `None` once every item is in
+    ///
+    /// After an item failed under `FailFast` only results that are ready are taken, so the items
+    /// still running are dropped along with `results`.
+    async fn next_result(results: &mut BoxStream<'_, (usize, Result<Value>)>, collector: &BatchCollector) -> Option<(usize, Result<Value>)> {
+        if collector.failed_fast() {
+            return results.next().now_or_never().flatten();
         }
+        results.next().await
+    }
+    
+    /// Compute the result of one item, or of one chunk with `with_chunk_size`
+    pub fn with_exec<F, Fut>(mut self, f: F) -> Self
+    where
+        F: Fn(Value) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Value>> + Send + 'static,
+    {
+        self.node = self.node.with_exec(f);
+        self
+    }
+    
+    /// Recover an item, or a chunk, from its last failed attempt
+    pub fn with_fallback<F, Fut>(mut self, f: F) -> Self
+    where
+        F: Fn(Value, Error) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Value>> + Send + 'static,
+    {
+        self.node = self.node.with_fallback(f);
+        self
+    }
+    
+    /// Route the waits between retries through `sleeper`
+    pub fn with_sleeper(mut self, sleeper: Arc<dyn Sleeper>) -> Self {
+        self.node = self.node.with_sleeper(sleeper);
+        self
+    }
+    
+    /// Replace the fixed wait between retries with `backoff`, keeping the number of attempts
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.node = self.node.with_backoff(backoff);
+        self
+    }
+    
+    /// Let `policy` decide which failures are retried and how long to wait before each retry
+    pub fn with_retry_policy(mut self, policy: Arc<dyn RetryPolicy>) -> Self {
+        self.node = self.node.with_retry_policy(policy);
+        self
+    }
+    
+    /// Fail an exec attempt with `Error::Timeout` once it runs longer than `timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.node = self.node.with_timeout(timeout);
+        self
+    }
+    
+    /// Space the starts of consecutive exec attempts at least `interval` apart, across all items
+    pub fn with_min_interval(mut self, interval: Duration) -> Self {
+        self.node = self.node.with_min_interval(interval);
+        self
+    }
+    
+    /// Acquire a permit from `limiter` before every exec attempt, sharing its budget with other nodes
+    pub fn with_rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.node = self.node.with_rate_limiter(limiter);
+        self
+    }
+    
+    /// Name the node in logs, errors and traces
+    pub fn with_name(self, name: &str) -> Self {
+        self.set_name(name);
+        self
+    }
+    
+    /// Decide what happens when an item fails; see `BatchReport` for the exec result it produces
+    ///
+    /// Under `FailFast` the first failure drops the items still running, and those not started
+    /// never start. The batch fails with `Error::BatchFailed`, holding the failures and results
+    /// of the items that were already done.
+    pub fn with_error_policy(mut self, policy: ErrorPolicy) -> Self {
+        self.error_policy = policy;
+        self
+    }
+    
+    /// Call exec once per chunk of `size` items, with the chunk as an array
+    ///
+    /// Exec must return an array for each chunk; the arrays are flattened back in item order.
+    /// Retries and the error policy apply to whole chunks.
+    pub fn with_chunk_size(mut self, size: usize) -> Self {
+        self.chunk_size = Some(size.max(1));
+        self
+    }
+    
+    /// Call `callback` after every item completes, or every chunk when batching in chunks
+    ///
+    /// Items run concurrently, so calls arrive in completion order and may come from several tasks.
+    pub fn set_progress_callback(&self, callback: ProgressCallback) {
+        *self.progress.write() = Some(callback);
+    }
+    
+    /// Call `callback` every `interval` while an exec attempt of an item or chunk runs
+    pub fn set_heartbeat(&self, interval: Duration, callback: HeartbeatCallback) {
+        self.node.set_heartbeat(interval, callback);
+    }
+    
+    /// Start reporting the progress of a batch of `total` items, if a callback is set
+    fn progress_tracker(&self, total: usize) -> Option<Arc<ProgressTracker>> {
+        let callback = self.progress.read().clone()?;
+        Some(Arc::new(ProgressTracker::new(callback, total)))
     }
 }
 
@@ -280,11 +780,27 @@ impl Default for AsyncParallelBatchNode {
 }
 
 impl NodeTrait for AsyncParallelBatchNode {
-    fn params(&self) -> Arc<RwLock<HashMap<String, Value>>> {
+    fn params(&self) -> Arc<ParamMap> {
         self.node.params()
     }
     
-    fn successors(&self) -> Arc<RwLock<HashMap<String, Arc<dyn NodeTrait>>>> {
+    fn name(&self) -> String {
+        self.node.name()
+    }
+    
+    fn set_name(&self, name: &str) {
+        self.node.set_name(name);
+    }
+    
+    fn hooks(&self) -> NodeHooks {
+        self.node.hooks()
+    }
+    
+    fn set_hooks(&self, hooks: NodeHooks) {
+        self.node.set_hooks(hooks);
+    }
+    
+    fn successors(&self) -> Arc<RwLock<Successors>> {
         self.node.successors()
     }
     
@@ -292,7 +808,7 @@ impl NodeTrait for AsyncParallelBatchNode {
         Err(Error::InvalidOperation("Use prep_async".into()))
     }
     
-    fn exec(&self, _prep_res: Value) -> Result<Value> {
+    fn exec(&self, _prep_res: &Value) -> Result<Value> {
         Err(Error::InvalidOperation("Use exec_async".into()))
     }
     
@@ -304,7 +820,11 @@ impl NodeTrait for AsyncParallelBatchNode {
         Err(Error::InvalidOperation("Use run_async".into()))
     }
     
-    fn set_params(&self, params: HashMap<String, Value>) {
+    fn as_async(&self) -> Option<&dyn AsyncNodeTrait> {
+        Some(self)
+    }
+    
+    fn set_params(&self, params: Arc<ParamMap>) {
         self.node.set_params(params);
     }
     
@@ -319,7 +839,7 @@ impl AsyncNodeTrait for AsyncParallelBatchNode {
         self.node.prep_async(shared).await
     }
     
-    async fn exec_async(&self, prep_res: Value) -> Result<Value> {
+    async fn exec_async(&self, prep_res: &Value) -> Result<Value> {
         self.node.exec_async(prep_res).await
     }
     
@@ -327,36 +847,76 @@ impl AsyncNodeTrait for AsyncParallelBatchNode {
         self.node.post_async(shared, prep_res, exec_res).await
     }
     
-    async fn exec_fallback_async(&self, prep_res: Value, error: Error) -> Result<Value> {
+    async fn exec_fallback_async(&self, prep_res: &Value, error: Error) -> Result<Value> {
         self.node.exec_fallback_async(prep_res, error).await
     }
     
-    async fn _exec_async(&self, items: Value) -> Result<Value> {
-        // Handle empty batches
-        if items.is_null() {
-            return Ok(Value::Array(vec![]));
-        }
-        
-        // Ensure we have an array
-        let items = match items {
-            Value::Array(items) => items,
-            _ => return Err(Error::NodeExecution("AsyncParallelBatchNode requires an array".into())),
-        };
-        
-        // Process all items in parallel
-        let futures = items
-            .into_iter()
-            .map(|item| {
-                let node = self.node.clone();
-                async move { node._exec_async(item).await }
-            })
-            .collect::<Vec<_>>();
+    async fn exec_fallback_async_ctx(&self, prep_res: &Value, error: Error, ctx: FallbackContext) -> Result<Value> {
+        self.node.exec_fallback_async_ctx(prep_res, error, ctx).await
+    }
+    
+    async fn _exec_async(&self, items: &Value) -> Result<Value> {
+        let items = batch_items(items);
         
-        let results = future::join_all(futures)
-            .await
-            .into_iter()
-            .collect::<Result<Vec<_>>>()?;
+        let mut collector = BatchCollector::new(self.error_policy, items.len()).aggregating();
+        if self.result_order == ResultOrder::Preserve {
+            collector = collector.in_batch_order();
+        }
+        let progress = self.progress_tracker(items.len());
+        match self.chunk_size {
+            // Process all chunks in parallel, each as one exec call
+            Some(size) => {
+                let futures = items.chunks(size).enumerate().map(|(i, chunk)| {
+                    let node = self.node.clone();
+                    let progress = progress.clone();
+                    async move {
+                        let result = retry::with_item_async(i * size, node._exec_async(&Value::Array(chunk.to_vec()))).await;
+                        if let Some(progress) = &progress {
+                            progress.record(i * size, chunk.len(), &result);
+                        }
+                        (i, result)
+                    }
+                });
+                
+                let mut results = self.drive(futures);
+                while let Some((i, result)) = Self::next_result(&mut results, &collector).await {
+                    cancel::checkpoint()?;
+                    shutdown::checkpoint()?;
+                    deadline::check_item(|| self.name())?;
+                    let start = i * size;
+                    let result = result.map(|res| match res {
+                        Value::Array(values) => Value::Array(
+                            values.into_iter().enumerate().map(|(k, value)| self.result_order.tag(start + k, value)).collect(),
+                        ),
+                        other => other,
+                    });
+                    collector.push_chunk(start, size.min(items.len() - start), result)?;
+                }
+            },
+            // Process all items in parallel
+            None => {
+                let futures = items.iter().enumerate().map(|(i, item)| {
+                    let node = self.node.clone();
+                    let progress = progress.clone();
+                    async move {
+                        let result = retry::with_item_async(i, node._exec_async(item)).await;
+                        if let Some(progress) = &progress {
+                            progress.record(i, 1, &result);
+                        }
+                        (i, result)
+                    }
+                });
+                
+                let mut results = self.drive(futures);
+                while let Some((i, result)) = Self::next_result(&mut results, &collector).await {
+                    cancel::checkpoint()?;
+                    shutdown::checkpoint()?;
+                    deadline::check_item(|| self.name())?;
+                    collector.push(i, result.map(|res| self.result_order.tag(i, res)))?;
+                }
+            },
+        }
         
-        Ok(Value::Array(results))
+        collector.finish()
     }
 } 
\ No newline at end of file
@@ -0,0 +1,106 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::determinism::splitmix64;
+
+/// How long a node waits before each retry
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Backoff {
+    /// The same wait before every retry
+    Fixed(Duration),
+    
+    /// A wait growing by `factor` after every failure, starting at `base` and capped at `max`
+    ///
+    /// With `jitter`, each wait is drawn uniformly from the upper half of the computed wait,
+    /// so retries of concurrent nodes spread out instead of hitting an API in lockstep.
+    Exponential {
+        base: Duration,
+        factor: f64,
+        max: Duration,
+        jitter: bool,
+    },
+}
+
+impl Backoff {
+    /// A fixed wait of `wait` milliseconds, as taken by the node constructors
+    pub fn fixed_millis(wait: u64) -> Self {
+        Backoff::Fixed(Duration::from_millis(wait))
+    }
+    
+    /// The wait before retry number `retry`, where 0 is the retry after the first failure
+    pub fn delay(&self, retry: usize) -> Duration {
+        match *self {
+            Backoff::Fixed(wait) => wait,
+            Backoff::Exponential { base, factor, max, jitter } => {
+                let exponent = i32::try_from(retry).unwrap_or(i32::MAX);
+                let secs = base.as_secs_f64() * factor.max(1.0).powi(exponent);
+                let capped = if secs.is_finite() {
+                    Duration::from_secs_f64(secs.min(max.as_secs_f64()))
+                } else {
+                    max
+                };
+                if jitter {
+                    capped.mul_f64(0.5 + 0.5 * unit_random())
+                } else {
+                    capped
+                }
+            },
+        }
+    }
+    
+    /// The waits before the first `retries` retries
+    pub fn delays(&self, retries: usize) -> Vec<Duration> {
+        (0..retries).map(|retry| self.delay(retry)).collect()
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff::Fixed(Duration::ZERO)
+    }
+}
+
+/// A pseudo-random number in [0, 1), good enough to spread retries apart
+fn unit_random() -> f64 {
+    static STATE: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos() as u64)
+        .unwrap_or_default();
+    let mut state = STATE.fetch_add(1, Ordering::Relaxed) ^ nanos.rotate_left(32);
+    (splitmix64(&mut state) >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn exponential(jitter: bool) -> Backoff {
+        Backoff::Exponential {
+            base: Duration::from_millis(100),
+            factor: 3.0,
+            max: Duration::from_secs(2),
+            jitter,
+        }
+    }
+    
+    #[test]
+    fn exponential_waits_grow_until_capped() {
+        let millis: Vec<u128> = exponential(false).delays(6).iter().map(Duration::as_millis).collect();
+        assert_eq!(millis, [100, 300, 900, 2_000, 2_000, 2_000]);
+        assert_eq!(exponential(false).delay(usize::MAX), Duration::from_secs(2));
+        assert_eq!(Backoff::fixed_millis(250).delays(3), [Duration::from_millis(250); 3]);
+        assert_eq!(Backoff::default().delay(7), Duration::ZERO);
+    }
+    
+    #[test]
+    fn jittered_waits_stay_in_the_upper_half() {
+        for retry in 0..6 {
+            let capped = exponential(false).delay(retry);
+            for _ in 0..50 {
+                let wait = exponential(true).delay(retry);
+                assert!(wait >= capped / 2 && wait <= capped, "{:?} outside the upper half of {:?}", wait, capped);
+            }
+        }
+    }
+}
\ No newline at end of file
@@ -1,47 +1,516 @@
-use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+use parking_lot::RwLock;
+use serde::{de::DeserializeOwned, Serialize};
 use serde_json::Value;
 use log::warn;
 
-use crate::error::{Result};
+use crate::action::{ActionName, IntoAction};
+use crate::error::{Error, Result};
+use crate::hooks::{NodeHooks, NodeHook, ErrorHook};
+use crate::dry_run;
+use crate::async_node::AsyncNodeTrait;
+use crate::nodes::JoinNode;
+use crate::param_spec::ParamSpec;
+use crate::dataflow::KeySpec;
+use crate::successors::{Successors, ConditionalEdge, EdgePredicate};
 
-/// Shared state that is passed between nodes in a flow
-pub type SharedState = HashMap<String, Value>;
+pub use crate::cow_state::SharedState;
+
+/// Typed access to shared state values through serde
+///
+/// Values are stored as plain JSON, so a reader can pick any type the JSON deserializes into.
+pub trait SharedStateExt {
+    /// Serialize `value` and store it under `key`
+    fn set_as<T: Serialize>(&mut self, key: &str, value: &T) -> Result<()>;
+    
+    /// Deserialize the value under `key`, or `None` if the key is absent
+    fn get_as<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>>;
+    
+    /// Deserialize the value under `key`, telling a missing key apart from a value of another type
+    fn get_checked<T: DeserializeOwned>(&self, key: &str) -> Result<T>;
+    
+    /// Deserialize the value under `key`, modify it in `f`, and store it back
+    ///
+    /// Fails without changing the state if the key is missing or holds another type. The update is
+    /// not atomic across forks: parallel batch items and concurrent branches each update their own
+    /// fork, and their results are combined by the merge policy, which keeps every item's additions
+    /// to a list only under `MergePolicy::Append`.
+    fn update<T, R>(&mut self, key: &str, f: impl FnOnce(&mut T) -> R) -> Result<R>
+    where
+        T: Serialize + DeserializeOwned;
+    
+    /// Copy the values of the present keys among `keys`; missing keys are left out
+    fn get_many(&self, keys: &[&str]) -> SharedState;
+    
+    /// Deserialize the values of the present keys among `keys`
+    fn get_many_as<T: DeserializeOwned>(&self, keys: &[&str]) -> Result<HashMap<String, T>>;
+    
+    /// Copy the values of `keys`, failing with one error that names every missing key
+    fn require_many(&self, keys: &[&str]) -> Result<SharedState>;
+    
+    /// Like `update`, starting from `T::default()` when the key is missing
+    ///
+    /// As with `update`, forks only see their own updates until they are merged back.
+    fn update_or_insert<T, R>(&mut self, key: &str, f: impl FnOnce(&mut T) -> R) -> Result<R>
+    where
+        T: Serialize + DeserializeOwned + Default;
+    
+    /// Store `new` under `key` only if the current value equals `expected`, returning whether it did
+    ///
+    /// `None` expects the key to be absent. A current value that is not a `T` is an error, not a mismatch.
+    /// Parallel batch items and concurrent branches swap against each other: once one of them swapped
+    /// a key, the others' swaps of it fail unless they expect the value it stored.
+    fn compare_and_swap<T>(&mut self, key: &str, expected: Option<&T>, new: T) -> Result<bool>
+    where
+        T: Serialize + DeserializeOwned + PartialEq;
+    
+    /// Keys starting with `prefix`, sorted
+    fn keys_with_prefix(&self, prefix: &str) -> Vec<String>;
+    
+    /// Copy every entry whose key starts with `prefix` into one JSON object, keyed by full key
+    fn get_prefix_json(&self, prefix: &str) -> serde_json::Map<String, Value>;
+    
+    /// Remove every entry whose key starts with `prefix`, returning how many were removed
+    fn remove_prefix(&mut self, prefix: &str) -> usize;
+    
+    /// Append `item` to the list under `key`, creating the list if missing, and return the new length
+    fn push_json(&mut self, key: &str, item: Value) -> Result<usize>;
+    
+    /// Serialize `item` and append it to the list under `key`
+    fn push<T: Serialize>(&mut self, key: &str, item: &T) -> Result<usize>;
+    
+    /// Add `delta` to the integer under `key`, starting from 0, and return the new value
+    fn incr(&mut self, key: &str, delta: i64) -> Result<i64>;
+    
+    /// Add `delta` to the number under `key`, starting from 0.0, and return the new value
+    fn incr_f64(&mut self, key: &str, delta: f64) -> Result<f64>;
+    
+    /// The whole state as one JSON object
+    fn to_json(&self) -> Value;
+    
+    /// Build a state from the entries of a JSON object
+    fn from_json(value: Value) -> Result<Self>
+    where
+        Self: Sized;
+    
+    /// Write every entry to `path` as a JSON object with sorted keys
+    ///
+    /// The file is written next to `path` first and then renamed over it, so a crash never leaves it half written.
+    fn save_json(&self, path: &Path) -> Result<()>;
+    
+    /// Read a state written by `save_json`
+    fn load_json(path: &Path) -> Result<Self>
+    where
+        Self: Sized;
+}
+
+impl SharedStateExt for SharedState {
+    fn set_as<T: Serialize>(&mut self, key: &str, value: &T) -> Result<()> {
+        let value = serde_json::to_value(value)
+            .map_err(|e| Error::Serialization(format!("Cannot store '{}': {}", key, e)))?;
+        self.insert(key.to_string(), value);
+        Ok(())
+    }
+    
+    fn get_as<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        self.get(key)
+            .map(|value| {
+                T::deserialize(value)
+                    .map_err(|e| Error::Serialization(format!("Cannot read '{}': {}", key, e)))
+            })
+            .transpose()
+    }
+    
+    fn get_checked<T: DeserializeOwned>(&self, key: &str) -> Result<T> {
+        let value = self.get(key).ok_or_else(|| Error::MissingKey(key.to_string()))?;
+        T::deserialize(value).map_err(|_| Error::TypeMismatch {
+            key: key.to_string(),
+            expected: std::any::type_name::<T>().to_string(),
+            found: json_type_name(value).to_string(),
+        })
+    }
+    
+    fn get_many(&self, keys: &[&str]) -> SharedState {
+        keys.iter()
+            .filter_map(|key| self.get(key).map(|value| (key.to_string(), value.clone())))
+            .collect()
+    }
+    
+    fn get_many_as<T: DeserializeOwned>(&self, keys: &[&str]) -> Result<HashMap<String, T>> {
+        keys.iter()
+            .filter_map(|key| self.get_as(key).transpose().map(|value| value.map(|v| (key.to_string(), v))))
+            .collect()
+    }
+    
+    fn require_many(&self, keys: &[&str]) -> Result<SharedState> {
+        let missing: Vec<&str> = keys.iter().copied().filter(|key| !self.contains_key(key)).collect();
+        if !missing.is_empty() {
+            return Err(Error::InvalidOperation(format!("Missing shared state keys: {}", missing.join(", "))));
+        }
+        Ok(self.get_many(keys))
+    }
+    
+    fn update<T, R>(&mut self, key: &str, f: impl FnOnce(&mut T) -> R) -> Result<R>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        let mut value: T = self.get_as(key)?.ok_or_else(|| Error::MissingKey(key.to_string()))?;
+        let result = f(&mut value);
+        self.set_as(key, &value)?;
+        Ok(result)
+    }
+    
+    fn update_or_insert<T, R>(&mut self, key: &str, f: impl FnOnce(&mut T) -> R) -> Result<R>
+    where
+        T: Serialize + DeserializeOwned + Default,
+    {
+        let mut value: T = self.get_as(key)?.unwrap_or_default();
+        let result = f(&mut value);
+        self.set_as(key, &value)?;
+        Ok(result)
+    }
+    
+    fn compare_and_swap<T>(&mut self, key: &str, expected: Option<&T>, new: T) -> Result<bool>
+    where
+        T: Serialize + DeserializeOwned + PartialEq,
+    {
+        let current: Option<T> = self.get_as(key)?;
+        if current.as_ref() != expected {
+            return Ok(false);
+        }
+        let to_value = |value| serde_json::to_value(value).map_err(|e| Error::Serialization(format!("Cannot store '{}': {}", key, e)));
+        let expected = expected.map(to_value).transpose()?;
+        Ok(self.claim(key, expected.as_ref(), to_value(&new)?))
+    }
+    
+    fn keys_with_prefix(&self, prefix: &str) -> Vec<String> {
+        let mut keys: Vec<String> = self.keys().filter(|key| key.starts_with(prefix)).cloned().collect();
+        keys.sort();
+        keys
+    }
+    
+    fn get_prefix_json(&self, prefix: &str) -> serde_json::Map<String, Value> {
+        self.iter()
+            .filter(|(key, _)| key.starts_with(prefix))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    }
+    
+    fn remove_prefix(&mut self, prefix: &str) -> usize {
+        let before = self.len();
+        self.retain(|key, _| !key.starts_with(prefix));
+        before - self.len()
+    }
+    
+    fn push_json(&mut self, key: &str, item: Value) -> Result<usize> {
+        match self.entry(key.to_string()).or_insert_with(|| Value::Array(Vec::new())) {
+            Value::Array(items) => {
+                items.push(item);
+                Ok(items.len())
+            },
+            other => Err(Error::InvalidOperation(format!("Cannot push to '{}': {} is not a list", key, other))),
+        }
+    }
+    
+    fn push<T: Serialize>(&mut self, key: &str, item: &T) -> Result<usize> {
+        let item = serde_json::to_value(item)
+            .map_err(|e| Error::Serialization(format!("Cannot push to '{}': {}", key, e)))?;
+        self.push_json(key, item)
+    }
+    
+    fn incr(&mut self, key: &str, delta: i64) -> Result<i64> {
+        let current = match self.get(key) {
+            None => 0,
+            Some(value) => value.as_i64().ok_or_else(|| {
+                Error::InvalidOperation(format!("Cannot increment '{}': {} is not an integer", key, value))
+            })?,
+        };
+        let next = current
+            .checked_add(delta)
+            .ok_or_else(|| Error::InvalidOperation(format!("Incrementing '{}' by {} overflows", key, delta)))?;
+        self.insert(key.to_string(), Value::from(next));
+        Ok(next)
+    }
+    
+    fn incr_f64(&mut self, key: &str, delta: f64) -> Result<f64> {
+        let current = match self.get(key) {
+            None => 0.0,
+            Some(value) => value.as_f64().ok_or_else(|| {
+                Error::InvalidOperation(format!("Cannot increment '{}': {} is not a number", key, value))
+            })?,
+        };
+        let next = current + delta;
+        let value = serde_json::Number::from_f64(next)
+            .ok_or_else(|| Error::InvalidOperation(format!("Incrementing '{}' gives {}, which JSON cannot hold", key, next)))?;
+        self.insert(key.to_string(), Value::Number(value));
+        Ok(next)
+    }
+    
+    fn to_json(&self) -> Value {
+        Value::Object(self.iter().map(|(k, v)| (k.clone(), v.clone())).collect())
+    }
+    
+    fn from_json(value: Value) -> Result<Self> {
+        match value {
+            Value::Object(map) => Ok(map.into_iter().collect()),
+            other => Err(Error::Serialization(format!("Shared state must be a JSON object, got {}", json_type_name(&other)))),
+        }
+    }
+    
+    fn save_json(&self, path: &Path) -> Result<()> {
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        
+        let sorted: BTreeMap<&String, &Value> = self.iter().collect();
+        let mut writer = BufWriter::new(fs::File::create(&tmp)?);
+        serde_json::to_writer_pretty(&mut writer, &sorted)
+            .map_err(|e| Error::Serialization(format!("Cannot save state to {}: {}", path.display(), e)))?;
+        writer.flush()?;
+        
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+    
+    fn load_json(path: &Path) -> Result<Self> {
+        let reader = BufReader::new(fs::File::open(path)?);
+        serde_json::from_reader(reader)
+            .map_err(|e| Error::Serialization(format!("Cannot load state from {}: {}", path.display(), e)))
+    }
+}
+
+/// Name of a JSON value's type, for error messages
+fn json_type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "bool",
+        Value::Number(n) if n.is_f64() => "float",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
 
 /// Action that determines the next node in a flow
-pub type Action = Option<String>;
+pub type Action = Option<ActionName>;
+
+/// Action followed when a node returns no action
+pub const DEFAULT_ACTION: &str = "default";
+
+/// Action reserved for routing a failed step to an error handler
+pub const ERROR_ACTION: &str = "__error__";
+
+/// Parameters attached to a node
+pub type ParamMap = HashMap<String, Value>;
 
 /// A base node in a workflow
 #[derive(Clone)]
 pub struct BaseNode {
     /// Parameters for the node
-    params: Arc<RwLock<HashMap<String, Value>>>,
+    params: Arc<RwLock<Arc<ParamMap>>>,
     
     /// Successors of this node, keyed by action
-    successors: Arc<RwLock<HashMap<String, Arc<dyn Node>>>>,
+    successors: Arc<RwLock<Successors>>,
+    
+    /// Name assigned with `set_name`, if any
+    name: Arc<RwLock<Option<String>>>,
+    
+    /// Callbacks invoked around exec
+    hooks: Arc<RwLock<NodeHooks>>,
 }
 
 /// Trait for node functionality
 pub trait Node: Send + Sync + 'static {
-    /// Get a reference to the node's parameters
-    fn params(&self) -> Arc<RwLock<HashMap<String, Value>>>;
+    /// Get a shared snapshot of the node's parameters
+    fn params(&self) -> Arc<ParamMap>;
+    
+    /// Params the node expects, checked before a flow starts running it
+    fn required_params(&self) -> &[ParamSpec] {
+        &[]
+    }
+    
+    /// Shared state keys the node reads, checked by `Flow::check_dataflow`
+    fn reads(&self) -> &[KeySpec] {
+        &[]
+    }
+    
+    /// Shared state keys the node writes, checked by `Flow::check_dataflow`
+    fn writes(&self) -> &[KeySpec] {
+        &[]
+    }
+    
+    /// Every action the node can return, when it declares an action type; empty otherwise
+    fn declared_actions(&self) -> &[String] {
+        &[]
+    }
+    
+    /// Read a param, deserialized into `T`
+    fn param<T: DeserializeOwned>(&self, key: &str) -> Result<T>
+    where
+        Self: Sized,
+    {
+        let params = self.params();
+        let value = params.get(key).ok_or_else(|| {
+            Error::NodeExecution(format!("{}: missing param '{}' ({})", self.name(), key, short_type_name::<T>()))
+        })?;
+        T::deserialize(value).map_err(|_| {
+            Error::NodeExecution(format!(
+                "{}: param '{}' should be {}, found {}",
+                self.name(),
+                key,
+                short_type_name::<T>(),
+                json_type_name(value)
+            ))
+        })
+    }
+    
+    /// Read a param deserialized into `T`, or `default` when it is missing or mistyped
+    fn param_or<T: DeserializeOwned>(&self, key: &str, default: T) -> T
+    where
+        Self: Sized,
+    {
+        match self.params().get(key) {
+            Some(_) => self.param(key).unwrap_or_else(|e| {
+                warn!(target: "minllm::node", "{}", e);
+                default
+            }),
+            None => default,
+        }
+    }
+    
+    /// Name used in logs, errors and traces, by default the node's type name
+    fn name(&self) -> String {
+        short_type_name::<Self>()
+    }
+    
+    /// The node's type name, shown next to its name in graph descriptions
+    fn type_name(&self) -> String {
+        short_type_name::<Self>()
+    }
+    
+    /// Whether the node does no work of its own, drawn dashed in graph descriptions
+    fn is_placeholder(&self) -> bool {
+        false
+    }
+    
+    /// Assign the name used in logs, errors and traces
+    fn set_name(&self, _name: &str) {
+        warn!(target: "minllm::node", "{} cannot be renamed", self.name());
+    }
+    
+    /// Callbacks invoked around exec, before the defaults of the enclosing flows are applied
+    fn hooks(&self) -> NodeHooks {
+        NodeHooks::default()
+    }
+    
+    /// Replace the callbacks invoked around exec
+    fn set_hooks(&self, _hooks: NodeHooks) {
+        warn!(target: "minllm::node", "{} does not support hooks", self.name());
+    }
     
     /// Get a reference to the node's successors
-    fn successors(&self) -> Arc<RwLock<HashMap<String, Arc<dyn Node>>>>;
+    fn successors(&self) -> Arc<RwLock<Successors>>;
     
-    /// Set parameters for the node
-    fn set_params(&self, params: HashMap<String, Value>);
+    /// Set parameters for the node, sharing the map instead of copying it
+    fn set_params(&self, params: Arc<ParamMap>);
+    
+    /// Set parameters for the node from an owned map
+    fn set_params_map(&self, params: ParamMap) {
+        self.set_params(Arc::new(params));
+    }
     
     /// Add a successor node for a given action
+    ///
+    /// An action can have several successors. When it is taken, every successor but the last
+    /// runs as a branch until its path ends, then the flow continues with the last one.
     fn add_successor(&self, node: Arc<dyn Node>, action: &str) -> Result<Arc<dyn Node>>;
     
+    /// Add a successor for a typed action, such as a variant of an enum declared with `actions!`
+    fn add_action_successor<A: IntoAction>(&self, node: Arc<dyn Node>, action: A) -> Result<Arc<dyn Node>>
+    where
+        Self: Sized,
+    {
+        self.add_successor(node, &action.into_action())
+    }
+    
+    /// Add several successors for a given action, in order
+    fn add_successors(&self, action: &str, nodes: Vec<Arc<dyn Node>>) -> Result<()> {
+        for node in nodes {
+            self.add_successor(node, action)?;
+        }
+        Ok(())
+    }
+    
+    /// Actions this node has successors for, sorted
+    fn successor_actions(&self) -> Vec<String> {
+        let mut actions: Vec<String> = self.successors().read().keys().map(|action| action.to_string()).collect();
+        actions.sort();
+        actions
+    }
+    
+    /// The first successor for an action
+    fn successor(&self, action: &str) -> Option<Arc<dyn Node>> {
+        self.successors().read().get(action).cloned()
+    }
+    
+    /// Remove every successor for an action, returning the first one removed
+    fn remove_successor(&self, action: &str) -> Option<Arc<dyn Node>> {
+        self.successors().write().remove(action)
+    }
+    
+    /// Make `node` the only successor for an action that already has one
+    fn replace_successor(&self, action: &str, node: Arc<dyn Node>) -> Result<Arc<dyn Node>> {
+        let successors_lock = self.successors();
+        let mut successors = successors_lock.write();
+        if !successors.contains_key(action) {
+            return Err(Error::MissingSuccessor(format!("{} has no successor for '{}'", self.name(), action)));
+        }
+        successors.insert(action.to_string(), node.clone());
+        Ok(node)
+    }
+    
+    /// Add a successor taken when `predicate` holds for the shared state and the exec result
+    ///
+    /// Conditional successors are checked in insertion order, before the returned action is looked up.
+    fn add_successor_if(&self, node: Arc<dyn Node>, predicate: EdgePredicate) -> Result<Arc<dyn Node>> {
+        self.successors().write().push_conditional(ConditionalEdge {
+            predicate,
+            node: node.clone(),
+            description: None,
+        });
+        Ok(node)
+    }
+    
+    /// Add a conditional successor labeled with `description` in graph descriptions
+    fn add_described_successor_if(&self, node: Arc<dyn Node>, description: &str, predicate: EdgePredicate) -> Result<Arc<dyn Node>> {
+        self.successors().write().push_conditional(ConditionalEdge {
+            predicate,
+            node: node.clone(),
+            description: Some(description.to_string()),
+        });
+        Ok(node)
+    }
+    
     /// Preparation step before execution
-    fn prep(&self, _shared: &mut SharedState) -> Result<Value> {
+    ///
+    /// Delegates to `prep_readonly` unless overridden.
+    fn prep(&self, shared: &mut SharedState) -> Result<Value> {
+        self.prep_readonly(shared)
+    }
+    
+    /// Preparation step that can only read the shared state
+    ///
+    /// Override this instead of `prep` to guarantee that only `post` writes to the state.
+    fn prep_readonly(&self, _shared: &SharedState) -> Result<Value> {
         Ok(Value::Null)
     }
     
     /// Execute the node logic
-    fn exec(&self, _prep_res: Value) -> Result<Value> {
+    fn exec(&self, _prep_res: &Value) -> Result<Value> {
         Ok(Value::Null)
     }
     
@@ -50,24 +519,70 @@ pub trait Node: Send + Sync + 'static {
         Ok(None) // No action, end the flow
     }
     
+    /// Actions whose successors run as branches before the flow follows the returned action
+    fn branch_actions(&self) -> &[String] {
+        &[]
+    }
+    
+    /// The node's async interface, for nodes that only run asynchronously
+    ///
+    /// `AsyncFlow` awaits the nodes that return one here instead of calling `_run`.
+    fn as_async(&self) -> Option<&dyn AsyncNodeTrait> {
+        None
+    }
+    
+    /// The start node of the graph a flow runs, for flows nested in another flow
+    fn nested_start(&self) -> Option<Arc<dyn Node>> {
+        None
+    }
+    
+    /// Whether `_run` works, so the node can run in a sync `Flow`
+    fn runs_sync(&self) -> bool {
+        self.as_async().is_none()
+    }
+    
+    /// The node as a join, for `AsyncFlow` to hold it until enough upstream branches have arrived
+    fn as_join(&self) -> Option<&JoinNode> {
+        None
+    }
+    
+    /// Stand-in for exec when the node runs in a dry run
+    ///
+    /// Defaults to the flow's dry-run stub, or to echoing the prep result back.
+    fn exec_dry_run(&self, prep_res: &Value) -> Result<Value> {
+        dry_run::stub_exec(&self.name(), prep_res)
+    }
+    
+    /// Recover from the last failed exec attempt of a node that retries, returning the error by default
+    fn exec_fallback(&self, _prep_res: &Value, error: Error) -> Result<Value> {
+        Err(error)
+    }
+    
     /// Internal execute method that can be overridden by derived nodes
-    fn _exec(&self, prep_res: Value) -> Result<Value> {
+    fn _exec(&self, prep_res: &Value) -> Result<Value> {
         self.exec(prep_res)
     }
     
     /// Run the node
     fn _run(&self, shared: &mut SharedState) -> Result<Action> {
         let prep_res = self.prep(shared)?;
-        let exec_res = self._exec(prep_res.clone())?;
+        let hooks = NodeHooks::effective(self.hooks());
+        if dry_run::is_active() {
+            let exec_res = hooks.observe(|| self.name(), &prep_res, || self.exec_dry_run(&prep_res))?;
+            let action = self.post(shared, prep_res.clone(), exec_res)?;
+            dry_run::record(self.name(), prep_res, &action);
+            return Ok(action);
+        }
+        let exec_res = hooks.observe(|| self.name(), &prep_res, || self._exec(&prep_res))?;
         self.post(shared, prep_res, exec_res)
     }
     
     /// Run the node as a standalone (warns if there are successors)
     fn run(&self, shared: &mut SharedState) -> Result<Action> {
         let successors_lock = self.successors();
-        let successors = successors_lock.read().unwrap();
+        let successors = successors_lock.read();
         if !successors.is_empty() {
-            warn!("Node won't run successors. Use Flow.");
+            warn!(target: "minllm::node", "Node won't run successors. Use Flow.");
         }
         self._run(shared)
     }
@@ -77,10 +592,52 @@ impl BaseNode {
     /// Create a new base node
     pub fn new() -> Self {
         Self {
-            params: Arc::new(RwLock::new(HashMap::new())),
-            successors: Arc::new(RwLock::new(HashMap::new())),
+            params: Arc::new(RwLock::new(Arc::new(HashMap::new()))),
+            successors: Arc::new(RwLock::new(Successors::new())),
+            name: Arc::new(RwLock::new(None)),
+            hooks: Arc::new(RwLock::new(NodeHooks::default())),
+        }
+    }
+    
+    /// Observe the prep result before exec
+    pub fn set_before_hook(&self, hook: NodeHook) {
+        self.hooks.write().set_before(Some(hook));
+    }
+    
+    /// Observe the exec result after a successful exec
+    pub fn set_after_hook(&self, hook: NodeHook) {
+        self.hooks.write().set_after(Some(hook));
+    }
+    
+    /// Observe the error of a failed exec
+    pub fn set_error_hook(&self, hook: ErrorHook) {
+        self.hooks.write().set_on_error(Some(hook));
+    }
+    
+    /// The name assigned with `set_name`, if any
+    pub fn assigned_name(&self) -> Option<String> {
+        self.name.read().clone()
+    }
+}
+
+/// A type's name without its module path, e.g. `Flow` or `ThrottleNode<dyn Node>`
+pub(crate) fn short_type_name<T: ?Sized>() -> String {
+    let full = std::any::type_name::<T>();
+    let mut out = String::with_capacity(full.len());
+    let mut segment = String::new();
+    for c in full.chars() {
+        match c {
+            ':' => segment.clear(),
+            '<' | '>' | ',' | ' ' | '(' | ')' | '[' | ']' | '&' => {
+                out.push_str(&segment);
+                segment.clear();
+                out.push(c);
+            },
+            _ => segment.push(c),
         }
     }
+    out.push_str(&segment);
+    out
 }
 
 impl Default for BaseNode {
@@ -90,27 +647,309 @@ impl Default for BaseNode {
 }
 
 impl Node for BaseNode {
-    fn params(&self) -> Arc<RwLock<HashMap<String, Value>>> {
-        self.params.clone()
+    fn params(&self) -> Arc<ParamMap> {
+        self.params.read().clone()
     }
     
-    fn successors(&self) -> Arc<RwLock<HashMap<String, Arc<dyn Node>>>> {
+    fn name(&self) -> String {
+        self.assigned_name().unwrap_or_else(short_type_name::<Self>)
+    }
+    
+    fn set_name(&self, name: &str) {
+        *self.name.write() = Some(name.to_string());
+    }
+    
+    fn hooks(&self) -> NodeHooks {
+        self.hooks.read().clone()
+    }
+    
+    fn set_hooks(&self, hooks: NodeHooks) {
+        *self.hooks.write() = hooks;
+    }
+    
+    fn successors(&self) -> Arc<RwLock<Successors>> {
         self.successors.clone()
     }
     
-    fn set_params(&self, params: HashMap<String, Value>) {
-        let params_lock = self.params();
-        let mut p = params_lock.write().unwrap();
-        *p = params;
+    fn set_params(&self, params: Arc<ParamMap>) {
+        *self.params.write() = params;
     }
     
     fn add_successor(&self, node: Arc<dyn Node>, action: &str) -> Result<Arc<dyn Node>> {
-        let successors_lock = self.successors();
-        let mut successors = successors_lock.write().unwrap();
-        if successors.contains_key(action) {
-            warn!("Overwriting successor for action '{}'", action);
-        }
-        successors.insert(action.to_string(), node.clone());
+        self.successors().write().push(action.to_string(), node.clone());
         Ok(node)
     }
-} 
\ No newline at end of file
+} 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use crate::{AsyncNodeTrait, AsyncParallelBatchFlow, FnNode, MergePolicy};
+    
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Author {
+        name: String,
+        tags: Vec<String>,
+    }
+    
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Draft {
+        title: String,
+        author: Author,
+        status: Status,
+    }
+    
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Status {
+        Pending,
+        Reviewed { score: u8, notes: Option<String> },
+        Rejected(String),
+    }
+    
+    #[test]
+    fn typed_values_round_trip_through_json() {
+        let mut shared = SharedState::new();
+        let draft = Draft {
+            title: "notes".to_string(),
+            author: Author { name: "ada".to_string(), tags: vec!["math".to_string()] },
+            status: Status::Reviewed { score: 4, notes: None },
+        };
+        shared.set_as("draft", &draft).unwrap();
+        assert_eq!(shared["draft"]["author"]["tags"], serde_json::json!(["math"]));
+        assert_eq!(shared["draft"]["status"], serde_json::json!({"Reviewed": {"score": 4, "notes": null}}));
+        assert_eq!(shared.get_as::<Draft>("draft").unwrap(), Some(draft));
+        
+        for status in [Status::Pending, Status::Rejected("too short".to_string())] {
+            shared.set_as("status", &status).unwrap();
+            assert_eq!(shared.get_as::<Status>("status").unwrap(), Some(status));
+        }
+        assert_eq!(shared.get_as::<Draft>("missing").unwrap(), None);
+    }
+    
+    #[test]
+    fn values_of_the_wrong_shape_fail_to_read() {
+        let mut shared = SharedState::new();
+        shared.insert("draft".to_string(), serde_json::json!({"title": "notes", "author": {"name": "ada"}}));
+        let err = shared.get_as::<Draft>("draft").unwrap_err();
+        assert!(matches!(&err, Error::Serialization(msg) if msg.starts_with("Cannot read 'draft'")), "{}", err);
+        
+        shared.insert("status".to_string(), serde_json::json!({"Reviewed": {"score": 300}}));
+        assert!(matches!(shared.get_as::<Status>("status"), Err(Error::Serialization(_))));
+        
+        let unkeyable = HashMap::from([((1, 2), "pair")]);
+        assert!(matches!(shared.set_as("pairs", &unkeyable), Err(Error::Serialization(_))));
+        assert!(!shared.contains_key("pairs"));
+    }
+    
+    #[test]
+    fn updates_report_missing_keys_and_mismatched_types() {
+        let mut shared = SharedState::new();
+        assert!(matches!(shared.update("results", |items: &mut Vec<String>| items.len()), Err(Error::MissingKey(key)) if key == "results"));
+        
+        let len = shared.update_or_insert("results", |items: &mut Vec<String>| {
+            items.push("first".to_string());
+            items.len()
+        }).unwrap();
+        assert_eq!(len, 1);
+        assert_eq!(shared.update("results", |items: &mut Vec<String>| items.pop()).unwrap(), Some("first".to_string()));
+        
+        shared.insert("count".to_string(), serde_json::json!("three"));
+        assert!(matches!(shared.update_or_insert("count", |n: &mut i64| *n += 1), Err(Error::Serialization(_))));
+        assert_eq!(shared["count"], serde_json::json!("three"));
+    }
+    
+    /// A batch of `items` items that each append their own number to "results"
+    fn appending_batch(items: usize, policy: MergePolicy) -> AsyncParallelBatchFlow {
+        let next = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let node = FnNode::new().with_post(move |shared, _, _| {
+            let item = next.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            shared.update_or_insert("results", |results: &mut Vec<usize>| results.push(item))?;
+            Ok(None)
+        });
+        AsyncParallelBatchFlow::new(Arc::new(node)).with_items(vec![ParamMap::new(); items]).with_merge_policy(policy)
+    }
+    
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn parallel_updates_are_all_kept_when_appending() {
+        let mut shared = SharedState::from([("results".to_string(), serde_json::json!([100]))]);
+        appending_batch(64, MergePolicy::Append).run_async(&mut shared).await.unwrap();
+        let mut results: Vec<usize> = shared.get_as("results").unwrap().unwrap();
+        assert_eq!(results.remove(0), 100, "the list the items started from comes first");
+        results.sort();
+        assert_eq!(results, (0..64).collect::<Vec<_>>());
+        
+        let mut shared = SharedState::new();
+        appending_batch(64, MergePolicy::LastWins).run_async(&mut shared).await.unwrap();
+        assert_eq!(shared.get_as::<Vec<usize>>("results").unwrap().unwrap().len(), 1, "each fork only sees its own update");
+    }
+    
+    #[test]
+    fn saved_states_load_back_unchanged() {
+        let path = std::env::temp_dir().join(format!("minllm-state-{}.json", std::process::id()));
+        let shared = SharedState::from([
+            ("draft/title".to_string(), serde_json::json!("notes")),
+            ("scores".to_string(), serde_json::json!([1.5, null, {"nested": [true, "x"]}])),
+            ("".to_string(), serde_json::json!({})),
+        ]);
+        shared.save_json(&path).unwrap();
+        let loaded = SharedState::load_json(&path);
+        let saved = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), shared);
+        assert!(saved.find("\"draft/title\"").unwrap() < saved.find("\"scores\"").unwrap(), "keys are saved sorted");
+        
+        fs::write(&path, "[1, 2]").unwrap();
+        let err = SharedState::load_json(&path).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(err, Error::Serialization(_)), "{}", err);
+        assert!(SharedState::load_json(&path).is_err());
+    }
+    
+    #[test]
+    fn many_keys_are_read_at_once() {
+        let shared = SharedState::from([
+            ("a".to_string(), serde_json::json!(1)),
+            ("b".to_string(), serde_json::json!(2)),
+            ("name".to_string(), serde_json::json!("ada")),
+        ]);
+        let found = shared.get_many(&["a", "name", "missing"]);
+        assert_eq!(found, SharedState::from([("a".to_string(), serde_json::json!(1)), ("name".to_string(), serde_json::json!("ada"))]));
+        
+        let numbers: HashMap<String, u32> = shared.get_many_as(&["a", "b", "missing"]).unwrap();
+        assert_eq!(numbers, HashMap::from([("a".to_string(), 1), ("b".to_string(), 2)]));
+        assert!(matches!(shared.get_many_as::<u32>(&["a", "name"]), Err(Error::Serialization(_))));
+        
+        assert_eq!(shared.require_many(&["a", "b"]).unwrap().len(), 2);
+        let err = shared.require_many(&["a", "x", "name", "y"]).unwrap_err();
+        assert_eq!(err.to_string(), Error::InvalidOperation("Missing shared state keys: x, y".to_string()).to_string());
+    }
+    
+    #[test]
+    fn swaps_happen_only_on_a_match() {
+        let mut shared = SharedState::new();
+        assert!(shared.compare_and_swap("owner", None, "a".to_string()).unwrap());
+        assert!(!shared.compare_and_swap("owner", None, "b".to_string()).unwrap());
+        assert!(!shared.compare_and_swap("owner", Some(&"b".to_string()), "c".to_string()).unwrap());
+        assert!(shared.compare_and_swap("owner", Some(&"a".to_string()), "c".to_string()).unwrap());
+        assert_eq!(shared["owner"], serde_json::json!("c"));
+        
+        assert!(matches!(shared.compare_and_swap("owner", Some(&1), 2), Err(Error::Serialization(_))));
+        assert_eq!(shared["owner"], serde_json::json!("c"));
+    }
+    
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn exactly_one_parallel_item_claims_a_key() {
+        let (next, winners) = (Arc::new(std::sync::atomic::AtomicUsize::new(0)), Arc::new(parking_lot::Mutex::new(Vec::new())));
+        let node = FnNode::new().with_post({
+            let winners = winners.clone();
+            move |shared, _, _| {
+                let item = next.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                if shared.compare_and_swap("claimed_by", None, item)? {
+                    winners.lock().push(item);
+                }
+                Ok(None)
+            }
+        });
+        let flow = AsyncParallelBatchFlow::new(Arc::new(node)).with_items(vec![ParamMap::new(); 64]).with_merge_policy(MergePolicy::Fail);
+        let mut shared = SharedState::new();
+        flow.run_async(&mut shared).await.unwrap();
+        
+        let winners = winners.lock().clone();
+        assert_eq!(winners.len(), 1, "{:?}", winners);
+        assert_eq!(shared.get_as::<usize>("claimed_by").unwrap(), Some(winners[0]));
+        assert!(shared.compare_and_swap("claimed_by", Some(&winners[0]), 100).unwrap(), "the merged state swaps on its own again");
+    }
+    
+    #[test]
+    fn counters_start_at_zero_and_refuse_other_values() {
+        let mut shared = SharedState::new();
+        assert_eq!(shared.incr("done", 1).unwrap(), 1);
+        assert_eq!(shared.incr("done", -3).unwrap(), -2);
+        assert_eq!(shared.incr_f64("score", 0.5).unwrap(), 0.5);
+        assert_eq!(shared.incr_f64("done", 0.5).unwrap(), -1.5);
+        
+        shared.insert("name".to_string(), serde_json::json!("ada"));
+        let err = shared.incr("name", 1).unwrap_err();
+        assert!(err.to_string().contains("\"ada\" is not an integer"), "{}", err);
+        assert!(shared.incr_f64("name", 1.0).is_err());
+        assert_eq!(shared["name"], serde_json::json!("ada"));
+        
+        shared.insert("max".to_string(), serde_json::json!(i64::MAX));
+        assert!(shared.incr("max", 1).unwrap_err().to_string().contains("overflows"));
+        assert!(shared.incr_f64("score", f64::NAN).is_err());
+    }
+    
+    #[test]
+    fn pushes_create_the_list_and_refuse_other_values() {
+        let mut shared = SharedState::new();
+        assert_eq!(shared.push("messages", &Author { name: "ada".to_string(), tags: Vec::new() }).unwrap(), 1);
+        assert_eq!(shared.push_json("messages", serde_json::json!("hi")).unwrap(), 2);
+        assert_eq!(shared["messages"], serde_json::json!([{"name": "ada", "tags": []}, "hi"]));
+        
+        shared.insert("count".to_string(), serde_json::json!(3));
+        let err = shared.push("count", &4).unwrap_err();
+        assert!(err.to_string().contains("Cannot push to 'count'"), "{}", err);
+        assert_eq!(shared["count"], serde_json::json!(3));
+    }
+    
+    #[test]
+    fn prefixes_select_a_group_of_keys() {
+        let mut shared = SharedState::from([
+            ("batch/7/result".to_string(), serde_json::json!(7)),
+            ("batch/3/result".to_string(), serde_json::json!(3)),
+            ("batches".to_string(), serde_json::json!(2)),
+            ("other".to_string(), serde_json::json!(null)),
+        ]);
+        assert_eq!(shared.keys_with_prefix("batch/"), ["batch/3/result", "batch/7/result"]);
+        let results = shared.get_prefix_json("batch/");
+        assert_eq!(Value::Object(results), serde_json::json!({"batch/3/result": 3, "batch/7/result": 7}));
+        
+        assert_eq!(shared.remove_prefix("batch/"), 2);
+        assert_eq!(shared.remove_prefix("batch/"), 0);
+        assert_eq!(shared.keys_with_prefix(""), ["batches", "other"]);
+    }
+    
+    #[test]
+    fn checked_reads_tell_missing_keys_from_wrong_types() {
+        let shared = SharedState::from([("count".to_string(), serde_json::json!(2.5))]);
+        assert_eq!(shared.get_checked::<f64>("count").unwrap(), 2.5);
+        assert!(matches!(shared.get_checked::<u32>("total"), Err(Error::MissingKey(key)) if key == "total"));
+        match shared.get_checked::<u32>("count") {
+            Err(Error::TypeMismatch { key, expected, found }) => assert_eq!((key.as_str(), expected.as_str(), found.as_str()), ("count", "u32", "float")),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+    
+    #[test]
+    fn states_convert_to_and_from_one_json_object() {
+        let document = serde_json::json!({"title": "notes", "scores": [1, 2], "meta": {"done": true}});
+        let shared = SharedState::from_json(document.clone()).unwrap();
+        assert_eq!(shared.len(), 3);
+        assert_eq!(shared.to_json(), document);
+        
+        let err = SharedState::from_json(serde_json::json!([1, 2])).unwrap_err();
+        assert_eq!(err.to_string(), Error::Serialization("Shared state must be a JSON object, got array".to_string()).to_string());
+    }
+    
+    #[test]
+    fn params_read_typed_with_errors_naming_the_node() {
+        let node = BaseNode::new();
+        node.set_name("fetch");
+        node.set_params_map(ParamMap::from([
+            ("retries".to_string(), serde_json::json!(3)),
+            ("url".to_string(), serde_json::json!("https://example.com")),
+        ]));
+        assert_eq!(node.param::<u32>("retries").unwrap(), 3);
+        assert_eq!(node.param::<String>("url").unwrap(), "https://example.com");
+        
+        let missing = node.param::<u32>("timeout").unwrap_err().to_string();
+        assert!(missing.contains("fetch: missing param 'timeout' (u32)"), "{}", missing);
+        let mistyped = node.param::<u32>("url").unwrap_err().to_string();
+        assert!(mistyped.contains("fetch: param 'url' should be u32, found string"), "{}", mistyped);
+        
+        assert_eq!(node.param_or("timeout", 30u32), 30);
+        assert_eq!(node.param_or("url", 30u32), 30);
+        assert_eq!(node.param_or("retries", 30u32), 3);
+    }
+}
\ No newline at end of file
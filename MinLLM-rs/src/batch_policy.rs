@@ -0,0 +1,301 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::{Error, Result};
+
+/// What a batch node does when one of its items fails
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Fail the whole batch with the first item error
+    #[default]
+    FailFast,
+    
+    /// Keep going and report the failed items next to the results
+    CollectErrors,
+    
+    /// Keep going and drop the failed items, only counting them
+    SkipFailed,
+}
+
+/// Order in which a parallel batch collects its results
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ResultOrder {
+    /// Results in batch order, whenever the items finish
+    #[default]
+    Preserve,
+    
+    /// Results in the order the items finish, each as `{"index": i, "value": ...}`
+    CompletionOrder,
+}
+
+impl ResultOrder {
+    /// A result as collected under this order, given the batch position of its item
+    pub(crate) fn tag(&self, index: usize, value: Value) -> Value {
+        match self {
+            ResultOrder::Preserve => value,
+            ResultOrder::CompletionOrder => serde_json::json!({"index": index, "value": value}),
+        }
+    }
+}
+
+/// A failed batch item
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ItemError {
+    /// Position of the item in the batch
+    pub index: usize,
+    
+    /// Message of the error the item failed with
+    pub error: String,
+}
+
+/// Outcome of a batch run
+///
+/// Under `CollectErrors` or `SkipFailed` this is the exec result the batch node hands to post,
+/// serialized as `{"results": [...], "errors": [{"index", "error"}], "skipped": n, "elapsed_ms": n}`.
+/// Under `FailFast` post receives the plain results array, which `from_exec` also reads.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct BatchReport {
+    /// Results of the items that succeeded, in batch order
+    pub results: Vec<Value>,
+    
+    /// Failed items, under `CollectErrors`; a failed chunk is reported at its first item
+    pub errors: Vec<ItemError>,
+    
+    /// Number of failed items dropped, under `SkipFailed`
+    pub skipped: usize,
+    
+    /// Time the batch took in milliseconds; zero when read from a plain results array
+    #[serde(default)]
+    pub elapsed_ms: u64,
+}
+
+impl BatchReport {
+    /// Read the report from a batch node's exec result, as received by post, under any policy
+    pub fn from_exec(exec_res: &Value) -> Option<Self> {
+        match exec_res {
+            Value::Array(results) => Some(Self {
+                results: results.clone(),
+                ..Self::default()
+            }),
+            _ => serde_json::from_value(exec_res.clone()).ok(),
+        }
+    }
+    
+    /// Whether any item failed
+    pub fn has_failures(&self) -> bool {
+        !self.errors.is_empty() || self.skipped > 0
+    }
+    
+    /// Number of items that failed, whether reported or skipped
+    pub fn failed_count(&self) -> usize {
+        self.errors.len() + self.skipped
+    }
+    
+    /// Time the batch took
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_millis(self.elapsed_ms)
+    }
+}
+
+/// Observer of a batch's progress, called after every item or chunk completes
+pub type ProgressCallback = Arc<dyn Fn(BatchProgress) + Send + Sync>;
+
+/// How far a batch has got
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BatchProgress {
+    /// Items completed so far, successfully or not
+    pub completed: usize,
+    
+    /// Items in the batch
+    pub total: usize,
+    
+    /// Items that failed so far
+    pub failed: usize,
+    
+    /// Position of the item that just completed; the last item of a chunk
+    pub last_index: usize,
+    
+    /// Time since the batch started
+    pub elapsed: Duration,
+}
+
+/// Counts completed items and reports them to a progress callback
+///
+/// Shared by the concurrent items of a parallel batch, so every call sees consistent counts
+/// and calls arrive in completion order.
+pub(crate) struct ProgressTracker {
+    /// Callback to report to
+    callback: ProgressCallback,
+    
+    /// Items in the batch
+    total: usize,
+    
+    /// Items completed so far
+    completed: AtomicUsize,
+    
+    /// Items failed so far
+    failed: AtomicUsize,
+    
+    /// When the batch started
+    started: Instant,
+}
+
+impl ProgressTracker {
+    /// Start tracking a batch of `total` items
+    pub(crate) fn new(callback: ProgressCallback, total: usize) -> Self {
+        Self {
+            callback,
+            total,
+            completed: AtomicUsize::new(0),
+            failed: AtomicUsize::new(0),
+            started: Instant::now(),
+        }
+    }
+    
+    /// Report that the `len` items starting at `start` completed, all of them failed when `result` is an error
+    pub(crate) fn record<T>(&self, start: usize, len: usize, result: &Result<T>) {
+        let failed = match result {
+            Ok(_) => self.failed.load(Ordering::SeqCst),
+            Err(_) => self.failed.fetch_add(len, Ordering::SeqCst) + len,
+        };
+        let completed = self.completed.fetch_add(len, Ordering::SeqCst) + len;
+        (self.callback)(BatchProgress {
+            completed,
+            total: self.total,
+            failed,
+            last_index: start + len.saturating_sub(1),
+            elapsed: self.started.elapsed(),
+        });
+    }
+}
+
+/// Gathers item results under an error policy
+pub(crate) struct BatchCollector {
+    /// How failed items are handled
+    policy: ErrorPolicy,
+    
+    /// Results and failures so far
+    report: BatchReport,
+    
+    /// When the batch started
+    started: Instant,
+    
+    /// Batch positions of the results, in the order they were collected
+    positions: Vec<usize>,
+    
+    /// Failures held back until the end, when aggregating under `FailFast`
+    failures: Option<Vec<(usize, Error)>>,
+    
+    /// Whether results collected as they finish are put back in batch order at the end
+    sort: bool,
+}
+
+impl BatchCollector {
+    /// Start collecting a batch of `len` items
+    pub(crate) fn new(policy: ErrorPolicy, len: usize) -> Self {
+        Self {
+            policy,
+            report: BatchReport {
+                results: Vec::with_capacity(len),
+                ..BatchReport::default()
+            },
+            started: Instant::now(),
+            positions: Vec::with_capacity(len),
+            failures: None,
+            sort: false,
+        }
+    }
+    
+    /// Under `FailFast`, keep collecting past failures and fail with all of them at the end
+    ///
+    /// For items that run side by side, as in a parallel batch, so no failure among the items
+    /// that already finished hides another.
+    pub(crate) fn aggregating(mut self) -> Self {
+        self.failures = Some(Vec::new());
+        self
+    }
+    
+    /// Put the results and failures back in batch order at the end, for items collected as they finish
+    pub(crate) fn in_batch_order(mut self) -> Self {
+        self.sort = true;
+        self
+    }
+    
+    /// Whether an item failed under `FailFast` and its failure is held back, so the batch is bound to fail
+    pub(crate) fn failed_fast(&self) -> bool {
+        self.failures.as_ref().is_some_and(|failures| !failures.is_empty())
+    }
+    
+    /// Record the result of item `index`, failing only under `FailFast`
+    pub(crate) fn push(&mut self, index: usize, result: Result<Value>) -> Result<()> {
+        match (result, self.policy) {
+            (Ok(res), _) => {
+                self.report.results.push(res);
+                self.positions.push(index);
+            },
+            (Err(e), ErrorPolicy::FailFast) => self.fail(index, e)?,
+            (Err(e), ErrorPolicy::CollectErrors) => self.report.errors.push(ItemError { index, error: e.to_string() }),
+            (Err(_), ErrorPolicy::SkipFailed) => self.report.skipped += 1,
+        }
+        Ok(())
+    }
+    
+    /// Record the result of the `len` items starting at `start` that were executed as one chunk
+    ///
+    /// A successful chunk must return an array, whose elements are flattened into the results.
+    pub(crate) fn push_chunk(&mut self, start: usize, len: usize, result: Result<Value>) -> Result<()> {
+        let result = result.and_then(|res| match res {
+            Value::Array(items) => Ok(items),
+            other => Err(Error::NodeExecution(format!("Chunk exec should return an array, got {}", other))),
+        });
+        match (result, self.policy) {
+            (Ok(items), _) => {
+                self.positions.extend(start..start + items.len());
+                self.report.results.extend(items);
+            },
+            (Err(e), ErrorPolicy::FailFast) => self.fail(start, e)?,
+            (Err(e), ErrorPolicy::CollectErrors) => self.report.errors.push(ItemError { index: start, error: e.to_string() }),
+            (Err(_), ErrorPolicy::SkipFailed) => self.report.skipped += len,
+        }
+        Ok(())
+    }
+    
+    /// Fail with `error` under `FailFast`, or hold it back when aggregating
+    fn fail(&mut self, index: usize, error: Error) -> Result<()> {
+        match &mut self.failures {
+            Some(failures) if !error.is_cancelled() => {
+                failures.push((index, error));
+                Ok(())
+            },
+            _ => Err(error),
+        }
+    }
+    
+    /// The exec result: a plain array under `FailFast`, a `BatchReport` otherwise
+    ///
+    /// When aggregating, held-back failures fail the batch with `Error::BatchFailed`,
+    /// which also carries the results of the items that succeeded.
+    pub(crate) fn finish(mut self) -> Result<Value> {
+        if self.sort {
+            let mut collected: Vec<(usize, Value)> = self.positions.drain(..).zip(self.report.results.drain(..)).collect();
+            collected.sort_by_key(|(index, _)| *index);
+            (self.positions, self.report.results) = collected.into_iter().unzip();
+            self.report.errors.sort_by_key(|error| error.index);
+        }
+        if let Some(mut failures) = self.failures.take().filter(|failures| !failures.is_empty()) {
+            failures.sort_by_key(|(index, _)| *index);
+            let results = self.positions.into_iter().zip(self.report.results).collect();
+            return Err(Error::BatchFailed { failures, results });
+        }
+        match self.policy {
+            ErrorPolicy::FailFast => Ok(Value::Array(self.report.results)),
+            _ => {
+                self.report.elapsed_ms = self.started.elapsed().as_millis() as u64;
+                serde_json::to_value(self.report).map_err(|e| Error::Serialization(e.to_string()))
+            },
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,87 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::Notify;
+
+use crate::error::{Error, Result};
+
+tokio::task_local! {
+    /// Token of the cancellable run the current task belongs to
+    static CANCEL: CancellationToken;
+}
+
+/// A handle that stops an async run at its next checkpoint
+///
+/// Clones share the same state, so one clone can be handed to the run and another kept to cancel it.
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    /// Shared cancellation state
+    inner: Arc<CancelState>,
+}
+
+#[derive(Default)]
+struct CancelState {
+    /// Whether `cancel` was called
+    cancelled: AtomicBool,
+    
+    /// Wakes the tasks waiting in `cancelled`
+    notify: Notify,
+}
+
+impl CancellationToken {
+    /// Create a token that is not cancelled
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Cancel the run; nodes that already finished keep their writes to the shared state
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst);
+        self.inner.notify.notify_waiters();
+    }
+    
+    /// Whether `cancel` was called
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+    
+    /// Wait until the token is cancelled
+    pub async fn cancelled(&self) {
+        loop {
+            let notified = self.inner.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+    
+    /// The token of the cancellable run the calling task belongs to, for execs that want to abort early
+    pub fn current() -> Option<CancellationToken> {
+        CANCEL.try_with(|token| token.clone()).ok()
+    }
+}
+
+/// Await `fut` with `token` as the current task's cancellation token
+pub(crate) async fn scope<F: Future>(token: CancellationToken, fut: F) -> F::Output {
+    CANCEL.scope(token, fut).await
+}
+
+/// Fail with `Error::Cancelled` when the current run has been cancelled
+pub(crate) fn checkpoint() -> Result<()> {
+    match CANCEL.try_with(|token| token.is_cancelled()) {
+        Ok(true) => Err(Error::Cancelled),
+        _ => Ok(()),
+    }
+}
+
+/// Await an exec attempt, abandoning it with `Error::Cancelled` as soon as the current run is cancelled
+pub(crate) async fn race<T>(fut: impl Future<Output = Result<T>>) -> Result<T> {
+    match CancellationToken::current() {
+        Some(token) => tokio::select! {
+            res = fut => res,
+            _ = token.cancelled() => Err(Error::Cancelled),
+        },
+        None => fut.await,
+    }
+}
\ No newline at end of file
@@ -0,0 +1,179 @@
+use std::iter;
+use std::sync::Arc;
+use serde_json::Value;
+use log::{debug, warn};
+
+use crate::action::ActionName;
+use crate::base::{Node, SharedState, Action, ParamMap, DEFAULT_ACTION};
+use crate::successors::{self, EdgePredicate};
+use crate::hooks::NodeHooks;
+use crate::flow::{NextSteps, Route};
+use crate::async_flow::AsyncFlow;
+use crate::dry_run::{self, DryRunReport};
+use crate::trace::{self, FlowTrace};
+use crate::error::Result;
+
+/// A snapshot of a flow's topology with nodes and edges resolved to indices
+///
+/// Nodes are still run through their own prep/exec/post by the same walk as the flow they were
+/// compiled from, with its hooks, step limit, state observers and checkpoints, but choosing the
+/// next node is a binary search over a sorted edge list instead of a locked map lookup.
+/// Successors added or replaced after compilation are not seen by the compiled plan.
+#[derive(Clone)]
+pub struct CompiledFlow {
+    /// The flow the plan was compiled from, which runs its steps
+    runner: AsyncFlow,
+    
+    /// Nodes reachable from the start node, with the start node at index 0
+    nodes: Vec<Arc<dyn Node>>,
+    
+    /// Outgoing edges of each node as (action, node index), sorted by action
+    edges: Vec<Vec<(ActionName, usize)>>,
+    
+    /// Conditional edges of each node as (predicate, node index), in insertion order
+    conditional: Vec<Vec<(EdgePredicate, usize)>>,
+    
+    /// Params handed to the start node on each run
+    params: Arc<ParamMap>,
+}
+
+impl CompiledFlow {
+    /// Snapshot every node reachable from the start node of `runner`
+    pub(crate) fn new(runner: AsyncFlow, params: Arc<ParamMap>) -> Self {
+        let (graph, predicates) = successors::reachable_with_predicates(runner.inner().start.clone());
+        let mut nodes = Vec::with_capacity(graph.len());
+        let mut edges = Vec::with_capacity(graph.len());
+        let mut conditional = Vec::with_capacity(graph.len());
+        for (entry, predicates) in graph.into_iter().zip(predicates) {
+            nodes.push(entry.node);
+            edges.push(entry.edges);
+            conditional.push(predicates.into_iter().zip(entry.conditional).map(|(predicate, (_, next))| (predicate, next)).collect());
+        }
+        
+        Self { runner, nodes, edges, conditional, params }
+    }
+    
+    /// Apply `hooks` to every node the plan runs, for the hooks a node leaves unset
+    pub fn with_default_hooks(mut self, hooks: NodeHooks) -> Self {
+        self.runner = self.runner.with_default_hooks(hooks);
+        self
+    }
+    
+    /// Stop a run with an error once it has taken `max_steps` node steps, or never with `None`
+    pub fn with_max_steps(mut self, max_steps: Option<usize>) -> Self {
+        self.runner = self.runner.with_max_steps(max_steps);
+        self
+    }
+    
+    /// Number of nodes in the plan
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+    
+    /// Whether the plan has no nodes
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+    
+    /// The node at an index
+    pub fn node(&self, idx: usize) -> Option<&Arc<dyn Node>> {
+        self.nodes.get(idx)
+    }
+    
+    /// Index of the node the flow continues with after `idx` for an action
+    ///
+    /// When the action has several successors this is the last one; see `targets`.
+    pub fn next(&self, idx: usize, action: Option<&str>) -> Option<usize> {
+        self.edges_for(idx, action).last().map(|(_, next)| *next)
+    }
+    
+    /// Indices of every node following `idx` for an action, in the order they were added
+    pub fn targets(&self, idx: usize, action: Option<&str>) -> Vec<usize> {
+        self.edges_for(idx, action).iter().map(|(_, next)| *next).collect()
+    }
+    
+    /// The edges `idx` follows for an action, logging why the flow ends when there are none
+    fn edges_for(&self, idx: usize, action: Option<&str>) -> &[(ActionName, usize)] {
+        let action = action.unwrap_or(DEFAULT_ACTION);
+        let targets = self.lookup(idx, action);
+        let edges = &self.edges[idx];
+        if targets.is_empty() && edges.is_empty() {
+            debug!(target: "minllm::flow", "Flow ends after {}", self.nodes[idx].name());
+        } else if targets.is_empty() {
+            let mut actions: Vec<&str> = edges.iter().map(|(a, _)| a.as_str()).collect();
+            actions.dedup();
+            warn!(target: "minllm::flow", "Flow ends after {}: '{}' not found in {:?}", self.nodes[idx].name(), action, actions);
+        }
+        targets
+    }
+    
+    /// The edges of `idx` for an action, found by binary search over the sorted edges
+    fn lookup(&self, idx: usize, action: &str) -> &[(ActionName, usize)] {
+        let edges = &self.edges[idx];
+        let lo = edges.partition_point(|(a, _)| a.as_str() < action);
+        let hi = edges.partition_point(|(a, _)| a.as_str() <= action);
+        &edges[lo..hi]
+    }
+    
+    /// Run the plan from the start node
+    ///
+    /// The plan was validated when it was compiled, so strict flows aren't validated again here.
+    pub fn run(&self, shared: &mut SharedState) -> Result<Action> {
+        self.runner.inner().run_route(self, 0, self.params.clone(), shared)
+    }
+    
+    /// Run the plan from the start node asynchronously, as `AsyncFlow::run_async` runs a flow
+    pub async fn run_async(&self, shared: &mut SharedState) -> Result<Action> {
+        self.runner.run_route_async(self, 0, self.params.clone(), shared).await
+    }
+    
+    /// Run the plan, recording every node step as `Flow::run_traced` does
+    pub fn run_traced(&self, shared: &mut SharedState) -> (Result<Action>, FlowTrace) {
+        trace::run(|| self.run(shared))
+    }
+    
+    /// Run the plan asynchronously, recording every node step as `AsyncFlow::run_async_traced` does
+    pub async fn run_async_traced(&self, shared: &mut SharedState) -> (Result<Action>, FlowTrace) {
+        trace::run_async(self.run_async(shared)).await
+    }
+    
+    /// Run the plan with every node's exec replaced by its `exec_dry_run`, as `Flow::dry_run` does
+    pub fn dry_run(&self, shared: &mut SharedState) -> Result<DryRunReport> {
+        dry_run::run(self.runner.inner().dry_run_stub.clone(), || self.run(shared))
+    }
+}
+
+impl Route for CompiledFlow {
+    type At = usize;
+    
+    fn node(&self, at: &usize) -> Arc<dyn Node> {
+        self.nodes[*at].clone()
+    }
+    
+    fn locate(&self, node: Arc<dyn Node>) -> usize {
+        let ptr = Arc::as_ptr(&node) as *const ();
+        self.nodes
+            .iter()
+            .position(|n| Arc::as_ptr(n) as *const () == ptr)
+            .expect("a compiled flow only reaches nodes in its plan")
+    }
+    
+    fn has_conditional(&self, at: &usize) -> bool {
+        !self.conditional[*at].is_empty()
+    }
+    
+    fn successors_for(&self, at: &usize, action: &str) -> Vec<usize> {
+        self.lookup(*at, action).iter().map(|(_, next)| *next).collect()
+    }
+    
+    fn next(&self, at: &usize, action: Option<&str>, shared: &SharedState, exec_res: &Value) -> NextSteps<usize> {
+        let routed = self.conditional[*at]
+            .iter()
+            .find(|(predicate, _)| predicate(shared, exec_res))
+            .map(|(_, idx)| *idx);
+        match routed {
+            Some(next) => iter::once(next).collect(),
+            None => self.edges_for(*at, action).iter().map(|(_, next)| *next).collect(),
+        }
+    }
+}
\ No newline at end of file
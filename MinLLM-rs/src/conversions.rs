@@ -0,0 +1,263 @@
+#![cfg(feature = "python")]
+
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyTuple, PyList};
+use pyo3::exceptions::PyTypeError;
+use serde_json::Value;
+
+/// Number of sequence items converted between releases of the GIL
+const CONVERT_CHUNK: usize = 4096;
+
+/// Convert the items of a Python sequence, letting other threads take the GIL between chunks
+fn py_items_to_values<'a>(py: Python, len: usize, items: impl Iterator<Item = &'a PyAny>) -> PyResult<Value> {
+    let mut values = Vec::with_capacity(len);
+    for (i, item) in items.enumerate() {
+        if i > 0 && i % CONVERT_CHUNK == 0 {
+            py.allow_threads(|| {});
+        }
+        values.push(py_to_value(py, item)?);
+    }
+    Ok(Value::Array(values))
+}
+
+/// Convert Python object to serde_json Value
+///
+/// Conversions that do not round-trip, left out of the round-trip properties in the tests below:
+///
+/// | Python                                         | JSON value              | Back in Python          |
+/// |------------------------------------------------|-------------------------|-------------------------|
+/// | `tuple`                                        | array                   | `list`                  |
+/// | `int` outside the u64/i64 range                | float                   | `float`                 |
+/// | `int` too large for a float                    | conversion error        | -                       |
+/// | other objects with `__float__`, e.g. `Decimal` | float                   | `float`                 |
+/// | `float` NaN or infinity                        | conversion error        | -                       |
+/// | `dict`                                         | object with sorted keys | `dict` with sorted keys |
+/// | `dict` with non-`str` keys                     | conversion error        | -                       |
+/// | `bytes`, `bytearray`, `range`, other sequences | conversion error        | -                       |
+/// | any other type                                 | conversion error        | -                       |
+pub(crate) fn py_to_value(py: Python, obj: &PyAny) -> PyResult<Value> {
+    if obj.is_none() {
+        return Ok(Value::Null);
+    }
+    
+    if let Ok(val) = obj.extract::<bool>() {
+        return Ok(Value::Bool(val));
+    }
+    
+    if let Ok(val) = obj.extract::<i64>() {
+        return Ok(Value::Number(val.into()));
+    }
+    
+    if let Ok(val) = obj.extract::<u64>() {
+        return Ok(Value::Number(val.into()));
+    }
+    
+    if let Ok(val) = obj.extract::<f64>() {
+        return match serde_json::Number::from_f64(val) {
+            Some(n) => Ok(Value::Number(n)),
+            None => Err(PyTypeError::new_err(format!("Cannot convert f64 to JSON Number: {}", val))),
+        };
+    }
+    
+    if let Ok(val) = obj.extract::<String>() {
+        return Ok(Value::String(val));
+    }
+    
+    if let Ok(tuple) = obj.downcast::<PyTuple>() {
+        return py_items_to_values(py, tuple.len(), tuple.iter());
+    }
+    
+    if let Ok(list) = obj.downcast::<PyList>() {
+        return py_items_to_values(py, list.len(), list.iter());
+    }
+    
+    if let Ok(dict) = obj.downcast::<PyDict>() {
+        let mut map = serde_json::Map::with_capacity(dict.len());
+        for (key, value) in dict.iter() {
+            let key = key.extract::<String>()?;
+            let value = py_to_value(py, value)?;
+            map.insert(key, value);
+        }
+        return Ok(Value::Object(map));
+    }
+    
+    Err(PyTypeError::new_err(format!("Cannot convert Python object to JSON: {:?}", obj)))
+}
+
+/// Convert serde_json Value to Python object
+pub(crate) fn value_to_py(py: Python, value: Value) -> PyResult<PyObject> {
+    match value {
+        Value::Null => Ok(py.None()),
+        Value::Bool(b) => Ok(b.to_object(py)),
+        Value::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                Ok(i.to_object(py))
+            } else if let Some(u) = n.as_u64() {
+                Ok(u.to_object(py))
+            } else if let Some(f) = n.as_f64() {
+                Ok(f.to_object(py))
+            } else {
+                Err(PyTypeError::new_err("Unsupported number type"))
+            }
+        },
+        Value::String(s) => Ok(s.to_object(py)),
+        Value::Array(arr) => {
+            let py_list = PyList::empty(py);
+            for item in arr {
+                py_list.append(value_to_py(py, item)?)?;
+            }
+            Ok(py_list.to_object(py))
+        },
+        Value::Object(obj) => {
+            let py_dict = PyDict::new(py);
+            for (key, value) in obj {
+                py_dict.set_item(key, value_to_py(py, value)?)?;
+            }
+            Ok(py_dict.to_object(py))
+        }
+    }
+}
+
+/// Round trips through the conversions, for checking what they preserve
+pub(crate) mod testing {
+    use super::*;
+    
+    /// Convert a Python object to JSON and back
+    pub(crate) fn roundtrip_json(py: Python, obj: &PyAny) -> PyResult<PyObject> {
+        value_to_py(py, py_to_value(py, obj)?)
+    }
+    
+    /// Convert a JSON value to Python and back
+    #[cfg(test)]
+    pub(crate) fn roundtrip_value(py: Python, value: Value) -> PyResult<Value> {
+        py_to_value(py, value_to_py(py, value)?.as_ref(py))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use serde_json::json;
+    
+    use super::testing::{roundtrip_json, roundtrip_value};
+    use super::*;
+    
+    /// JSON values every conversion preserves: numbers are i64, u64 or finite floats
+    fn json_values() -> impl Strategy<Value = Value> {
+        let leaf = prop_oneof![
+            Just(Value::Null),
+            any::<bool>().prop_map(Value::Bool),
+            any::<i64>().prop_map(|n| json!(n)),
+            (i64::MAX as u64 + 1..=u64::MAX).prop_map(|n| json!(n)),
+            any::<f64>().prop_filter("JSON has no NaN or infinity", |f| f.is_finite()).prop_map(|f| json!(f)),
+            ".*".prop_map(Value::String),
+        ];
+        leaf.prop_recursive(4, 64, 8, |inner| {
+            prop_oneof![
+                prop::collection::vec(inner.clone(), 0..8).prop_map(Value::Array),
+                prop::collection::btree_map(".*", inner, 0..8).prop_map(|map| Value::Object(map.into_iter().collect())),
+            ]
+        })
+    }
+    
+    /// Python source for objects that survive `py -> json -> py` unchanged
+    ///
+    /// Tuples, ints outside the i64/u64 range, NaN and the other rows of the table on
+    /// `py_to_value` are left out; `lossy_conversions_follow_the_table` covers them.
+    fn python_literals() -> impl Strategy<Value = String> {
+        let leaf = prop_oneof![
+            Just("None".to_string()),
+            any::<bool>().prop_map(|b| if b { "True" } else { "False" }.to_string()),
+            any::<i64>().prop_map(|n| n.to_string()),
+            any::<u64>().prop_map(|n| n.to_string()),
+            any::<f64>().prop_filter("JSON has no NaN or infinity", |f| f.is_finite()).prop_map(|f| format!("float({:?})", f.to_string())),
+            ".*".prop_map(|s| json!(s).to_string()),
+        ];
+        leaf.prop_recursive(4, 64, 8, |inner| {
+            prop_oneof![
+                prop::collection::vec(inner.clone(), 0..8).prop_map(|items| format!("[{}]", items.join(", "))),
+                prop::collection::btree_map("[a-z]{0,6}", inner, 0..8).prop_map(|map| {
+                    let items: Vec<String> = map.into_iter().map(|(key, value)| format!("{:?}: {}", key, value)).collect();
+                    format!("{{{}}}", items.join(", "))
+                }),
+            ]
+        })
+    }
+    
+    /// Evaluate a Python expression
+    fn eval<'py>(py: Python<'py>, source: &str) -> &'py PyAny {
+        py.eval(source, None, None).unwrap_or_else(|e| panic!("{}: {}", source, e))
+    }
+    
+    /// Whether two Python objects are equal and of the same type, all the way down
+    fn same(a: &PyAny, b: &PyAny) -> bool {
+        if !a.get_type().is(b.get_type()) {
+            return false;
+        }
+        if let (Ok(a), Ok(b)) = (a.downcast::<PyList>(), b.downcast::<PyList>()) {
+            return a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| same(a, b));
+        }
+        if let (Ok(a), Ok(b)) = (a.downcast::<PyDict>(), b.downcast::<PyDict>()) {
+            return a.len() == b.len()
+                && a.iter().all(|(key, value)| b.get_item(key).ok().flatten().is_some_and(|other| same(value, other)));
+        }
+        a.eq(b).unwrap_or(false)
+    }
+    
+    proptest! {
+        #[test]
+        fn json_survives_python(value in json_values()) {
+            pyo3::prepare_freethreaded_python();
+            let back = Python::with_gil(|py| roundtrip_value(py, value.clone())).unwrap();
+            prop_assert_eq!(back, value);
+        }
+        
+        #[test]
+        fn lossless_python_objects_survive_json(source in python_literals()) {
+            pyo3::prepare_freethreaded_python();
+            Python::with_gil(|py| {
+                let obj = eval(py, &source);
+                let back = roundtrip_json(py, obj).unwrap();
+                prop_assert!(same(obj, back.as_ref(py)), "{} came back as {}", source, back);
+                Ok(())
+            })?;
+        }
+    }
+    
+    #[test]
+    fn lossy_conversions_follow_the_table() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let converted = |source: &str| py_to_value(py, eval(py, source));
+            assert_eq!(converted("(1, 'a', None)").unwrap(), json!([1, "a", null]));
+            assert_eq!(converted("2 ** 70").unwrap(), json!(2f64.powi(70)));
+            assert_eq!(converted("__import__('decimal').Decimal('1.5')").unwrap(), json!(1.5));
+            
+            let sorted = roundtrip_json(py, eval(py, "{'b': 1, 'a': 2}")).unwrap();
+            let keys: Vec<String> = sorted.as_ref(py).downcast::<PyDict>().unwrap().keys().extract().unwrap();
+            assert_eq!(keys, ["a", "b"]);
+            
+            for rejected in ["10 ** 400", "float('nan')", "float('inf')", "{1: 'a'}", "b'ab'", "bytearray(b'ab')", "range(3)", "{1, 2}", "object()"] {
+                assert!(converted(rejected).is_err(), "{} should not convert", rejected);
+            }
+        });
+    }
+    
+    #[test]
+    fn a_large_batch_converts_like_json_dumps() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let items = eval(py, "[{'id': i, 'name': f'item {i}', 'score': i / 4, 'tags': ['a', None, True]} for i in range(200_000)]");
+            let dumped: String = py.import("json").unwrap().call_method1("dumps", (items,)).unwrap().extract().unwrap();
+            let expected: Value = serde_json::from_str(&dumped).unwrap();
+            
+            let started = std::time::Instant::now();
+            let converted = py_to_value(py, items).unwrap();
+            let elapsed = started.elapsed();
+            assert_eq!(converted, expected);
+            // Generous enough for unoptimized builds on slow machines
+            assert!(elapsed < std::time::Duration::from_secs(30), "200k items took {:?}", elapsed);
+        });
+    }
+}
\ No newline at end of file
@@ -0,0 +1,726 @@
+use std::collections::hash_map::{self, Entry, HashMap};
+use std::collections::HashSet;
+use std::fmt;
+use std::mem;
+use std::ops::Index;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use parking_lot::Mutex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+
+use crate::error::{Error, Result};
+use crate::trace::{self, AccessKind};
+
+/// Resolves a conflict from the key, the value already present, and the incoming value
+pub type MergeFn = Arc<dyn Fn(&str, &Value, &Value) -> Result<Value> + Send + Sync>;
+
+/// How conflicting writes from batch items are resolved when merged into the parent state
+#[derive(Clone, Default)]
+pub enum MergePolicy {
+    /// The write of the last item in batch order wins
+    #[default]
+    LastWins,
+    
+    /// The write of the first item in batch order wins
+    FirstWins,
+    
+    /// Differing writes to the same key fail the flow
+    Fail,
+    
+    /// Differing writes to the same key are combined by a function
+    Custom(MergeFn),
+    
+    /// Items' additions to the same list are all kept, in batch order; other differing writes fail the flow
+    Append,
+}
+
+impl MergePolicy {
+    /// Resolve conflicts with `f`, called as `f(key, existing, incoming)`
+    pub fn custom(f: impl Fn(&str, &Value, &Value) -> Result<Value> + Send + Sync + 'static) -> Self {
+        MergePolicy::Custom(Arc::new(f))
+    }
+    
+    /// The value to keep when `existing` and `incoming` differ for `key`
+    fn resolve(&self, key: &str, existing: &Value, incoming: Value) -> Result<Value> {
+        match self {
+            MergePolicy::LastWins => Ok(incoming),
+            MergePolicy::FirstWins => Ok(existing.clone()),
+            MergePolicy::Fail => Err(Error::FlowExecution(format!("Conflicting values for '{}' while merging state", key))),
+            MergePolicy::Custom(f) => f(key, existing, &incoming),
+            MergePolicy::Append => Self::append(key, None, existing, incoming),
+        }
+    }
+    
+    /// The change to keep when `existing` and `incoming` differ for `key`, `None` being a removal
+    ///
+    /// `base` is the value both changes were made on top of.
+    fn resolve_change(&self, key: &str, base: Option<&Value>, existing: &Option<Value>, incoming: Option<Value>) -> Result<Option<Value>> {
+        match (existing, incoming, self) {
+            (Some(existing), Some(incoming), MergePolicy::Append) => Self::append(key, base, existing, incoming).map(Some),
+            (Some(existing), Some(incoming), _) => self.resolve(key, existing, incoming).map(Some),
+            (_, incoming, MergePolicy::LastWins) => Ok(incoming),
+            (existing, _, MergePolicy::FirstWins) => Ok(existing.clone()),
+            _ => Err(Error::FlowExecution(format!("Conflicting removal of '{}' while merging state", key))),
+        }
+    }
+    
+    /// `existing` followed by what `incoming` added to `base`, when both are lists extending `base`
+    fn append(key: &str, base: Option<&Value>, existing: &Value, incoming: Value) -> Result<Value> {
+        let base = match base {
+            Some(Value::Array(base)) => base.as_slice(),
+            None => &[],
+            Some(_) => return Err(Error::FlowExecution(format!("Conflicting values for '{}' while merging state", key))),
+        };
+        match (existing, incoming) {
+            (Value::Array(existing), Value::Array(incoming)) if existing.starts_with(base) && incoming.starts_with(base) => {
+                Ok(Value::Array(existing.iter().cloned().chain(incoming.into_iter().skip(base.len())).collect()))
+            },
+            _ => Err(Error::FlowExecution(format!("Conflicting values for '{}' while merging state", key))),
+        }
+    }
+}
+
+impl fmt::Debug for MergePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MergePolicy::LastWins => f.write_str("LastWins"),
+            MergePolicy::FirstWins => f.write_str("FirstWins"),
+            MergePolicy::Fail => f.write_str("Fail"),
+            MergePolicy::Custom(_) => f.write_str("Custom(..)"),
+            MergePolicy::Append => f.write_str("Append"),
+        }
+    }
+}
+
+/// Shared state that is passed between nodes in a flow
+///
+/// A map from keys to JSON values. `fork` hands out a copy that reads through to a snapshot
+/// shared with this state, keeping only its own writes and removals, so batch items and
+/// concurrent branches never copy the values they only read.
+#[derive(Clone, Default)]
+pub struct SharedState {
+    /// Snapshot this state was forked from
+    parent: Option<Arc<SharedState>>,
+    
+    /// Values written on top of the snapshot
+    entries: HashMap<String, Value>,
+    
+    /// Keys of the snapshot that are overwritten or removed
+    hidden: HashSet<String>,
+    
+    /// Node the accesses to this state are recorded for, while a traced run records them
+    accessor: Option<Arc<str>>,
+    
+    /// Swaps shared with the states this one runs alongside, if it was forked to race them
+    claims: Option<Claims>,
+}
+
+/// Source of the ids telling racing forks apart
+static NEXT_RACER: AtomicU64 = AtomicU64::new(0);
+
+/// A racing fork's view of the compare-and-swaps of the forks it runs alongside
+#[derive(Clone)]
+struct Claims {
+    /// Per key, the fork that last swapped it and the value it stored
+    table: Arc<Mutex<HashMap<String, (u64, Value)>>>,
+    
+    /// The fork holding this view
+    racer: u64,
+}
+
+impl SharedState {
+    /// Create an empty state
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// A state reading through to `parent` until it writes
+    fn over(parent: Arc<SharedState>) -> Self {
+        Self {
+            parent: Some(parent),
+            entries: HashMap::new(),
+            hidden: HashSet::new(),
+            accessor: None,
+            claims: None,
+        }
+    }
+    
+    /// Get a value
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.note(key, AccessKind::Read);
+        self.lookup(key)
+    }
+    
+    /// Get a value to modify, copying it out of the snapshot first
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Value> {
+        self.note(key, AccessKind::Update);
+        self.own(key);
+        self.entries.get_mut(key)
+    }
+    
+    /// The entry of `key` for in-place updates, copying its value out of the snapshot first
+    pub fn entry(&mut self, key: String) -> Entry<'_, String, Value> {
+        self.note(&key, AccessKind::Update);
+        self.own(&key);
+        self.entries.entry(key)
+    }
+    
+    /// Whether `key` has a value
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+    
+    /// Store a value, returning the one it replaces
+    pub fn insert(&mut self, key: String, value: Value) -> Option<Value> {
+        self.note(&key, AccessKind::Write);
+        let replaced = self.inherited(&key).cloned();
+        self.set(key, value).or(replaced)
+    }
+    
+    /// Remove a value, returning it
+    pub fn remove(&mut self, key: &str) -> Option<Value> {
+        self.note(key, AccessKind::Remove);
+        let removed = self.inherited(key).cloned();
+        self.unset(key).or(removed)
+    }
+    
+    /// Keep only the entries for which `f` returns true
+    pub fn retain(&mut self, mut f: impl FnMut(&String, &Value) -> bool) {
+        self.entries.retain(|key, value| f(key, value));
+        if let Some(parent) = &self.parent {
+            let dropped: Vec<String> = parent
+                .iter()
+                .filter(|(key, value)| !self.hidden.contains(*key) && !f(key, value))
+                .map(|(key, _)| key.clone())
+                .collect();
+            self.hidden.extend(dropped);
+        }
+    }
+    
+    /// Remove every entry
+    pub fn clear(&mut self) {
+        *self = Self { accessor: self.accessor.take(), claims: self.claims.take(), ..Self::new() };
+    }
+    
+    /// Number of entries
+    pub fn len(&self) -> usize {
+        self.entries.len() + self.parent.as_ref().map_or(0, |parent| parent.len()) - self.hidden.len()
+    }
+    
+    /// Whether there are no entries
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    
+    /// Entries in no particular order
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            entries: self.entries.iter(),
+            parent: self.parent.as_deref().map(|parent| (Box::new(parent.iter()), &self.hidden)),
+        }
+    }
+    
+    /// Keys in no particular order
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.iter().map(|(key, _)| key)
+    }
+    
+    /// Values in no particular order
+    pub fn values(&self) -> impl Iterator<Item = &Value> {
+        self.iter().map(|(_, value)| value)
+    }
+    
+    /// A copy of this state that shares its values until either side writes them
+    ///
+    /// Both states read through to one snapshot afterwards, and the copy's own writes and
+    /// removals are kept apart from it, to be merged back by `merge_overlays`.
+    pub fn fork(&mut self) -> SharedState {
+        self.flatten();
+        let accessor = self.accessor.take();
+        let claims = self.claims.take();
+        let fork = if self.entries.is_empty() && self.hidden.is_empty() {
+            match &self.parent {
+                Some(parent) => Self::over(parent.clone()),
+                None => Self::new(),
+            }
+        } else {
+            let snapshot = Arc::new(mem::take(self));
+            *self = Self::over(snapshot.clone());
+            Self::over(snapshot)
+        };
+        self.accessor = accessor.clone();
+        self.claims = claims.clone();
+        Self { accessor, claims, ..fork }
+    }
+    
+    /// `count` forks that run alongside each other, whose compare-and-swaps see each other's
+    ///
+    /// Forks of these forks swap as the fork they came from, and racing forks of them join the same race.
+    pub(crate) fn racing_forks(&mut self, count: usize) -> Vec<SharedState> {
+        let table = self.claims.as_ref().map_or_else(Default::default, |claims| claims.table.clone());
+        (0..count)
+            .map(|_| {
+                let racer = NEXT_RACER.fetch_add(1, Ordering::Relaxed);
+                Self { claims: Some(Claims { table: table.clone(), racer }), ..self.fork() }
+            })
+            .collect()
+    }
+    
+    /// Store `new` under `key` unless a racing fork swapped it to a value other than `expected`, returning whether it did
+    pub(crate) fn claim(&mut self, key: &str, expected: Option<&Value>, new: Value) -> bool {
+        if let Some(claims) = &self.claims {
+            let mut table = claims.table.lock();
+            match table.get(key) {
+                Some((racer, value)) if *racer != claims.racer && Some(value) != expected => return false,
+                _ => {
+                    table.insert(key.to_string(), (claims.racer, new.clone()));
+                },
+            }
+        }
+        self.insert(key.to_string(), new);
+        true
+    }
+    
+    /// Record later accesses to this state for `accessor`, returning the node they were recorded for
+    ///
+    /// Accesses are only recorded while a traced run records writes; `None` stops recording them.
+    pub(crate) fn attribute(&mut self, accessor: Option<Arc<str>>) -> Option<Arc<str>> {
+        mem::replace(&mut self.accessor, accessor)
+    }
+    
+    /// Record an access to `key` for the node this state is attributed to
+    fn note(&self, key: &str, kind: AccessKind) {
+        if let Some(accessor) = &self.accessor {
+            trace::access(accessor, key, kind);
+        }
+    }
+    
+    /// A value, without recording the read
+    fn lookup(&self, key: &str) -> Option<&Value> {
+        match self.entries.get(key) {
+            Some(value) => Some(value),
+            None => self.inherited(key),
+        }
+    }
+    
+    /// Writes (`Some`) and removals (`None`) made since the state was forked
+    fn into_changes(mut self) -> Vec<(String, Option<Value>)> {
+        self.flatten();
+        self.into_own_changes()
+    }
+    
+    /// Writes and removals on top of the parent, whether or not the parent is shared
+    fn into_own_changes(self) -> Vec<(String, Option<Value>)> {
+        let entries = self.entries;
+        let removed: Vec<String> = self.hidden.into_iter().filter(|key| !entries.contains_key(key)).collect();
+        entries.into_iter().map(|(key, value)| (key, Some(value))).chain(removed.into_iter().map(|key| (key, None))).collect()
+    }
+    
+    /// Fold this state into the snapshots below it that nothing else shares
+    fn flatten(&mut self) {
+        let accessor = self.accessor.take();
+        let claims = self.claims.take();
+        while let Some(parent) = self.parent.take() {
+            match Arc::try_unwrap(parent) {
+                Ok(parent) => {
+                    let child = mem::replace(self, parent);
+                    self.apply(child.into_own_changes());
+                },
+                Err(parent) => {
+                    self.parent = Some(parent);
+                    break;
+                },
+            }
+        }
+        self.accessor = accessor;
+        self.claims = claims;
+    }
+    
+    /// Apply writes (`Some`) and removals (`None`)
+    fn apply(&mut self, changes: Vec<(String, Option<Value>)>) {
+        for (key, change) in changes {
+            match change {
+                Some(value) => {
+                    self.set(key, value);
+                },
+                None => {
+                    self.unset(&key);
+                },
+            }
+        }
+    }
+    
+    /// Store a value, returning the one it replaces among this state's own writes
+    fn set(&mut self, key: String, value: Value) -> Option<Value> {
+        if self.inherited(&key).is_some() {
+            self.hidden.insert(key.clone());
+        }
+        self.entries.insert(key, value)
+    }
+    
+    /// Remove a value, returning it if it was among this state's own writes
+    fn unset(&mut self, key: &str) -> Option<Value> {
+        if self.inherited(key).is_some() {
+            self.hidden.insert(key.to_string());
+        }
+        self.entries.remove(key)
+    }
+    
+    /// Copy the snapshot's value of `key` into this state's own writes
+    fn own(&mut self, key: &str) {
+        if !self.entries.contains_key(key) {
+            if let Some(value) = self.inherited(key).cloned() {
+                self.hidden.insert(key.to_string());
+                self.entries.insert(key.to_string(), value);
+            }
+        }
+    }
+    
+    /// The snapshot's value of `key`, unless this state overwrote or removed it
+    fn inherited(&self, key: &str) -> Option<&Value> {
+        match &self.parent {
+            Some(parent) if !self.hidden.contains(key) => parent.lookup(key),
+            _ => None,
+        }
+    }
+}
+
+/// Iterator over the entries of a `SharedState`
+pub struct Iter<'a> {
+    /// The state's own writes
+    entries: hash_map::Iter<'a, String, Value>,
+    
+    /// The snapshot's entries and the keys the state hides
+    parent: Option<(Box<Iter<'a>>, &'a HashSet<String>)>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a String, &'a Value);
+    
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(entry) = self.entries.next() {
+            return Some(entry);
+        }
+        let (parent, hidden) = self.parent.as_mut()?;
+        parent.find(|(key, _)| !hidden.contains(*key))
+    }
+}
+
+impl<'a> IntoIterator for &'a SharedState {
+    type Item = (&'a String, &'a Value);
+    type IntoIter = Iter<'a>;
+    
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+impl IntoIterator for SharedState {
+    type Item = (String, Value);
+    type IntoIter = hash_map::IntoIter<String, Value>;
+    
+    fn into_iter(mut self) -> Self::IntoIter {
+        self.flatten();
+        if let Some(parent) = self.parent.take() {
+            let own = mem::take(&mut self.entries);
+            let hidden = mem::take(&mut self.hidden);
+            self.entries = parent.iter().filter(|(key, _)| !hidden.contains(*key)).map(|(key, value)| (key.clone(), value.clone())).collect();
+            self.entries.extend(own);
+        }
+        self.entries.into_iter()
+    }
+}
+
+impl FromIterator<(String, Value)> for SharedState {
+    fn from_iter<I: IntoIterator<Item = (String, Value)>>(iter: I) -> Self {
+        Self::from(iter.into_iter().collect::<HashMap<_, _>>())
+    }
+}
+
+impl Extend<(String, Value)> for SharedState {
+    fn extend<I: IntoIterator<Item = (String, Value)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.note(&key, AccessKind::Write);
+            self.set(key, value);
+        }
+    }
+}
+
+impl From<HashMap<String, Value>> for SharedState {
+    fn from(entries: HashMap<String, Value>) -> Self {
+        Self {
+            parent: None,
+            entries,
+            hidden: HashSet::new(),
+            accessor: None,
+            claims: None,
+        }
+    }
+}
+
+impl<const N: usize> From<[(String, Value); N]> for SharedState {
+    fn from(entries: [(String, Value); N]) -> Self {
+        Self::from(HashMap::from(entries))
+    }
+}
+
+impl Index<&str> for SharedState {
+    type Output = Value;
+    
+    fn index(&self, key: &str) -> &Value {
+        self.get(key).unwrap_or_else(|| panic!("no shared state entry for '{}'", key))
+    }
+}
+
+impl Index<&String> for SharedState {
+    type Output = Value;
+    
+    fn index(&self, key: &String) -> &Value {
+        &self[key.as_str()]
+    }
+}
+
+impl PartialEq for SharedState {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().all(|(key, value)| other.lookup(key) == Some(value))
+    }
+}
+
+impl Eq for SharedState {}
+
+impl fmt::Debug for SharedState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl Serialize for SharedState {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_map(self.iter())
+    }
+}
+
+impl<'de> Deserialize<'de> for SharedState {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        HashMap::deserialize(deserializer).map(Self::from)
+    }
+}
+
+/// Merge `other` into `target`, resolving keys present in both with differing values by `policy`
+///
+/// Keys only in `other` are always copied. Nothing is written when resolving any key fails.
+pub fn merge_into(target: &mut SharedState, other: SharedState, policy: &MergePolicy) -> Result<()> {
+    let mut resolved = Vec::with_capacity(other.len());
+    for (key, value) in other {
+        let value = match target.get(&key) {
+            Some(existing) if *existing != value => policy.resolve(&key, existing, value)?,
+            _ => value,
+        };
+        resolved.push((key, value));
+    }
+    
+    target.extend(resolved);
+    Ok(())
+}
+
+/// Merge the writes and removals of states forked from `parent` back into it in batch order
+///
+/// Only changes of different items conflict with each other; an item's change always replaces the parent's value.
+/// A removal conflicting with a write keeps the later or earlier change under `LastWins` and `FirstWins`
+/// and fails otherwise. Under `Append`, lists that several items extended keep every item's additions,
+/// even identical ones. Nothing is written to `parent` when resolving any conflict fails.
+pub fn merge_overlays(parent: &mut SharedState, overlays: Vec<SharedState>, policy: &MergePolicy) -> Result<()> {
+    let appends = matches!(policy, MergePolicy::Append);
+    let mut merged: HashMap<String, Option<Value>> = HashMap::new();
+    for overlay in overlays {
+        for (key, incoming) in overlay.into_changes() {
+            let change = match merged.get(&key) {
+                Some(existing) if appends || *existing != incoming => policy.resolve_change(&key, parent.lookup(&key), existing, incoming)?,
+                _ => incoming,
+            };
+            merged.insert(key, change);
+        }
+    }
+    
+    parent.apply(merged.into_iter().collect());
+    parent.flatten();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    
+    use super::*;
+    
+    fn state(entries: &[(&str, Value)]) -> SharedState {
+        entries.iter().map(|(k, v)| (k.to_string(), v.clone())).collect()
+    }
+    
+    #[test]
+    fn forks_read_through_and_keep_their_own_changes() {
+        let mut parent = state(&[("doc", json!("large")), ("count", json!(1)), ("stale", json!(true))]);
+        let mut item = parent.fork();
+        assert_eq!(item.get("doc"), Some(&json!("large")));
+        item.insert("count".to_string(), json!(2));
+        item.insert("result".to_string(), json!("done"));
+        assert_eq!(item.remove("stale"), Some(json!(true)));
+        assert_eq!(item, state(&[("doc", json!("large")), ("count", json!(2)), ("result", json!("done"))]));
+        assert_eq!(item.len(), 3);
+        assert_eq!(parent, state(&[("doc", json!("large")), ("count", json!(1)), ("stale", json!(true))]));
+        
+        merge_overlays(&mut parent, vec![item], &MergePolicy::LastWins).unwrap();
+        assert_eq!(parent, state(&[("doc", json!("large")), ("count", json!(2)), ("result", json!("done"))]));
+        assert!(parent.parent.is_none(), "the snapshot is folded back in once no item holds it");
+    }
+    
+    #[test]
+    fn nested_forks_merge_back_level_by_level() {
+        let mut parent = state(&[("items", json!([])), ("seen", json!(0))]);
+        let mut item = parent.fork();
+        item.insert("seen".to_string(), json!(1));
+        let mut inner = item.fork();
+        inner.get_mut("items").unwrap().as_array_mut().unwrap().push(json!("a"));
+        inner.remove("seen");
+        assert_eq!(item["seen"], json!(1));
+        
+        merge_overlays(&mut item, vec![inner], &MergePolicy::LastWins).unwrap();
+        merge_overlays(&mut parent, vec![item], &MergePolicy::LastWins).unwrap();
+        assert_eq!(parent, state(&[("items", json!(["a"]))]));
+    }
+    
+    #[test]
+    fn removals_conflict_with_writes_of_other_items() {
+        let parent = state(&[("draft", json!("v1"))]);
+        let items = |parent: &mut SharedState| {
+            let (mut remover, mut writer) = (parent.fork(), parent.fork());
+            remover.remove("draft");
+            writer.insert("draft".to_string(), json!("v2"));
+            vec![remover, writer]
+        };
+        for (policy, draft) in [(MergePolicy::LastWins, Some(json!("v2"))), (MergePolicy::FirstWins, None)] {
+            let mut merged = parent.clone();
+            let overlays = items(&mut merged);
+            merge_overlays(&mut merged, overlays, &policy).unwrap();
+            assert_eq!(merged.get("draft"), draft.as_ref(), "{:?}", policy);
+        }
+        
+        let mut merged = parent.clone();
+        let overlays = items(&mut merged);
+        let err = merge_overlays(&mut merged, overlays, &MergePolicy::custom(|_, _, incoming| Ok(incoming.clone()))).unwrap_err();
+        assert!(err.to_string().contains("removal of 'draft'"), "{}", err);
+        assert_eq!(merged, parent);
+    }
+    
+    /// Three items writing "winner", and one of them "own"
+    fn overlays() -> Vec<SharedState> {
+        vec![
+            state(&[("winner", json!("first"))]),
+            state(&[("winner", json!("second")), ("own", json!(true))]),
+            state(&[("winner", json!("third"))]),
+        ]
+    }
+    
+    #[test]
+    fn conflicts_resolve_in_batch_order() {
+        let parent = state(&[("winner", json!("parent")), ("kept", json!(1))]);
+        let cases = [
+            (MergePolicy::LastWins, json!("third")),
+            (MergePolicy::FirstWins, json!("first")),
+            (MergePolicy::custom(|_, existing, incoming| Ok(json!(format!("{}+{}", existing.as_str().unwrap(), incoming.as_str().unwrap())))), json!("first+second+third")),
+        ];
+        for (policy, winner) in cases {
+            let mut merged = parent.clone();
+            merge_overlays(&mut merged, overlays(), &policy).unwrap();
+            assert_eq!(merged, state(&[("winner", winner), ("own", json!(true)), ("kept", json!(1))]), "{:?}", policy);
+        }
+    }
+    
+    #[test]
+    fn a_failing_merge_writes_nothing() {
+        let parent = state(&[("winner", json!("parent"))]);
+        let mut merged = parent.clone();
+        let err = merge_overlays(&mut merged, overlays(), &MergePolicy::Fail).unwrap_err();
+        assert!(err.to_string().contains("'winner'"), "{}", err);
+        assert_eq!(merged, parent);
+        
+        let same = vec![state(&[("winner", json!("same"))]), state(&[("winner", json!("same"))])];
+        merge_overlays(&mut merged, same, &MergePolicy::Fail).unwrap();
+        assert_eq!(merged["winner"], json!("same"));
+    }
+    
+    #[test]
+    fn appending_keeps_every_items_additions_and_refuses_other_conflicts() {
+        let parent = state(&[("log", json!(["start"])), ("title", json!("draft"))]);
+        let mut merged = parent.clone();
+        let mut items: Vec<SharedState> = (0..3).map(|_| merged.fork()).collect();
+        for (i, item) in items.iter_mut().enumerate() {
+            item.get_mut("log").unwrap().as_array_mut().unwrap().push(json!(if i == 1 { "same" } else { "other" }));
+        }
+        items[0].get_mut("log").unwrap().as_array_mut().unwrap().push(json!("same"));
+        merge_overlays(&mut merged, items, &MergePolicy::Append).unwrap();
+        assert_eq!(merged["log"], json!(["start", "other", "same", "same", "other"]));
+        
+        let mut merged = parent.clone();
+        let (mut first, mut second) = (merged.fork(), merged.fork());
+        first.insert("title".to_string(), json!("one"));
+        second.insert("title".to_string(), json!("two"));
+        let err = merge_overlays(&mut merged, vec![first, second], &MergePolicy::Append).unwrap_err();
+        assert!(err.to_string().contains("'title'"), "{}", err);
+        assert_eq!(merged, parent);
+    }
+    
+    #[test]
+    fn racing_forks_and_their_forks_claim_against_each_other() {
+        let mut parent = SharedState::new();
+        let mut racers = parent.racing_forks(2);
+        let mut nested = racers[0].racing_forks(1).remove(0);
+        let mut plain = racers[1].fork();
+        assert!(nested.claim("owner", None, json!("nested")));
+        assert!(!plain.claim("owner", None, json!("plain")), "a plain fork races as the fork it came from");
+        assert!(!racers[1].claim("owner", None, json!("second")));
+        assert!(racers[1].claim("owner", Some(&json!("nested")), json!("second")));
+        assert!(parent.claim("owner", None, json!("parent")), "the state that forked the racers is not one of them");
+    }
+    
+    #[test]
+    fn merging_states_combines_plain_values_and_payloads() {
+        let target = state(&[("count", json!(1)), ("title", json!("draft")), ("doc", json!({"tags": ["a"], "meta": {"pages": 2}}))]);
+        let other = state(&[("count", json!(1)), ("title", json!("final")), ("doc", json!({"tags": ["b"], "meta": {"pages": 2}})), ("new", json!([1, 2]))]);
+        let union = MergePolicy::custom(|key, existing, incoming| match (existing, incoming) {
+            (Value::Object(existing), Value::Object(incoming)) => {
+                let mut merged = existing.clone();
+                for (field, value) in incoming {
+                    match (merged.get_mut(field), value) {
+                        (Some(Value::Array(items)), Value::Array(more)) => items.extend(more.iter().cloned()),
+                        _ => {
+                            merged.insert(field.clone(), value.clone());
+                        },
+                    }
+                }
+                Ok(Value::Object(merged))
+            },
+            _ => Err(Error::InvalidOperation(format!("Cannot combine '{}'", key))),
+        });
+        
+        let mut merged = target.clone();
+        let err = merge_into(&mut merged, other.clone(), &union).unwrap_err();
+        assert!(err.to_string().contains("'title'"), "{}", err);
+        assert_eq!(merged, target);
+        
+        let mut other = other;
+        other.remove("title");
+        merge_into(&mut merged, other.clone(), &union).unwrap();
+        assert_eq!(merged["doc"], json!({"tags": ["a", "b"], "meta": {"pages": 2}}));
+        assert_eq!(merged["new"], json!([1, 2]), "keys only in the other state are copied");
+        assert_eq!(merged["title"], json!("draft"));
+        
+        for (policy, doc) in [(MergePolicy::LastWins, &other["doc"]), (MergePolicy::FirstWins, &target["doc"])] {
+            let mut merged = target.clone();
+            merge_into(&mut merged, other.clone(), &policy).unwrap();
+            assert_eq!(&merged["doc"], doc);
+            assert_eq!(merged["new"], json!([1, 2]));
+        }
+    }
+}
\ No newline at end of file
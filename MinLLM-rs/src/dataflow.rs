@@ -0,0 +1,154 @@
+use std::collections::HashSet;
+use serde::{Deserialize, Serialize};
+
+use crate::successors::GraphNode;
+
+/// A shared state key a node reads or writes
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeySpec {
+    /// The shared state key
+    pub key: String,
+    
+    /// Expected JSON type: "string", "number", "integer", "boolean", "array", "object" or "null"
+    pub json_type: Option<String>,
+}
+
+impl KeySpec {
+    /// A key holding a value of any type
+    pub fn new(key: &str) -> Self {
+        Self {
+            key: key.to_string(),
+            json_type: None,
+        }
+    }
+    
+    /// Also declare the JSON type of the value
+    pub fn of_type(mut self, json_type: &str) -> Self {
+        self.json_type = Some(json_type.to_string());
+        self
+    }
+}
+
+/// A key a node reads that no upstream node writes and the caller does not provide
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnsatisfiedRead {
+    /// Name of the reading node
+    pub node: String,
+    
+    /// The key read
+    pub key: String,
+    
+    /// Nodes that write the key but do not run before the reader
+    pub writers: Vec<String>,
+}
+
+/// A key whose declared type differs between an upstream writer and a reader
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TypeConflict {
+    /// The key
+    pub key: String,
+    
+    /// Name of the reading node
+    pub reader: String,
+    
+    /// Type the reader expects
+    pub expected: String,
+    
+    /// Name of the writing node
+    pub writer: String,
+    
+    /// Type the writer declares
+    pub found: String,
+}
+
+/// Outcome of `Flow::check_dataflow`
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DataflowReport {
+    /// Reads nothing upstream satisfies, in graph order
+    pub unsatisfied: Vec<UnsatisfiedRead>,
+    
+    /// Reads whose declared type disagrees with an upstream write
+    pub type_conflicts: Vec<TypeConflict>,
+}
+
+impl DataflowReport {
+    /// Whether every read is satisfied with a matching type
+    pub fn is_ok(&self) -> bool {
+        self.unsatisfied.is_empty() && self.type_conflicts.is_empty()
+    }
+}
+
+/// Check the declared reads of every node against the writes of the nodes that can run before it
+///
+/// A node's upstream is every node with a path to it; its own writes only count on a later pass
+/// through a cycle, so they never satisfy its reads.
+pub(crate) fn check(graph: &[GraphNode], initial_keys: &[&str]) -> DataflowReport {
+    let mut report = DataflowReport::default();
+    let mut parents: Vec<Vec<usize>> = vec![Vec::new(); graph.len()];
+    for (idx, entry) in graph.iter().enumerate() {
+        let targets = entry.edges.iter().map(|(_, next)| *next).chain(entry.conditional.iter().map(|(_, next)| *next));
+        for next in targets {
+            parents[next].push(idx);
+        }
+    }
+    
+    for (idx, entry) in graph.iter().enumerate() {
+        let reads = entry.node.reads();
+        if reads.is_empty() {
+            continue;
+        }
+        let upstream = ancestors(&parents, idx);
+        let reader = entry.node.name();
+        
+        for read in reads {
+            if initial_keys.contains(&read.key.as_str()) {
+                continue;
+            }
+            let mut satisfied = false;
+            let mut writers = Vec::new();
+            for (writer_idx, writer) in graph.iter().enumerate() {
+                let Some(write) = writer.node.writes().iter().find(|w| w.key == read.key) else {
+                    continue;
+                };
+                if !upstream.contains(&writer_idx) {
+                    writers.push(writer.node.name());
+                    continue;
+                }
+                satisfied = true;
+                if let (Some(expected), Some(found)) = (&read.json_type, &write.json_type) {
+                    if !type_satisfies(found, expected) {
+                        report.type_conflicts.push(TypeConflict {
+                            key: read.key.clone(),
+                            reader: reader.clone(),
+                            expected: expected.clone(),
+                            writer: writer.node.name(),
+                            found: found.clone(),
+                        });
+                    }
+                }
+            }
+            if !satisfied {
+                report.unsatisfied.push(UnsatisfiedRead { node: reader.clone(), key: read.key.clone(), writers });
+            }
+        }
+    }
+    
+    report
+}
+
+/// Whether values written as `found` are acceptable where `expected` is read
+fn type_satisfies(found: &str, expected: &str) -> bool {
+    found == expected || (found == "integer" && expected == "number")
+}
+
+/// Indices of the nodes with a path to `idx`, excluding `idx` itself
+fn ancestors(parents: &[Vec<usize>], idx: usize) -> HashSet<usize> {
+    let mut seen = HashSet::new();
+    let mut stack = parents[idx].clone();
+    while let Some(parent) = stack.pop() {
+        if parent != idx && seen.insert(parent) {
+            stack.extend(parents[parent].iter().copied());
+        }
+    }
+    seen
+}
\ No newline at end of file
@@ -0,0 +1,88 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::base::Node;
+use crate::successors;
+use crate::error::{Error, Result};
+
+tokio::task_local! {
+    /// Deadline of the run the current task belongs to
+    static DEADLINE: Instant;
+}
+
+/// The deadline of the run the calling exec belongs to, if it has one
+pub fn current_deadline() -> Option<Instant> {
+    DEADLINE.try_with(|deadline| *deadline).ok()
+}
+
+/// Await `fut` as a run that must end by `deadline`, or by the deadline of the enclosing run if that is earlier
+pub(crate) async fn scope<F: Future>(deadline: Instant, fut: F) -> F::Output {
+    let deadline = current_deadline().map_or(deadline, |outer| outer.min(deadline));
+    DEADLINE.scope(deadline, fut).await
+}
+
+/// Fail with `Error::DeadlineExceeded` when the current run is out of time before `next` runs
+///
+/// The error lists every node reachable from `next`, which includes the nodes the run never got to.
+pub(crate) fn checkpoint(next: &Arc<dyn Node>) -> Result<()> {
+    match current_deadline() {
+        Some(deadline) if Instant::now() >= deadline => Err(Error::DeadlineExceeded {
+            remaining_nodes: remaining_from(next),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Make a node's deadline failure list every node reachable from `node` as remaining, leaving other errors as they are
+pub(crate) fn cut_short(error: Error, node: &Arc<dyn Node>) -> Error {
+    if !error.is_deadline_exceeded() {
+        return error;
+    }
+    Error::DeadlineExceeded { remaining_nodes: remaining_from(node) }.in_node(node.name())
+}
+
+/// Names of every node reachable from `next`, `next` first
+fn remaining_from(next: &Arc<dyn Node>) -> Vec<String> {
+    successors::reachable(next.clone()).iter().map(|entry| entry.node.name()).collect()
+}
+
+/// Fail with `Error::DeadlineExceeded` when the current run is out of time before the next item of `node` runs
+pub(crate) fn check_item(node: impl FnOnce() -> String) -> Result<()> {
+    match current_deadline() {
+        Some(deadline) if Instant::now() >= deadline => Err(Error::DeadlineExceeded { remaining_nodes: vec![node()] }),
+        _ => Ok(()),
+    }
+}
+
+/// Await an exec attempt of `node` within `timeout` and the time left to the current run
+///
+/// Fails without starting the attempt when the run is already out of time. An attempt cut short
+/// by the run's deadline fails with `Error::DeadlineExceeded`, one cut short by `timeout` with
+/// `Error::Timeout`.
+pub(crate) async fn limit<T>(
+    node: impl Fn() -> String,
+    timeout: Option<Duration>,
+    attempt: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    let Some(deadline) = current_deadline() else {
+        return match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, attempt).await.unwrap_or(Err(Error::Timeout(timeout))),
+            None => attempt.await,
+        };
+    };
+    
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    if remaining.is_zero() {
+        return Err(Error::DeadlineExceeded { remaining_nodes: vec![node()] });
+    }
+    match timeout {
+        Some(timeout) if timeout < remaining => {
+            tokio::time::timeout(timeout, attempt).await.unwrap_or(Err(Error::Timeout(timeout)))
+        },
+        _ => tokio::time::timeout(remaining, attempt)
+            .await
+            .unwrap_or_else(|_| Err(Error::DeadlineExceeded { remaining_nodes: vec![node()] })),
+    }
+}
\ No newline at end of file
@@ -0,0 +1,80 @@
+use std::future::Future;
+use futures::future;
+use futures::stream::{self, FuturesUnordered, Stream, StreamExt};
+
+/// How a parallel batch schedules its items
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Determinism {
+    /// Run every item concurrently
+    #[default]
+    Concurrent,
+    
+    /// Run items one at a time in a pseudo-random order derived from the seed
+    ///
+    /// The same seed always produces the same order, which makes parallel batches reproducible in tests.
+    Seeded(u64),
+}
+
+impl Determinism {
+    /// The order items are started in for a batch of `len` items
+    pub fn order(&self, len: usize) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..len).collect();
+        if let Determinism::Seeded(seed) = *self {
+            let mut state = seed;
+            for i in (1..len).rev() {
+                let j = (splitmix64(&mut state) % (i as u64 + 1)) as usize;
+                order.swap(i, j);
+            }
+        }
+        order
+    }
+    
+    /// Drive batch futures to completion, returning their outputs in batch order
+    pub(crate) async fn run_all<F: Future>(&self, futures: Vec<F>) -> Vec<F::Output> {
+        if *self == Determinism::Concurrent {
+            return future::join_all(futures).await;
+        }
+        
+        let order = self.order(futures.len());
+        let mut pending: Vec<Option<F>> = futures.into_iter().map(Some).collect();
+        let mut outputs: Vec<Option<F::Output>> = pending.iter().map(|_| None).collect();
+        for i in order {
+            if let Some(fut) = pending[i].take() {
+                outputs[i] = Some(fut.await);
+            }
+        }
+        outputs.into_iter().flatten().collect()
+    }
+    
+    /// Stream the outputs of batch futures with their batch positions, in the order they finish
+    ///
+    /// Seeded batches run one item at a time, so they finish in the order they start. Dropping
+    /// the stream drops the items still running, and those not started yet never start.
+    pub(crate) fn unordered<F: Future>(&self, futures: impl IntoIterator<Item = F>) -> impl Stream<Item = (usize, F::Output)> {
+        if *self == Determinism::Concurrent {
+            let running: FuturesUnordered<_> = futures
+                .into_iter()
+                .enumerate()
+                .map(|(i, fut)| async move { (i, fut.await) })
+                .collect();
+            return running.left_stream();
+        }
+        
+        let mut pending: Vec<Option<F>> = futures.into_iter().map(Some).collect();
+        stream::iter(self.order(pending.len()))
+            .filter_map(move |i| {
+                let fut = pending[i].take();
+                async move { Some((i, fut?.await)) }
+            })
+            .right_stream()
+    }
+}
+
+/// Advance a splitmix64 generator
+pub(crate) fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
\ No newline at end of file
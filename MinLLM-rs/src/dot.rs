@@ -0,0 +1,166 @@
+use std::fmt::Write;
+use std::sync::Arc;
+
+use crate::base::Node;
+use crate::successors;
+
+/// Where edges to or from a node of the drawing attach
+struct Anchor {
+    /// Id of the drawn node edges attach to
+    id: String,
+    
+    /// Cluster the edges are clipped to, for nested flows
+    cluster: Option<String>,
+}
+
+/// A Graphviz DOT description being written
+struct Dot {
+    /// The description so far
+    out: String,
+    
+    /// Number of nodes drawn so far
+    nodes: usize,
+    
+    /// Number of clusters drawn so far
+    clusters: usize,
+}
+
+/// Describe the graph reachable from `start` in Graphviz DOT, as the graph `name`
+///
+/// Nodes are labeled with their name and, when it differs, their type. Edges are labeled with
+/// their action, conditional edges are dashed and labeled with their description, and the
+/// start node of every graph is drawn with a double border. Placeholder nodes such as `NoOpNode`
+/// are drawn dashed, and nested flows are drawn as clusters.
+pub(crate) fn render(name: &str, start: Arc<dyn Node>) -> String {
+    let mut dot = Dot {
+        out: String::new(),
+        nodes: 0,
+        clusters: 0,
+    };
+    let _ = writeln!(dot.out, "digraph {} {{", quote(name));
+    dot.out.push_str("    compound=true;\n");
+    dot.out.push_str("    node [shape=box];\n");
+    dot.graph(start, 1);
+    dot.out.push('}');
+    dot.out
+}
+
+impl Dot {
+    /// Draw the graph reachable from `start` at `depth`, returning the anchor of `start`
+    fn graph(&mut self, start: Arc<dyn Node>, depth: usize) -> Anchor {
+        let indent = "    ".repeat(depth);
+        let graph = successors::reachable(start);
+        
+        let mut anchors = Vec::with_capacity(graph.len());
+        for (idx, entry) in graph.iter().enumerate() {
+            let anchor = match entry.node.nested_start() {
+                Some(inner) => {
+                    let cluster = format!("cluster_{}", self.clusters);
+                    self.clusters += 1;
+                    let _ = writeln!(self.out, "{}subgraph {} {{", indent, cluster);
+                    let _ = writeln!(self.out, "{}    label={};", indent, quote(&entry.node.name()));
+                    let inner = self.graph(inner, depth + 1);
+                    let _ = writeln!(self.out, "{}}}", indent);
+                    Anchor { id: inner.id, cluster: Some(cluster) }
+                },
+                None => {
+                    let id = format!("n{}", self.nodes);
+                    self.nodes += 1;
+                    let _ = write!(self.out, "{}{} [label={}", indent, id, quote(&label(entry.node.as_ref())));
+                    if idx == 0 {
+                        self.out.push_str(", peripheries=2");
+                    }
+                    if entry.node.is_placeholder() {
+                        self.out.push_str(", style=dashed");
+                    }
+                    self.out.push_str("];\n");
+                    Anchor { id, cluster: None }
+                },
+            };
+            anchors.push(anchor);
+        }
+        
+        for (from, entry) in anchors.iter().zip(&graph) {
+            for (action, to) in &entry.edges {
+                self.edge(&indent, from, &anchors[*to], action, false);
+            }
+            for (description, to) in &entry.conditional {
+                self.edge(&indent, from, &anchors[*to], description.as_deref().unwrap_or("if"), true);
+            }
+        }
+        
+        anchors.into_iter().next().expect("a graph has its start node")
+    }
+    
+    /// Draw an edge labeled `label`, dashed when `conditional`
+    fn edge(&mut self, indent: &str, from: &Anchor, to: &Anchor, label: &str, conditional: bool) {
+        let _ = write!(self.out, "{}{} -> {} [label={}", indent, from.id, to.id, quote(label));
+        if conditional {
+            self.out.push_str(", style=dashed");
+        }
+        if let Some(cluster) = &from.cluster {
+            let _ = write!(self.out, ", ltail={}", cluster);
+        }
+        if let Some(cluster) = &to.cluster {
+            let _ = write!(self.out, ", lhead={}", cluster);
+        }
+        self.out.push_str("];\n");
+    }
+}
+
+/// The label of a drawn node: its name, followed by its type when the name isn't already that
+fn label(node: &dyn Node) -> String {
+    let (name, type_name) = (node.name(), node.type_name());
+    if name == type_name {
+        name
+    } else {
+        format!("{}\n({})", name, type_name)
+    }
+}
+
+/// `text` as a quoted DOT string
+fn quote(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::flow::Flow;
+    use crate::nodes::NoOpNode;
+    use crate::node::Node;
+    
+    use super::*;
+    
+    #[test]
+    fn draws_placeholders_dashed_and_nested_flows_as_clusters() {
+        let start: Arc<dyn crate::base::Node> = Arc::new(NoOpNode::new().with_name("landmark"));
+        let inner: Arc<dyn crate::base::Node> = Arc::new(Node::new(1, 0).with_name("work"));
+        let nested: Arc<dyn crate::base::Node> = Arc::new(Flow::new(inner).with_name("inner \"flow\""));
+        start.add_successor(nested.clone(), "go").unwrap();
+        nested.add_successor(start.clone(), "default").unwrap();
+        
+        let expected = r#"digraph "outer" {
+    compound=true;
+    node [shape=box];
+    n0 [label="landmark\n(NoOpNode)", peripheries=2, style=dashed];
+    subgraph cluster_0 {
+        label="inner \"flow\"";
+        n1 [label="work\n(Node)", peripheries=2];
+    }
+    n0 -> n1 [label="go", lhead=cluster_0];
+    n1 -> n0 [label="default", ltail=cluster_0];
+}"#;
+        assert_eq!(render("outer", start), expected);
+    }
+}
\ No newline at end of file
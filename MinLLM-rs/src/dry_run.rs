@@ -0,0 +1,91 @@
+use std::future::Future;
+use std::sync::Arc;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::base::Action;
+use crate::error::Result;
+
+/// Stand-in for exec during a dry run, called with the node name and the prep result
+pub type DryRunStub = Arc<dyn Fn(&str, &Value) -> Result<Value> + Send + Sync>;
+
+tokio::task_local! {
+    /// Dry run the current flow is part of, if any
+    static DRY_RUN: DryRun;
+}
+
+/// State of an active dry run
+struct DryRun {
+    /// Stub replacing exec, echoing the prep result when unset
+    stub: Option<DryRunStub>,
+    
+    /// Steps recorded so far
+    steps: Arc<Mutex<Vec<DryRunStep>>>,
+}
+
+/// A node step taken during a dry run
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DryRunStep {
+    /// Name of the node
+    pub node: String,
+    
+    /// Prep result exec would have been called with
+    pub prep_res: Value,
+    
+    /// Action post chose from the stubbed exec result
+    pub action: Action,
+}
+
+/// What a flow did during a dry run
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct DryRunReport {
+    /// Node steps in the order they ran, including those of nested flows
+    pub steps: Vec<DryRunStep>,
+    
+    /// Action the flow returned
+    pub action: Action,
+}
+
+impl DryRunReport {
+    /// Names of the nodes that ran, in order
+    pub fn nodes(&self) -> Vec<&str> {
+        self.steps.iter().map(|step| step.node.as_str()).collect()
+    }
+}
+
+/// Whether the current node runs as part of a dry run
+pub(crate) fn is_active() -> bool {
+    DRY_RUN.try_with(|_| ()).is_ok()
+}
+
+/// The default stand-in for exec: the dry run's stub, or the prep result echoed back
+pub(crate) fn stub_exec(name: &str, prep_res: &Value) -> Result<Value> {
+    match DRY_RUN.try_with(|dry_run| dry_run.stub.clone()).ok().flatten() {
+        Some(stub) => stub(name, prep_res),
+        None => Ok(prep_res.clone()),
+    }
+}
+
+/// Record a node step of the current dry run
+pub(crate) fn record(node: String, prep_res: Value, action: &Action) {
+    let _ = DRY_RUN.try_with(|dry_run| {
+        dry_run.steps.lock().push(DryRunStep { node, prep_res, action: action.clone() });
+    });
+}
+
+/// Run `f` as a dry run, returning what it recorded
+pub(crate) fn run(stub: Option<DryRunStub>, f: impl FnOnce() -> Result<Action>) -> Result<DryRunReport> {
+    let steps = Arc::new(Mutex::new(Vec::new()));
+    let action = DRY_RUN.sync_scope(DryRun { stub, steps: steps.clone() }, f)?;
+    let steps = std::mem::take(&mut *steps.lock());
+    Ok(DryRunReport { steps, action })
+}
+
+/// Await `fut` as a dry run, returning what it recorded
+pub(crate) async fn run_async(stub: Option<DryRunStub>, fut: impl Future<Output = Result<Action>>) -> Result<DryRunReport> {
+    let steps = Arc::new(Mutex::new(Vec::new()));
+    let action = DRY_RUN.scope(DryRun { stub, steps: steps.clone() }, fut).await?;
+    let steps = std::mem::take(&mut *steps.lock());
+    Ok(DryRunReport { steps, action })
+}
\ No newline at end of file
@@ -1,5 +1,7 @@
 use thiserror::Error;
 
+use crate::validation::ValidationReport;
+
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Error, Debug)]
@@ -13,12 +15,63 @@ pub enum Error {
     #[error("Invalid action: {0}")]
     InvalidAction(String),
     
+    #[error("Invalid flow:\n{0}")]
+    InvalidFlow(ValidationReport),
+    
     #[error("Missing successor for action: {0}")]
     MissingSuccessor(String),
     
     #[error("Invalid operation: {0}")]
     InvalidOperation(String),
     
+    #[error("Serialization error: {0}")]
+    Serialization(String),
+    
+    #[error("Missing shared state key: {0}")]
+    MissingKey(String),
+    
+    #[error("Type mismatch for '{key}': expected {expected}, found {found}")]
+    TypeMismatch {
+        key: String,
+        expected: String,
+        found: String,
+    },
+    
+    #[error("Execution timed out after {0:?}")]
+    Timeout(std::time::Duration),
+    
+    #[error("HTTP request failed with status {status}")]
+    HttpStatus {
+        status: u16,
+        response: serde_json::Value,
+    },
+    
+    #[error("Capacity exceeded: {0}")]
+    CapacityExceeded(String),
+    
+    #[error("Run cancelled")]
+    Cancelled,
+    
+    #[error("Deadline exceeded before running: {}", .remaining_nodes.join(", "))]
+    DeadlineExceeded {
+        remaining_nodes: Vec<String>,
+    },
+    
+    #[error("{} batch item(s) failed: {}", .failures.len(), describe_failures(.failures))]
+    BatchFailed {
+        failures: Vec<(usize, Error)>,
+        results: Vec<(usize, serde_json::Value)>,
+    },
+    
+    #[error("Node '{node}' failed: {source}")]
+    InNode {
+        node: String,
+        source: Box<Error>,
+    },
+    
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    
     #[cfg(feature = "python")]
     #[error("Python error: {0}")]
     Python(#[from] pyo3::PyErr),
@@ -28,4 +81,34 @@ pub enum Error {
     
     #[error("Unknown error: {0}")]
     Unknown(String),
-} 
\ No newline at end of file
+}
+
+impl Error {
+    /// Attribute this error to the node named `node`
+    pub fn in_node(self, node: String) -> Self {
+        Error::InNode { node, source: Box::new(self) }
+    }
+    
+    /// Whether the run stopped because its cancellation token was cancelled
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self.root(), Error::Cancelled)
+    }
+    
+    /// Whether the run stopped because it ran out of time
+    pub fn is_deadline_exceeded(&self) -> bool {
+        matches!(self.root(), Error::DeadlineExceeded { .. })
+    }
+    
+    /// The underlying error, without the node names wrapped around it
+    pub fn root(&self) -> &Error {
+        match self {
+            Error::InNode { source, .. } => source.root(),
+            other => other,
+        }
+    }
+}
+
+/// List batch failures as `index: error` pairs for the `BatchFailed` message
+fn describe_failures(failures: &[(usize, Error)]) -> String {
+    failures.iter().map(|(index, e)| format!("{}: {}", index, e)).collect::<Vec<_>>().join("; ")
+}
\ No newline at end of file
@@ -1,12 +1,37 @@
-use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
+use std::collections::{HashMap, HashSet};
+use std::iter;
+use std::sync::Arc;
+use parking_lot::RwLock;
 use serde_json::Value;
-use log::warn;
+use log::{debug, warn};
 
-use crate::base::{BaseNode, Node, SharedState, Action};
+use crate::base::{BaseNode, short_type_name, Node, SharedState, Action, ParamMap, DEFAULT_ACTION};
+use crate::successors::{self, Successors, GraphNode};
+use crate::hooks::{self, NodeHooks};
+use crate::cancel;
+use crate::deadline;
+use crate::step_guard::{self, DEFAULT_MAX_STEPS};
+use crate::dry_run::{self, DryRunStub, DryRunReport};
+use crate::trace::{self, FlowTrace};
+use crate::dataflow::{self, DataflowReport};
+use crate::dot;
+use crate::validation::{self, ValidationReport};
+use crate::param_spec::{ParamSpec, check_params, resolve_params};
+use crate::compiled_flow::CompiledFlow;
+use crate::async_node::AsyncNodeTrait;
+use crate::async_flow::AsyncFlow;
+use crate::namespace::Namespace;
+use crate::watch::StateWatchers;
+use crate::history::{StateHistory, StateEvent, EntryMeta};
+use crate::state_limit::StateLimit;
+use crate::nodes::fn_node::{PrepFn, PostFn};
 use crate::error::{Error, Result};
 
 /// A workflow that orchestrates execution through nodes
+///
+/// Nodes are held by `Arc`, so a flow always runs the very nodes that were wired into it.
+/// Cloning a flow is shallow: the clone runs the same node graph and shares its params,
+/// name, watchers and history with the original.
 #[derive(Clone)]
 pub struct Flow {
     /// Base node implementation
@@ -14,6 +39,30 @@ pub struct Flow {
     
     /// The starting node of the flow
     pub start: Arc<dyn Node>,
+    
+    /// Namespace the flow's nodes run in when the flow is nested in another flow
+    namespace: Option<Namespace>,
+    
+    /// Subscribers to shared state keys
+    watchers: StateWatchers,
+    
+    /// Record of the changes each node step made, when enabled
+    history: Option<Arc<StateHistory>>,
+    
+    /// Bound on the number of shared state entries, when set
+    limit: Option<Arc<StateLimit>>,
+    
+    /// Hooks applied to every node the flow runs, when set
+    default_hooks: Option<NodeHooks>,
+    
+    /// Stand-in for exec during a dry run, when set
+    pub(crate) dry_run_stub: Option<DryRunStub>,
+    
+    /// Most node steps a run takes before it is stopped, when limited
+    pub(crate) max_steps: Option<usize>,
+    
+    /// Whether every run is validated first
+    pub(crate) strict: bool,
 }
 
 impl Flow {
@@ -22,87 +71,634 @@ impl Flow {
         Self {
             base: BaseNode::new(),
             start,
+            namespace: None,
+            watchers: StateWatchers::default(),
+            history: None,
+            limit: None,
+            default_hooks: None,
+            dry_run_stub: None,
+            max_steps: Some(DEFAULT_MAX_STEPS),
+            strict: false,
         }
     }
     
-    /// Get the next node based on the current node and action
-    pub fn get_next_node(&self, curr: Arc<dyn Node>, action: Action) -> Option<Arc<dyn Node>> {
-        let action_key = action.unwrap_or_else(|| "default".to_string());
+    /// Name the flow in logs, errors and traces
+    pub fn with_name(self, name: &str) -> Self {
+        self.set_name(name);
+        self
+    }
+    
+    /// Apply `hooks` to every node the flow runs, including the nodes of nested flows
+    ///
+    /// A node's own hooks take precedence; the defaults only fill the hooks it leaves unset.
+    pub fn with_default_hooks(mut self, hooks: NodeHooks) -> Self {
+        self.default_hooks = Some(hooks);
+        self
+    }
+    
+    /// Stop a run with an error once it has taken `max_steps` node steps, or never with `None`
+    ///
+    /// Defaults to `DEFAULT_MAX_STEPS`, so a loop that never ends fails instead of spinning.
+    /// Steps of nested flows count toward their own limit, and each branch step counts.
+    pub fn with_max_steps(mut self, max_steps: Option<usize>) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+    
+    /// Groups of reachable nodes that can loop back to each other, each in graph order
+    ///
+    /// A node with an edge to itself is a group of its own. Loops are often intentional, so
+    /// this is a report for the caller to judge rather than an error.
+    pub fn detect_cycles(&self) -> Vec<Vec<String>> {
+        let graph = self.nodes();
+        cycles(&graph)
+            .into_iter()
+            .map(|group| group.into_iter().map(|idx| graph[idx].node.name()).collect())
+            .collect()
+    }
+    
+    /// Run one node step with the flow's default hooks in place
+    ///
+    /// Also returns the step's exec result when `armed` (the node has conditional successors), null otherwise.
+    pub(crate) fn run_step(&self, node: &Arc<dyn Node>, armed: bool, shared: &mut SharedState) -> Result<(Action, Value)> {
+        let (action, exec_res) = hooks::capture_exec(armed, || {
+            NodeHooks::scope(self.default_hooks.as_ref(), || node._run(shared))
+        });
+        Ok((action.map_err(|e| e.in_node(node.name()))?, exec_res))
+    }
+    
+    /// Await one step of an async-only node with the flow's default hooks in place, as `run_step` does
+    pub(crate) async fn run_step_async(&self, node: &Arc<dyn Node>, async_node: &dyn AsyncNodeTrait, armed: bool, shared: &mut SharedState) -> Result<(Action, Value)> {
+        let step = NodeHooks::scope_async(self.default_hooks.as_ref(), async_node._run_async(shared));
+        let (action, exec_res) = hooks::capture_exec_async(armed, step).await;
+        Ok((action.map_err(|e| e.in_node(node.name()))?, exec_res))
+    }
+    
+    /// Keep the shared state within `limit` after every node step
+    pub fn with_state_limit(mut self, limit: StateLimit) -> Self {
+        self.limit = Some(Arc::new(limit));
+        self
+    }
+    
+    /// Record every change node steps make to the shared state, keeping the last `capacity` events
+    pub fn with_history(mut self, capacity: usize) -> Self {
+        self.history = Some(Arc::new(StateHistory::new(capacity)));
+        self
+    }
+    
+    /// Recorded state changes, oldest first; empty unless `with_history` was used
+    pub fn history(&self) -> Vec<StateEvent> {
+        self.history.as_ref().map(|h| h.events()).unwrap_or_default()
+    }
+    
+    /// Recorded changes to one key, oldest first
+    pub fn history_for(&self, key: &str) -> Vec<StateEvent> {
+        self.history.as_ref().map(|h| h.events_for(key)).unwrap_or_default()
+    }
+    
+    /// When a key was written and by which step; `None` unless `with_history` was used
+    pub fn metadata(&self, key: &str) -> Option<EntryMeta> {
+        self.history.as_ref().and_then(|h| h.metadata(key))
+    }
+    
+    /// Isolate the writes of the flow's nodes under `prefix` in the state the flow runs on
+    ///
+    /// Its nodes see a view of the state where "../key" reaches the enclosing flow's keys.
+    pub fn with_namespace(mut self, prefix: &str) -> Self {
+        self.namespace = Some(Namespace::new(prefix));
+        self
+    }
+    
+    /// Subscribe to changes of a shared state key made while the flow runs
+    ///
+    /// The value is checked after every node step; `None` means the key was removed.
+    /// Await `changed()` on the receiver to react to the next write.
+    pub fn watch(&self, key: &str) -> tokio::sync::watch::Receiver<Option<Value>> {
+        self.watchers.subscribe(key)
+    }
+    
+    /// Prepare the step observers for a run over `shared`
+    pub(crate) fn begin_steps(&self, shared: &SharedState) {
+        self.watchers.prime(shared);
+    }
+    
+    /// Snapshot taken before a node step, when history or a state limit needs it
+    pub(crate) fn before_step(&self, shared: &SharedState) -> Option<SharedState> {
+        (self.history.is_some() || self.limit.is_some()).then(|| shared.clone())
+    }
+    
+    /// Apply the state limit to a finished step of `node`, then report it to the history and watchers
+    pub(crate) fn after_step(&self, node: &Arc<dyn Node>, before: Option<SharedState>, shared: &mut SharedState) -> Result<()> {
+        if let Some(before) = &before {
+            if let Some(limit) = &self.limit {
+                limit.enforce(before, shared)?;
+            }
+            if let Some(history) = &self.history {
+                history.record(before, shared, Some(&node.name()));
+            }
+        }
+        self.watchers.notify(shared);
+        Ok(())
+    }
+    
+    /// Get the node the flow continues with, based on the current node and action
+    ///
+    /// When the action has several successors this is the last one; see `get_next_nodes`.
+    pub fn get_next_node(&self, curr: Arc<dyn Node>, action: Option<&str>, shared: &SharedState, exec_res: &Value) -> Option<Arc<dyn Node>> {
+        self.next_nodes::<NextSteps<_>>(&curr, action, shared, exec_res).last
+    }
+    
+    /// Get every successor for the current node and action
+    ///
+    /// Conditional successors are checked first, against the shared state and the node's exec result.
+    pub fn get_next_nodes(&self, curr: Arc<dyn Node>, action: Option<&str>, shared: &SharedState, exec_res: &Value) -> Vec<Arc<dyn Node>> {
+        self.next_nodes(&curr, action, shared, exec_res)
+    }
+    
+    /// The successors `get_next_nodes` finds, collected into `C`
+    fn next_nodes<C: FromIterator<Arc<dyn Node>>>(&self, curr: &Arc<dyn Node>, action: Option<&str>, shared: &SharedState, exec_res: &Value) -> C {
+        let action_key = action.unwrap_or(DEFAULT_ACTION);
         let successors_lock = curr.successors();
-        let successors = successors_lock.read().unwrap();
+        let successors = successors_lock.read();
         
-        let next = successors.get(&action_key).cloned();
+        if let Some(next) = successors.route(shared, exec_res) {
+            return iter::once(next.clone()).collect();
+        }
+        
+        let mut next = successors.iter_for(action_key).peekable();
         
-        if next.is_none() && !successors.is_empty() {
-            let actions: Vec<String> = successors.keys().cloned().collect();
-            warn!("Flow ends: '{}' not found in {:?}", action_key, actions);
+        if next.peek().is_none() {
+            if successors.is_empty() {
+                debug!(target: "minllm::flow", "Flow ends after {}", curr.name());
+            } else {
+                let actions: Vec<String> = successors.keys().map(|action| action.to_string()).collect();
+                warn!(target: "minllm::flow", "Flow ends after {}: '{}' not found in {:?}", curr.name(), action_key, actions);
+            }
         }
         
-        next
+        next.cloned().collect()
     }
     
-    /// Orchestrate flow through nodes
-    pub fn _orch(&self, shared: &mut SharedState, params: Option<HashMap<String, Value>>) -> Result<()> {
-        let mut curr = self.start.clone();
-        let params = params.unwrap_or_else(|| {
-            self.base.params().read().unwrap().clone()
-        });
+    /// Run the successor registered for each branch action of a node until every branch ends
+    pub fn _run_branches(&self, node: &Arc<dyn Node>, shared: &mut SharedState) -> Result<()> {
+        self.run_branches(self, node, shared)
+    }
+    
+    /// Run the successors `route` has for each branch action of the node at `at` until every branch ends
+    pub(crate) fn run_branches<R: Route>(&self, route: &R, at: &R::At, shared: &mut SharedState) -> Result<()> {
+        for start in self.branch_starts(route, at) {
+            self.walk(route, start, shared)?;
+        }
+        
+        Ok(())
+    }
+    
+    /// The successors `route` has for each branch action of the node at `at`, in branch order
+    pub(crate) fn branch_starts<R: Route>(&self, route: &R, at: &R::At) -> Vec<R::At> {
+        let node = route.node(at);
+        let branches = node.branch_actions();
+        if branches.is_empty() {
+            return Vec::new();
+        }
         
-        curr.set_params(params);
+        let mut starts = Vec::new();
+        for branch in branches {
+            let successors = route.successors_for(at, branch);
+            if successors.is_empty() {
+                warn!(target: "minllm::flow", "{}: fan-out branch '{}' has no successor", node.name(), branch);
+            }
+            starts.extend(successors);
+        }
+        starts
+    }
+    
+    /// Run nodes from `start`, following `route`, until no successor matches the returned action
+    ///
+    /// When an action has several successors, all but the last run as branches first.
+    pub(crate) fn walk<R: Route>(&self, route: &R, start: R::At, shared: &mut SharedState) -> Result<()> {
+        // State accesses between steps are the flow's own, so none are recorded for the step around it
+        let outer = shared.attribute(None);
+        let walked = self.walk_steps(route, start, shared);
+        shared.attribute(outer);
+        walked
+    }
+    
+    /// The steps of `walk`, recording each step's state accesses for its node
+    fn walk_steps<R: Route>(&self, route: &R, start: R::At, shared: &mut SharedState) -> Result<()> {
+        let mut at = start;
         
-        while let Some(node) = curr.clone().into() {
-            let action = node._run(shared)?;
-            curr = match self.get_next_node(node, action) {
-                Some(next) => next,
+        loop {
+            let curr = route.node(&at);
+            cancel::checkpoint()?;
+            deadline::checkpoint(&curr)?;
+            step_guard::step(&curr)?;
+            let before = self.before_step(shared);
+            let started = trace::start(shared);
+            shared.attribute(trace::accessor(|| curr.name()));
+            let step = self.run_step(&curr, route.has_conditional(&at), shared);
+            shared.attribute(None);
+            trace::record(started, || curr.name(), step.as_ref().map(|(action, _)| action), None, shared);
+            let (action, exec_res) = step?;
+            self.run_branches(route, &at, shared)?;
+            self.after_step(&curr, before, shared)?;
+            let next = route.next(&at, action.as_deref(), shared, &exec_res);
+            at = match next.last {
+                Some(last) => last,
                 None => break,
             };
+            for branch in next.branches {
+                self.walk(route, branch, shared)?;
+            }
         }
         
         Ok(())
     }
+    
+    /// Snapshot the flow's current topology into an index-based execution plan
+    ///
+    /// The flow is validated once here, failing with `Error::InvalidFlow` when `validate` finds errors.
+    pub fn compile(&self) -> Result<CompiledFlow> {
+        validation::enforce(self.validate()?)?;
+        Ok(CompiledFlow::new(AsyncFlow::wrap(self.clone()), self.base.params()))
+    }
+    
+    /// Replace exec with `stub` in dry runs, instead of echoing the prep result
+    pub fn with_dry_run_stub(mut self, stub: DryRunStub) -> Self {
+        self.dry_run_stub = Some(stub);
+        self
+    }
+    
+    /// Run the flow with every node's exec replaced by its `exec_dry_run`, recording each step
+    ///
+    /// Prep and post run as usual, so the shared state is updated from the stubbed results.
+    pub fn dry_run(&self, shared: &mut SharedState) -> Result<DryRunReport> {
+        dry_run::run(self.dry_run_stub.clone(), || self.run(shared))
+    }
+    
+    /// Run the flow, recording every node step and batch iteration with its action and timing
+    ///
+    /// Steps of nested flows are included. The trace is returned even when the run fails, ending
+    /// with the steps that failed; untraced runs record nothing and pay nothing for it.
+    pub fn run_traced(&self, shared: &mut SharedState) -> (Result<Action>, FlowTrace) {
+        trace::run(|| self.run(shared))
+    }
+    
+    /// Every node reachable from the start node, breadth-first, with its outgoing edges
+    ///
+    /// The start node comes first. Nodes are told apart by identity, so cycles are listed once.
+    pub fn nodes(&self) -> Vec<GraphNode> {
+        successors::reachable(self.start.clone())
+    }
+    
+    /// Describe the flow in Graphviz DOT, one drawn node per graph node and one edge per action
+    ///
+    /// The start node has a double border, conditional edges are dashed and nested flows are
+    /// drawn as clusters. Render it with e.g. `dot -Tsvg`.
+    pub fn to_dot(&self) -> String {
+        dot::render(&self.name(), self.start.clone())
+    }
+    
+    /// Check that every key a reachable node reads is written by a node that can run before it
+    ///
+    /// `initial_keys` are the keys the caller puts in the shared state before running the flow.
+    /// Only keys declared through `reads` and `writes` are checked; nothing is executed.
+    pub fn check_dataflow(&self, initial_keys: &[&str]) -> DataflowReport {
+        dataflow::check(&self.nodes(), initial_keys)
+    }
+    
+    /// Check the graph reachable from the start node without running it, reporting every problem at once
+    ///
+    /// Errors are params that don't match a node's `required_params` (the start node is checked
+    /// against the flow's params), declared actions without a successor, the same successor
+    /// registered twice for an action, joins that can't be satisfied, and async-only nodes in a
+    /// sync flow. Warnings are successors under actions a node never returns, nodes only reached
+    /// through those, and several successors under the default action. Only nodes that declare
+    /// their actions can be checked for the actions they return.
+    pub fn validate(&self) -> Result<ValidationReport> {
+        self.validate_with(self.base.params(), true)
+    }
+    
+    /// `validate`, checking the start node against `start_params` and, when `sync`, that every node runs synchronously
+    pub(crate) fn validate_with(&self, start_params: Arc<ParamMap>, sync: bool) -> Result<ValidationReport> {
+        let mut report = ValidationReport::default();
+        let graph = self.nodes();
+        for (idx, entry) in graph.iter().enumerate() {
+            let params = if idx == 0 { start_params.clone() } else { entry.node.params() };
+            let mut problems = Vec::new();
+            check_params(entry.node.as_ref(), params, &mut problems)?;
+            for problem in problems {
+                report.error(entry.node.name(), problem);
+            }
+            check_actions(&graph, entry, &mut report);
+            if sync && !entry.node.runs_sync() {
+                report.error(entry.node.name(), "only runs asynchronously, so it needs an AsyncFlow".into());
+            }
+        }
+        check_unreachable(&graph, &mut report);
+        check_joins(&graph, &mut report);
+        Ok(report)
+    }
+    
+    /// Validate the flow before every run, failing with `Error::InvalidFlow` when `validate` finds errors
+    ///
+    /// Warnings are logged and the run goes ahead.
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+    
+    /// Orchestrate flow through nodes
+    pub fn _orch(&self, shared: &mut SharedState, params: Option<Arc<ParamMap>>) -> Result<()> {
+        let params = params.unwrap_or_else(|| {
+            self.base.params()
+        });
+        
+        self.orch(self, self.start.clone(), params, shared)
+    }
+    
+    /// Orchestrate a walk from `start` along `route`, handing `params` to the start node
+    pub(crate) fn orch<R: Route>(&self, route: &R, start: R::At, params: Arc<ParamMap>, shared: &mut SharedState) -> Result<()> {
+        let node = route.node(&start);
+        node.set_params(resolve_params(node.as_ref(), params)?);
+        self.begin_steps(shared);
+        step_guard::scope_sync(self.max_steps, || self.walk(route, start, shared))
+    }
+    
+    /// Run the flow with `params` handed to the start node in place of the flow's own
+    pub(crate) fn run_with_params(&self, params: Arc<ParamMap>, shared: &mut SharedState) -> Result<Action> {
+        if self.strict {
+            validation::enforce(self.validate_with(params.clone(), true)?)?;
+        }
+        self.run_route(self, self.start.clone(), params, shared)
+    }
+    
+    /// Run the flow's prep, a walk from `start` along `route` in the flow's namespace, then its post
+    pub(crate) fn run_route<R: Route>(&self, route: &R, start: R::At, params: Arc<ParamMap>, shared: &mut SharedState) -> Result<Action> {
+        let prep_res = self.prep(shared)?;
+        match &self.namespace {
+            Some(namespace) => namespace.scope(shared, |view| self.orch(route, start, params, view))?,
+            None => self.orch(route, start, params, shared)?,
+        }
+        self.post(shared, prep_res, Value::Null)
+    }
+}
+
+/// How a walk finds the nodes that follow a step
+///
+/// A `Flow` reads each node's successors as it goes, while a `CompiledFlow` looks them up in its
+/// snapshot. Both are walked by the same loop, so a step runs the same way either way.
+pub(crate) trait Route: Sync {
+    /// Where the walk is: a node, or what the route finds it and its successors by
+    type At: Clone + Send + 'static;
+    
+    /// The node at `at`
+    fn node(&self, at: &Self::At) -> Arc<dyn Node>;
+    
+    /// Where the walk is once it reaches `node`, such as a join resumed after its upstream arrived
+    fn locate(&self, node: Arc<dyn Node>) -> Self::At;
+    
+    /// Whether the node at `at` has conditional successors
+    fn has_conditional(&self, at: &Self::At) -> bool;
+    
+    /// The successors of the node at `at` for `action`, in the order they were added
+    fn successors_for(&self, at: &Self::At, action: &str) -> Vec<Self::At>;
+    
+    /// Every successor the walk continues with after the node at `at`, as `Flow::get_next_nodes` finds them
+    fn next(&self, at: &Self::At, action: Option<&str>, shared: &SharedState, exec_res: &Value) -> NextSteps<Self::At>;
+}
+
+/// The successors a walk continues with after a step
+///
+/// Steps almost always have one successor, which is kept apart so that it needs no allocation.
+pub(crate) struct NextSteps<At> {
+    /// Successors run as branches before the walk goes on, in the order they were added
+    pub(crate) branches: Vec<At>,
+    
+    /// The successor the walk goes on with, `None` when the walk ends
+    pub(crate) last: Option<At>,
+}
+
+impl<At> NextSteps<At> {
+    /// Keep only the successors for which `keep` holds, calling it on each in order
+    pub(crate) fn retain(&mut self, mut keep: impl FnMut(&At) -> bool) {
+        self.branches.retain(&mut keep);
+        if let Some(last) = self.last.take() {
+            self.last = if keep(&last) { Some(last) } else { self.branches.pop() };
+        }
+    }
+}
+
+impl<At> FromIterator<At> for NextSteps<At> {
+    fn from_iter<I: IntoIterator<Item = At>>(iter: I) -> Self {
+        let mut next = NextSteps { branches: Vec::new(), last: None };
+        for at in iter {
+            next.branches.extend(next.last.replace(at));
+        }
+        next
+    }
+}
+
+impl Route for Flow {
+    type At = Arc<dyn Node>;
+    
+    fn node(&self, at: &Arc<dyn Node>) -> Arc<dyn Node> {
+        at.clone()
+    }
+    
+    fn locate(&self, node: Arc<dyn Node>) -> Arc<dyn Node> {
+        node
+    }
+    
+    fn has_conditional(&self, at: &Arc<dyn Node>) -> bool {
+        at.successors().read().has_conditional()
+    }
+    
+    fn successors_for(&self, at: &Arc<dyn Node>, action: &str) -> Vec<Arc<dyn Node>> {
+        at.successors().read().get_all(action)
+    }
+    
+    fn next(&self, at: &Arc<dyn Node>, action: Option<&str>, shared: &SharedState, exec_res: &Value) -> NextSteps<Arc<dyn Node>> {
+        self.next_nodes(at, action, shared, exec_res)
+    }
+}
+
+/// Indices of every node `entry` has an edge to
+fn targets(entry: &GraphNode) -> Vec<usize> {
+    entry.edges.iter().map(|(_, next)| *next).chain(entry.conditional.iter().map(|(_, next)| *next)).collect()
+}
+
+/// Indices of the nodes reachable from `idx` through at least one edge
+fn reachable_from(graph: &[GraphNode], idx: usize) -> Vec<bool> {
+    let mut seen = vec![false; graph.len()];
+    let mut stack = targets(&graph[idx]);
+    while let Some(next) = stack.pop() {
+        if !std::mem::replace(&mut seen[next], true) {
+            stack.extend(targets(&graph[next]));
+        }
+    }
+    seen
+}
+
+/// Groups of nodes that can reach each other, ordered by their first node
+fn cycles(graph: &[GraphNode]) -> Vec<Vec<usize>> {
+    let reach: Vec<Vec<bool>> = (0..graph.len()).map(|idx| reachable_from(graph, idx)).collect();
+    let mut grouped = vec![false; graph.len()];
+    let mut groups = Vec::new();
+    for idx in 0..graph.len() {
+        if grouped[idx] || !reach[idx][idx] {
+            continue;
+        }
+        let group: Vec<usize> = (idx..graph.len()).filter(|&other| reach[idx][other] && reach[other][idx]).collect();
+        for &member in &group {
+            grouped[member] = true;
+        }
+        groups.push(group);
+    }
+    groups
+}
+
+/// Whether `entry` can follow its successors under `action`: it returns it, runs it as branches, or doesn't say
+fn can_take(entry: &GraphNode, action: &str) -> bool {
+    let declared = entry.node.declared_actions();
+    declared.is_empty() || declared.iter().any(|a| a == action) || entry.node.branch_actions().iter().any(|a| a == action)
+}
+
+/// Report declared actions without a successor, successors under actions that are never returned and repeated edges
+fn check_actions(graph: &[GraphNode], entry: &GraphNode, report: &mut ValidationReport) {
+    let name = entry.node.name();
+    for action in entry.node.declared_actions() {
+        if !entry.edges.iter().any(|(a, _)| a == action) {
+            report.error(name.clone(), format!("no successor for action '{}'", action));
+        }
+    }
+    
+    // Edges are sorted by action, so the successors of an action are next to each other
+    for (i, (action, next)) in entry.edges.iter().enumerate() {
+        let first_of_action = i == 0 || entry.edges[i - 1].0 != *action;
+        if first_of_action && !can_take(entry, action) {
+            report.warning(name.clone(), format!("has a successor for '{}', an action it never returns", action));
+        }
+        if entry.edges[..i].contains(&(action.clone(), *next)) {
+            report.error(name.clone(), format!("'{}' is a successor for '{}' more than once", graph[*next].node.name(), action));
+        }
+    }
+    
+    let defaults = entry.edges.iter().filter(|(a, _)| a == DEFAULT_ACTION).count();
+    let distinct = entry.edges.iter().filter(|(a, _)| a == DEFAULT_ACTION).map(|(_, next)| *next).collect::<HashSet<_>>().len();
+    if defaults > 1 && distinct == defaults {
+        report.warning(name, format!("has {} default successors; all but the last run as branches", defaults));
+    }
+}
+
+/// Report nodes that are only reached through successors under actions that are never returned
+fn check_unreachable(graph: &[GraphNode], report: &mut ValidationReport) {
+    let mut seen = vec![false; graph.len()];
+    let mut stack = vec![0];
+    while let Some(idx) = stack.pop() {
+        if std::mem::replace(&mut seen[idx], true) {
+            continue;
+        }
+        let entry = &graph[idx];
+        stack.extend(entry.edges.iter().filter(|(action, _)| can_take(entry, action)).map(|(_, next)| *next));
+        stack.extend(entry.conditional.iter().map(|(_, next)| *next));
+    }
+    
+    for (entry, seen) in graph.iter().zip(seen) {
+        if !seen {
+            report.warning(entry.node.name(), "is unreachable from the start node through the actions nodes return".into());
+        }
+    }
+}
+
+/// Report joins that sit on a cycle or wait for a node with no edge to them
+fn check_joins(graph: &[GraphNode], report: &mut ValidationReport) {
+    for (idx, entry) in graph.iter().enumerate() {
+        let Some(join) = entry.node.as_join() else {
+            continue;
+        };
+        for label in join.upstream() {
+            let leads_in = graph.iter().any(|other| other.node.name() == *label && targets(other).contains(&idx));
+            if !leads_in {
+                report.error(entry.node.name(), format!("waits for '{}', which has no edge to it", label));
+            }
+        }
+        
+        if reachable_from(graph, idx)[idx] {
+            report.error(entry.node.name(), "join is part of a cycle".into());
+        }
+    }
 }
 
 impl Node for Flow {
-    fn params(&self) -> Arc<RwLock<HashMap<String, Value>>> {
+    fn params(&self) -> Arc<ParamMap> {
         self.base.params()
     }
     
-    fn successors(&self) -> Arc<RwLock<HashMap<String, Arc<dyn Node>>>> {
+    fn name(&self) -> String {
+        self.base.assigned_name().unwrap_or_else(short_type_name::<Self>)
+    }
+    
+    fn set_name(&self, name: &str) {
+        self.base.set_name(name);
+    }
+    
+    fn required_params(&self) -> &[ParamSpec] {
+        self.start.required_params()
+    }
+    
+    fn nested_start(&self) -> Option<Arc<dyn Node>> {
+        Some(self.start.clone())
+    }
+    
+    fn successors(&self) -> Arc<RwLock<Successors>> {
         self.base.successors()
     }
     
-    fn set_params(&self, params: HashMap<String, Value>) {
-        let params_lock = self.params();
-        let mut p = params_lock.write().unwrap();
-        *p = params;
+    fn set_params(&self, params: Arc<ParamMap>) {
+        self.base.set_params(params);
     }
     
     fn add_successor(&self, node: Arc<dyn Node>, action: &str) -> Result<Arc<dyn Node>> {
-        let successors_lock = self.successors();
-        let mut successors = successors_lock.write().unwrap();
-        if successors.contains_key(action) {
-            warn!("Overwriting successor for action '{}'", action);
-        }
-        successors.insert(action.to_string(), node.clone());
+        self.successors().write().push(action.to_string(), node.clone());
         Ok(node)
     }
     
     fn _run(&self, shared: &mut SharedState) -> Result<Action> {
-        let prep_res = self.prep(shared)?;
-        self._orch(shared, None)?;
-        self.post(shared, prep_res, Value::Null)
+        self.run_with_params(self.base.params(), shared)
     }
     
-    fn exec(&self, _prep_res: Value) -> Result<Value> {
+    fn exec(&self, _prep_res: &Value) -> Result<Value> {
         Err(Error::InvalidOperation("Flow can't exec.".into()))
     }
 }
 
+/// Overlay batch params on the flow params, sharing the flow's map when there is nothing to add
+pub(crate) fn merge_params(flow_params: &Arc<ParamMap>, batch_params: ParamMap) -> Arc<ParamMap> {
+    if batch_params.is_empty() {
+        return flow_params.clone();
+    }
+    
+    let mut merged = ParamMap::clone(flow_params);
+    merged.extend(batch_params);
+    Arc::new(merged)
+}
+
 /// A flow that processes batches of items
+///
+/// Like `Flow`, clones share the node graph with the original.
 #[derive(Clone)]
 pub struct BatchFlow {
     /// The underlying flow
     flow: Flow,
+    
+    /// Whether each item starts from the state as it was before the first item
+    reset_between_items: bool,
+    
+    /// Prep closure returning the batch params, when set instead of the default prep
+    prep: Option<PrepFn>,
+    
+    /// Post closure, when set instead of the default post
+    post: Option<PostFn>,
 }
 
 impl BatchFlow {
@@ -110,20 +706,67 @@ impl BatchFlow {
     pub fn new(start: Arc<dyn Node>) -> Self {
         Self {
             flow: Flow::new(start),
+            reset_between_items: false,
+            prep: None,
+            post: None,
         }
     }
+    
+    /// Compute the batch params from the shared state, as an array of objects with one run per object
+    pub fn with_prep(mut self, f: impl Fn(&SharedState) -> Result<Value> + Send + Sync + 'static) -> Self {
+        self.prep = Some(Arc::new(f));
+        self
+    }
+    
+    /// Run the flow once for each of `items`, with its entries overlaid on the flow params
+    pub fn with_items(self, items: Vec<ParamMap>) -> Self {
+        let items = Value::Array(items.into_iter().map(|item| Value::Object(item.into_iter().collect())).collect());
+        self.with_prep(move |_| Ok(items.clone()))
+    }
+    
+    /// Write results into the shared state after every item has run and choose the action
+    pub fn with_post(mut self, f: impl Fn(&mut SharedState, Value, Value) -> Result<Action> + Send + Sync + 'static) -> Self {
+        self.post = Some(Arc::new(f));
+        self
+    }
+    
+    /// Name the flow in logs, errors and traces
+    pub fn with_name(self, name: &str) -> Self {
+        self.set_name(name);
+        self
+    }
+    
+    /// Start every item from the state as it was after prep, discarding the previous item's writes
+    ///
+    /// The state left by the last item is what post sees.
+    pub fn reset_state_between_items(mut self) -> Self {
+        self.reset_between_items = true;
+        self
+    }
 }
 
 impl Node for BatchFlow {
-    fn params(&self) -> Arc<RwLock<HashMap<String, Value>>> {
+    fn params(&self) -> Arc<ParamMap> {
         self.flow.params()
     }
     
-    fn successors(&self) -> Arc<RwLock<HashMap<String, Arc<dyn Node>>>> {
+    fn name(&self) -> String {
+        self.flow.name()
+    }
+    
+    fn set_name(&self, name: &str) {
+        self.flow.set_name(name);
+    }
+    
+    fn nested_start(&self) -> Option<Arc<dyn Node>> {
+        self.flow.nested_start()
+    }
+    
+    fn successors(&self) -> Arc<RwLock<Successors>> {
         self.flow.successors()
     }
     
-    fn set_params(&self, params: HashMap<String, Value>) {
+    fn set_params(&self, params: Arc<ParamMap>) {
         self.flow.set_params(params);
     }
     
@@ -131,6 +774,20 @@ impl Node for BatchFlow {
         self.flow.add_successor(node, action)
     }
     
+    fn prep_readonly(&self, shared: &SharedState) -> Result<Value> {
+        match &self.prep {
+            Some(f) => f(shared),
+            None => Ok(Value::Null),
+        }
+    }
+    
+    fn post(&self, shared: &mut SharedState, prep_res: Value, exec_res: Value) -> Result<Action> {
+        match &self.post {
+            Some(f) => f(shared, prep_res, exec_res),
+            None => Ok(None),
+        }
+    }
+    
     fn _run(&self, shared: &mut SharedState) -> Result<Action> {
         let prep_res = self.prep(shared)?;
         
@@ -153,21 +810,24 @@ impl Node for BatchFlow {
             _ => return Err(Error::NodeExecution("BatchFlow prep should return array or null".into())),
         };
         
-        let flow_params = self.flow.params().read().unwrap().clone();
+        let flow_params = self.flow.params();
+        let mut initial = self.reset_between_items.then(|| shared.fork());
         
-        for mut bp in batch_params {
-            // Merge batch params with flow params
-            for (k, v) in flow_params.clone() {
-                bp.entry(k).or_insert(v);
+        for (i, bp) in batch_params.into_iter().enumerate() {
+            if let Some(initial) = initial.as_mut().filter(|_| i > 0) {
+                *shared = initial.fork();
             }
-            
-            self.flow._orch(shared, Some(bp))?;
+            let params = merge_params(&flow_params, bp);
+            let started = trace::start(shared);
+            let result = self.flow._orch(shared, Some(params.clone()));
+            trace::record(started, || self.name(), result.as_ref().map(|_| &None), Some(params.as_ref()), shared);
+            result?;
         }
         
         self.post(shared, prep_res, Value::Null)
     }
     
-    fn exec(&self, _prep_res: Value) -> Result<Value> {
+    fn exec(&self, _prep_res: &Value) -> Result<Value> {
         Err(Error::InvalidOperation("BatchFlow can't exec.".into()))
     }
 } 
\ No newline at end of file
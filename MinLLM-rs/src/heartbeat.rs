@@ -0,0 +1,91 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, MissedTickBehavior};
+
+tokio::task_local! {
+    /// Heartbeat of the async flow run the current task belongs to, for nodes without their own
+    static DEFAULT: HeartbeatConfig;
+}
+
+/// A sign of life from an exec attempt that is still running
+#[derive(Clone, Debug, PartialEq)]
+pub struct Heartbeat {
+    /// Name of the node
+    pub node: String,
+    
+    /// The attempt that is running, counting from 0
+    pub attempt: usize,
+    
+    /// Time since the attempt started
+    pub elapsed: Duration,
+}
+
+/// Called with a `Heartbeat` every interval while an exec attempt runs
+pub type HeartbeatCallback = Arc<dyn Fn(Heartbeat) + Send + Sync>;
+
+/// How often a heartbeat fires and where it goes
+#[derive(Clone)]
+pub(crate) struct HeartbeatConfig {
+    /// Time between heartbeats
+    interval: Duration,
+    
+    /// Receives every heartbeat
+    callback: HeartbeatCallback,
+}
+
+impl HeartbeatConfig {
+    /// Fire `callback` every `interval`, at least a millisecond apart
+    pub(crate) fn new(interval: Duration, callback: HeartbeatCallback) -> Self {
+        Self {
+            interval: interval.max(Duration::from_millis(1)),
+            callback,
+        }
+    }
+}
+
+/// Stops the ticker of an attempt when the attempt ends, however it ends
+struct Ticker(JoinHandle<()>);
+
+impl Drop for Ticker {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Await the exec attempt `fut` with `config`, or the run's default heartbeat, firing every interval until it resolves
+pub(crate) async fn watch<T>(
+    config: Option<HeartbeatConfig>,
+    node: impl FnOnce() -> String,
+    attempt: usize,
+    fut: impl Future<Output = T>,
+) -> T {
+    let Some(config) = config.or_else(|| DEFAULT.try_with(|config| config.clone()).ok()) else {
+        return fut.await;
+    };
+    
+    let node = node();
+    let _ticker = Ticker(tokio::spawn(async move {
+        let started = Instant::now();
+        let mut ticks = tokio::time::interval_at(started + config.interval, config.interval);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticks.tick().await;
+            (config.callback)(Heartbeat {
+                node: node.clone(),
+                attempt,
+                elapsed: started.elapsed(),
+            });
+        }
+    }));
+    fut.await
+}
+
+/// Await `fut` with `config` as the heartbeat of every async node without its own
+pub(crate) async fn scope<F: Future>(config: Option<HeartbeatConfig>, fut: F) -> F::Output {
+    match config {
+        Some(config) => DEFAULT.scope(config, fut).await,
+        None => fut.await,
+    }
+}
\ No newline at end of file
@@ -0,0 +1,224 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::SystemTime;
+use parking_lot::Mutex;
+use serde_json::Value;
+
+use crate::base::SharedState;
+
+/// Change made to a shared state key
+#[derive(Clone, Debug, PartialEq)]
+pub enum StateOp {
+    /// The key was set to this value
+    Set(Value),
+    
+    /// The key was removed
+    Remove,
+}
+
+/// One change to a shared state key, attributed to the node step that made it
+#[derive(Clone, Debug)]
+pub struct StateEvent {
+    /// Key that changed
+    pub key: String,
+    
+    /// What happened to the key
+    pub op: StateOp,
+    
+    /// Name of the node that made the change, when it has one
+    pub writer: Option<String>,
+    
+    /// Sequence number of the node step, counted across runs
+    pub step: usize,
+    
+    /// When the step finished
+    pub timestamp: SystemTime,
+}
+
+/// When a shared state key was written and by which node step
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EntryMeta {
+    /// When a recorded step first set the key, or set it again after removing it
+    pub created_at: SystemTime,
+    
+    /// When the key was last set
+    pub updated_at: SystemTime,
+    
+    /// Name of the node that last set the key, when it has one
+    pub writer: Option<String>,
+    
+    /// Sequence number of the step that last set the key
+    pub step: usize,
+}
+
+/// Bounded record of shared state changes, dropping the oldest events once full
+#[derive(Debug)]
+pub struct StateHistory {
+    /// Maximum number of events kept
+    capacity: usize,
+    
+    /// Recorded events, oldest first
+    events: Mutex<VecDeque<StateEvent>>,
+    
+    /// Steps recorded so far
+    steps: AtomicUsize,
+    
+    /// Metadata of every key currently present, independent of the event capacity
+    meta: Mutex<HashMap<String, EntryMeta>>,
+}
+
+impl StateHistory {
+    /// Create a history keeping at most `capacity` events
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            events: Mutex::new(VecDeque::with_capacity(capacity.min(1024))),
+            steps: AtomicUsize::new(0),
+            meta: Mutex::new(HashMap::new()),
+        }
+    }
+    
+    /// Every recorded event, oldest first
+    pub fn events(&self) -> Vec<StateEvent> {
+        self.events.lock().iter().cloned().collect()
+    }
+    
+    /// Recorded events for one key, oldest first
+    pub fn events_for(&self, key: &str) -> Vec<StateEvent> {
+        self.events.lock().iter().filter(|e| e.key == key).cloned().collect()
+    }
+    
+    /// When a key was written and by which step, if a recorded step set it
+    pub fn metadata(&self, key: &str) -> Option<EntryMeta> {
+        self.meta.lock().get(key).cloned()
+    }
+    
+    /// Forget every recorded event and all metadata
+    pub fn clear(&self) {
+        self.events.lock().clear();
+        self.meta.lock().clear();
+    }
+    
+    /// Record the differences between the state before and after one node step
+    pub(crate) fn record(&self, before: &SharedState, after: &SharedState, writer: Option<&str>) {
+        let step = self.steps.fetch_add(1, Ordering::Relaxed);
+        let timestamp = SystemTime::now();
+        
+        let mut changes: Vec<(&String, StateOp)> = after
+            .iter()
+            .filter(|(key, value)| before.get(key) != Some(*value))
+            .map(|(key, value)| (key, StateOp::Set(value.clone())))
+            .chain(before.keys().filter(|key| !after.contains_key(key)).map(|key| (key, StateOp::Remove)))
+            .collect();
+        changes.sort_by(|a, b| a.0.cmp(b.0));
+        
+        let mut meta = self.meta.lock();
+        for (key, op) in &changes {
+            match op {
+                StateOp::Set(_) => {
+                    let entry = meta.entry((*key).clone()).or_insert_with(|| EntryMeta {
+                        created_at: timestamp,
+                        updated_at: timestamp,
+                        writer: None,
+                        step,
+                    });
+                    entry.updated_at = timestamp;
+                    entry.writer = writer.map(|w| w.to_string());
+                    entry.step = step;
+                },
+                StateOp::Remove => {
+                    meta.remove(*key);
+                },
+            }
+        }
+        drop(meta);
+        
+        if self.capacity == 0 {
+            return;
+        }
+        let mut events = self.events.lock();
+        for (key, op) in changes {
+            if events.len() == self.capacity {
+                events.pop_front();
+            }
+            events.push_back(StateEvent {
+                key: key.clone(),
+                op,
+                writer: writer.map(|w| w.to_string()),
+                step,
+                timestamp,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    
+    use super::*;
+    
+    fn state(entries: &[(&str, Value)]) -> SharedState {
+        entries.iter().map(|(k, v)| (k.to_string(), v.clone())).collect()
+    }
+    
+    #[test]
+    fn steps_record_sets_and_removals_in_key_order() {
+        let history = StateHistory::new(10);
+        let before = state(&[("draft", json!("v1")), ("kept", json!(1)), ("stale", json!(true))]);
+        let after = state(&[("draft", json!("v2")), ("kept", json!(1)), ("added", json!([]))]);
+        history.record(&before, &after, Some("editor"));
+        history.record(&after, &after, None);
+        history.record(&after, &state(&[]), None);
+        
+        let events = history.events();
+        let summary: Vec<_> = events.iter().map(|e| (e.key.as_str(), e.op.clone(), e.writer.as_deref(), e.step)).collect();
+        assert_eq!(summary[..3], [
+            ("added", StateOp::Set(json!([])), Some("editor"), 0),
+            ("draft", StateOp::Set(json!("v2")), Some("editor"), 0),
+            ("stale", StateOp::Remove, Some("editor"), 0),
+        ]);
+        assert_eq!(events.len(), 6);
+        assert!(events[3..].iter().all(|e| e.op == StateOp::Remove && e.step == 2));
+        assert_eq!(history.events_for("draft").len(), 2);
+    }
+    
+    #[test]
+    fn full_histories_drop_the_oldest_events() {
+        let history = StateHistory::new(3);
+        let mut before = SharedState::new();
+        for i in 0..100 {
+            let after = state(&[("count", json!(i))]);
+            history.record(&before, &after, None);
+            before = after;
+        }
+        let kept: Vec<_> = history.events().into_iter().map(|e| (e.op, e.step)).collect();
+        assert_eq!(kept, [(StateOp::Set(json!(97)), 97), (StateOp::Set(json!(98)), 98), (StateOp::Set(json!(99)), 99)]);
+        
+        let none = StateHistory::new(0);
+        none.record(&SharedState::new(), &before, None);
+        assert!(none.events().is_empty());
+    }
+    
+    #[test]
+    fn metadata_follows_the_last_write() {
+        let history = StateHistory::new(0);
+        let draft = state(&[("draft", json!("v1"))]);
+        history.record(&SharedState::new(), &draft, Some("writer"));
+        let created = history.metadata("draft").unwrap();
+        assert_eq!((created.writer.as_deref(), created.step), (Some("writer"), 0));
+        assert_eq!(created.created_at, created.updated_at);
+        
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        history.record(&draft, &state(&[("draft", json!("v2"))]), Some("editor"));
+        let updated = history.metadata("draft").unwrap();
+        assert_eq!((updated.writer.as_deref(), updated.step), (Some("editor"), 1));
+        assert_eq!(updated.created_at, created.created_at);
+        assert!(updated.updated_at > created.updated_at);
+        
+        history.record(&draft, &SharedState::new(), None);
+        assert_eq!(history.metadata("draft"), None);
+        history.record(&SharedState::new(), &draft, None);
+        assert!(history.metadata("draft").unwrap().created_at > created.created_at, "a removed key starts over");
+    }
+}
\ No newline at end of file
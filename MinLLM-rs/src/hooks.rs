@@ -0,0 +1,217 @@
+use std::cell::RefCell;
+use std::future::Future;
+use std::sync::Arc;
+use serde_json::Value;
+
+use crate::error::{Error, Result};
+
+/// Observer of a node's exec input or output, called with the node name
+pub type NodeHook = Arc<dyn Fn(&str, &Value) + Send + Sync>;
+
+/// Observer of a node's exec failure, called with the node name
+pub type ErrorHook = Arc<dyn Fn(&str, &Error) + Send + Sync>;
+
+tokio::task_local! {
+    /// Default hooks installed by the flows the current node runs in
+    static FLOW_HOOKS: NodeHooks;
+    
+    /// Exec result of the node step being run, kept when its conditional successors need it
+    static EXEC_CAPTURE: ExecCapture;
+}
+
+/// Slot for the exec result of one node step
+struct ExecCapture {
+    /// Whether the step's exec result is wanted
+    armed: bool,
+    
+    /// The exec result, once the step has one
+    value: RefCell<Option<Value>>,
+}
+
+/// Run one node step, returning its exec result too when `armed`
+///
+/// Nodes without an exec step of their own, such as flows, leave the result null.
+pub(crate) fn capture_exec<R>(armed: bool, f: impl FnOnce() -> R) -> (R, Value) {
+    let capture = ExecCapture { armed, value: RefCell::new(None) };
+    EXEC_CAPTURE.sync_scope(capture, || {
+        let res = f();
+        let exec_res = EXEC_CAPTURE.with(|c| c.value.borrow_mut().take());
+        (res, exec_res.unwrap_or(Value::Null))
+    })
+}
+
+/// Await one node step, returning its exec result too when `armed`
+pub(crate) async fn capture_exec_async<F: Future>(armed: bool, fut: F) -> (F::Output, Value) {
+    let capture = ExecCapture { armed, value: RefCell::new(None) };
+    EXEC_CAPTURE.scope(capture, async {
+        let res = fut.await;
+        let exec_res = EXEC_CAPTURE.with(|c| c.value.borrow_mut().take());
+        (res, exec_res.unwrap_or(Value::Null))
+    }).await
+}
+
+/// Keep an exec result for the step being captured, if it wants one
+fn record_exec(result: &Result<Value>) {
+    if let Ok(res) = result {
+        let _ = EXEC_CAPTURE.try_with(|c| {
+            if c.armed {
+                *c.value.borrow_mut() = Some(res.clone());
+            }
+        });
+    }
+}
+
+/// Callbacks invoked around a node's exec step
+///
+/// Hooks only observe: they see the prep result before exec, then either the exec result
+/// or the error, once per run regardless of retries.
+#[derive(Clone, Default)]
+pub struct NodeHooks {
+    /// Called with the prep result before exec
+    before: Option<NodeHook>,
+    
+    /// Called with the exec result after a successful exec
+    after: Option<NodeHook>,
+    
+    /// Called with the error when exec fails after its retries and fallback
+    on_error: Option<ErrorHook>,
+}
+
+impl NodeHooks {
+    /// Create an empty set of hooks
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Observe the prep result before exec
+    pub fn before<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&str, &Value) + Send + Sync + 'static,
+    {
+        self.before = Some(Arc::new(hook));
+        self
+    }
+    
+    /// Observe the exec result after a successful exec
+    pub fn after<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&str, &Value) + Send + Sync + 'static,
+    {
+        self.after = Some(Arc::new(hook));
+        self
+    }
+    
+    /// Observe the error of a failed exec
+    pub fn on_error<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&str, &Error) + Send + Sync + 'static,
+    {
+        self.on_error = Some(Arc::new(hook));
+        self
+    }
+    
+    /// Replace the before hook
+    pub fn set_before(&mut self, hook: Option<NodeHook>) {
+        self.before = hook;
+    }
+    
+    /// Replace the after hook
+    pub fn set_after(&mut self, hook: Option<NodeHook>) {
+        self.after = hook;
+    }
+    
+    /// Replace the error hook
+    pub fn set_on_error(&mut self, hook: Option<ErrorHook>) {
+        self.on_error = hook;
+    }
+    
+    /// Whether no hook is set
+    pub fn is_empty(&self) -> bool {
+        self.before.is_none() && self.after.is_none() && self.on_error.is_none()
+    }
+    
+    /// Fill the hooks left unset from `fallback`
+    pub fn or(mut self, fallback: &NodeHooks) -> Self {
+        self.before = self.before.or_else(|| fallback.before.clone());
+        self.after = self.after.or_else(|| fallback.after.clone());
+        self.on_error = self.on_error.or_else(|| fallback.on_error.clone());
+        self
+    }
+    
+    /// A node's own hooks, completed by the defaults of the flows it runs in
+    pub(crate) fn effective(own: NodeHooks) -> NodeHooks {
+        FLOW_HOOKS.try_with(|defaults| own.clone().or(defaults)).unwrap_or(own)
+    }
+    
+    /// Run `f` with `defaults` layered over the defaults already in place
+    pub(crate) fn scope<R>(defaults: Option<&NodeHooks>, f: impl FnOnce() -> R) -> R {
+        match defaults {
+            Some(defaults) => FLOW_HOOKS.sync_scope(Self::effective(defaults.clone()), f),
+            None => f(),
+        }
+    }
+    
+    /// Await `fut` with `defaults` layered over the defaults already in place
+    pub(crate) async fn scope_async<F: Future>(defaults: Option<&NodeHooks>, fut: F) -> F::Output {
+        match defaults {
+            Some(defaults) => FLOW_HOOKS.scope(Self::effective(defaults.clone()), fut).await,
+            None => fut.await,
+        }
+    }
+    
+    /// Run an exec step for the node named by `name`, reporting it to the hooks
+    ///
+    /// Every exec result passes through here, which is also how conditional successors see it.
+    pub(crate) fn observe(&self, name: impl FnOnce() -> String, prep_res: &Value, exec: impl FnOnce() -> Result<Value>) -> Result<Value> {
+        if self.is_empty() {
+            let result = exec();
+            record_exec(&result);
+            return result;
+        }
+        let name = name();
+        let name = name.as_str();
+        if let Some(before) = &self.before {
+            before(name, prep_res);
+        }
+        let result = exec();
+        self.report(name, &result);
+        record_exec(&result);
+        result
+    }
+    
+    /// Await an exec step for the node named by `name`, reporting it to the hooks
+    pub(crate) async fn observe_async<F>(&self, name: impl FnOnce() -> String, prep_res: &Value, exec: F) -> Result<Value>
+    where
+        F: Future<Output = Result<Value>>,
+    {
+        if self.is_empty() {
+            let result = exec.await;
+            record_exec(&result);
+            return result;
+        }
+        let name = name();
+        let name = name.as_str();
+        if let Some(before) = &self.before {
+            before(name, prep_res);
+        }
+        let result = exec.await;
+        self.report(name, &result);
+        record_exec(&result);
+        result
+    }
+    
+    fn report(&self, name: &str, result: &Result<Value>) {
+        match result {
+            Ok(res) => {
+                if let Some(after) = &self.after {
+                    after(name, res);
+                }
+            },
+            Err(e) => {
+                if let Some(on_error) = &self.on_error {
+                    on_error(name, e);
+                }
+            },
+        }
+    }
+}
\ No newline at end of file
@@ -1,17 +1,80 @@
 mod base;
 mod node;
 mod flow;
+mod compiled_flow;
 mod async_node;
 mod async_flow;
 mod python;
+mod conversions;
 mod error;
+mod rate_limit;
+mod sleeper;
+mod backoff;
+mod retry;
+mod hooks;
+mod batch_policy;
+mod param_spec;
+mod cow_state;
+mod namespace;
+mod determinism;
+mod successors;
+mod action;
+mod dry_run;
+mod trace;
+mod dataflow;
+mod dot;
+mod validation;
+mod cancel;
+mod deadline;
+mod shutdown;
+mod heartbeat;
+mod step_guard;
+mod streaming;
+mod watch;
+mod history;
+mod state_limit;
+#[cfg(any(feature = "python", test))]
+mod runtime;
+mod nodes;
+#[cfg(feature = "testing")]
+pub mod testing;
 
-pub use base::BaseNode;
+pub use base::{BaseNode, Node as NodeTrait, SharedState, SharedStateExt, Action, ParamMap, DEFAULT_ACTION, ERROR_ACTION};
 pub use node::{Node, BatchNode};
 pub use flow::{Flow, BatchFlow};
-pub use async_node::{AsyncNode, AsyncBatchNode, AsyncParallelBatchNode};
+pub use compiled_flow::CompiledFlow;
+pub use async_node::{AsyncNodeTrait, AsyncNode, AsyncBatchNode, AsyncParallelBatchNode};
 pub use async_flow::{AsyncFlow, AsyncBatchFlow, AsyncParallelBatchFlow};
 pub use error::{Error, Result};
+pub use rate_limit::RateLimiter;
+pub use sleeper::{Sleeper, RealSleeper, TestSleeper};
+pub use backoff::Backoff;
+pub use retry::{RetryPolicy, FixedRetry, BackoffRetry, NoRetry, FallbackContext, current_attempt};
+pub use hooks::{NodeHooks, NodeHook, ErrorHook};
+pub use batch_policy::{ErrorPolicy, ResultOrder, ItemError, BatchReport, BatchProgress, ProgressCallback};
+pub use param_spec::ParamSpec;
+pub use cow_state::{MergePolicy, MergeFn, merge_into, merge_overlays};
+pub use namespace::{Namespace, PARENT_PREFIX};
+pub use determinism::Determinism;
+pub use action::{ActionName, IntoAction, ActionSet};
+pub use dry_run::{DryRunReport, DryRunStep, DryRunStub};
+pub use trace::{FlowTrace, TraceEntry, StateAccess, AccessKind};
+pub use dataflow::{KeySpec, DataflowReport, UnsatisfiedRead, TypeConflict};
+pub use validation::{ValidationReport, ValidationIssue};
+pub use cancel::CancellationToken;
+pub use deadline::current_deadline;
+pub use shutdown::{ShutdownHandle, RunOutcome};
+pub use heartbeat::{Heartbeat, HeartbeatCallback};
+pub use step_guard::DEFAULT_MAX_STEPS;
+pub use streaming::FlowEvent;
+pub use successors::{Successors, ConditionalEdge, EdgePredicate, GraphNode};
+pub use state_limit::{StateLimit, EvictionPolicy};
+pub use history::{StateHistory, StateEvent, StateOp, EntryMeta};
+pub use nodes::{ThrottleNode, PromptTemplateNode, MissingRef, JsonExtractNode, Extraction, FanOutNode, RaceNode, SyncAdapter, JoinNode, ValidateNode, Constraint, Violation, NoOpNode, ActionSource, FnNode, AsyncFnNode, BlockingNode, TypedNode, TypedLogic};
+#[cfg(feature = "http")]
+pub use nodes::HttpRequestNode;
+#[cfg(feature = "process")]
+pub use nodes::ShellCommandNode;
 
 #[cfg(feature = "python")]
 pub use python::{PyNode, PyAsyncNode, PyAsyncBatchNode, PyAsyncParallelBatchNode, PyFlow, PyAsyncFlow, PyAsyncBatchFlow, PyAsyncParallelBatchFlow};
@@ -0,0 +1,164 @@
+use serde_json::Value;
+
+use crate::base::SharedState;
+
+/// Key prefix that lets a namespaced view reach the enclosing state
+pub const PARENT_PREFIX: &str = "../";
+
+/// A key prefix isolating a sub-flow's writes from the rest of the shared state
+///
+/// Inside a view, key "result" is stored as "{prefix}/result" in the enclosing state, and
+/// "../config" addresses the enclosing state's own "config". Nested namespaces compose,
+/// since a view of a view is again a plain `SharedState`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Namespace {
+    /// Prefix without the trailing separator
+    prefix: String,
+}
+
+impl Namespace {
+    /// Create a namespace for a prefix
+    pub fn new(prefix: &str) -> Self {
+        Self {
+            prefix: prefix.trim_end_matches('/').to_string(),
+        }
+    }
+    
+    /// Namespace nested inside this one
+    pub fn nested(&self, prefix: &str) -> Self {
+        Self::new(&format!("{}/{}", self.prefix, prefix.trim_end_matches('/')))
+    }
+    
+    /// The prefix, without the trailing separator
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+    
+    /// Full key in the enclosing state for a key inside the namespace
+    pub fn key(&self, key: &str) -> String {
+        format!("{}/{}", self.prefix, key)
+    }
+    
+    /// Key inside the namespace for a full key, if it belongs to the namespace
+    pub fn strip<'a>(&self, key: &'a str) -> Option<&'a str> {
+        key.strip_prefix(self.prefix.as_str())?.strip_prefix('/')
+    }
+    
+    /// Build the view a sub-flow runs on
+    ///
+    /// Entries inside the namespace appear with the prefix stripped, every other entry under `PARENT_PREFIX`.
+    pub fn view(&self, parent: &SharedState) -> SharedState {
+        parent
+            .iter()
+            .map(|(key, value)| match self.strip(key) {
+                Some(inner) => (inner.to_string(), value.clone()),
+                None => (format!("{}{}", PARENT_PREFIX, key), value.clone()),
+            })
+            .collect()
+    }
+    
+    /// Write the changes between `before` and `after` back into the enclosing state
+    ///
+    /// `before` is the view as built by `view`; keys missing from `after` are removed.
+    pub fn write_back(&self, parent: &mut SharedState, before: &SharedState, after: SharedState) {
+        for key in before.keys() {
+            if !after.contains_key(key) {
+                parent.remove(&self.outer_key(key));
+            }
+        }
+        
+        for (key, value) in after {
+            if before.get(&key) != Some(&value) {
+                parent.insert(self.outer_key(&key), value);
+            }
+        }
+    }
+    
+    /// Run `f` on a view of `parent`, then write its changes back
+    pub fn scope<R>(&self, parent: &mut SharedState, f: impl FnOnce(&mut SharedState) -> R) -> R {
+        let before = self.view(parent);
+        let mut view = before.clone();
+        let result = f(&mut view);
+        self.write_back(parent, &before, view);
+        result
+    }
+    
+    /// Entries of the namespace, with the prefix stripped
+    pub fn entries<'a>(&'a self, parent: &'a SharedState) -> impl Iterator<Item = (&'a str, &'a Value)> + 'a {
+        parent
+            .iter()
+            .filter_map(move |(key, value)| self.strip(key).map(|inner| (inner, value)))
+    }
+    
+    fn outer_key(&self, key: &str) -> String {
+        match key.strip_prefix(PARENT_PREFIX) {
+            Some(outer) => outer.to_string(),
+            None => self.key(key),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    
+    fn parent() -> SharedState {
+        SharedState::from([
+            ("config".to_string(), json!("fast")),
+            ("result".to_string(), json!("outer")),
+            ("sub/result".to_string(), json!("inner")),
+            ("sub/deep/result".to_string(), json!("deepest")),
+        ])
+    }
+    
+    #[test]
+    fn views_strip_the_prefix_and_reach_the_parent() {
+        let namespace = Namespace::new("sub/");
+        assert_eq!(namespace.prefix(), "sub");
+        assert_eq!(namespace.strip("sub/result"), Some("result"));
+        assert_eq!(namespace.strip("subway/result"), None);
+        
+        let view = namespace.view(&parent());
+        assert_eq!(view["result"], json!("inner"));
+        assert_eq!(view["deep/result"], json!("deepest"));
+        assert_eq!(view["../result"], json!("outer"));
+        assert_eq!(view["../config"], json!("fast"));
+        assert_eq!(view.len(), 4);
+    }
+    
+    #[test]
+    fn scopes_write_back_only_what_changed() {
+        let mut shared = parent();
+        Namespace::new("sub").scope(&mut shared, |view| {
+            view.insert("result".to_string(), json!("rewritten"));
+            view.insert("items".to_string(), json!([1, 2]));
+            view.insert("../config".to_string(), json!("slow"));
+            view.remove("deep/result");
+        });
+        assert_eq!(shared["sub/result"], json!("rewritten"));
+        assert_eq!(shared["sub/items"], json!([1, 2]));
+        assert_eq!(shared["result"], json!("outer"));
+        assert_eq!(shared["config"], json!("slow"));
+        assert!(!shared.contains_key("sub/deep/result"));
+    }
+    
+    #[test]
+    fn nested_namespaces_compose() {
+        let outer = Namespace::new("sub");
+        let inner = outer.nested("deep");
+        assert_eq!(inner.key("result"), "sub/deep/result");
+        
+        let mut shared = parent();
+        outer.scope(&mut shared, |view| {
+            Namespace::new("deep").scope(view, |deep| {
+                assert_eq!(deep["result"], json!("deepest"));
+                assert_eq!(deep["../result"], json!("inner"));
+                assert_eq!(deep["../../result"], json!("outer"));
+                deep.insert("result".to_string(), json!("rewritten"));
+            });
+        });
+        assert_eq!(shared["sub/deep/result"], json!("rewritten"));
+        assert_eq!(inner.entries(&shared).collect::<Vec<_>>(), [("result", &json!("rewritten"))]);
+    }
+}
\ No newline at end of file
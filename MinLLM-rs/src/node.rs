@@ -1,12 +1,18 @@
-use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
-use std::thread;
+use std::sync::Arc;
 use std::time::Duration;
+use parking_lot::RwLock;
 use serde_json::Value;
-use log::warn;
 
-use crate::base::{BaseNode, Node as NodeTrait};
-use crate::error::{Error, Result};
+use crate::base::{BaseNode, short_type_name, Node as NodeTrait, SharedState, Action, ParamMap};
+use crate::successors::Successors;
+use crate::hooks::{NodeHooks, NodeHook, ErrorHook};
+use crate::sleeper::{self, Sleeper};
+use crate::backoff::Backoff;
+use crate::rate_limit::RateLimiter;
+use crate::retry::{self, RetryPolicy, FixedRetry, BackoffRetry};
+use crate::batch_policy::{BatchCollector, ErrorPolicy};
+use crate::nodes::fn_node::{PrepFn, ExecFn, PostFn};
+use crate::error::Result;
 
 /// A node with retry capability
 #[derive(Clone)]
@@ -17,11 +23,20 @@ pub struct Node {
     /// Maximum number of retries
     max_retries: usize,
     
-    /// Wait time between retries in milliseconds
-    wait: u64,
+    /// Decides which failures are retried and the wait before each retry
+    retry: Arc<dyn RetryPolicy>,
     
-    /// Current retry count
-    cur_retry: Arc<RwLock<usize>>,
+    /// Longest a single exec attempt may take
+    timeout: Option<Duration>,
+    
+    /// Source of the waits between retries
+    sleeper: Arc<dyn Sleeper>,
+    
+    /// Limiter every exec attempt acquires a permit from, when set
+    limiter: Option<Arc<RateLimiter>>,
+    
+    /// Exec closure, when set instead of the default exec
+    exec: Option<ExecFn>,
 }
 
 impl Node {
@@ -30,14 +45,81 @@ impl Node {
         Self {
             base: BaseNode::new(),
             max_retries,
-            wait,
-            cur_retry: Arc::new(RwLock::new(0)),
+            retry: Arc::new(FixedRetry::new(max_retries, Duration::from_millis(wait))),
+            timeout: None,
+            sleeper: sleeper::real(),
+            limiter: None,
+            exec: None,
         }
     }
     
-    /// Called on execution failure, can be overridden
-    pub fn exec_fallback(&self, _prep_res: Value, error: Error) -> Result<Value> {
-        Err(error)
+    /// Compute each exec attempt's result from the prep result
+    pub fn with_exec(mut self, f: impl Fn(&Value) -> Result<Value> + Send + Sync + 'static) -> Self {
+        self.exec = Some(Arc::new(f));
+        self
+    }
+    
+    /// Route the waits between retries through `sleeper`
+    pub fn with_sleeper(mut self, sleeper: Arc<dyn Sleeper>) -> Self {
+        self.sleeper = sleeper;
+        self
+    }
+    
+    /// Replace the fixed wait between retries with `backoff`, keeping the number of attempts
+    ///
+    /// This replaces any policy set with `with_retry_policy`.
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.retry = Arc::new(BackoffRetry::new(self.max_retries, backoff));
+        self
+    }
+    
+    /// Let `policy` decide which failures are retried and how long to wait before each retry
+    pub fn with_retry_policy(mut self, policy: Arc<dyn RetryPolicy>) -> Self {
+        self.retry = policy;
+        self
+    }
+    
+    /// Fail an exec attempt with `Error::Timeout` once it runs longer than `timeout`
+    ///
+    /// A timed-out attempt counts as a failure, so it is retried and finally handed to the fallback.
+    /// The attempt runs on its own thread, which is left to finish in the background after a timeout.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+    
+    /// Space the starts of consecutive exec attempts at least `interval` apart
+    ///
+    /// Clones of the node share the spacing, so it holds across batch items and flows.
+    pub fn with_min_interval(self, interval: Duration) -> Self {
+        self.with_rate_limiter(Arc::new(RateLimiter::new(1, interval)))
+    }
+    
+    /// Acquire a permit from `limiter` before every exec attempt, sharing its budget with other nodes
+    pub fn with_rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.limiter = Some(limiter);
+        self
+    }
+    
+    /// Name the node in logs, errors and traces
+    pub fn with_name(self, name: &str) -> Self {
+        self.set_name(name);
+        self
+    }
+    
+    /// Observe the prep result before exec
+    pub fn set_before_hook(&self, hook: NodeHook) {
+        self.base.set_before_hook(hook);
+    }
+    
+    /// Observe the exec result after a successful exec
+    pub fn set_after_hook(&self, hook: NodeHook) {
+        self.base.set_after_hook(hook);
+    }
+    
+    /// Observe the error of a failed exec
+    pub fn set_error_hook(&self, hook: ErrorHook) {
+        self.base.set_error_hook(hook);
     }
 }
 
@@ -48,53 +130,60 @@ impl Default for Node {
 }
 
 impl NodeTrait for Node {
-    fn params(&self) -> Arc<RwLock<HashMap<String, Value>>> {
+    fn params(&self) -> Arc<ParamMap> {
         self.base.params()
     }
     
-    fn successors(&self) -> Arc<RwLock<HashMap<String, Arc<dyn NodeTrait>>>> {
+    fn name(&self) -> String {
+        self.base.assigned_name().unwrap_or_else(short_type_name::<Self>)
+    }
+    
+    fn set_name(&self, name: &str) {
+        self.base.set_name(name);
+    }
+    
+    fn hooks(&self) -> NodeHooks {
+        self.base.hooks()
+    }
+    
+    fn set_hooks(&self, hooks: NodeHooks) {
+        self.base.set_hooks(hooks);
+    }
+    
+    fn successors(&self) -> Arc<RwLock<Successors>> {
         self.base.successors()
     }
     
-    fn set_params(&self, params: HashMap<String, Value>) {
-        let params_lock = self.params();
-        let mut p = params_lock.write().unwrap();
-        *p = params;
+    fn set_params(&self, params: Arc<ParamMap>) {
+        self.base.set_params(params);
     }
     
     fn add_successor(&self, node: Arc<dyn NodeTrait>, action: &str) -> Result<Arc<dyn NodeTrait>> {
-        let successors_lock = self.successors();
-        let mut successors = successors_lock.write().unwrap();
-        if successors.contains_key(action) {
-            warn!("Overwriting successor for action '{}'", action);
-        }
-        successors.insert(action.to_string(), node.clone());
+        self.successors().write().push(action.to_string(), node.clone());
         Ok(node)
     }
     
-    fn _exec(&self, prep_res: Value) -> Result<Value> {
-        for retry in 0..self.max_retries {
-            {
-                let mut cur_retry = self.cur_retry.write().unwrap();
-                *cur_retry = retry;
-            }
-            
-            match self.exec(prep_res.clone()) {
-                Ok(res) => return Ok(res),
-                Err(e) => {
-                    if retry == self.max_retries - 1 {
-                        return self.exec_fallback(prep_res, e);
-                    }
-                    
-                    if self.wait > 0 {
-                        thread::sleep(Duration::from_millis(self.wait));
-                    }
-                }
-            }
+    fn exec(&self, prep_res: &Value) -> Result<Value> {
+        match &self.exec {
+            Some(f) => f(prep_res),
+            None => Ok(Value::Null),
         }
-        
-        // This should never happen if max_retries > 0
-        Err(Error::NodeExecution("Max retries exceeded".into()))
+    }
+    
+    fn _exec(&self, prep_res: &Value) -> Result<Value> {
+        let attempts = retry::Attempts::new(self.retry.as_ref(), self.sleeper.as_ref())
+            .limiter(self.limiter.as_deref())
+            .timeout(self.timeout);
+        retry::drive(self, Self::exec, attempts, prep_res, |e, _| self.exec_fallback(prep_res, e))
+    }
+}
+
+/// Items of a batch prep result: an array's elements, none for null, or a single value as a one-item batch
+pub(crate) fn batch_items(prep_res: &Value) -> &[Value] {
+    match prep_res {
+        Value::Array(items) => items,
+        Value::Null => &[],
+        item => std::slice::from_ref(item),
     }
 }
 
@@ -103,6 +192,18 @@ impl NodeTrait for Node {
 pub struct BatchNode {
     /// The underlying node
     node: Node,
+    
+    /// What happens when an item fails
+    error_policy: ErrorPolicy,
+    
+    /// Number of items passed to each exec call, when batching in chunks
+    chunk_size: Option<usize>,
+    
+    /// Prep closure returning the items, when set instead of the default prep
+    prep: Option<PrepFn>,
+    
+    /// Post closure, when set instead of the default post
+    post: Option<PostFn>,
 }
 
 impl BatchNode {
@@ -110,8 +211,87 @@ impl BatchNode {
     pub fn new(max_retries: usize, wait: u64) -> Self {
         Self {
             node: Node::new(max_retries, wait),
+            error_policy: ErrorPolicy::default(),
+            chunk_size: None,
+            prep: None,
+            post: None,
         }
     }
+    
+    /// Compute the items to process from the shared state
+    pub fn with_prep(mut self, f: impl Fn(&SharedState) -> Result<Value> + Send + Sync + 'static) -> Self {
+        self.prep = Some(Arc::new(f));
+        self
+    }
+    
+    /// Compute the result of one item, or of one chunk with `with_chunk_size`
+    pub fn with_exec(mut self, f: impl Fn(&Value) -> Result<Value> + Send + Sync + 'static) -> Self {
+        self.node = self.node.with_exec(f);
+        self
+    }
+    
+    /// Write the results into the shared state and choose the action
+    pub fn with_post(mut self, f: impl Fn(&mut SharedState, Value, Value) -> Result<Action> + Send + Sync + 'static) -> Self {
+        self.post = Some(Arc::new(f));
+        self
+    }
+    
+    /// Route the waits between retries through `sleeper`
+    pub fn with_sleeper(mut self, sleeper: Arc<dyn Sleeper>) -> Self {
+        self.node = self.node.with_sleeper(sleeper);
+        self
+    }
+    
+    /// Replace the fixed wait between retries with `backoff`, keeping the number of attempts
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.node = self.node.with_backoff(backoff);
+        self
+    }
+    
+    /// Let `policy` decide which failures are retried and how long to wait before each retry
+    pub fn with_retry_policy(mut self, policy: Arc<dyn RetryPolicy>) -> Self {
+        self.node = self.node.with_retry_policy(policy);
+        self
+    }
+    
+    /// Fail an exec attempt with `Error::Timeout` once it runs longer than `timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.node = self.node.with_timeout(timeout);
+        self
+    }
+    
+    /// Space the starts of consecutive exec attempts at least `interval` apart, across all items
+    pub fn with_min_interval(mut self, interval: Duration) -> Self {
+        self.node = self.node.with_min_interval(interval);
+        self
+    }
+    
+    /// Acquire a permit from `limiter` before every exec attempt, sharing its budget with other nodes
+    pub fn with_rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.node = self.node.with_rate_limiter(limiter);
+        self
+    }
+    
+    /// Name the node in logs, errors and traces
+    pub fn with_name(self, name: &str) -> Self {
+        self.set_name(name);
+        self
+    }
+    
+    /// Decide what happens when an item fails; see `BatchReport` for the exec result it produces
+    pub fn with_error_policy(mut self, policy: ErrorPolicy) -> Self {
+        self.error_policy = policy;
+        self
+    }
+    
+    /// Call exec once per chunk of `size` items, with the chunk as an array
+    ///
+    /// Exec must return an array for each chunk; the arrays are flattened back in item order.
+    /// Retries and the error policy apply to whole chunks.
+    pub fn with_chunk_size(mut self, size: usize) -> Self {
+        self.chunk_size = Some(size.max(1));
+        self
+    }
 }
 
 impl Default for BatchNode {
@@ -121,15 +301,31 @@ impl Default for BatchNode {
 }
 
 impl NodeTrait for BatchNode {
-    fn params(&self) -> Arc<RwLock<HashMap<String, Value>>> {
+    fn params(&self) -> Arc<ParamMap> {
         self.node.params()
     }
     
-    fn successors(&self) -> Arc<RwLock<HashMap<String, Arc<dyn NodeTrait>>>> {
+    fn name(&self) -> String {
+        self.node.name()
+    }
+    
+    fn set_name(&self, name: &str) {
+        self.node.set_name(name);
+    }
+    
+    fn hooks(&self) -> NodeHooks {
+        self.node.hooks()
+    }
+    
+    fn set_hooks(&self, hooks: NodeHooks) {
+        self.node.set_hooks(hooks);
+    }
+    
+    fn successors(&self) -> Arc<RwLock<Successors>> {
         self.node.successors()
     }
     
-    fn set_params(&self, params: HashMap<String, Value>) {
+    fn set_params(&self, params: Arc<ParamMap>) {
         self.node.set_params(params);
     }
     
@@ -137,25 +333,40 @@ impl NodeTrait for BatchNode {
         self.node.add_successor(node, action)
     }
     
-    fn _exec(&self, items: Value) -> Result<Value> {
-        // Handle empty batches
-        if items.is_null() {
-            return Ok(Value::Array(vec![]));
+    fn prep_readonly(&self, shared: &SharedState) -> Result<Value> {
+        match &self.prep {
+            Some(f) => f(shared),
+            None => Ok(Value::Null),
         }
+    }
+    
+    fn post(&self, shared: &mut SharedState, prep_res: Value, exec_res: Value) -> Result<Action> {
+        match &self.post {
+            Some(f) => f(shared, prep_res, exec_res),
+            None => Ok(None),
+        }
+    }
+    
+    fn _exec(&self, items: &Value) -> Result<Value> {
+        let items = batch_items(items);
         
-        // Ensure we have an array
-        let items = match items {
-            Value::Array(items) => items,
-            _ => return Err(Error::NodeExecution("BatchNode requires an array".into())),
-        };
-        
-        // Process each item using the node's exec method
-        let mut results = Vec::with_capacity(items.len());
-        for item in items {
-            let result = self.node._exec(item)?;
-            results.push(result);
+        let mut collector = BatchCollector::new(self.error_policy, items.len());
+        match self.chunk_size {
+            // Process each chunk as one exec call, retrying each chunk on its own
+            Some(size) => {
+                for (i, chunk) in items.chunks(size).enumerate() {
+                    let result = self.node._exec(&Value::Array(chunk.to_vec()));
+                    collector.push_chunk(i * size, chunk.len(), result)?;
+                }
+            },
+            // Process each item using the node's exec method, retrying each item on its own
+            None => {
+                for (i, item) in items.iter().enumerate() {
+                    collector.push(i, self.node._exec(item))?;
+                }
+            },
         }
         
-        Ok(Value::Array(results))
+        collector.finish()
     }
 } 
\ No newline at end of file
@@ -0,0 +1,301 @@
+use std::sync::Arc;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::base::{BaseNode, short_type_name, Node as NodeTrait, SharedState, Action, ParamMap};
+use crate::action::ActionName;
+use crate::successors::Successors;
+use crate::hooks::NodeHooks;
+use crate::error::{Error, Result};
+
+/// A single value to copy out of a JSON document in the shared state
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Extraction {
+    /// Shared state key holding the source document
+    pub source_key: String,
+    
+    /// RFC 6901 pointer (`/choices/0/text`) or dotted path (`choices[0].text`)
+    pub pointer: String,
+    
+    /// Shared state key receiving the extracted value
+    pub dest_key: String,
+    
+    /// Whether a missing value routes the flow to "missing"
+    #[serde(default)]
+    pub required: bool,
+    
+    /// Value written when the pointer resolves to nothing
+    #[serde(default)]
+    pub default: Option<Value>,
+}
+
+impl Extraction {
+    /// Create a required extraction
+    pub fn new(source_key: &str, pointer: &str, dest_key: &str) -> Self {
+        Self {
+            source_key: source_key.to_string(),
+            pointer: pointer.to_string(),
+            dest_key: dest_key.to_string(),
+            required: true,
+            default: None,
+        }
+    }
+    
+    /// Make the extraction optional, writing `default` when nothing is found
+    pub fn optional(mut self, default: Option<Value>) -> Self {
+        self.required = false;
+        self.default = default;
+        self
+    }
+    
+    /// The pointer normalized to RFC 6901 syntax
+    pub fn json_pointer(&self) -> Result<String> {
+        to_json_pointer(&self.pointer)
+    }
+}
+
+/// Convert a pointer or dotted path into an RFC 6901 pointer, rejecting malformed input
+fn to_json_pointer(path: &str) -> Result<String> {
+    let invalid = |reason: &str| Error::InvalidOperation(format!("Invalid JSON path '{}': {}", path, reason));
+    
+    if path.is_empty() || path.starts_with('/') {
+        let mut chars = path.chars();
+        while let Some(c) = chars.next() {
+            if c == '~' && !matches!(chars.next(), Some('0') | Some('1')) {
+                return Err(invalid("'~' must be followed by '0' or '1'"));
+            }
+        }
+        return Ok(path.to_string());
+    }
+    
+    let mut pointer = String::new();
+    for part in path.split('.') {
+        let (name, mut indices) = match part.find('[') {
+            Some(i) => (&part[..i], &part[i..]),
+            None => (part, ""),
+        };
+        if name.is_empty() && indices.is_empty() {
+            return Err(invalid("empty segment"));
+        }
+        if !name.is_empty() {
+            pointer.push('/');
+            pointer.push_str(&name.replace('~', "~0").replace('/', "~1"));
+        }
+        while !indices.is_empty() {
+            let close = indices.find(']').ok_or_else(|| invalid("unclosed '['"))?;
+            let index = &indices[1..close];
+            if index.is_empty() || !index.chars().all(|c| c.is_ascii_digit()) {
+                return Err(invalid("array index must be a non-negative integer"));
+            }
+            pointer.push('/');
+            pointer.push_str(index);
+            indices = &indices[close + 1..];
+            if !indices.is_empty() && !indices.starts_with('[') {
+                return Err(invalid("unexpected characters after ']'"));
+            }
+        }
+    }
+    
+    Ok(pointer)
+}
+
+/// A node copying fields out of JSON documents in the shared state
+///
+/// `post` returns "missing" when a required extraction found nothing and "default" otherwise.
+/// Extractions can also be supplied through the `extractions` param.
+#[derive(Clone)]
+pub struct JsonExtractNode {
+    /// Base node implementation
+    base: BaseNode,
+    
+    /// Extractions applied in order
+    extractions: Vec<Extraction>,
+}
+
+impl JsonExtractNode {
+    /// Create a node applying the given extractions
+    pub fn new(extractions: Vec<Extraction>) -> Self {
+        Self {
+            base: BaseNode::new(),
+            extractions,
+        }
+    }
+    
+    /// The configured extractions, preferring the `extractions` param when present
+    fn extractions(&self) -> Result<Vec<Extraction>> {
+        match self.params().get("extractions") {
+            Some(value) => serde_json::from_value(value.clone())
+                .map_err(|e| Error::InvalidOperation(format!("Invalid 'extractions' param: {}", e))),
+            None => Ok(self.extractions.clone()),
+        }
+    }
+    
+    /// Check every configured pointer without running the node
+    pub fn validate(&self) -> Result<()> {
+        for extraction in self.extractions()? {
+            extraction.json_pointer()?;
+        }
+        Ok(())
+    }
+}
+
+impl NodeTrait for JsonExtractNode {
+    fn params(&self) -> Arc<ParamMap> {
+        self.base.params()
+    }
+    
+    fn name(&self) -> String {
+        self.base.assigned_name().unwrap_or_else(short_type_name::<Self>)
+    }
+    
+    fn set_name(&self, name: &str) {
+        self.base.set_name(name);
+    }
+    
+    fn hooks(&self) -> NodeHooks {
+        self.base.hooks()
+    }
+    
+    fn set_hooks(&self, hooks: NodeHooks) {
+        self.base.set_hooks(hooks);
+    }
+    
+    fn successors(&self) -> Arc<RwLock<Successors>> {
+        self.base.successors()
+    }
+    
+    fn set_params(&self, params: Arc<ParamMap>) {
+        self.base.set_params(params);
+    }
+    
+    fn add_successor(&self, node: Arc<dyn NodeTrait>, action: &str) -> Result<Arc<dyn NodeTrait>> {
+        self.base.add_successor(node, action)
+    }
+    
+    fn prep(&self, shared: &mut SharedState) -> Result<Value> {
+        let extractions = self.extractions()?;
+        let mut sources = serde_json::Map::new();
+        for extraction in &extractions {
+            if let Some(source) = shared.get(&extraction.source_key) {
+                sources.insert(extraction.source_key.clone(), source.clone());
+            }
+        }
+        
+        Ok(json!({
+            "extractions": extractions,
+            "sources": sources,
+        }))
+    }
+    
+    fn exec(&self, prep_res: &Value) -> Result<Value> {
+        let extractions: Vec<Extraction> = serde_json::from_value(prep_res["extractions"].clone())
+            .map_err(|e| Error::NodeExecution(format!("Invalid extractions: {}", e)))?;
+        
+        let mut values = serde_json::Map::new();
+        let mut missing = Vec::new();
+        for extraction in extractions {
+            let pointer = extraction.json_pointer()?;
+            let found = prep_res["sources"]
+                .get(&extraction.source_key)
+                .and_then(|source| source.pointer(&pointer))
+                .cloned();
+            
+            match found.or(extraction.default) {
+                Some(value) => {
+                    values.insert(extraction.dest_key, value);
+                },
+                None if extraction.required => missing.push(Value::String(extraction.dest_key)),
+                None => {},
+            }
+        }
+        
+        Ok(json!({
+            "values": values,
+            "missing": missing,
+        }))
+    }
+    
+    fn post(&self, shared: &mut SharedState, _prep_res: Value, exec_res: Value) -> Result<Action> {
+        if let Some(values) = exec_res["values"].as_object() {
+            for (key, value) in values {
+                shared.insert(key.clone(), value.clone());
+            }
+        }
+        
+        let any_missing = exec_res["missing"].as_array().map(|m| !m.is_empty()).unwrap_or(false);
+        Ok(Some(if any_missing { ActionName::new("missing") } else { ActionName::default_action() }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    
+    use super::*;
+    
+    /// A store holding a chat completion response under "response"
+    fn response() -> SharedState {
+        SharedState::from([(
+            "response".to_string(),
+            json!({"choices": [{"message": {"content": "first"}}, {"message": {"content": "second"}}], "usage": {"total_tokens": 12}}),
+        )])
+    }
+    
+    #[test]
+    fn extracts_nested_pointers_and_array_indices() {
+        let node = JsonExtractNode::new(vec![
+            Extraction::new("response", "/choices/0/message/content", "answer"),
+            Extraction::new("response", "choices[1].message.content", "alternative"),
+            Extraction::new("response", "usage.total_tokens", "tokens"),
+        ]);
+        let mut shared = response();
+        assert_eq!(node.run(&mut shared).unwrap().as_deref(), Some("default"));
+        assert_eq!(shared["answer"], json!("first"));
+        assert_eq!(shared["alternative"], json!("second"));
+        assert_eq!(shared["tokens"], json!(12));
+    }
+    
+    #[test]
+    fn optional_extractions_fall_back_to_their_default() {
+        let node = JsonExtractNode::new(vec![
+            Extraction::new("response", "choices[5].message.content", "answer").optional(Some(json!("none"))),
+            Extraction::new("response", "/model", "model").optional(None),
+        ]);
+        let mut shared = response();
+        assert_eq!(node.run(&mut shared).unwrap().as_deref(), Some("default"));
+        assert_eq!(shared["answer"], json!("none"));
+        assert!(!shared.contains_key("model"));
+    }
+    
+    #[test]
+    fn a_missing_required_value_routes_to_missing() {
+        let node = JsonExtractNode::new(vec![
+            Extraction::new("response", "choices[0].message.content", "answer"),
+            Extraction::new("response", "choices[9].message.content", "later"),
+        ]);
+        let mut shared = response();
+        assert_eq!(node.run(&mut shared).unwrap().as_deref(), Some("missing"));
+        assert_eq!(shared["answer"], json!("first"));
+        assert!(!shared.contains_key("later"));
+        
+        let mut shared = SharedState::new();
+        assert_eq!(node.run(&mut shared).unwrap().as_deref(), Some("missing"));
+    }
+    
+    #[test]
+    fn reports_pointer_syntax_errors_when_validated() {
+        assert!(JsonExtractNode::new(vec![Extraction::new("response", "choices[0].text", "answer")]).validate().is_ok());
+        for pointer in ["/choices/~2", "choices[0", "choices[x].text", "choices..text", "choices[0]text"] {
+            let node = JsonExtractNode::new(vec![Extraction::new("response", pointer, "answer")]);
+            match node.validate() {
+                Err(Error::InvalidOperation(message)) => assert!(message.contains(pointer), "{}", message),
+                other => panic!("'{}' validated as {:?}", pointer, other),
+            }
+        }
+        
+        let node = JsonExtractNode::new(Vec::new());
+        node.set_params_map(ParamMap::from([("extractions".to_string(), json!([{"source_key": "response", "pointer": "a[", "dest_key": "b"}]))]));
+        assert!(node.validate().is_err());
+    }
+}
\ No newline at end of file
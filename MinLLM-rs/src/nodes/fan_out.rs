@@ -0,0 +1,111 @@
+use std::sync::Arc;
+use parking_lot::RwLock;
+use serde_json::Value;
+
+use crate::base::{BaseNode, short_type_name, Node as NodeTrait, SharedState, Action, ParamMap};
+use crate::action::ActionName;
+use crate::successors::Successors;
+use crate::hooks::NodeHooks;
+use crate::error::Result;
+
+/// A node handing one input to several labeled branches
+///
+/// `post` copies the value under `input_key` to `{key_prefix}/{branch}` for every branch.
+/// The flow then runs the successor of each branch action in order until that branch ends,
+/// and finally continues from the continuation action (default "default").
+#[derive(Clone)]
+pub struct FanOutNode {
+    /// Base node implementation
+    base: BaseNode,
+    
+    /// Shared state key holding the value to duplicate
+    input_key: String,
+    
+    /// Branch actions, run in order
+    branches: Vec<String>,
+    
+    /// Action followed once every branch has finished
+    continuation: ActionName,
+    
+    /// Prefix of the per-branch shared state keys
+    key_prefix: String,
+}
+
+impl FanOutNode {
+    /// Create a fan-out node duplicating `input_key` to each branch
+    pub fn new(input_key: &str, branches: &[&str]) -> Self {
+        Self {
+            base: BaseNode::new(),
+            input_key: input_key.to_string(),
+            branches: branches.iter().map(|b| b.to_string()).collect(),
+            continuation: ActionName::default_action(),
+            key_prefix: "fan_out".to_string(),
+        }
+    }
+    
+    /// Set the action followed after every branch has run
+    pub fn continue_with(mut self, action: &str) -> Self {
+        self.continuation = ActionName::from(action);
+        self
+    }
+    
+    /// Set the prefix of the per-branch shared state keys
+    pub fn key_prefix(mut self, prefix: &str) -> Self {
+        self.key_prefix = prefix.to_string();
+        self
+    }
+    
+    /// The shared state key a branch reads its copy from
+    pub fn branch_key(&self, branch: &str) -> String {
+        format!("{}/{}", self.key_prefix, branch)
+    }
+}
+
+impl NodeTrait for FanOutNode {
+    fn params(&self) -> Arc<ParamMap> {
+        self.base.params()
+    }
+    
+    fn name(&self) -> String {
+        self.base.assigned_name().unwrap_or_else(short_type_name::<Self>)
+    }
+    
+    fn set_name(&self, name: &str) {
+        self.base.set_name(name);
+    }
+    
+    fn hooks(&self) -> NodeHooks {
+        self.base.hooks()
+    }
+    
+    fn set_hooks(&self, hooks: NodeHooks) {
+        self.base.set_hooks(hooks);
+    }
+    
+    fn successors(&self) -> Arc<RwLock<Successors>> {
+        self.base.successors()
+    }
+    
+    fn set_params(&self, params: Arc<ParamMap>) {
+        self.base.set_params(params);
+    }
+    
+    fn add_successor(&self, node: Arc<dyn NodeTrait>, action: &str) -> Result<Arc<dyn NodeTrait>> {
+        self.base.add_successor(node, action)
+    }
+    
+    fn branch_actions(&self) -> &[String] {
+        &self.branches
+    }
+    
+    fn prep(&self, shared: &mut SharedState) -> Result<Value> {
+        Ok(shared.get(&self.input_key).cloned().unwrap_or(Value::Null))
+    }
+    
+    fn post(&self, shared: &mut SharedState, prep_res: Value, _exec_res: Value) -> Result<Action> {
+        for branch in &self.branches {
+            shared.insert(self.branch_key(branch), prep_res.clone());
+        }
+        Ok(Some(self.continuation.clone()))
+    }
+}
\ No newline at end of file
@@ -0,0 +1,908 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use parking_lot::RwLock;
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use serde_json::Value;
+
+use crate::base::{BaseNode, short_type_name, Node as NodeTrait, SharedState, Action, ParamMap};
+use crate::successors::Successors;
+use crate::hooks::NodeHooks;
+use crate::param_spec::ParamSpec;
+use crate::dataflow::KeySpec;
+use crate::action::ActionSet;
+use crate::async_node::AsyncNodeTrait;
+use crate::retry::{self, RetryPolicy, FixedRetry};
+use crate::sleeper::{self, Sleeper};
+use crate::rate_limit::RateLimiter;
+use crate::heartbeat::{HeartbeatCallback, HeartbeatConfig};
+use crate::error::{Error, Result};
+
+/// Prep closure, reading the shared state
+pub(crate) type PrepFn = Arc<dyn Fn(&SharedState) -> Result<Value> + Send + Sync>;
+
+/// Exec closure, mapping the prep result
+pub(crate) type ExecFn = Arc<dyn Fn(&Value) -> Result<Value> + Send + Sync>;
+
+/// Fallback closure, mapping the prep result and the last error
+type FallbackFn = Arc<dyn Fn(&Value, Error) -> Result<Value> + Send + Sync>;
+
+/// Async exec closure, mapping an owned prep result
+pub(crate) type AsyncExecFn = Arc<dyn Fn(Value) -> BoxFuture<'static, Result<Value>> + Send + Sync>;
+
+/// Async fallback closure, mapping the prep result and the last error
+pub(crate) type AsyncFallbackFn = Arc<dyn Fn(Value, Error) -> BoxFuture<'static, Result<Value>> + Send + Sync>;
+
+/// Post closure, writing results and choosing the action
+pub(crate) type PostFn = Arc<dyn Fn(&mut SharedState, Value, Value) -> Result<Action> + Send + Sync>;
+
+/// A node built from closures instead of a trait impl
+///
+/// Without closures it preps `null`, passes the prep result through exec, and returns no action.
+/// Failed exec attempts are retried according to its retry policy, a single attempt by default,
+/// and the last error is handed to the fallback closure, if any.
+#[derive(Clone)]
+pub struct FnNode {
+    /// Base node implementation
+    base: BaseNode,
+    
+    /// Prep closure, if set
+    prep: Option<PrepFn>,
+    
+    /// Exec closure, if set
+    exec: Option<ExecFn>,
+    
+    /// Fallback closure, if set
+    fallback: Option<FallbackFn>,
+    
+    /// Post closure, if set
+    post: Option<PostFn>,
+    
+    /// Decides which failed attempts are retried
+    retry: Arc<dyn RetryPolicy>,
+    
+    /// Longest a single exec attempt may take
+    timeout: Option<Duration>,
+    
+    /// Source of the waits between attempts
+    sleeper: Arc<dyn Sleeper>,
+    
+    /// Limiter every exec attempt acquires a permit from, when set
+    limiter: Option<Arc<RateLimiter>>,
+    
+    /// Params the node expects
+    param_specs: Vec<ParamSpec>,
+    
+    /// Actions the node can return, when declared
+    actions: Vec<String>,
+    
+    /// Shared state keys the node reads
+    reads: Vec<KeySpec>,
+    
+    /// Shared state keys the node writes
+    writes: Vec<KeySpec>,
+}
+
+impl FnNode {
+    /// Create a node with the default steps
+    pub fn new() -> Self {
+        Self {
+            base: BaseNode::new(),
+            prep: None,
+            exec: None,
+            fallback: None,
+            post: None,
+            retry: Arc::new(FixedRetry::new(1, Duration::ZERO)),
+            timeout: None,
+            sleeper: sleeper::real(),
+            limiter: None,
+            param_specs: Vec::new(),
+            actions: Vec::new(),
+            reads: Vec::new(),
+            writes: Vec::new(),
+        }
+    }
+    
+    /// Compute the prep result from the shared state
+    pub fn with_prep(mut self, f: impl Fn(&SharedState) -> Result<Value> + Send + Sync + 'static) -> Self {
+        self.prep = Some(Arc::new(f));
+        self
+    }
+    
+    /// Compute the exec result from the prep result
+    pub fn with_exec(mut self, f: impl Fn(&Value) -> Result<Value> + Send + Sync + 'static) -> Self {
+        self.exec = Some(Arc::new(f));
+        self
+    }
+    
+    /// Recover from the last failed exec attempt, given the prep result and the error
+    pub fn with_fallback(mut self, f: impl Fn(&Value, Error) -> Result<Value> + Send + Sync + 'static) -> Self {
+        self.fallback = Some(Arc::new(f));
+        self
+    }
+    
+    /// Write results into the shared state and choose the action
+    pub fn with_post(mut self, f: impl Fn(&mut SharedState, Value, Value) -> Result<Action> + Send + Sync + 'static) -> Self {
+        self.post = Some(Arc::new(f));
+        self
+    }
+    
+    /// Allow up to `max_attempts` exec attempts, waiting `wait` milliseconds between them
+    pub fn retries(mut self, max_attempts: usize, wait: u64) -> Self {
+        self.retry = Arc::new(FixedRetry::new(max_attempts, Duration::from_millis(wait)));
+        self
+    }
+    
+    /// Let `policy` decide which failures are retried and how long to wait before each retry
+    pub fn with_retry_policy(mut self, policy: Arc<dyn RetryPolicy>) -> Self {
+        self.retry = policy;
+        self
+    }
+    
+    /// Route the waits between retries through `sleeper`
+    pub fn with_sleeper(mut self, sleeper: Arc<dyn Sleeper>) -> Self {
+        self.sleeper = sleeper;
+        self
+    }
+    
+    /// Fail any exec attempt that runs longer than `timeout` with `Error::Timeout`
+    ///
+    /// The attempt runs on its own thread, which is left to finish in the background after a timeout.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+    
+    /// Acquire a permit from `limiter` before every exec attempt, sharing its budget with other nodes
+    pub fn with_rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.limiter = Some(limiter);
+        self
+    }
+    
+    /// Declare a param the node expects, checked before a flow runs it
+    pub fn require_param(mut self, spec: ParamSpec) -> Self {
+        self.param_specs.push(spec);
+        self
+    }
+    
+    /// Declare a shared state key prep reads, checked by `Flow::check_dataflow`
+    pub fn declare_read(mut self, spec: KeySpec) -> Self {
+        self.reads.push(spec);
+        self
+    }
+    
+    /// Declare a shared state key post writes, checked by `Flow::check_dataflow`
+    pub fn declare_write(mut self, spec: KeySpec) -> Self {
+        self.writes.push(spec);
+        self
+    }
+    
+    /// Declare the action type post returns, so `Flow::validate` can check every action has a successor
+    pub fn with_actions<A: ActionSet>(mut self) -> Self {
+        self.actions = A::all_actions();
+        self
+    }
+}
+
+impl Default for FnNode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NodeTrait for FnNode {
+    fn params(&self) -> Arc<ParamMap> {
+        self.base.params()
+    }
+    
+    fn name(&self) -> String {
+        self.base.assigned_name().unwrap_or_else(short_type_name::<Self>)
+    }
+    
+    fn set_name(&self, name: &str) {
+        self.base.set_name(name);
+    }
+    
+    fn hooks(&self) -> NodeHooks {
+        self.base.hooks()
+    }
+    
+    fn set_hooks(&self, hooks: NodeHooks) {
+        self.base.set_hooks(hooks);
+    }
+    
+    fn required_params(&self) -> &[ParamSpec] {
+        &self.param_specs
+    }
+    
+    fn declared_actions(&self) -> &[String] {
+        &self.actions
+    }
+    
+    fn reads(&self) -> &[KeySpec] {
+        &self.reads
+    }
+    
+    fn writes(&self) -> &[KeySpec] {
+        &self.writes
+    }
+    
+    fn successors(&self) -> Arc<RwLock<Successors>> {
+        self.base.successors()
+    }
+    
+    fn set_params(&self, params: Arc<ParamMap>) {
+        self.base.set_params(params);
+    }
+    
+    fn add_successor(&self, node: Arc<dyn NodeTrait>, action: &str) -> Result<Arc<dyn NodeTrait>> {
+        self.base.add_successor(node, action)
+    }
+    
+    fn prep_readonly(&self, shared: &SharedState) -> Result<Value> {
+        match &self.prep {
+            Some(f) => f(shared),
+            None => Ok(Value::Null),
+        }
+    }
+    
+    fn exec(&self, prep_res: &Value) -> Result<Value> {
+        match &self.exec {
+            Some(f) => f(prep_res),
+            None => Ok(prep_res.clone()),
+        }
+    }
+    
+    fn post(&self, shared: &mut SharedState, prep_res: Value, exec_res: Value) -> Result<Action> {
+        match &self.post {
+            Some(f) => f(shared, prep_res, exec_res),
+            None => Ok(None),
+        }
+    }
+    
+    fn exec_fallback(&self, prep_res: &Value, error: Error) -> Result<Value> {
+        match &self.fallback {
+            Some(f) => f(prep_res, error),
+            None => Err(error),
+        }
+    }
+    
+    fn _exec(&self, prep_res: &Value) -> Result<Value> {
+        let attempts = retry::Attempts::new(self.retry.as_ref(), self.sleeper.as_ref())
+            .limiter(self.limiter.as_deref())
+            .timeout(self.timeout);
+        retry::drive(self, Self::exec, attempts, prep_res, |e, _| self.exec_fallback(prep_res, e))
+    }
+}
+
+/// An async node built from closures, with an async exec step
+///
+/// Prep and post stay synchronous since they borrow the shared state; the exec closure
+/// returns a future, so `|v| async move { ... }` works directly.
+#[derive(Clone)]
+pub struct AsyncFnNode {
+    /// Base node implementation
+    base: BaseNode,
+    
+    /// Prep closure, if set
+    prep: Option<PrepFn>,
+    
+    /// Async exec closure, if set
+    exec: Option<AsyncExecFn>,
+    
+    /// Async fallback closure, if set
+    fallback: Option<AsyncFallbackFn>,
+    
+    /// Post closure, if set
+    post: Option<PostFn>,
+    
+    /// Decides which failed attempts are retried
+    retry: Arc<dyn RetryPolicy>,
+    
+    /// Longest a single exec attempt may take
+    timeout: Option<Duration>,
+    
+    /// Source of the waits between attempts
+    sleeper: Arc<dyn Sleeper>,
+    
+    /// Limiter every exec attempt acquires a permit from, when set
+    limiter: Option<Arc<RateLimiter>>,
+    
+    /// Heartbeat fired while an exec attempt runs, when set
+    heartbeat: Option<HeartbeatConfig>,
+    
+    /// Params the node expects
+    param_specs: Vec<ParamSpec>,
+    
+    /// Actions the node can return, when declared
+    actions: Vec<String>,
+    
+    /// Shared state keys the node reads
+    reads: Vec<KeySpec>,
+    
+    /// Shared state keys the node writes
+    writes: Vec<KeySpec>,
+}
+
+impl AsyncFnNode {
+    /// Create a node with the default steps
+    pub fn new() -> Self {
+        Self {
+            base: BaseNode::new(),
+            prep: None,
+            exec: None,
+            fallback: None,
+            post: None,
+            retry: Arc::new(FixedRetry::new(1, Duration::ZERO)),
+            timeout: None,
+            sleeper: sleeper::real(),
+            limiter: None,
+            heartbeat: None,
+            param_specs: Vec::new(),
+            actions: Vec::new(),
+            reads: Vec::new(),
+            writes: Vec::new(),
+        }
+    }
+    
+    /// Compute the prep result from the shared state
+    pub fn with_prep(mut self, f: impl Fn(&SharedState) -> Result<Value> + Send + Sync + 'static) -> Self {
+        self.prep = Some(Arc::new(f));
+        self
+    }
+    
+    /// Recover from the last failed exec attempt asynchronously, given the prep result and the error
+    pub fn with_fallback<F, Fut>(mut self, f: F) -> Self
+    where
+        F: Fn(Value, Error) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Value>> + Send + 'static,
+    {
+        self.fallback = Some(Arc::new(move |prep_res, error| Box::pin(f(prep_res, error))));
+        self
+    }
+    
+    /// Compute the exec result from the prep result asynchronously
+    pub fn with_exec<F, Fut>(mut self, f: F) -> Self
+    where
+        F: Fn(Value) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Value>> + Send + 'static,
+    {
+        self.exec = Some(Arc::new(move |prep_res| Box::pin(f(prep_res))));
+        self
+    }
+    
+    /// Write results into the shared state and choose the action
+    pub fn with_post(mut self, f: impl Fn(&mut SharedState, Value, Value) -> Result<Action> + Send + Sync + 'static) -> Self {
+        self.post = Some(Arc::new(f));
+        self
+    }
+    
+    /// Allow up to `max_attempts` exec attempts, waiting `wait` milliseconds between them
+    pub fn retries(mut self, max_attempts: usize, wait: u64) -> Self {
+        self.retry = Arc::new(FixedRetry::new(max_attempts, Duration::from_millis(wait)));
+        self
+    }
+    
+    /// Let `policy` decide which failures are retried and how long to wait before each retry
+    pub fn with_retry_policy(mut self, policy: Arc<dyn RetryPolicy>) -> Self {
+        self.retry = policy;
+        self
+    }
+    
+    /// Route the waits between retries through `sleeper`
+    pub fn with_sleeper(mut self, sleeper: Arc<dyn Sleeper>) -> Self {
+        self.sleeper = sleeper;
+        self
+    }
+    
+    /// Fail any exec attempt that runs longer than `timeout` with `Error::Timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+    
+    /// Acquire a permit from `limiter` before every exec attempt, sharing its budget with other nodes
+    pub fn with_rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.limiter = Some(limiter);
+        self
+    }
+    
+    /// Call `callback` every `interval` while an exec attempt runs, in place of the flow's default heartbeat
+    pub fn with_heartbeat(mut self, interval: Duration, callback: HeartbeatCallback) -> Self {
+        self.heartbeat = Some(HeartbeatConfig::new(interval, callback));
+        self
+    }
+    
+    /// Declare a param the node expects, checked before a flow runs it
+    pub fn require_param(mut self, spec: ParamSpec) -> Self {
+        self.param_specs.push(spec);
+        self
+    }
+    
+    /// Declare a shared state key prep reads, checked by `Flow::check_dataflow`
+    pub fn declare_read(mut self, spec: KeySpec) -> Self {
+        self.reads.push(spec);
+        self
+    }
+    
+    /// Declare a shared state key post writes, checked by `Flow::check_dataflow`
+    pub fn declare_write(mut self, spec: KeySpec) -> Self {
+        self.writes.push(spec);
+        self
+    }
+    
+    /// Declare the action type post returns, so `Flow::validate` can check every action has a successor
+    pub fn with_actions<A: ActionSet>(mut self) -> Self {
+        self.actions = A::all_actions();
+        self
+    }
+}
+
+impl Default for AsyncFnNode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NodeTrait for AsyncFnNode {
+    fn params(&self) -> Arc<ParamMap> {
+        self.base.params()
+    }
+    
+    fn name(&self) -> String {
+        self.base.assigned_name().unwrap_or_else(short_type_name::<Self>)
+    }
+    
+    fn set_name(&self, name: &str) {
+        self.base.set_name(name);
+    }
+    
+    fn hooks(&self) -> NodeHooks {
+        self.base.hooks()
+    }
+    
+    fn set_hooks(&self, hooks: NodeHooks) {
+        self.base.set_hooks(hooks);
+    }
+    
+    fn required_params(&self) -> &[ParamSpec] {
+        &self.param_specs
+    }
+    
+    fn declared_actions(&self) -> &[String] {
+        &self.actions
+    }
+    
+    fn reads(&self) -> &[KeySpec] {
+        &self.reads
+    }
+    
+    fn writes(&self) -> &[KeySpec] {
+        &self.writes
+    }
+    
+    fn successors(&self) -> Arc<RwLock<Successors>> {
+        self.base.successors()
+    }
+    
+    fn set_params(&self, params: Arc<ParamMap>) {
+        self.base.set_params(params);
+    }
+    
+    fn add_successor(&self, node: Arc<dyn NodeTrait>, action: &str) -> Result<Arc<dyn NodeTrait>> {
+        self.base.add_successor(node, action)
+    }
+    
+    fn prep_readonly(&self, shared: &SharedState) -> Result<Value> {
+        match &self.prep {
+            Some(f) => f(shared),
+            None => Ok(Value::Null),
+        }
+    }
+    
+    fn exec(&self, _prep_res: &Value) -> Result<Value> {
+        Err(Error::InvalidOperation("Use exec_async".into()))
+    }
+    
+    fn post(&self, shared: &mut SharedState, prep_res: Value, exec_res: Value) -> Result<Action> {
+        match &self.post {
+            Some(f) => f(shared, prep_res, exec_res),
+            None => Ok(None),
+        }
+    }
+    
+    fn _run(&self, _shared: &mut SharedState) -> Result<Action> {
+        Err(Error::InvalidOperation("Use run_async".into()))
+    }
+    
+    fn as_async(&self) -> Option<&dyn AsyncNodeTrait> {
+        Some(self)
+    }
+}
+
+#[async_trait]
+impl AsyncNodeTrait for AsyncFnNode {
+    async fn exec_async(&self, prep_res: &Value) -> Result<Value> {
+        match &self.exec {
+            Some(f) => f(prep_res.clone()).await,
+            None => Ok(prep_res.clone()),
+        }
+    }
+    
+    async fn post_async(&self, shared: &mut SharedState, prep_res: Value, exec_res: Value) -> Result<Action> {
+        self.post(shared, prep_res, exec_res)
+    }
+    
+    async fn exec_fallback_async(&self, prep_res: &Value, error: Error) -> Result<Value> {
+        match &self.fallback {
+            Some(f) => f(prep_res.clone(), error).await,
+            None => Err(error),
+        }
+    }
+    
+    async fn _exec_async(&self, prep_res: &Value) -> Result<Value> {
+        let attempts = retry::Attempts::new(self.retry.as_ref(), self.sleeper.as_ref())
+            .limiter(self.limiter.as_deref())
+            .timeout(self.timeout)
+            .heartbeat(self.heartbeat.clone());
+        retry::drive_async(
+            attempts,
+            || self.name(),
+            |_| self.exec_async(prep_res),
+            |e, ctx| self.exec_fallback_async_ctx(prep_res, e, ctx),
+        ).await
+    }
+}
+
+/// A node built from the same closures as `FnNode`, running exec on tokio's blocking thread pool
+///
+/// Meant for CPU-bound work inside an `AsyncFlow`: each exec attempt runs on a blocking thread,
+/// so the other branches and batch items of the flow keep running meanwhile. A panicking attempt
+/// fails like any other error and can be retried, and all attempts share one copy of the prep
+/// result. An attempt that times out, or outlives the
+/// deadline of the run, fails right away, though its thread runs the closure to the end.
+#[derive(Clone)]
+pub struct BlockingNode {
+    /// Base node implementation
+    base: BaseNode,
+    
+    /// Prep closure, if set
+    prep: Option<PrepFn>,
+    
+    /// Exec closure, if set
+    exec: Option<ExecFn>,
+    
+    /// Post closure, if set
+    post: Option<PostFn>,
+    
+    /// Decides which failed attempts are retried
+    retry: Arc<dyn RetryPolicy>,
+    
+    /// Longest a single exec attempt may take
+    timeout: Option<Duration>,
+    
+    /// Source of the waits between attempts
+    sleeper: Arc<dyn Sleeper>,
+    
+    /// Limiter every exec attempt acquires a permit from, when set
+    limiter: Option<Arc<RateLimiter>>,
+    
+    /// Params the node expects
+    param_specs: Vec<ParamSpec>,
+    
+    /// Actions the node can return, when declared
+    actions: Vec<String>,
+    
+    /// Shared state keys the node reads
+    reads: Vec<KeySpec>,
+    
+    /// Shared state keys the node writes
+    writes: Vec<KeySpec>,
+}
+
+impl BlockingNode {
+    /// Create a node with the default steps
+    pub fn new() -> Self {
+        Self {
+            base: BaseNode::new(),
+            prep: None,
+            exec: None,
+            post: None,
+            retry: Arc::new(FixedRetry::new(1, Duration::ZERO)),
+            timeout: None,
+            sleeper: sleeper::real(),
+            limiter: None,
+            param_specs: Vec::new(),
+            actions: Vec::new(),
+            reads: Vec::new(),
+            writes: Vec::new(),
+        }
+    }
+    
+    /// Compute the prep result from the shared state
+    pub fn with_prep(mut self, f: impl Fn(&SharedState) -> Result<Value> + Send + Sync + 'static) -> Self {
+        self.prep = Some(Arc::new(f));
+        self
+    }
+    
+    /// Compute the exec result from the prep result, on a blocking thread
+    pub fn with_exec(mut self, f: impl Fn(&Value) -> Result<Value> + Send + Sync + 'static) -> Self {
+        self.exec = Some(Arc::new(f));
+        self
+    }
+    
+    /// Write results into the shared state and choose the action
+    pub fn with_post(mut self, f: impl Fn(&mut SharedState, Value, Value) -> Result<Action> + Send + Sync + 'static) -> Self {
+        self.post = Some(Arc::new(f));
+        self
+    }
+    
+    /// Allow up to `max_attempts` exec attempts, waiting `wait` milliseconds between them
+    pub fn retries(mut self, max_attempts: usize, wait: u64) -> Self {
+        self.retry = Arc::new(FixedRetry::new(max_attempts, Duration::from_millis(wait)));
+        self
+    }
+    
+    /// Let `policy` decide which failures are retried and how long to wait before each retry
+    pub fn with_retry_policy(mut self, policy: Arc<dyn RetryPolicy>) -> Self {
+        self.retry = policy;
+        self
+    }
+    
+    /// Fail any exec attempt that runs longer than `timeout` with `Error::Timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+    
+    /// Route the waits between retries through `sleeper`
+    pub fn with_sleeper(mut self, sleeper: Arc<dyn Sleeper>) -> Self {
+        self.sleeper = sleeper;
+        self
+    }
+    
+    /// Declare a param the node expects, checked before a flow runs it
+    pub fn require_param(mut self, spec: ParamSpec) -> Self {
+        self.param_specs.push(spec);
+        self
+    }
+    
+    /// Declare a shared state key prep reads, checked by `Flow::check_dataflow`
+    pub fn declare_read(mut self, spec: KeySpec) -> Self {
+        self.reads.push(spec);
+        self
+    }
+    
+    /// Declare a shared state key post writes, checked by `Flow::check_dataflow`
+    pub fn declare_write(mut self, spec: KeySpec) -> Self {
+        self.writes.push(spec);
+        self
+    }
+    
+    /// Declare the action type post returns, so `Flow::validate` can check every action has a successor
+    pub fn with_actions<A: ActionSet>(mut self) -> Self {
+        self.actions = A::all_actions();
+        self
+    }
+    
+    /// Acquire a permit from `limiter` before every exec attempt, sharing its budget with other nodes
+    pub fn with_rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.limiter = Some(limiter);
+        self
+    }
+    
+    /// Run one exec attempt on a blocking thread, sharing `prep_res` with the other attempts
+    async fn exec_blocking(&self, prep_res: Arc<Value>, attempt: usize) -> Result<Value> {
+        let exec = self.exec.clone();
+        let task = tokio::task::spawn_blocking(move || {
+            retry::with_attempt(attempt, || retry::guard_attempt(|| match &exec {
+                Some(f) => f(&prep_res),
+                None => Ok(Value::clone(&prep_res)),
+            }))
+        });
+        task.await.map_err(|e| Error::NodeExecution(format!("Blocking exec attempt failed: {}", e)))?
+    }
+}
+
+impl Default for BlockingNode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NodeTrait for BlockingNode {
+    fn params(&self) -> Arc<ParamMap> {
+        self.base.params()
+    }
+    
+    fn name(&self) -> String {
+        self.base.assigned_name().unwrap_or_else(short_type_name::<Self>)
+    }
+    
+    fn set_name(&self, name: &str) {
+        self.base.set_name(name);
+    }
+    
+    fn hooks(&self) -> NodeHooks {
+        self.base.hooks()
+    }
+    
+    fn set_hooks(&self, hooks: NodeHooks) {
+        self.base.set_hooks(hooks);
+    }
+    
+    fn required_params(&self) -> &[ParamSpec] {
+        &self.param_specs
+    }
+    
+    fn declared_actions(&self) -> &[String] {
+        &self.actions
+    }
+    
+    fn reads(&self) -> &[KeySpec] {
+        &self.reads
+    }
+    
+    fn writes(&self) -> &[KeySpec] {
+        &self.writes
+    }
+    
+    fn successors(&self) -> Arc<RwLock<Successors>> {
+        self.base.successors()
+    }
+    
+    fn set_params(&self, params: Arc<ParamMap>) {
+        self.base.set_params(params);
+    }
+    
+    fn add_successor(&self, node: Arc<dyn NodeTrait>, action: &str) -> Result<Arc<dyn NodeTrait>> {
+        self.base.add_successor(node, action)
+    }
+    
+    fn prep_readonly(&self, shared: &SharedState) -> Result<Value> {
+        match &self.prep {
+            Some(f) => f(shared),
+            None => Ok(Value::Null),
+        }
+    }
+    
+    fn exec(&self, prep_res: &Value) -> Result<Value> {
+        match &self.exec {
+            Some(f) => f(prep_res),
+            None => Ok(prep_res.clone()),
+        }
+    }
+    
+    fn post(&self, shared: &mut SharedState, prep_res: Value, exec_res: Value) -> Result<Action> {
+        match &self.post {
+            Some(f) => f(shared, prep_res, exec_res),
+            None => Ok(None),
+        }
+    }
+    
+    fn _run(&self, _shared: &mut SharedState) -> Result<Action> {
+        Err(Error::InvalidOperation("Use run_async".into()))
+    }
+    
+    fn as_async(&self) -> Option<&dyn AsyncNodeTrait> {
+        Some(self)
+    }
+}
+
+#[async_trait]
+impl AsyncNodeTrait for BlockingNode {
+    async fn post_async(&self, shared: &mut SharedState, prep_res: Value, exec_res: Value) -> Result<Action> {
+        self.post(shared, prep_res, exec_res)
+    }
+    
+    async fn _exec_async(&self, prep_res: &Value) -> Result<Value> {
+        let attempts = retry::Attempts::new(self.retry.as_ref(), self.sleeper.as_ref())
+            .limiter(self.limiter.as_deref())
+            .timeout(self.timeout);
+        let moved = Arc::new(prep_res.clone());
+        retry::drive_async(
+            attempts,
+            || self.name(),
+            |attempt| self.exec_blocking(moved.clone(), attempt),
+            |e, ctx| self.exec_fallback_async_ctx(prep_res, e, ctx),
+        ).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use parking_lot::Mutex;
+    use serde_json::json;
+    
+    use crate::retry::current_attempt;
+    
+    use super::*;
+    
+    fn flaky(failures: usize) -> impl Fn(&Value) -> Result<Value> + Send + Sync + 'static {
+        move |prep| match current_attempt() {
+            Some(attempt) if attempt < failures => Err(Error::NodeExecution(format!("attempt {}", attempt))),
+            _ => Ok(prep.clone()),
+        }
+    }
+    
+    #[test]
+    fn fn_node_runs_its_closures_and_retries() {
+        let node = FnNode::new()
+            .with_prep(|shared| Ok(shared["in"].clone()))
+            .with_exec(flaky(1))
+            .retries(2, 0)
+            .with_post(|shared, _, exec_res| {
+                shared.insert("out".to_string(), exec_res);
+                Ok(Some("done".into()))
+            });
+        let mut shared = SharedState::from([("in".to_string(), json!(7))]);
+        assert_eq!(node.run(&mut shared).unwrap().as_deref(), Some("done"));
+        assert_eq!(shared["out"], json!(7));
+    }
+    
+    #[test]
+    fn fn_node_hands_the_last_error_to_its_fallback() {
+        let node = FnNode::new()
+            .with_exec(flaky(usize::MAX))
+            .retries(2, 0)
+            .with_fallback(|prep, e| Ok(json!({"prep": prep, "error": e.to_string()})));
+        assert_eq!(
+            node._exec(&json!(1)).unwrap(),
+            json!({"prep": 1, "error": "Node execution error: attempt 1"})
+        );
+        assert!(FnNode::new().with_exec(flaky(1))._exec(&json!(1)).is_err());
+    }
+    
+    #[test]
+    fn fn_node_times_out_slow_attempts() {
+        let node = FnNode::new()
+            .with_exec(|_| {
+                std::thread::sleep(Duration::from_millis(200));
+                Ok(Value::Null)
+            })
+            .with_timeout(Duration::from_millis(10));
+        assert!(matches!(node._exec(&Value::Null), Err(Error::Timeout(_))));
+    }
+    
+    #[tokio::test(start_paused = true)]
+    async fn async_fn_node_times_out_attempts_and_fires_its_heartbeat() {
+        let beats = Arc::new(Mutex::new(Vec::new()));
+        let node = AsyncFnNode::new()
+            .with_exec(|prep| async move {
+                if current_attempt() == Some(0) {
+                    tokio::time::sleep(Duration::from_secs(10)).await;
+                }
+                Ok(prep)
+            })
+            .retries(2, 0)
+            .with_timeout(Duration::from_secs(3))
+            .with_heartbeat(Duration::from_secs(1), {
+                let beats = beats.clone();
+                Arc::new(move |beat| beats.lock().push(beat.attempt))
+            });
+        assert_eq!(node._exec_async(&json!("ok")).await.unwrap(), json!("ok"));
+        assert_eq!(*beats.lock(), [0, 0]);
+    }
+    
+    #[tokio::test]
+    async fn blocking_node_retries_on_the_blocking_pool() {
+        let node = BlockingNode::new().with_exec(flaky(2)).retries(3, 0);
+        assert_eq!(node._exec_async(&json!([1, 2])).await.unwrap(), json!([1, 2]));
+        
+        let node = BlockingNode::new().with_exec(flaky(2)).retries(2, 0);
+        assert!(matches!(node._exec_async(&json!(1)).await, Err(Error::NodeExecution(msg)) if msg == "attempt 1"));
+    }
+    
+    #[tokio::test]
+    async fn prep_reads_through_the_read_only_hook() {
+        let mut shared = SharedState::from([("in".to_string(), json!("draft"))]);
+        let node = FnNode::new().with_prep(|shared| Ok(shared["in"].clone()));
+        assert_eq!(node.prep_readonly(&shared).unwrap(), json!("draft"));
+        assert_eq!(node.prep(&mut shared).unwrap(), json!("draft"));
+        
+        let node = AsyncFnNode::new().with_prep(|shared| Ok(json!(shared.len())));
+        assert_eq!(node.prep_async(&mut shared).await.unwrap(), json!(1));
+        assert_eq!(shared, SharedState::from([("in".to_string(), json!("draft"))]));
+    }
+}
\ No newline at end of file
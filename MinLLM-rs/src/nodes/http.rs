@@ -0,0 +1,378 @@
+use std::sync::Arc;
+use std::time::Duration;
+use parking_lot::RwLock;
+use async_trait::async_trait;
+use reqwest::{Client, Method, StatusCode};
+use serde_json::{json, Value};
+
+use crate::base::{BaseNode, short_type_name, Node as NodeTrait, SharedState, Action, ParamMap};
+use crate::action::ActionName;
+use crate::successors::Successors;
+use crate::hooks::NodeHooks;
+use crate::async_node::AsyncNodeTrait;
+use crate::sleeper::{self, Sleeper};
+use crate::retry::{self, RetryPolicy, FixedRetry};
+use crate::nodes::interpolate::{interpolate, interpolate_value};
+use crate::error::{Error, Result};
+
+/// An async node performing an HTTP request described by its params
+///
+/// Recognized params: `method` (default "GET"), `url`, `headers`, `body` (a JSON template),
+/// `timeout_ms`, and `output_key` (default "response"). Strings in `url`, `headers` and `body`
+/// may reference `${param:key}` and `${store:key}`.
+///
+/// A 5xx or 429 response fails the attempt with `Error::HttpStatus`, so the retry policy can
+/// retry it; once the policy gives up, the fallback stores that response like any other.
+/// `timeout_ms` bounds each attempt, failing it with `Error::Timeout`.
+#[derive(Clone)]
+pub struct HttpRequestNode {
+    /// Base node implementation
+    base: BaseNode,
+    
+    /// HTTP client reused across executions
+    client: Client,
+    
+    /// Decides which failures are retried and the wait before each retry
+    retry: Arc<dyn RetryPolicy>,
+    
+    /// Source of the waits between retries
+    sleeper: Arc<dyn Sleeper>,
+}
+
+impl HttpRequestNode {
+    /// Create a new HTTP request node making up to `max_retries` attempts, at least one
+    pub fn new(max_retries: usize, wait: u64) -> Self {
+        Self {
+            base: BaseNode::new(),
+            client: Client::new(),
+            retry: Arc::new(FixedRetry::new(max_retries.max(1), Duration::from_millis(wait))),
+            sleeper: sleeper::real(),
+        }
+    }
+    
+    /// Let `policy` decide which failures are retried and how long to wait before each retry
+    pub fn with_retry_policy(mut self, policy: Arc<dyn RetryPolicy>) -> Self {
+        self.retry = policy;
+        self
+    }
+    
+    /// Route the waits between retries through `sleeper`
+    pub fn with_sleeper(mut self, sleeper: Arc<dyn Sleeper>) -> Self {
+        self.sleeper = sleeper;
+        self
+    }
+    
+    /// Whether a response status is worth retrying
+    fn is_retryable(status: StatusCode) -> bool {
+        status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+    }
+    
+    /// Send the request described by the prep result
+    async fn send(&self, request: &Value) -> Result<(StatusCode, Value)> {
+        let method = request["method"].as_str().unwrap_or("GET");
+        let method = Method::from_bytes(method.to_uppercase().as_bytes())
+            .map_err(|_| Error::InvalidOperation(format!("Invalid HTTP method '{}'", method)))?;
+        let url = request["url"]
+            .as_str()
+            .ok_or_else(|| Error::InvalidOperation("HttpRequestNode requires a 'url' param".into()))?;
+        
+        let mut builder = self.client.request(method, url);
+        if let Some(headers) = request["headers"].as_object() {
+            for (name, value) in headers {
+                let value = match value {
+                    Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                };
+                builder = builder.header(name.as_str(), value);
+            }
+        }
+        if !request["body"].is_null() {
+            builder = builder.json(&request["body"]);
+        }
+        
+        let response = builder
+            .send()
+            .await
+            .map_err(|e| Error::NodeExecution(format!("HTTP request to {} failed: {}", url, e)))?;
+        
+        let status = response.status();
+        let headers: serde_json::Map<String, Value> = response
+            .headers()
+            .iter()
+            .map(|(name, value)| {
+                let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+                (name.to_string(), Value::String(value))
+            })
+            .collect();
+        let text = response
+            .text()
+            .await
+            .map_err(|e| Error::NodeExecution(format!("Failed to read HTTP response body: {}", e)))?;
+        let body = serde_json::from_str(&text).unwrap_or(Value::String(text));
+        
+        Ok((status, json!({
+            "status": status.as_u16(),
+            "headers": headers,
+            "body": body,
+        })))
+    }
+}
+
+impl Default for HttpRequestNode {
+    fn default() -> Self {
+        Self::new(1, 0)
+    }
+}
+
+impl NodeTrait for HttpRequestNode {
+    fn params(&self) -> Arc<ParamMap> {
+        self.base.params()
+    }
+    
+    fn name(&self) -> String {
+        self.base.assigned_name().unwrap_or_else(short_type_name::<Self>)
+    }
+    
+    fn set_name(&self, name: &str) {
+        self.base.set_name(name);
+    }
+    
+    fn hooks(&self) -> NodeHooks {
+        self.base.hooks()
+    }
+    
+    fn set_hooks(&self, hooks: NodeHooks) {
+        self.base.set_hooks(hooks);
+    }
+    
+    fn successors(&self) -> Arc<RwLock<Successors>> {
+        self.base.successors()
+    }
+    
+    fn prep(&self, _shared: &mut SharedState) -> Result<Value> {
+        Err(Error::InvalidOperation("Use prep_async".into()))
+    }
+    
+    fn exec(&self, _prep_res: &Value) -> Result<Value> {
+        Err(Error::InvalidOperation("Use exec_async".into()))
+    }
+    
+    fn post(&self, _shared: &mut SharedState, _prep_res: Value, _exec_res: Value) -> Result<Action> {
+        Err(Error::InvalidOperation("Use post_async".into()))
+    }
+    
+    fn _run(&self, _shared: &mut SharedState) -> Result<Action> {
+        Err(Error::InvalidOperation("Use run_async".into()))
+    }
+    
+    fn as_async(&self) -> Option<&dyn AsyncNodeTrait> {
+        Some(self)
+    }
+    
+    fn set_params(&self, params: Arc<ParamMap>) {
+        self.base.set_params(params);
+    }
+    
+    fn add_successor(&self, node: Arc<dyn NodeTrait>, action: &str) -> Result<Arc<dyn NodeTrait>> {
+        self.base.add_successor(node, action)
+    }
+}
+
+#[async_trait]
+impl AsyncNodeTrait for HttpRequestNode {
+    async fn prep_async(&self, shared: &mut SharedState) -> Result<Value> {
+        let params = self.params();
+        
+        let url = params
+            .get("url")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| Error::InvalidOperation("HttpRequestNode requires a 'url' param".into()))?;
+        
+        Ok(json!({
+            "method": params.get("method").cloned().unwrap_or_else(|| json!("GET")),
+            "url": interpolate(url, &params, shared)?,
+            "headers": interpolate_value(params.get("headers").unwrap_or(&Value::Null), &params, shared)?,
+            "body": interpolate_value(params.get("body").unwrap_or(&Value::Null), &params, shared)?,
+            "timeout_ms": params.get("timeout_ms").cloned().unwrap_or(Value::Null),
+        }))
+    }
+    
+    async fn exec_async(&self, prep_res: &Value) -> Result<Value> {
+        match self.send(prep_res).await? {
+            (status, response) if Self::is_retryable(status) => Err(Error::HttpStatus { status: status.as_u16(), response }),
+            (_, response) => Ok(response),
+        }
+    }
+    
+    async fn exec_fallback_async(&self, _prep_res: &Value, error: Error) -> Result<Value> {
+        match error {
+            Error::HttpStatus { response, .. } => Ok(response),
+            error => Err(error),
+        }
+    }
+    
+    async fn post_async(&self, shared: &mut SharedState, _prep_res: Value, exec_res: Value) -> Result<Action> {
+        let output_key = self
+            .params()
+            .get("output_key")
+            .and_then(|v| v.as_str())
+            .unwrap_or("response")
+            .to_string();
+        
+        let action = match exec_res["status"].as_u64().unwrap_or(0) {
+            400..=499 => "client_error",
+            500..=599 => "server_error",
+            _ => "success",
+        };
+        
+        shared.insert(output_key, exec_res);
+        Ok(Some(ActionName::new(action)))
+    }
+    
+    async fn _exec_async(&self, prep_res: &Value) -> Result<Value> {
+        let attempts = retry::Attempts::new(self.retry.as_ref(), self.sleeper.as_ref())
+            .timeout(prep_res["timeout_ms"].as_u64().map(Duration::from_millis));
+        retry::drive_async(
+            attempts,
+            || self.name(),
+            |_| self.exec_async(prep_res),
+            |e, ctx| self.exec_fallback_async_ctx(prep_res, e, ctx),
+        ).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    
+    use super::*;
+    
+    /// A canned response of the stub server
+    struct Reply {
+        status: u16,
+        content_type: &'static str,
+        body: &'static str,
+        delay: Duration,
+    }
+    
+    fn reply(status: u16, content_type: &'static str, body: &'static str) -> Reply {
+        Reply { status, content_type, body, delay: Duration::ZERO }
+    }
+    
+    /// Serve `replies` in order, repeating the last one, returning the base URL and the request count
+    async fn serve(replies: Vec<Reply>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let count = requests.clone();
+        let replies = Arc::new(replies);
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let n = count.fetch_add(1, Ordering::SeqCst);
+                let replies = replies.clone();
+                tokio::spawn(async move {
+                    let reply = &replies[n.min(replies.len() - 1)];
+                    let mut request = Vec::new();
+                    let mut buf = [0; 1024];
+                    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                        let read = socket.read(&mut buf).await.unwrap_or(0);
+                        if read == 0 {
+                            break;
+                        }
+                        request.extend_from_slice(&buf[..read]);
+                    }
+                    tokio::time::sleep(reply.delay).await;
+                    let response = format!(
+                        "HTTP/1.1 {} X\r\ncontent-type: {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                        reply.status,
+                        reply.content_type,
+                        reply.body.len(),
+                        reply.body,
+                    );
+                    let _ = socket.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        (url, requests)
+    }
+    
+    fn node(url: &str, max_retries: usize, extra: Value) -> HttpRequestNode {
+        let node = HttpRequestNode::new(max_retries, 0);
+        let mut params = ParamMap::from([
+            ("url".to_string(), json!(format!("{}/${{param:path}}", url))),
+            ("path".to_string(), json!("items")),
+        ]);
+        if let Value::Object(extra) = extra {
+            params.extend(extra);
+        }
+        node.set_params_map(params);
+        node
+    }
+    
+    #[tokio::test]
+    async fn stores_a_json_response_and_routes_on_success() {
+        let (url, requests) = serve(vec![reply(200, "application/json", r#"{"ok":true}"#)]).await;
+        let mut shared = SharedState::new();
+        let action = node(&url, 1, json!({"output_key": "out"}))._run_async(&mut shared).await.unwrap();
+        
+        assert_eq!(action.as_deref(), Some("success"));
+        assert_eq!(shared["out"]["status"], json!(200));
+        assert_eq!(shared["out"]["body"], json!({"ok": true}));
+        assert_eq!(shared["out"]["headers"]["content-type"], json!("application/json"));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+    
+    #[tokio::test]
+    async fn retries_server_errors_then_routes_on_the_last_response() {
+        let (url, requests) = serve(vec![reply(500, "text/plain", "down"), reply(200, "application/json", "[1]")]).await;
+        let mut shared = SharedState::new();
+        let action = node(&url, 3, json!({}))._run_async(&mut shared).await.unwrap();
+        assert_eq!(action.as_deref(), Some("success"));
+        assert_eq!(shared["response"]["body"], json!([1]));
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        
+        let (url, requests) = serve(vec![reply(503, "text/plain", "down")]).await;
+        let mut shared = SharedState::new();
+        let action = node(&url, 2, json!({}))._run_async(&mut shared).await.unwrap();
+        assert_eq!(action.as_deref(), Some("server_error"));
+        assert_eq!(shared["response"]["status"], json!(503));
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+    
+    #[tokio::test]
+    async fn client_errors_are_not_retried() {
+        let (url, requests) = serve(vec![reply(404, "text/plain", "missing")]).await;
+        let mut shared = SharedState::new();
+        let action = node(&url, 3, json!({}))._run_async(&mut shared).await.unwrap();
+        assert_eq!(action.as_deref(), Some("client_error"));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+    
+    #[tokio::test]
+    async fn times_out_slow_responses() {
+        let slow = Reply { delay: Duration::from_secs(2), ..reply(200, "text/plain", "late") };
+        let (url, requests) = serve(vec![slow]).await;
+        let result = node(&url, 2, json!({"timeout_ms": 50}))._run_async(&mut SharedState::new()).await;
+        assert!(matches!(result, Err(Error::Timeout(timeout)) if timeout == Duration::from_millis(50)));
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+    
+    #[tokio::test]
+    async fn keeps_non_json_bodies_as_text() {
+        let (url, _) = serve(vec![reply(200, "text/html", "<p>hi</p>")]).await;
+        let mut shared = SharedState::new();
+        node(&url, 1, json!({}))._run_async(&mut shared).await.unwrap();
+        assert_eq!(shared["response"]["body"], json!("<p>hi</p>"));
+    }
+    
+    #[tokio::test]
+    async fn zero_retries_still_sends_the_request() {
+        let (url, requests) = serve(vec![reply(200, "application/json", "{}")]).await;
+        let action = node(&url, 0, json!({}))._run_async(&mut SharedState::new()).await.unwrap();
+        assert_eq!(action.as_deref(), Some("success"));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+use serde_json::Value;
+
+use crate::base::SharedState;
+use crate::error::{Error, Result};
+
+/// Resolve a single `param:key` or `store:key` reference
+fn resolve_reference<'a>(reference: &str, params: &'a HashMap<String, Value>, shared: &'a SharedState) -> Result<&'a Value> {
+    let (source, key) = reference
+        .split_once(':')
+        .ok_or_else(|| Error::InvalidOperation(format!("Invalid reference '${{{}}}'", reference)))?;
+    
+    let value = match source.trim() {
+        "param" => params.get(key.trim()),
+        "store" => shared.get(key.trim()),
+        other => return Err(Error::InvalidOperation(format!("Unknown reference source '{}'", other))),
+    };
+    
+    value.ok_or_else(|| Error::NodeExecution(format!("Unresolved reference '${{{}}}'", reference)))
+}
+
+/// Replace every `${param:key}` and `${store:key}` in a string
+pub(crate) fn interpolate(template: &str, params: &HashMap<String, Value>, shared: &SharedState) -> Result<String> {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    
+    while let Some(start) = rest.find("${") {
+        out.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| Error::InvalidOperation(format!("Unterminated reference in '{}'", template)))?;
+        
+        match resolve_reference(&rest[start + 2..start + end], params, shared)? {
+            Value::String(s) => out.push_str(s),
+            other => out.push_str(&other.to_string()),
+        }
+        rest = &rest[start + end + 1..];
+    }
+    
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Interpolate every string in a JSON template, keeping the type of values referenced on their own
+pub(crate) fn interpolate_value(template: &Value, params: &HashMap<String, Value>, shared: &SharedState) -> Result<Value> {
+    match template {
+        Value::String(s) => {
+            if s.starts_with("${") && s.ends_with('}') && s.matches("${").count() == 1 {
+                return resolve_reference(&s[2..s.len() - 1], params, shared).cloned();
+            }
+            Ok(Value::String(interpolate(s, params, shared)?))
+        },
+        Value::Array(items) => items
+            .iter()
+            .map(|item| interpolate_value(item, params, shared))
+            .collect::<Result<Vec<_>>>()
+            .map(Value::Array),
+        Value::Object(map) => {
+            let mut out = serde_json::Map::new();
+            for (key, value) in map {
+                out.insert(key.clone(), interpolate_value(value, params, shared)?);
+            }
+            Ok(Value::Object(out))
+        },
+        other => Ok(other.clone()),
+    }
+}
\ No newline at end of file
@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use parking_lot::{Mutex, RwLock};
+use tokio::time::Instant;
+use serde_json::{Map, Value};
+use log::warn;
+
+use crate::base::{BaseNode, short_type_name, Node as NodeTrait, SharedState, Action, ParamMap};
+use crate::successors::Successors;
+use crate::hooks::NodeHooks;
+use crate::error::Result;
+
+tokio::task_local! {
+    /// Arrivals at the joins of the async flow run the current task belongs to, by join node address
+    static ARRIVALS: Arc<Mutex<HashMap<usize, Arrivals>>>;
+}
+
+/// Branches that reached one join during a run
+struct Arrivals {
+    /// The join node
+    node: Arc<dyn NodeTrait>,
+    
+    /// Number of arrivals that lets the join run
+    quorum: usize,
+    
+    /// Longest the join waits for its quorum after the first arrival
+    timeout: Option<Duration>,
+    
+    /// Labels of the declared upstream nodes that arrived, in arrival order
+    from: Vec<String>,
+    
+    /// When the first branch arrived
+    first: Instant,
+    
+    /// Whether a branch has gone on through the join
+    fired: bool,
+}
+
+/// A node gathering the outputs of several upstream branches once enough of them have arrived
+///
+/// Each upstream branch is labeled by the name of the node that leads into the join. In an
+/// `AsyncFlow`, branches reaching the join before its quorum is met end there, and the branch
+/// completing the quorum runs it once. Branches of a concurrent fan-out only record their arrival,
+/// so the join runs on the merged state once the fan-out ends. With a timeout, a join whose quorum
+/// is never met runs when the flow has nothing else left to do, or as soon as a branch arrives
+/// after the timeout. A `Flow` runs the join every time a branch reaches it. Since a join runs at
+/// most once per async run, flow validation rejects joins that sit on a cycle.
+///
+/// Prep gathers `{label: value}` from the shared state key of each label (the label itself unless
+/// set with `with_key`), skipping absent keys, and post stores it under the output key.
+#[derive(Clone)]
+pub struct JoinNode {
+    /// Base node implementation
+    base: BaseNode,
+    
+    /// Labels of the upstream nodes the join waits for
+    upstream: Vec<String>,
+    
+    /// Shared state key each label's output is read from, when not the label itself
+    keys: HashMap<String, String>,
+    
+    /// Number of arrivals that lets the join run
+    quorum: usize,
+    
+    /// Longest the join waits for its quorum after the first arrival
+    timeout: Option<Duration>,
+    
+    /// Shared state key the gathered object is written to
+    output_key: String,
+}
+
+impl JoinNode {
+    /// Create a join waiting for every node in `upstream`, by name
+    pub fn new(upstream: &[&str]) -> Self {
+        Self {
+            base: BaseNode::new(),
+            upstream: upstream.iter().map(|u| u.to_string()).collect(),
+            keys: HashMap::new(),
+            quorum: upstream.len(),
+            timeout: None,
+            output_key: "joined".to_string(),
+        }
+    }
+    
+    /// Name the node in logs, errors and traces
+    pub fn with_name(self, name: &str) -> Self {
+        self.set_name(name);
+        self
+    }
+    
+    /// Read the output of the `label` branch from `key` instead of from the label itself
+    pub fn with_key(mut self, label: &str, key: &str) -> Self {
+        self.keys.insert(label.to_string(), key.to_string());
+        self
+    }
+    
+    /// Run once `k` of the upstream branches have arrived, instead of all of them
+    pub fn with_quorum(mut self, k: usize) -> Self {
+        self.quorum = k.clamp(1, self.upstream.len().max(1));
+        self
+    }
+    
+    /// Run with the branches that arrived once `timeout` has passed since the first of them
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+    
+    /// Write the gathered object to `key` (default "joined")
+    pub fn with_output_key(mut self, key: &str) -> Self {
+        self.output_key = key.to_string();
+        self
+    }
+    
+    /// Labels of the upstream nodes the join waits for
+    pub fn upstream(&self) -> &[String] {
+        &self.upstream
+    }
+    
+    /// The shared state key the output of the `label` branch is read from
+    fn key_for<'a>(&'a self, label: &'a str) -> &'a str {
+        self.keys.get(label).map(String::as_str).unwrap_or(label)
+    }
+}
+
+impl NodeTrait for JoinNode {
+    fn params(&self) -> Arc<ParamMap> {
+        self.base.params()
+    }
+    
+    fn name(&self) -> String {
+        self.base.assigned_name().unwrap_or_else(short_type_name::<Self>)
+    }
+    
+    fn set_name(&self, name: &str) {
+        self.base.set_name(name);
+    }
+    
+    fn hooks(&self) -> NodeHooks {
+        self.base.hooks()
+    }
+    
+    fn set_hooks(&self, hooks: NodeHooks) {
+        self.base.set_hooks(hooks);
+    }
+    
+    fn successors(&self) -> Arc<RwLock<Successors>> {
+        self.base.successors()
+    }
+    
+    fn set_params(&self, params: Arc<ParamMap>) {
+        self.base.set_params(params);
+    }
+    
+    fn add_successor(&self, node: Arc<dyn NodeTrait>, action: &str) -> Result<Arc<dyn NodeTrait>> {
+        self.base.add_successor(node, action)
+    }
+    
+    fn as_join(&self) -> Option<&JoinNode> {
+        Some(self)
+    }
+    
+    fn prep(&self, shared: &mut SharedState) -> Result<Value> {
+        let mut gathered = Map::new();
+        for label in &self.upstream {
+            if let Some(value) = shared.get(self.key_for(label)) {
+                gathered.insert(label.clone(), value.clone());
+            }
+        }
+        Ok(Value::Object(gathered))
+    }
+    
+    fn exec(&self, prep_res: &Value) -> Result<Value> {
+        Ok(prep_res.clone())
+    }
+    
+    fn post(&self, shared: &mut SharedState, _prep_res: Value, exec_res: Value) -> Result<Action> {
+        shared.insert(self.output_key.clone(), exec_res);
+        Ok(None)
+    }
+}
+
+/// Await `fut` as an async flow run with its own join arrivals
+pub(crate) async fn scope<F: Future>(fut: F) -> F::Output {
+    ARRIVALS.scope(Arc::new(Mutex::new(HashMap::new())), fut).await
+}
+
+/// Record that a branch coming from the node named `from` reached `node`, returning whether it goes on through the join
+///
+/// Branches of a concurrent fan-out pass `detached` and never go on, leaving the join to the
+/// merged state. Outside an async flow run every branch goes on.
+pub(crate) fn arrive(node: &Arc<dyn NodeTrait>, join: &JoinNode, from: &str, detached: bool) -> bool {
+    let Ok(arrivals) = ARRIVALS.try_with(|arrivals| arrivals.clone()) else {
+        return true;
+    };
+    let mut arrivals = arrivals.lock();
+    let entry = arrivals.entry(address(node)).or_insert_with(|| Arrivals {
+        node: node.clone(),
+        quorum: join.quorum,
+        timeout: join.timeout,
+        from: Vec::new(),
+        first: Instant::now(),
+        fired: false,
+    });
+    if entry.fired {
+        return false;
+    }
+    if join.upstream.iter().any(|label| label == from) && !entry.from.iter().any(|label| label == from) {
+        entry.from.push(from.to_string());
+    }
+    let timed_out = entry.timeout.is_some_and(|timeout| entry.first.elapsed() >= timeout);
+    if detached || (entry.from.len() < entry.quorum && !timed_out) {
+        return false;
+    }
+    entry.fired = true;
+    true
+}
+
+/// The next join the run has left behind that should still run, waiting out its timeout if needed
+///
+/// Joins whose quorum was met in a concurrent fan-out come first; joins short of their quorum
+/// run once their timeout has passed, and are abandoned with a warning when they have none.
+pub(crate) async fn next_pending() -> Option<Arc<dyn NodeTrait>> {
+    let arrivals = ARRIVALS.try_with(|arrivals| arrivals.clone()).ok()?;
+    let (node, deadline) = {
+        let mut arrivals = arrivals.lock();
+        let mut pending: Vec<&mut Arrivals> = arrivals.values_mut().filter(|entry| !entry.fired).collect();
+        pending.sort_by_key(|entry| entry.first);
+        
+        if let Some(entry) = pending.iter_mut().find(|entry| entry.from.len() >= entry.quorum) {
+            entry.fired = true;
+            return Some(entry.node.clone());
+        }
+        let timed = pending
+            .iter_mut()
+            .filter_map(|entry| Some((entry.first + entry.timeout?, entry)))
+            .min_by_key(|(deadline, _)| *deadline);
+        match timed {
+            Some((deadline, entry)) => {
+                entry.fired = true;
+                (entry.node.clone(), deadline)
+            },
+            None => {
+                for entry in pending {
+                    entry.fired = true;
+                    warn!(
+                        target: "minllm::flow",
+                        "Join '{}' ran short of its quorum ({} of {} arrived) and has no timeout",
+                        entry.node.name(), entry.from.len(), entry.quorum,
+                    );
+                }
+                return None;
+            },
+        }
+    };
+    tokio::time::sleep_until(deadline).await;
+    Some(node)
+}
+
+/// Identity of a node across the `Arc`s pointing to it
+fn address(node: &Arc<dyn NodeTrait>) -> usize {
+    Arc::as_ptr(node) as *const () as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[tokio::test(start_paused = true)]
+    async fn arrival_after_the_timeout_runs_the_join_on_a_paused_clock() {
+        let join = JoinNode::new(&["a", "b"]).with_timeout(Duration::from_secs(5));
+        let node: Arc<dyn NodeTrait> = Arc::new(join.clone());
+        scope(async {
+            assert!(!arrive(&node, &join, "a", false));
+            tokio::time::advance(Duration::from_secs(6)).await;
+            assert!(arrive(&node, &join, "late", false));
+            assert!(!arrive(&node, &join, "b", false));
+        }).await;
+    }
+    
+    #[tokio::test(start_paused = true)]
+    async fn arrival_before_the_timeout_waits_for_the_quorum() {
+        let join = JoinNode::new(&["a", "b"]).with_timeout(Duration::from_secs(5));
+        let node: Arc<dyn NodeTrait> = Arc::new(join.clone());
+        scope(async {
+            assert!(!arrive(&node, &join, "a", false));
+            tokio::time::advance(Duration::from_secs(4)).await;
+            assert!(!arrive(&node, &join, "late", false));
+            assert!(arrive(&node, &join, "b", false));
+        }).await;
+    }
+}
\ No newline at end of file
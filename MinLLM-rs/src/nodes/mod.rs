@@ -0,0 +1,33 @@
+mod throttle;
+mod template;
+mod extract;
+mod fan_out;
+mod race;
+mod sync_adapter;
+pub(crate) mod join;
+mod validate;
+mod noop;
+pub(crate) mod fn_node;
+mod typed;
+#[cfg(any(feature = "http", feature = "process"))]
+mod interpolate;
+#[cfg(feature = "http")]
+mod http;
+#[cfg(feature = "process")]
+mod shell;
+
+pub use throttle::ThrottleNode;
+pub use template::{PromptTemplateNode, MissingRef};
+pub use extract::{JsonExtractNode, Extraction};
+pub use fan_out::FanOutNode;
+pub use race::RaceNode;
+pub use sync_adapter::SyncAdapter;
+pub use join::JoinNode;
+pub use validate::{ValidateNode, Constraint, Violation};
+pub use noop::{NoOpNode, ActionSource};
+pub use fn_node::{FnNode, AsyncFnNode, BlockingNode};
+pub use typed::{TypedNode, TypedLogic};
+#[cfg(feature = "http")]
+pub use http::HttpRequestNode;
+#[cfg(feature = "process")]
+pub use shell::ShellCommandNode;
\ No newline at end of file
@@ -0,0 +1,199 @@
+use std::sync::Arc;
+use parking_lot::RwLock;
+use async_trait::async_trait;
+use serde_json::Value;
+
+use crate::base::{BaseNode, Node as NodeTrait, SharedState, Action, ParamMap};
+use crate::action::ActionName;
+use crate::successors::Successors;
+use crate::hooks::NodeHooks;
+use crate::async_node::AsyncNodeTrait;
+use crate::error::Result;
+
+/// Where a `NoOpNode` takes the action it returns from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ActionSource {
+    /// Always return this action
+    Constant(ActionName),
+    
+    /// Return the string stored under this param
+    Param(String),
+    
+    /// Return the string stored under this shared state key
+    Store(String),
+}
+
+/// A node that does no work, useful as a join point, landmark, or placeholder
+///
+/// It passes its prep result through, optionally copies one shared state key to another,
+/// and returns an action taken from its `ActionSource` (by default the constant "default").
+#[derive(Clone)]
+pub struct NoOpNode {
+    /// Base node implementation
+    base: BaseNode,
+    
+    /// Where the returned action comes from
+    action: ActionSource,
+    
+    /// Optional `(from, to)` shared state keys to copy
+    copy: Option<(String, String)>,
+}
+
+impl NoOpNode {
+    /// Create a no-op node returning "default"
+    pub fn new() -> Self {
+        Self {
+            base: BaseNode::new(),
+            action: ActionSource::Constant(ActionName::default_action()),
+            copy: None,
+        }
+    }
+    
+    /// Set the node name
+    pub fn with_name(self, name: &str) -> Self {
+        self.base.set_name(name);
+        self
+    }
+    
+    /// Set where the returned action comes from
+    pub fn with_action(mut self, action: ActionSource) -> Self {
+        self.action = action;
+        self
+    }
+    
+    /// Copy the value under `from` to `to` during post
+    pub fn copy_key(mut self, from: &str, to: &str) -> Self {
+        self.copy = Some((from.to_string(), to.to_string()));
+        self
+    }
+    
+    fn resolve_action(&self, shared: &SharedState) -> Action {
+        match &self.action {
+            ActionSource::Constant(action) => Some(action.clone()),
+            ActionSource::Param(key) => self
+                .params()
+                .get(key)
+                .and_then(|v| v.as_str())
+                .map(ActionName::from),
+            ActionSource::Store(key) => shared.get(key).and_then(|v| v.as_str()).map(ActionName::from),
+        }
+    }
+}
+
+impl Default for NoOpNode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NodeTrait for NoOpNode {
+    fn params(&self) -> Arc<ParamMap> {
+        self.base.params()
+    }
+    
+    fn name(&self) -> String {
+        self.base.assigned_name().unwrap_or_else(|| "noop".to_string())
+    }
+    
+    fn is_placeholder(&self) -> bool {
+        true
+    }
+    
+    fn set_name(&self, name: &str) {
+        self.base.set_name(name);
+    }
+    
+    fn hooks(&self) -> NodeHooks {
+        self.base.hooks()
+    }
+    
+    fn set_hooks(&self, hooks: NodeHooks) {
+        self.base.set_hooks(hooks);
+    }
+    
+    fn successors(&self) -> Arc<RwLock<Successors>> {
+        self.base.successors()
+    }
+    
+    fn set_params(&self, params: Arc<ParamMap>) {
+        self.base.set_params(params);
+    }
+    
+    fn add_successor(&self, node: Arc<dyn NodeTrait>, action: &str) -> Result<Arc<dyn NodeTrait>> {
+        self.base.add_successor(node, action)
+    }
+    
+    fn post(&self, shared: &mut SharedState, _prep_res: Value, _exec_res: Value) -> Result<Action> {
+        if let Some((from, to)) = &self.copy {
+            if let Some(value) = shared.get(from).cloned() {
+                shared.insert(to.clone(), value);
+            }
+        }
+        Ok(self.resolve_action(shared))
+    }
+}
+
+#[async_trait]
+impl AsyncNodeTrait for NoOpNode {
+    async fn prep_async(&self, shared: &mut SharedState) -> Result<Value> {
+        self.prep(shared)
+    }
+    
+    async fn exec_async(&self, prep_res: &Value) -> Result<Value> {
+        self.exec(prep_res)
+    }
+    
+    async fn post_async(&self, shared: &mut SharedState, prep_res: Value, exec_res: Value) -> Result<Action> {
+        self.post(shared, prep_res, exec_res)
+    }
+    
+    async fn _exec_async(&self, prep_res: &Value) -> Result<Value> {
+        self.exec_async(prep_res).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    
+    use super::*;
+    
+    #[test]
+    fn returns_a_constant_action() {
+        let node = NoOpNode::new().with_action(ActionSource::Constant("next".into()));
+        assert_eq!(node.run(&mut SharedState::new()).unwrap().as_deref(), Some("next"));
+        assert_eq!(NoOpNode::new().run(&mut SharedState::new()).unwrap().as_deref(), Some("default"));
+    }
+    
+    #[test]
+    fn returns_the_action_from_the_store_or_params() {
+        let node = NoOpNode::new().with_action(ActionSource::Store("route".to_string()));
+        let mut shared = SharedState::from([("route".to_string(), json!("left"))]);
+        assert_eq!(node.run(&mut shared).unwrap().as_deref(), Some("left"));
+        assert_eq!(node.run(&mut SharedState::new()).unwrap(), None);
+        
+        let node = NoOpNode::new().with_action(ActionSource::Param("route".to_string()));
+        node.set_params_map(ParamMap::from([("route".to_string(), json!("right"))]));
+        assert_eq!(node.run(&mut SharedState::new()).unwrap().as_deref(), Some("right"));
+    }
+    
+    #[test]
+    fn copies_a_key_when_present() {
+        let node = NoOpNode::new().copy_key("from", "to");
+        let mut shared = SharedState::from([("from".to_string(), json!({"a": 1}))]);
+        node.run(&mut shared).unwrap();
+        assert_eq!(shared["to"], json!({"a": 1}));
+        
+        let mut shared = SharedState::new();
+        node.run(&mut shared).unwrap();
+        assert!(!shared.contains_key("to"));
+    }
+    
+    #[tokio::test]
+    async fn runs_the_same_way_asynchronously() {
+        let node = NoOpNode::new().copy_key("from", "to").with_action(ActionSource::Store("from".to_string()));
+        let mut shared = SharedState::from([("from".to_string(), json!("x"))]);
+        assert_eq!(node._run_async(&mut shared).await.unwrap().as_deref(), Some("x"));
+        assert_eq!(shared["to"], json!("x"));
+    }
+}
\ No newline at end of file
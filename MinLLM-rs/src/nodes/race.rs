@@ -0,0 +1,203 @@
+use std::sync::Arc;
+use parking_lot::RwLock;
+use async_trait::async_trait;
+use futures::stream::{FuturesUnordered, StreamExt};
+use serde_json::{json, Value};
+
+use crate::base::{BaseNode, short_type_name, Node as NodeTrait, SharedState, Action, ParamMap};
+use crate::successors::Successors;
+use crate::hooks::NodeHooks;
+use crate::async_node::AsyncNodeTrait;
+use crate::error::{Error, Result};
+
+/// An async node running several children on the same input and keeping the first to succeed
+///
+/// Every child execs on the prep result of the first child, with its own retries and timeout.
+/// The first success wins and the other children are dropped mid-flight; the node only fails
+/// once every child has. The exec result is `{"winner": name, "index": i, "value": ...}`,
+/// and post hands the winning value to the winner's own post.
+#[derive(Clone)]
+pub struct RaceNode {
+    /// Base node implementation
+    base: BaseNode,
+    
+    /// Children racing each other, in declaration order
+    children: Vec<Arc<dyn AsyncNodeTrait>>,
+}
+
+impl RaceNode {
+    /// Create a node racing `children`
+    pub fn new(children: Vec<Arc<dyn AsyncNodeTrait>>) -> Self {
+        Self {
+            base: BaseNode::new(),
+            children,
+        }
+    }
+    
+    /// Name the node in logs, errors and traces
+    pub fn with_name(self, name: &str) -> Self {
+        self.set_name(name);
+        self
+    }
+    
+    /// Children racing each other, in declaration order
+    pub fn children(&self) -> &[Arc<dyn AsyncNodeTrait>] {
+        &self.children
+    }
+}
+
+impl NodeTrait for RaceNode {
+    fn params(&self) -> Arc<ParamMap> {
+        self.base.params()
+    }
+    
+    fn name(&self) -> String {
+        self.base.assigned_name().unwrap_or_else(short_type_name::<Self>)
+    }
+    
+    fn set_name(&self, name: &str) {
+        self.base.set_name(name);
+    }
+    
+    fn hooks(&self) -> NodeHooks {
+        self.base.hooks()
+    }
+    
+    fn set_hooks(&self, hooks: NodeHooks) {
+        self.base.set_hooks(hooks);
+    }
+    
+    fn successors(&self) -> Arc<RwLock<Successors>> {
+        self.base.successors()
+    }
+    
+    fn set_params(&self, params: Arc<ParamMap>) {
+        for child in &self.children {
+            child.set_params(params.clone());
+        }
+        self.base.set_params(params);
+    }
+    
+    fn add_successor(&self, node: Arc<dyn NodeTrait>, action: &str) -> Result<Arc<dyn NodeTrait>> {
+        self.base.add_successor(node, action)
+    }
+    
+    fn _run(&self, _shared: &mut SharedState) -> Result<Action> {
+        Err(Error::InvalidOperation("Use run_async".into()))
+    }
+    
+    fn as_async(&self) -> Option<&dyn AsyncNodeTrait> {
+        Some(self)
+    }
+}
+
+#[async_trait]
+impl AsyncNodeTrait for RaceNode {
+    async fn prep_async(&self, shared: &mut SharedState) -> Result<Value> {
+        match self.children.first() {
+            Some(child) => child.prep_async(shared).await,
+            None => Ok(Value::Null),
+        }
+    }
+    
+    async fn post_async(&self, shared: &mut SharedState, prep_res: Value, exec_res: Value) -> Result<Action> {
+        let winner = exec_res.get("index").and_then(Value::as_u64).and_then(|i| self.children.get(i as usize));
+        match winner {
+            Some(child) => child.post_async(shared, prep_res, exec_res["value"].clone()).await,
+            None => Ok(None),
+        }
+    }
+    
+    async fn _exec_async(&self, prep_res: &Value) -> Result<Value> {
+        if self.children.is_empty() {
+            return Err(Error::InvalidOperation("RaceNode has no children".into()));
+        }
+        
+        let mut racing: FuturesUnordered<_> = self.children
+            .iter()
+            .enumerate()
+            .map(|(i, child)| async move { (i, child._exec_async(prep_res).await) })
+            .collect();
+        let mut failures = Vec::new();
+        while let Some((i, result)) = racing.next().await {
+            match result {
+                Ok(value) => return Ok(json!({"winner": self.children[i].name(), "index": i, "value": value})),
+                Err(e) if e.is_cancelled() => return Err(e),
+                Err(e) => failures.push((i, e)),
+            }
+        }
+        
+        failures.sort_by_key(|(i, _)| *i);
+        let failures: Vec<String> = failures
+            .into_iter()
+            .map(|(i, e)| format!("{}: {}", self.children[i].name(), e))
+            .collect();
+        Err(Error::NodeExecution(format!("Every raced child failed: {}", failures.join("; "))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+    use tokio::time::{self, Instant};
+    
+    use crate::nodes::AsyncFnNode;
+    
+    use super::*;
+    
+    /// A child named `name` taking `millis` to double its input, or to fail when `fails`
+    fn child(name: &str, millis: u64, fails: bool) -> AsyncFnNode {
+        let node = AsyncFnNode::new().with_exec(move |prep: Value| async move {
+            time::sleep(Duration::from_millis(millis)).await;
+            if fails {
+                return Err(Error::NodeExecution("unavailable".into()));
+            }
+            Ok(json!(prep.as_i64().unwrap() * 2))
+        });
+        node.set_name(name);
+        node
+    }
+    
+    #[tokio::test(start_paused = true)]
+    async fn the_first_child_to_succeed_wins_and_posts() {
+        let finished = Arc::new(AtomicBool::new(false));
+        let slow_done = finished.clone();
+        let slow = AsyncFnNode::new().with_exec(move |prep: Value| {
+            let slow_done = slow_done.clone();
+            async move {
+                time::sleep(Duration::from_millis(300)).await;
+                slow_done.store(true, Ordering::SeqCst);
+                Ok(prep)
+            }
+        });
+        let primary = child("primary", 10, true).with_prep(|_| Ok(json!(21)));
+        let backup = child("backup", 100, false).with_post(|shared, _, exec_res| {
+            shared.insert("answer".to_string(), exec_res);
+            Ok(Some("done".into()))
+        });
+        let race = RaceNode::new(vec![Arc::new(primary), Arc::new(backup), Arc::new(slow)]);
+        
+        let mut shared = SharedState::new();
+        let started = Instant::now();
+        let action = race.run_async(&mut shared).await.unwrap();
+        assert_eq!(started.elapsed(), Duration::from_millis(100));
+        assert_eq!(action.as_deref(), Some("done"));
+        assert_eq!(shared["answer"], json!(42));
+        
+        // The losing child was dropped mid-exec, so it never gets past its sleep
+        time::advance(Duration::from_millis(400)).await;
+        assert!(!finished.load(Ordering::SeqCst));
+    }
+    
+    #[tokio::test(start_paused = true)]
+    async fn the_race_fails_only_when_every_child_does() {
+        let race = RaceNode::new(vec![Arc::new(child("a", 20, true)), Arc::new(child("b", 10, true))]);
+        let err = race._exec_async(&json!(1)).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            Error::NodeExecution("Every raced child failed: a: Node execution error: unavailable; b: Node execution error: unavailable".into()).to_string()
+        );
+        assert!(RaceNode::new(Vec::new())._exec_async(&json!(1)).await.is_err());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,253 @@
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use parking_lot::RwLock;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+use crate::base::{BaseNode, short_type_name, Node as NodeTrait, SharedState, Action, ParamMap};
+use crate::action::ActionName;
+use crate::successors::Successors;
+use crate::hooks::NodeHooks;
+use crate::async_node::AsyncNodeTrait;
+use crate::nodes::interpolate::{interpolate, interpolate_value};
+use crate::retry::{self, NoRetry};
+use crate::sleeper::RealSleeper;
+use crate::error::{Error, Result};
+
+/// A node running a local command and storing its output
+///
+/// Recognized params: `program`, `args`, `cwd`, `env`, `timeout_ms`, `parse_json`,
+/// `stdout_key` (default "stdout"), `stderr_key` (default "stderr"),
+/// `exit_code_key` (default "exit_code") and `failure_action` (default "failed").
+/// `program`, `args`, `cwd` and `env` may reference `${param:key}` and `${store:key}`.
+///
+/// A zero exit code returns "default" and any other exit code returns the failure action.
+/// Hitting the timeout kills the child and fails the execution with `Error::Timeout`.
+#[derive(Clone)]
+pub struct ShellCommandNode {
+    /// Base node implementation
+    base: BaseNode,
+}
+
+impl ShellCommandNode {
+    /// Create a new shell command node
+    pub fn new() -> Self {
+        Self {
+            base: BaseNode::new(),
+        }
+    }
+    
+    fn param_str(&self, key: &str, default: &str) -> String {
+        self.params()
+            .get(key)
+            .and_then(|v| v.as_str())
+            .unwrap_or(default)
+            .to_string()
+    }
+    
+    /// Build the std command described by the prep result
+    fn command(prep_res: &Value) -> Result<Command> {
+        let program = prep_res["program"]
+            .as_str()
+            .ok_or_else(|| Error::InvalidOperation("ShellCommandNode requires a 'program' param".into()))?;
+        
+        let mut command = Command::new(program);
+        if let Some(args) = prep_res["args"].as_array() {
+            for arg in args {
+                match arg {
+                    Value::String(s) => command.arg(s),
+                    other => command.arg(other.to_string()),
+                };
+            }
+        }
+        if let Some(cwd) = prep_res["cwd"].as_str() {
+            command.current_dir(cwd);
+        }
+        if let Some(env) = prep_res["env"].as_object() {
+            for (key, value) in env {
+                match value {
+                    Value::String(s) => command.env(key, s),
+                    other => command.env(key, other.to_string()),
+                };
+            }
+        }
+        command.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped());
+        Ok(command)
+    }
+    
+    /// Convert captured output into the exec result
+    fn output(prep_res: &Value, stdout: &[u8], stderr: &[u8], exit_code: Option<i32>) -> Result<Value> {
+        let stdout = String::from_utf8_lossy(stdout).into_owned();
+        let stdout = if prep_res["parse_json"].as_bool().unwrap_or(false) {
+            serde_json::from_str(&stdout)
+                .map_err(|e| Error::NodeExecution(format!("Command output is not valid JSON: {}", e)))?
+        } else {
+            Value::String(stdout)
+        };
+        
+        Ok(json!({
+            "stdout": stdout,
+            "stderr": String::from_utf8_lossy(stderr),
+            "exit_code": exit_code,
+        }))
+    }
+    
+    fn timeout(prep_res: &Value) -> Option<Duration> {
+        prep_res["timeout_ms"].as_u64().map(Duration::from_millis)
+    }
+}
+
+impl Default for ShellCommandNode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NodeTrait for ShellCommandNode {
+    fn params(&self) -> Arc<ParamMap> {
+        self.base.params()
+    }
+    
+    fn name(&self) -> String {
+        self.base.assigned_name().unwrap_or_else(short_type_name::<Self>)
+    }
+    
+    fn set_name(&self, name: &str) {
+        self.base.set_name(name);
+    }
+    
+    fn hooks(&self) -> NodeHooks {
+        self.base.hooks()
+    }
+    
+    fn set_hooks(&self, hooks: NodeHooks) {
+        self.base.set_hooks(hooks);
+    }
+    
+    fn successors(&self) -> Arc<RwLock<Successors>> {
+        self.base.successors()
+    }
+    
+    fn set_params(&self, params: Arc<ParamMap>) {
+        self.base.set_params(params);
+    }
+    
+    fn add_successor(&self, node: Arc<dyn NodeTrait>, action: &str) -> Result<Arc<dyn NodeTrait>> {
+        self.base.add_successor(node, action)
+    }
+    
+    fn prep(&self, shared: &mut SharedState) -> Result<Value> {
+        let params = self.params();
+        let program = params
+            .get("program")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| Error::InvalidOperation("ShellCommandNode requires a 'program' param".into()))?;
+        
+        Ok(json!({
+            "program": interpolate(program, &params, shared)?,
+            "args": interpolate_value(params.get("args").unwrap_or(&Value::Null), &params, shared)?,
+            "cwd": interpolate_value(params.get("cwd").unwrap_or(&Value::Null), &params, shared)?,
+            "env": interpolate_value(params.get("env").unwrap_or(&Value::Null), &params, shared)?,
+            "timeout_ms": params.get("timeout_ms").cloned().unwrap_or(Value::Null),
+            "parse_json": params.get("parse_json").cloned().unwrap_or(Value::Bool(false)),
+        }))
+    }
+    
+    fn exec(&self, prep_res: &Value) -> Result<Value> {
+        let mut child = Self::command(prep_res)?
+            .spawn()
+            .map_err(|e| Error::NodeExecution(format!("Failed to spawn command: {}", e)))?;
+        
+        // Drain both pipes on their own threads so a chatty child can't block on a full pipe
+        let drain = |pipe: Option<Box<dyn Read + Send>>| {
+            thread::spawn(move || {
+                let mut buf = Vec::new();
+                if let Some(mut pipe) = pipe {
+                    let _ = pipe.read_to_end(&mut buf);
+                }
+                buf
+            })
+        };
+        let stdout = drain(child.stdout.take().map(|p| Box::new(p) as Box<dyn Read + Send>));
+        let stderr = drain(child.stderr.take().map(|p| Box::new(p) as Box<dyn Read + Send>));
+        
+        let timeout = Self::timeout(prep_res);
+        let deadline = timeout.map(|t| Instant::now() + t);
+        let status = loop {
+            if let Some(status) = child
+                .try_wait()
+                .map_err(|e| Error::NodeExecution(format!("Failed to wait for command: {}", e)))?
+            {
+                break status;
+            }
+            
+            if let (Some(deadline), Some(timeout)) = (deadline, timeout) {
+                if Instant::now() >= deadline {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(Error::Timeout(timeout));
+                }
+            }
+            
+            thread::sleep(Duration::from_millis(10));
+        };
+        
+        let stdout = stdout.join().unwrap_or_default();
+        let stderr = stderr.join().unwrap_or_default();
+        Self::output(prep_res, &stdout, &stderr, status.code())
+    }
+    
+    fn post(&self, shared: &mut SharedState, _prep_res: Value, exec_res: Value) -> Result<Action> {
+        let success = exec_res["exit_code"].as_i64() == Some(0);
+        
+        shared.insert(self.param_str("stdout_key", "stdout"), exec_res["stdout"].clone());
+        shared.insert(self.param_str("stderr_key", "stderr"), exec_res["stderr"].clone());
+        shared.insert(self.param_str("exit_code_key", "exit_code"), exec_res["exit_code"].clone());
+        
+        if success {
+            Ok(Some(ActionName::default_action()))
+        } else {
+            Ok(Some(self.param_str("failure_action", "failed").into()))
+        }
+    }
+}
+
+#[async_trait]
+impl AsyncNodeTrait for ShellCommandNode {
+    async fn prep_async(&self, shared: &mut SharedState) -> Result<Value> {
+        self.prep(shared)
+    }
+    
+    async fn exec_async(&self, prep_res: &Value) -> Result<Value> {
+        let mut command = tokio::process::Command::from(Self::command(prep_res)?);
+        command.kill_on_drop(true);
+        let child = command
+            .spawn()
+            .map_err(|e| Error::NodeExecution(format!("Failed to spawn command: {}", e)))?;
+        
+        let output = child
+            .wait_with_output()
+            .await
+            .map_err(|e| Error::NodeExecution(format!("Failed to wait for command: {}", e)))?;
+        
+        Self::output(prep_res, &output.stdout, &output.stderr, output.status.code())
+    }
+    
+    async fn post_async(&self, shared: &mut SharedState, prep_res: Value, exec_res: Value) -> Result<Action> {
+        self.post(shared, prep_res, exec_res)
+    }
+    
+    async fn _exec_async(&self, prep_res: &Value) -> Result<Value> {
+        // Dropping the attempt on timeout, deadline or cancellation kills the child
+        let attempts = retry::Attempts::new(&NoRetry, &RealSleeper).timeout(Self::timeout(prep_res));
+        retry::drive_async(
+            attempts,
+            || self.name(),
+            |_| self.exec_async(prep_res),
+            |e, ctx| self.exec_fallback_async_ctx(prep_res, e, ctx),
+        ).await
+    }
+}
\ No newline at end of file
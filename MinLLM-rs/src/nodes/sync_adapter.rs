@@ -0,0 +1,166 @@
+use std::sync::Arc;
+use parking_lot::RwLock;
+use async_trait::async_trait;
+use serde_json::Value;
+
+use crate::base::{Node as NodeTrait, SharedState, Action, ParamMap};
+use crate::successors::Successors;
+use crate::hooks::NodeHooks;
+use crate::param_spec::ParamSpec;
+use crate::dataflow::KeySpec;
+use crate::async_node::AsyncNodeTrait;
+use crate::error::Result;
+
+/// A wrapper running a synchronous node wherever an async node is expected
+///
+/// Prep, exec (retries included) and post are the wrapped node's own. Exec runs on the calling
+/// task unless the adapter is blocking, in which case it moves to tokio's blocking thread pool
+/// so slow CPU work doesn't stall the other branches of the flow; prep and post always run on
+/// the calling task. Name, params, hooks and successors are the wrapped node's, so the adapter
+/// can stand in for it in an existing graph. Flows don't need an adapter: an `AsyncFlow` already
+/// runs a synchronous sub-flow as it is.
+#[derive(Clone)]
+pub struct SyncAdapter {
+    /// The wrapped node
+    inner: Arc<dyn NodeTrait>,
+    
+    /// Whether exec runs on the blocking thread pool
+    blocking: bool,
+}
+
+impl SyncAdapter {
+    /// Wrap a synchronous node, running its exec on the calling task
+    pub fn wrap(inner: Arc<dyn NodeTrait>) -> Self {
+        Self {
+            inner,
+            blocking: false,
+        }
+    }
+    
+    /// Run exec on tokio's blocking thread pool when `blocking` is set
+    pub fn with_blocking(mut self, blocking: bool) -> Self {
+        self.blocking = blocking;
+        self
+    }
+    
+    /// The wrapped node
+    pub fn inner(&self) -> &Arc<dyn NodeTrait> {
+        &self.inner
+    }
+    
+    /// Whether exec runs on the blocking thread pool
+    pub fn is_blocking(&self) -> bool {
+        self.blocking
+    }
+}
+
+impl NodeTrait for SyncAdapter {
+    fn params(&self) -> Arc<ParamMap> {
+        self.inner.params()
+    }
+    
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+    
+    fn set_name(&self, name: &str) {
+        self.inner.set_name(name);
+    }
+    
+    fn hooks(&self) -> NodeHooks {
+        self.inner.hooks()
+    }
+    
+    fn set_hooks(&self, hooks: NodeHooks) {
+        self.inner.set_hooks(hooks);
+    }
+    
+    fn required_params(&self) -> &[ParamSpec] {
+        self.inner.required_params()
+    }
+    
+    fn declared_actions(&self) -> &[String] {
+        self.inner.declared_actions()
+    }
+    
+    fn reads(&self) -> &[KeySpec] {
+        self.inner.reads()
+    }
+    
+    fn writes(&self) -> &[KeySpec] {
+        self.inner.writes()
+    }
+    
+    fn exec_dry_run(&self, prep_res: &Value) -> Result<Value> {
+        self.inner.exec_dry_run(prep_res)
+    }
+    
+    fn successors(&self) -> Arc<RwLock<Successors>> {
+        self.inner.successors()
+    }
+    
+    fn set_params(&self, params: Arc<ParamMap>) {
+        self.inner.set_params(params);
+    }
+    
+    fn add_successor(&self, node: Arc<dyn NodeTrait>, action: &str) -> Result<Arc<dyn NodeTrait>> {
+        self.inner.add_successor(node, action)
+    }
+    
+    fn branch_actions(&self) -> &[String] {
+        self.inner.branch_actions()
+    }
+    
+    fn prep(&self, shared: &mut SharedState) -> Result<Value> {
+        self.inner.prep(shared)
+    }
+    
+    fn exec(&self, prep_res: &Value) -> Result<Value> {
+        self.inner.exec(prep_res)
+    }
+    
+    fn post(&self, shared: &mut SharedState, prep_res: Value, exec_res: Value) -> Result<Action> {
+        self.inner.post(shared, prep_res, exec_res)
+    }
+    
+    fn _exec(&self, prep_res: &Value) -> Result<Value> {
+        self.inner._exec(prep_res)
+    }
+    
+    fn _run(&self, shared: &mut SharedState) -> Result<Action> {
+        self.inner._run(shared)
+    }
+    
+    fn as_async(&self) -> Option<&dyn AsyncNodeTrait> {
+        Some(self)
+    }
+    
+    fn runs_sync(&self) -> bool {
+        self.inner.runs_sync()
+    }
+}
+
+#[async_trait]
+impl AsyncNodeTrait for SyncAdapter {
+    async fn prep_async(&self, shared: &mut SharedState) -> Result<Value> {
+        self.inner.prep(shared)
+    }
+    
+    async fn exec_async(&self, prep_res: &Value) -> Result<Value> {
+        self.inner.exec(prep_res)
+    }
+    
+    async fn post_async(&self, shared: &mut SharedState, prep_res: Value, exec_res: Value) -> Result<Action> {
+        self.inner.post(shared, prep_res, exec_res)
+    }
+    
+    async fn _exec_async(&self, prep_res: &Value) -> Result<Value> {
+        if !self.blocking {
+            return self.inner._exec(prep_res);
+        }
+        
+        let inner = self.inner.clone();
+        let prep_res = prep_res.clone();
+        tokio::task::spawn_blocking(move || inner._exec(&prep_res)).await?
+    }
+}
\ No newline at end of file
@@ -0,0 +1,426 @@
+use std::sync::Arc;
+use parking_lot::RwLock;
+use serde_json::{json, Value};
+
+use crate::base::{BaseNode, short_type_name, Node as NodeTrait, SharedState, Action, ParamMap};
+use crate::action::ActionName;
+use crate::successors::Successors;
+use crate::hooks::NodeHooks;
+use crate::error::{Error, Result};
+
+/// How a template reference that resolves to nothing is rendered
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MissingRef {
+    /// Fail the render
+    Error,
+    
+    /// Render an empty string
+    Empty,
+    
+    /// Leave the placeholder untouched
+    Keep,
+}
+
+/// A parsed piece of a template
+#[derive(Debug)]
+enum Segment {
+    Text(String),
+    Var(String),
+    Each(String, Vec<Segment>),
+    If(String, Vec<Segment>, Vec<Segment>),
+}
+
+/// The innermost `{{#each}}` item being rendered
+struct Scope<'a> {
+    item: &'a Value,
+    index: usize,
+}
+
+/// A node rendering a template from the shared state and params into an output key
+///
+/// Templates support `{{store.path}}`, `{{param.key}}`, `{{#each path}}...{{/each}}` (with
+/// `{{this}}`, `{{this.field}}` and `{{@index}}` inside the loop) and
+/// `{{#if path}}...{{else}}...{{/if}}`. A bare path is looked up on the current loop item
+/// and then in the shared state. `\{{` renders a literal `{{`.
+///
+/// The `template` and `output_key` params override the values given at construction.
+#[derive(Clone)]
+pub struct PromptTemplateNode {
+    /// Base node implementation
+    base: BaseNode,
+    
+    /// Template source
+    template: String,
+    
+    /// Shared state key receiving the rendered string
+    output_key: String,
+    
+    /// Handling of unresolved references
+    missing: MissingRef,
+}
+
+impl PromptTemplateNode {
+    /// Create a template node writing its output to `output_key`
+    pub fn new(template: &str, output_key: &str) -> Self {
+        Self {
+            base: BaseNode::new(),
+            template: template.to_string(),
+            output_key: output_key.to_string(),
+            missing: MissingRef::Error,
+        }
+    }
+    
+    /// Set how unresolved references are rendered
+    pub fn on_missing(mut self, missing: MissingRef) -> Self {
+        self.missing = missing;
+        self
+    }
+    
+    /// Render a template against a context object holding `store` and `param`
+    pub fn render(template: &str, context: &Value, missing: MissingRef) -> Result<String> {
+        let segments = parse(template)?;
+        let mut out = String::with_capacity(template.len());
+        render_segments(&segments, context, &mut Vec::new(), missing, &mut out)?;
+        Ok(out)
+    }
+    
+    fn output_key(&self) -> String {
+        self.params()
+            .get("output_key")
+            .and_then(|v| v.as_str())
+            .unwrap_or(&self.output_key)
+            .to_string()
+    }
+}
+
+/// Parse a template into segments
+fn parse(template: &str) -> Result<Vec<Segment>> {
+    let mut stack: Vec<(String, Vec<Segment>, Option<Vec<Segment>>)> = Vec::new();
+    let mut current = Vec::new();
+    let mut text = String::new();
+    let mut rest = template;
+    
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix("\\{{") {
+            text.push_str("{{");
+            rest = after;
+            continue;
+        }
+        
+        if !rest.starts_with("{{") {
+            let first = rest.chars().next().map(char::len_utf8).unwrap_or(1);
+            let next = rest[first..]
+                .find(['{', '\\'])
+                .map(|i| i + first)
+                .unwrap_or(rest.len());
+            text.push_str(&rest[..next]);
+            rest = &rest[next..];
+            continue;
+        }
+        
+        let end = rest
+            .find("}}")
+            .ok_or_else(|| Error::InvalidOperation(format!("Unclosed '{{{{' in template: {}", template)))?;
+        let tag = rest[2..end].trim();
+        rest = &rest[end + 2..];
+        
+        if !text.is_empty() {
+            current.push(Segment::Text(std::mem::take(&mut text)));
+        }
+        
+        if let Some(path) = tag.strip_prefix("#each ") {
+            stack.push((format!("each {}", path.trim()), std::mem::take(&mut current), None));
+        } else if let Some(path) = tag.strip_prefix("#if ") {
+            stack.push((format!("if {}", path.trim()), std::mem::take(&mut current), None));
+        } else if tag == "else" {
+            match stack.last_mut() {
+                Some((kind, _, otherwise)) if kind.starts_with("if ") && otherwise.is_none() => {
+                    *otherwise = Some(std::mem::take(&mut current));
+                },
+                _ => return Err(Error::InvalidOperation("'{{else}}' outside of '{{#if}}'".into())),
+            }
+        } else if tag == "/each" || tag == "/if" {
+            let (kind, parent, otherwise) = stack
+                .pop()
+                .ok_or_else(|| Error::InvalidOperation(format!("Unexpected '{{{{{}}}}}'", tag)))?;
+            let body = std::mem::replace(&mut current, parent);
+            let segment = match (kind.split_once(' '), tag) {
+                (Some(("each", path)), "/each") => Segment::Each(path.to_string(), body),
+                (Some(("if", path)), "/if") => match otherwise {
+                    Some(then) => Segment::If(path.to_string(), then, body),
+                    None => Segment::If(path.to_string(), body, Vec::new()),
+                },
+                _ => return Err(Error::InvalidOperation(format!("Mismatched '{{{{{}}}}}' for '{}'", tag, kind))),
+            };
+            current.push(segment);
+        } else {
+            current.push(Segment::Var(tag.to_string()));
+        }
+    }
+    
+    if let Some((kind, _, _)) = stack.last() {
+        return Err(Error::InvalidOperation(format!("Unclosed '{{{{#{}}}}}' in template", kind)));
+    }
+    
+    if !text.is_empty() {
+        current.push(Segment::Text(text));
+    }
+    
+    Ok(current)
+}
+
+/// Follow a dotted path (with numeric array indices) into a value
+fn lookup<'a>(value: &'a Value, path: &[&str]) -> Option<&'a Value> {
+    path.iter().try_fold(value, |current, part| match current {
+        Value::Object(map) => map.get(*part),
+        Value::Array(items) => part.parse::<usize>().ok().and_then(|i| items.get(i)),
+        _ => None,
+    })
+}
+
+/// Resolve a template path against the context and the enclosing loop scopes
+fn resolve(path: &str, context: &Value, scopes: &[Scope]) -> Option<Value> {
+    let parts: Vec<&str> = path.split('.').collect();
+    
+    match parts[0] {
+        "@index" => return scopes.last().map(|scope| json!(scope.index)),
+        "this" => return scopes.last().and_then(|scope| lookup(scope.item, &parts[1..])).cloned(),
+        "store" | "param" => return lookup(context, &parts).cloned(),
+        _ => {},
+    }
+    
+    if let Some(found) = scopes.last().and_then(|scope| lookup(scope.item, &parts)) {
+        return Some(found.clone());
+    }
+    
+    context.get("store").and_then(|store| lookup(store, &parts)).cloned()
+}
+
+/// The shared state keys a template may read: the first part of every `store.` path and bare path
+fn store_keys(segments: &[Segment]) -> Vec<&str> {
+    let mut keys = Vec::new();
+    for segment in segments {
+        let path = match segment {
+            Segment::Text(_) => continue,
+            Segment::Var(path) => path,
+            Segment::Each(path, body) => {
+                keys.extend(store_keys(body));
+                path
+            },
+            Segment::If(path, then, otherwise) => {
+                keys.extend(store_keys(then));
+                keys.extend(store_keys(otherwise));
+                path
+            },
+        };
+        let mut parts = path.split('.');
+        let key = match parts.next() {
+            Some("store") => parts.next(),
+            Some("param" | "this" | "@index") => None,
+            first => first,
+        };
+        keys.extend(key);
+    }
+    keys.sort_unstable();
+    keys.dedup();
+    keys
+}
+
+/// Whether a value counts as true in `{{#if}}`
+fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64().map(|f| f != 0.0).unwrap_or(true),
+        Value::String(s) => !s.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(map) => !map.is_empty(),
+    }
+}
+
+fn render_segments<'a>(
+    segments: &[Segment],
+    context: &'a Value,
+    scopes: &mut Vec<Scope<'a>>,
+    missing: MissingRef,
+    out: &mut String,
+) -> Result<()> {
+    for segment in segments {
+        match segment {
+            Segment::Text(text) => out.push_str(text),
+            Segment::Var(path) => match resolve(path, context, scopes) {
+                Some(Value::String(s)) => out.push_str(&s),
+                Some(value) => out.push_str(&value.to_string()),
+                None => match missing {
+                    MissingRef::Error => {
+                        return Err(Error::NodeExecution(format!("Unresolved template reference '{}'", path)));
+                    },
+                    MissingRef::Empty => {},
+                    MissingRef::Keep => {
+                        out.push_str("{{");
+                        out.push_str(path);
+                        out.push_str("}}");
+                    },
+                },
+            },
+            Segment::Each(path, body) => {
+                let items = match resolve(path, context, scopes) {
+                    Some(Value::Array(items)) => items,
+                    Some(Value::Null) | None if missing != MissingRef::Error => Vec::new(),
+                    _ => return Err(Error::NodeExecution(format!("'{{{{#each {}}}}}' requires an array", path))),
+                };
+                for (index, item) in items.iter().enumerate() {
+                    let mut inner: Vec<Scope> = scopes.iter().map(|s| Scope { item: s.item, index: s.index }).collect();
+                    inner.push(Scope { item, index });
+                    render_segments(body, context, &mut inner, missing, out)?;
+                }
+            },
+            Segment::If(path, then, otherwise) => {
+                let branch = if resolve(path, context, scopes).map(|v| truthy(&v)).unwrap_or(false) {
+                    then
+                } else {
+                    otherwise
+                };
+                render_segments(branch, context, scopes, missing, out)?;
+            },
+        }
+    }
+    
+    Ok(())
+}
+
+impl NodeTrait for PromptTemplateNode {
+    fn params(&self) -> Arc<ParamMap> {
+        self.base.params()
+    }
+    
+    fn name(&self) -> String {
+        self.base.assigned_name().unwrap_or_else(short_type_name::<Self>)
+    }
+    
+    fn set_name(&self, name: &str) {
+        self.base.set_name(name);
+    }
+    
+    fn hooks(&self) -> NodeHooks {
+        self.base.hooks()
+    }
+    
+    fn set_hooks(&self, hooks: NodeHooks) {
+        self.base.set_hooks(hooks);
+    }
+    
+    fn successors(&self) -> Arc<RwLock<Successors>> {
+        self.base.successors()
+    }
+    
+    fn set_params(&self, params: Arc<ParamMap>) {
+        self.base.set_params(params);
+    }
+    
+    fn add_successor(&self, node: Arc<dyn NodeTrait>, action: &str) -> Result<Arc<dyn NodeTrait>> {
+        self.base.add_successor(node, action)
+    }
+    
+    fn prep(&self, shared: &mut SharedState) -> Result<Value> {
+        let params = self.params();
+        let template = params
+            .get("template")
+            .and_then(|v| v.as_str())
+            .unwrap_or(&self.template)
+            .to_string();
+        
+        let mut store = serde_json::Map::new();
+        if let Ok(segments) = parse(&template) {
+            for key in store_keys(&segments) {
+                if let Some(value) = shared.get(key) {
+                    store.insert(key.to_string(), value.clone());
+                }
+            }
+        }
+        
+        Ok(json!({
+            "template": template,
+            "context": {
+                "store": store,
+                "param": *params,
+            },
+        }))
+    }
+    
+    fn exec(&self, prep_res: &Value) -> Result<Value> {
+        let template = prep_res["template"].as_str().unwrap_or_default();
+        Self::render(template, &prep_res["context"], self.missing).map(Value::String)
+    }
+    
+    fn post(&self, shared: &mut SharedState, _prep_res: Value, exec_res: Value) -> Result<Action> {
+        shared.insert(self.output_key(), exec_res);
+        Ok(Some(ActionName::default_action()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::base::DEFAULT_ACTION;
+    
+    use super::*;
+    
+    fn render(template: &str, context: Value, missing: MissingRef) -> Result<String> {
+        PromptTemplateNode::render(template, &context, missing)
+    }
+    
+    #[test]
+    fn substitutes_nested_paths() {
+        let context = json!({"store": {"user": {"name": "Ada", "langs": ["en", "fr"]}}, "param": {"tone": "dry"}});
+        let out = render("{{store.user.name}} speaks {{user.langs.1}}, {{param.tone}}", context, MissingRef::Error).unwrap();
+        assert_eq!(out, "Ada speaks fr, dry");
+    }
+    
+    #[test]
+    fn loops_over_an_array_of_objects() {
+        let context = json!({"store": {"items": [{"name": "a", "n": 1}, {"name": "b", "n": 2}]}});
+        let out = render("{{#each items}}{{@index}}:{{name}}={{this.n}};{{/each}}", context, MissingRef::Error).unwrap();
+        assert_eq!(out, "0:a=1;1:b=2;");
+    }
+    
+    #[test]
+    fn renders_conditionals_and_escaped_braces() {
+        let context = json!({"store": {"flag": true, "empty": ""}});
+        let out = render("{{#if flag}}yes{{else}}no{{/if}} {{#if empty}}yes{{else}}no{{/if}} \\{{x}}", context, MissingRef::Error).unwrap();
+        assert_eq!(out, "yes no {{x}}");
+    }
+    
+    #[test]
+    fn missing_references_follow_the_configured_mode() {
+        let context = json!({"store": {}});
+        assert!(matches!(render("a{{gone}}b", context.clone(), MissingRef::Error), Err(Error::NodeExecution(_))));
+        assert_eq!(render("a{{gone}}b", context.clone(), MissingRef::Empty).unwrap(), "ab");
+        assert_eq!(render("a{{gone}}b", context, MissingRef::Keep).unwrap(), "a{{gone}}b");
+    }
+    
+    #[test]
+    fn prep_only_copies_referenced_keys() {
+        let node = PromptTemplateNode::new("{{store.a.x}} {{#each list}}{{this}}{{/each}} {{#if c}}{{param.p}}{{/if}}", "out");
+        let mut shared = SharedState::new();
+        for key in ["a", "list", "c", "unrelated"] {
+            shared.insert(key.to_string(), json!([1]));
+        }
+        let prep = node.prep(&mut shared).unwrap();
+        let mut keys: Vec<&String> = prep["context"]["store"].as_object().unwrap().keys().collect();
+        keys.sort();
+        assert_eq!(keys, ["a", "c", "list"]);
+    }
+    
+    #[test]
+    fn template_and_output_key_params_override_construction() {
+        let node = PromptTemplateNode::new("unused", "unused");
+        node.set_params_map(ParamMap::from([
+            ("template".to_string(), json!("hi {{name}}")),
+            ("output_key".to_string(), json!("greeting")),
+        ]));
+        let mut shared = SharedState::from([("name".to_string(), json!("Bo"))]);
+        let action = node.run(&mut shared).unwrap();
+        assert_eq!(action.as_deref(), Some(DEFAULT_ACTION));
+        assert_eq!(shared["greeting"], json!("hi Bo"));
+    }
+}
\ No newline at end of file
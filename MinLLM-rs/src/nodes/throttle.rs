@@ -0,0 +1,384 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use parking_lot::RwLock;
+use async_trait::async_trait;
+use serde_json::Value;
+
+use crate::base::{BaseNode, Node as NodeTrait, SharedState, Action, ParamMap};
+use crate::successors::Successors;
+use crate::hooks::NodeHooks;
+use crate::param_spec::ParamSpec;
+use crate::dataflow::KeySpec;
+use crate::async_node::AsyncNodeTrait;
+use crate::rate_limit::RateLimiter;
+use crate::sleeper::{self, Sleeper};
+use crate::retry::{self, FallbackContext, FixedRetry};
+use crate::error::{Error, Result};
+
+/// A wrapper node that acquires a permit from a shared rate limiter before executing
+pub struct ThrottleNode<N: ?Sized = dyn NodeTrait> {
+    /// The wrapped node
+    inner: Arc<N>,
+    
+    /// Limiter shared by every node drawing from the same budget
+    limiter: Arc<RateLimiter>,
+    
+    /// Base node holding the wrapper's successors
+    base: BaseNode,
+    
+    /// Attempts the wrapper makes itself, each acquiring its own permit, when counting retries
+    retry: Option<FixedRetry>,
+    
+    /// Executions currently holding a permit
+    in_flight: Arc<AtomicUsize>,
+    
+    /// Executions currently waiting for a permit
+    waiting: Arc<AtomicUsize>,
+    
+    /// Source of the waits between attempts
+    sleeper: Arc<dyn Sleeper>,
+}
+
+/// Decrements the in-flight counter when an execution finishes
+struct InFlight(Arc<AtomicUsize>);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl<N: ?Sized> ThrottleNode<N> {
+    /// Wrap a node so its executions draw from the given limiter
+    pub fn wrap(inner: Arc<N>, limiter: Arc<RateLimiter>) -> Self {
+        Self {
+            inner,
+            limiter,
+            base: BaseNode::new(),
+            retry: None,
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            waiting: Arc::new(AtomicUsize::new(0)),
+            sleeper: sleeper::real(),
+        }
+    }
+    
+    /// Drive the inner node's attempts from the wrapper so every retry acquires its own permit
+    ///
+    /// At least one attempt is made; the last error goes to the inner node's fallback.
+    pub fn count_retries(mut self, max_retries: usize, wait: u64) -> Self {
+        self.retry = Some(FixedRetry::new(max_retries.max(1), Duration::from_millis(wait)));
+        self
+    }
+    
+    /// Route the sync waits for a permit and the waits between counted attempts through `sleeper`
+    pub fn with_sleeper(mut self, sleeper: Arc<dyn Sleeper>) -> Self {
+        self.sleeper = sleeper;
+        self
+    }
+    
+    /// The wrapped node
+    pub fn inner(&self) -> &Arc<N> {
+        &self.inner
+    }
+    
+    /// The shared limiter
+    pub fn limiter(&self) -> &Arc<RateLimiter> {
+        &self.limiter
+    }
+    
+    /// Number of executions currently holding a permit
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+    
+    /// Number of executions currently waiting for a permit
+    pub fn waiting(&self) -> usize {
+        self.waiting.load(Ordering::SeqCst)
+    }
+    
+    fn acquire_blocking(&self) -> InFlight {
+        self.waiting.fetch_add(1, Ordering::SeqCst);
+        self.limiter.acquire_with(self.sleeper.as_ref());
+        self.waiting.fetch_sub(1, Ordering::SeqCst);
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        InFlight(self.in_flight.clone())
+    }
+    
+    /// Run the inner node's exec once a permit is available, as one of the attempts the wrapper drives
+    fn exec_permitted(&self, prep_res: &Value) -> Result<Value>
+    where
+        N: NodeTrait,
+    {
+        let _permit = self.acquire_blocking();
+        self.inner.exec(prep_res)
+    }
+    
+    async fn acquire(&self) -> InFlight {
+        self.waiting.fetch_add(1, Ordering::SeqCst);
+        self.limiter.acquire().await;
+        self.waiting.fetch_sub(1, Ordering::SeqCst);
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        InFlight(self.in_flight.clone())
+    }
+}
+
+impl<N: ?Sized> Clone for ThrottleNode<N> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            limiter: self.limiter.clone(),
+            base: self.base.clone(),
+            retry: self.retry,
+            in_flight: self.in_flight.clone(),
+            waiting: self.waiting.clone(),
+            sleeper: self.sleeper.clone(),
+        }
+    }
+}
+
+impl<N: NodeTrait + ?Sized + 'static> NodeTrait for ThrottleNode<N> {
+    fn params(&self) -> Arc<ParamMap> {
+        self.inner.params()
+    }
+    
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+    
+    fn set_name(&self, name: &str) {
+        self.inner.set_name(name);
+    }
+    
+    fn hooks(&self) -> NodeHooks {
+        self.inner.hooks()
+    }
+    
+    fn set_hooks(&self, hooks: NodeHooks) {
+        self.inner.set_hooks(hooks);
+    }
+    
+    fn required_params(&self) -> &[ParamSpec] {
+        self.inner.required_params()
+    }
+    
+    fn declared_actions(&self) -> &[String] {
+        self.inner.declared_actions()
+    }
+    
+    fn reads(&self) -> &[KeySpec] {
+        self.inner.reads()
+    }
+    
+    fn writes(&self) -> &[KeySpec] {
+        self.inner.writes()
+    }
+    
+    fn exec_dry_run(&self, prep_res: &Value) -> Result<Value> {
+        self.inner.exec_dry_run(prep_res)
+    }
+    
+    fn successors(&self) -> Arc<RwLock<Successors>> {
+        self.base.successors()
+    }
+    
+    fn set_params(&self, params: Arc<ParamMap>) {
+        self.inner.set_params(params);
+    }
+    
+    fn add_successor(&self, node: Arc<dyn NodeTrait>, action: &str) -> Result<Arc<dyn NodeTrait>> {
+        self.base.add_successor(node, action)
+    }
+    
+    fn prep(&self, shared: &mut SharedState) -> Result<Value> {
+        self.inner.prep(shared)
+    }
+    
+    fn exec(&self, prep_res: &Value) -> Result<Value> {
+        self.inner.exec(prep_res)
+    }
+    
+    fn post(&self, shared: &mut SharedState, prep_res: Value, exec_res: Value) -> Result<Action> {
+        self.inner.post(shared, prep_res, exec_res)
+    }
+    
+    fn exec_fallback(&self, prep_res: &Value, error: Error) -> Result<Value> {
+        self.inner.exec_fallback(prep_res, error)
+    }
+    
+    fn _exec(&self, prep_res: &Value) -> Result<Value> {
+        let Some(policy) = &self.retry else {
+            let _permit = self.acquire_blocking();
+            return self.inner._exec(prep_res);
+        };
+        
+        let attempts = retry::Attempts::new(policy, self.sleeper.as_ref());
+        retry::drive(self, Self::exec_permitted, attempts, prep_res, |e, _| self.inner.exec_fallback(prep_res, e))
+    }
+}
+
+#[async_trait]
+impl<N: AsyncNodeTrait + ?Sized + 'static> AsyncNodeTrait for ThrottleNode<N> {
+    async fn prep_async(&self, shared: &mut SharedState) -> Result<Value> {
+        self.inner.prep_async(shared).await
+    }
+    
+    async fn exec_async(&self, prep_res: &Value) -> Result<Value> {
+        self.inner.exec_async(prep_res).await
+    }
+    
+    async fn post_async(&self, shared: &mut SharedState, prep_res: Value, exec_res: Value) -> Result<Action> {
+        self.inner.post_async(shared, prep_res, exec_res).await
+    }
+    
+    async fn exec_fallback_async(&self, prep_res: &Value, error: Error) -> Result<Value> {
+        self.inner.exec_fallback_async(prep_res, error).await
+    }
+    
+    async fn exec_fallback_async_ctx(&self, prep_res: &Value, error: Error, ctx: FallbackContext) -> Result<Value> {
+        self.inner.exec_fallback_async_ctx(prep_res, error, ctx).await
+    }
+    
+    async fn _exec_async(&self, prep_res: &Value) -> Result<Value> {
+        let Some(policy) = &self.retry else {
+            let _permit = self.acquire().await;
+            return self.inner._exec_async(prep_res).await;
+        };
+        
+        retry::drive_async(
+            retry::Attempts::new(policy, self.sleeper.as_ref()),
+            || self.name(),
+            |_| async {
+                let _permit = self.acquire().await;
+                self.inner.exec_async(prep_res).await
+            },
+            |e, ctx| self.inner.exec_fallback_async_ctx(prep_res, e, ctx),
+        ).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use parking_lot::Mutex;
+    use serde_json::json;
+    use tokio::time::Instant;
+    
+    use crate::nodes::{AsyncFnNode, FnNode};
+    use crate::retry::current_attempt;
+    use crate::sleeper::TestSleeper;
+    
+    use super::*;
+    
+    fn millis(durations: &[Duration]) -> Vec<u128> {
+        durations.iter().map(|d| (d.as_secs_f64() * 1000.0).round() as u128).collect()
+    }
+    
+    /// Whole permits left in `limiter`, taking them all
+    fn available(limiter: &RateLimiter) -> usize {
+        std::iter::from_fn(|| limiter.try_acquire().ok()).count()
+    }
+    
+    #[tokio::test(start_paused = true)]
+    async fn twelve_executions_at_five_per_second_take_three_windows() {
+        let limiter = Arc::new(RateLimiter::new(5, Duration::from_secs(1)));
+        let started = Arc::new(Mutex::new(Vec::new()));
+        let inner = AsyncFnNode::new().with_exec({
+            let started = started.clone();
+            move |prep| {
+                started.lock().push(Instant::now());
+                async move { Ok(prep) }
+            }
+        });
+        let node = ThrottleNode::wrap(Arc::new(inner), limiter);
+        
+        let start = Instant::now();
+        for i in 0..12 {
+            assert_eq!(node._exec_async(&json!(i)).await.unwrap(), json!(i));
+        }
+        let offsets: Vec<Duration> = started.lock().iter().map(|t| t.duration_since(start)).collect();
+        assert_eq!(millis(&offsets), [0, 0, 0, 0, 0, 200, 400, 600, 800, 1000, 1200, 1400]);
+        assert_eq!((node.in_flight(), node.waiting()), (0, 0));
+    }
+    
+    #[tokio::test(start_paused = true)]
+    async fn throttles_sharing_a_limiter_share_its_budget() {
+        let limiter = Arc::new(RateLimiter::new(5, Duration::from_secs(1)));
+        let runs = Arc::new(Mutex::new(Vec::new()));
+        let throttle = |name: &'static str| {
+            let runs = runs.clone();
+            let inner = AsyncFnNode::new().with_exec(move |prep| {
+                runs.lock().push((name, Instant::now()));
+                async move { Ok(prep) }
+            });
+            ThrottleNode::wrap(Arc::new(inner), limiter.clone())
+        };
+        let (a, b) = (throttle("a"), throttle("b"));
+        
+        let start = Instant::now();
+        let drive = |node: ThrottleNode<AsyncFnNode>| async move {
+            for _ in 0..6 {
+                node._exec_async(&Value::Null).await.unwrap();
+            }
+        };
+        tokio::join!(drive(a), drive(b));
+        
+        let runs = runs.lock();
+        assert_eq!(runs.len(), 12);
+        assert_eq!(runs.iter().filter(|(name, _)| *name == "a").count(), 6);
+        let mut offsets: Vec<Duration> = runs.iter().map(|(_, t)| t.duration_since(start)).collect();
+        offsets.sort();
+        // The burst of five, then one permit every 200ms for the pair combined
+        assert_eq!(millis(&offsets), [0, 0, 0, 0, 0, 200, 400, 600, 800, 1000, 1200, 1400]);
+    }
+    
+    #[tokio::test(start_paused = true)]
+    async fn sync_executions_wait_for_permits_through_the_sleeper() {
+        let limiter = Arc::new(RateLimiter::new(5, Duration::from_secs(1)));
+        let sleeper = Arc::new(TestSleeper::new());
+        let node = ThrottleNode::wrap(Arc::new(FnNode::new()), limiter).with_sleeper(sleeper.clone());
+        
+        for i in 0..12 {
+            assert_eq!(node._exec(&json!(i)).unwrap(), json!(i));
+        }
+        assert_eq!(millis(&sleeper.requested()), [200, 400, 600, 800, 1000, 1200, 1400]);
+    }
+    
+    #[test]
+    fn counted_retries_each_take_a_permit_and_end_in_the_fallback() {
+        let limiter = Arc::new(RateLimiter::new(100, Duration::from_secs(3600)));
+        let inner = FnNode::new()
+            .with_exec(|_| Err(Error::NodeExecution(format!("attempt {:?}", current_attempt()))))
+            .with_fallback(|_, e| Ok(json!(e.to_string())));
+        let node = ThrottleNode::wrap(Arc::new(inner), limiter.clone()).count_retries(3, 0);
+        
+        assert_eq!(node._exec(&Value::Null).unwrap(), json!("Node execution error: attempt Some(2)"));
+        assert_eq!(limiter.permits() - available(&limiter), 3);
+    }
+    
+    #[tokio::test]
+    async fn counted_retries_fall_back_the_same_way_asynchronously() {
+        let limiter = Arc::new(RateLimiter::new(100, Duration::from_secs(3600)));
+        let inner = AsyncFnNode::new()
+            .with_exec(|_| async { Err(Error::NodeExecution(format!("attempt {:?}", current_attempt()))) })
+            .with_fallback(|_, e| async move { Ok(json!(e.to_string())) });
+        let node = ThrottleNode::wrap(Arc::new(inner), limiter.clone()).count_retries(3, 0);
+        
+        assert_eq!(node._exec_async(&Value::Null).await.unwrap(), json!("Node execution error: attempt Some(2)"));
+        assert_eq!(limiter.permits() - available(&limiter), 3);
+    }
+    
+    #[test]
+    fn counting_zero_retries_still_runs_the_inner_node_once() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let inner = FnNode::new().with_exec({
+            let runs = runs.clone();
+            move |prep| {
+                runs.fetch_add(1, Ordering::SeqCst);
+                Ok(prep.clone())
+            }
+        });
+        let node = ThrottleNode::wrap(Arc::new(inner), Arc::new(RateLimiter::new(1, Duration::ZERO))).count_retries(0, 0);
+        assert_eq!(node._exec(&json!("x")).unwrap(), json!("x"));
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,217 @@
+use std::any::type_name;
+use std::sync::Arc;
+use parking_lot::RwLock;
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+use crate::base::{BaseNode, short_type_name, Node as NodeTrait, SharedState, Action, ParamMap};
+use crate::successors::Successors;
+use crate::hooks::NodeHooks;
+use crate::async_node::AsyncNodeTrait;
+use crate::error::{Error, Result};
+
+/// Node steps over typed values, adapted to the `Node` trait by `TypedNode`
+pub trait TypedLogic: Send + Sync + 'static {
+    /// Result of prep, handed to exec
+    type Input: Serialize + DeserializeOwned + Send + Sync;
+    
+    /// Result of exec, handed to post
+    type Output: Serialize + DeserializeOwned + Send + Sync;
+    
+    /// Read the input from the shared state
+    fn prep(&self, shared: &SharedState) -> Result<Self::Input>;
+    
+    /// Compute the output from the input
+    fn exec(&self, input: Self::Input) -> Result<Self::Output>;
+    
+    /// Write results into the shared state and choose the action
+    fn post(&self, _shared: &mut SharedState, _input: &Self::Input, _output: &Self::Output) -> Result<Action> {
+        Ok(None)
+    }
+}
+
+/// Adapts `TypedLogic` to the untyped `Node` trait, so typed and untyped nodes share a flow
+///
+/// Values cross the prep/exec/post boundaries as JSON; a value that does not fit the
+/// expected type fails the node with an error naming the logic type and the step.
+pub struct TypedNode<T: TypedLogic> {
+    /// Base node implementation
+    base: BaseNode,
+    
+    /// The typed steps
+    logic: T,
+}
+
+impl<T: TypedLogic> TypedNode<T> {
+    /// Wrap typed steps in a node
+    pub fn new(logic: T) -> Self {
+        Self {
+            base: BaseNode::new(),
+            logic,
+        }
+    }
+    
+    /// The typed steps
+    pub fn logic(&self) -> &T {
+        &self.logic
+    }
+    
+    fn to_json<V: Serialize>(value: &V, step: &str) -> Result<Value> {
+        serde_json::to_value(value).map_err(|e| {
+            Error::NodeExecution(format!("{}: cannot serialize the {} result: {}", type_name::<T>(), step, e))
+        })
+    }
+    
+    fn from_json<V: DeserializeOwned>(value: &Value, step: &str) -> Result<V> {
+        V::deserialize(value).map_err(|e| {
+            Error::NodeExecution(format!(
+                "{}: {} expected {}: {}",
+                type_name::<T>(),
+                step,
+                type_name::<V>(),
+                e
+            ))
+        })
+    }
+}
+
+impl<T: TypedLogic> NodeTrait for TypedNode<T> {
+    fn params(&self) -> Arc<ParamMap> {
+        self.base.params()
+    }
+    
+    fn name(&self) -> String {
+        self.base.assigned_name().unwrap_or_else(short_type_name::<Self>)
+    }
+    
+    fn set_name(&self, name: &str) {
+        self.base.set_name(name);
+    }
+    
+    fn hooks(&self) -> NodeHooks {
+        self.base.hooks()
+    }
+    
+    fn set_hooks(&self, hooks: NodeHooks) {
+        self.base.set_hooks(hooks);
+    }
+    
+    fn successors(&self) -> Arc<RwLock<Successors>> {
+        self.base.successors()
+    }
+    
+    fn set_params(&self, params: Arc<ParamMap>) {
+        self.base.set_params(params);
+    }
+    
+    fn add_successor(&self, node: Arc<dyn NodeTrait>, action: &str) -> Result<Arc<dyn NodeTrait>> {
+        self.base.add_successor(node, action)
+    }
+    
+    fn prep_readonly(&self, shared: &SharedState) -> Result<Value> {
+        Self::to_json(&self.logic.prep(shared)?, "prep")
+    }
+    
+    fn exec(&self, prep_res: &Value) -> Result<Value> {
+        let input = Self::from_json(prep_res, "exec")?;
+        Self::to_json(&self.logic.exec(input)?, "exec")
+    }
+    
+    fn post(&self, shared: &mut SharedState, prep_res: Value, exec_res: Value) -> Result<Action> {
+        let input = Self::from_json(&prep_res, "post")?;
+        let output = Self::from_json(&exec_res, "post")?;
+        self.logic.post(shared, &input, &output)
+    }
+}
+
+#[async_trait]
+impl<T: TypedLogic> AsyncNodeTrait for TypedNode<T> {
+    async fn exec_async(&self, prep_res: &Value) -> Result<Value> {
+        self.exec(prep_res)
+    }
+    
+    async fn post_async(&self, shared: &mut SharedState, prep_res: Value, exec_res: Value) -> Result<Action> {
+        self.post(shared, prep_res, exec_res)
+    }
+    
+    async fn _exec_async(&self, prep_res: &Value) -> Result<Value> {
+        self.exec_async(prep_res).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use serde_json::json;
+    
+    use crate::flow::Flow;
+    use crate::nodes::FnNode;
+    
+    use super::*;
+    
+    #[derive(Serialize, Deserialize)]
+    struct Order {
+        item: String,
+        quantity: u32,
+    }
+    
+    #[derive(Serialize, Deserialize)]
+    struct Invoice {
+        line: String,
+        total: u32,
+    }
+    
+    /// Prices an order read from "order" at 3 per unit
+    struct PriceOrder;
+    
+    impl TypedLogic for PriceOrder {
+        type Input = Order;
+        type Output = Invoice;
+        
+        fn prep(&self, shared: &SharedState) -> Result<Order> {
+            Ok(Order::deserialize(&shared["order"]).unwrap())
+        }
+        
+        fn exec(&self, order: Order) -> Result<Invoice> {
+            Ok(Invoice {
+                line: format!("{} x{}", order.item, order.quantity),
+                total: order.quantity * 3,
+            })
+        }
+        
+        fn post(&self, shared: &mut SharedState, _order: &Order, invoice: &Invoice) -> Result<Action> {
+            shared.insert("invoice".to_string(), serde_json::to_value(invoice).unwrap());
+            Ok(Some("bill".into()))
+        }
+    }
+    
+    #[test]
+    fn a_typed_node_feeds_an_untyped_node() {
+        let price: Arc<dyn NodeTrait> = Arc::new(TypedNode::new(PriceOrder));
+        let bill: Arc<dyn NodeTrait> = Arc::new(
+            FnNode::new()
+                .with_prep(|shared| Ok(shared["invoice"].clone()))
+                .with_exec(|invoice| Ok(json!(format!("{}: {}", invoice["line"].as_str().unwrap(), invoice["total"]))))
+                .with_post(|shared, _, exec_res| {
+                    shared.insert("bill".to_string(), exec_res);
+                    Ok(None)
+                }),
+        );
+        price.add_successor(bill, "bill").unwrap();
+        
+        let mut shared = SharedState::from([("order".to_string(), json!({"item": "pen", "quantity": 4}))]);
+        Flow::new(price).run(&mut shared).unwrap();
+        assert_eq!(shared["invoice"], json!({"line": "pen x4", "total": 12}));
+        assert_eq!(shared["bill"], json!("pen x4: 12"));
+    }
+    
+    #[test]
+    fn a_value_of_the_wrong_shape_names_the_logic_and_the_step() {
+        let node = TypedNode::new(PriceOrder);
+        let message = node.exec(&json!({"item": "pen"})).unwrap_err().to_string();
+        assert!(message.contains(type_name::<PriceOrder>()), "{}", message);
+        assert!(message.contains("exec expected"), "{}", message);
+        assert!(message.contains("quantity"), "{}", message);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,380 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use parking_lot::RwLock;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::base::{BaseNode, short_type_name, Node as NodeTrait, SharedState, Action, ParamMap};
+use crate::action::ActionName;
+use crate::successors::Successors;
+use crate::hooks::NodeHooks;
+use crate::param_spec::matches_json_type;
+use crate::error::{Error, Result};
+
+/// Constraints checked against the value stored under one key
+///
+/// Every field is optional; a value must satisfy all of the ones that are set.
+/// `schema` holds a JSON Schema and requires the `schema` feature.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Constraint {
+    /// Expected JSON type: "string", "number", "integer", "boolean", "array", "object" or "null"
+    #[serde(rename = "type")]
+    pub json_type: Option<String>,
+    
+    /// Reject empty strings, arrays and objects
+    pub non_empty: bool,
+    
+    /// Regular expression strings must match
+    pub pattern: Option<String>,
+    
+    /// Inclusive lower bound for numbers
+    pub min: Option<f64>,
+    
+    /// Inclusive upper bound for numbers
+    pub max: Option<f64>,
+    
+    /// JSON Schema the value must satisfy
+    pub schema: Option<Value>,
+}
+
+impl Constraint {
+    /// A constraint on the JSON type of the value
+    pub fn of_type(json_type: &str) -> Self {
+        Self {
+            json_type: Some(json_type.to_string()),
+            ..Self::default()
+        }
+    }
+    
+    /// A constraint delegating to a JSON Schema
+    pub fn schema(schema: Value) -> Self {
+        Self {
+            schema: Some(schema),
+            ..Self::default()
+        }
+    }
+    
+    /// Also reject empty values
+    pub fn non_empty(mut self) -> Self {
+        self.non_empty = true;
+        self
+    }
+    
+    /// Also require strings to match a regular expression
+    pub fn pattern(mut self, pattern: &str) -> Self {
+        self.pattern = Some(pattern.to_string());
+        self
+    }
+    
+    /// Also require numbers to fall within an inclusive range
+    pub fn range(mut self, min: Option<f64>, max: Option<f64>) -> Self {
+        self.min = min;
+        self.max = max;
+        self
+    }
+    
+    /// Check a value, appending every failed constraint to `violations`
+    fn check(&self, key: &str, value: &Value, violations: &mut Vec<Violation>) -> Result<()> {
+        let mut fail = |constraint: &str, message: String| violations.push(Violation {
+            key: key.to_string(),
+            pointer: String::new(),
+            constraint: constraint.to_string(),
+            message,
+        });
+        
+        if let Some(expected) = &self.json_type {
+            if !matches_json_type(expected, value)? {
+                fail("type", format!("expected {}, found {}", expected, value));
+            }
+        }
+        
+        if self.non_empty {
+            let empty = match value {
+                Value::Null => true,
+                Value::String(s) => s.is_empty(),
+                Value::Array(items) => items.is_empty(),
+                Value::Object(map) => map.is_empty(),
+                _ => false,
+            };
+            if empty {
+                fail("non_empty", "value is empty".to_string());
+            }
+        }
+        
+        if let (Some(pattern), Some(s)) = (&self.pattern, value.as_str()) {
+            let regex = Regex::new(pattern)
+                .map_err(|e| Error::InvalidOperation(format!("Invalid pattern '{}': {}", pattern, e)))?;
+            if !regex.is_match(s) {
+                fail("pattern", format!("'{}' does not match '{}'", s, pattern));
+            }
+        }
+        
+        if let Some(n) = value.as_f64() {
+            if self.min.map(|min| n < min).unwrap_or(false) {
+                fail("min", format!("{} is below {}", n, self.min.unwrap_or_default()));
+            }
+            if self.max.map(|max| n > max).unwrap_or(false) {
+                fail("max", format!("{} is above {}", n, self.max.unwrap_or_default()));
+            }
+        }
+        
+        if let Some(schema) = &self.schema {
+            check_schema(key, schema, value, violations)?;
+        }
+        
+        Ok(())
+    }
+}
+
+#[cfg(feature = "schema")]
+fn check_schema(key: &str, schema: &Value, value: &Value, violations: &mut Vec<Violation>) -> Result<()> {
+    let validator = jsonschema::validator_for(schema)
+        .map_err(|e| Error::InvalidOperation(format!("Invalid JSON Schema for '{}': {}", key, e)))?;
+    
+    for error in validator.iter_errors(value) {
+        violations.push(Violation {
+            key: key.to_string(),
+            pointer: error.instance_path.to_string(),
+            constraint: format!("schema{}", error.schema_path),
+            message: error.to_string(),
+        });
+    }
+    
+    Ok(())
+}
+
+#[cfg(not(feature = "schema"))]
+fn check_schema(key: &str, _schema: &Value, _value: &Value, _violations: &mut Vec<Violation>) -> Result<()> {
+    Err(Error::InvalidOperation(format!(
+        "JSON Schema constraint on '{}' requires the 'schema' feature",
+        key
+    )))
+}
+
+/// A failed constraint
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Violation {
+    /// Shared state key that was checked
+    pub key: String,
+    
+    /// JSON pointer to the offending part of the value (empty for the whole value)
+    pub pointer: String,
+    
+    /// Name of the constraint that failed
+    pub constraint: String,
+    
+    /// Human-readable description
+    pub message: String,
+}
+
+/// A node checking shared state entries against constraints
+///
+/// `post` writes the list of violations to the report key (default "violations") and returns
+/// "valid" or "invalid". Rules can also be supplied through the `rules` param as an object
+/// mapping keys to constraints.
+#[derive(Clone)]
+pub struct ValidateNode {
+    /// Base node implementation
+    base: BaseNode,
+    
+    /// Constraints by shared state key
+    rules: BTreeMap<String, Constraint>,
+    
+    /// Shared state key receiving the violations report
+    report_key: String,
+}
+
+impl ValidateNode {
+    /// Create a validate node without rules
+    pub fn new() -> Self {
+        Self {
+            base: BaseNode::new(),
+            rules: BTreeMap::new(),
+            report_key: "violations".to_string(),
+        }
+    }
+    
+    /// Add a constraint for a shared state key
+    pub fn rule(mut self, key: &str, constraint: Constraint) -> Self {
+        self.rules.insert(key.to_string(), constraint);
+        self
+    }
+    
+    /// Set the shared state key receiving the violations report
+    pub fn report_key(mut self, key: &str) -> Self {
+        self.report_key = key.to_string();
+        self
+    }
+    
+    fn rules(&self) -> Result<BTreeMap<String, Constraint>> {
+        match self.params().get("rules") {
+            Some(value) => serde_json::from_value(value.clone())
+                .map_err(|e| Error::InvalidOperation(format!("Invalid 'rules' param: {}", e))),
+            None => Ok(self.rules.clone()),
+        }
+    }
+}
+
+impl Default for ValidateNode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NodeTrait for ValidateNode {
+    fn params(&self) -> Arc<ParamMap> {
+        self.base.params()
+    }
+    
+    fn name(&self) -> String {
+        self.base.assigned_name().unwrap_or_else(short_type_name::<Self>)
+    }
+    
+    fn set_name(&self, name: &str) {
+        self.base.set_name(name);
+    }
+    
+    fn hooks(&self) -> NodeHooks {
+        self.base.hooks()
+    }
+    
+    fn set_hooks(&self, hooks: NodeHooks) {
+        self.base.set_hooks(hooks);
+    }
+    
+    fn successors(&self) -> Arc<RwLock<Successors>> {
+        self.base.successors()
+    }
+    
+    fn set_params(&self, params: Arc<ParamMap>) {
+        self.base.set_params(params);
+    }
+    
+    fn add_successor(&self, node: Arc<dyn NodeTrait>, action: &str) -> Result<Arc<dyn NodeTrait>> {
+        self.base.add_successor(node, action)
+    }
+    
+    fn prep(&self, shared: &mut SharedState) -> Result<Value> {
+        let rules = self.rules()?;
+        let values: serde_json::Map<String, Value> = rules
+            .keys()
+            .filter_map(|key| shared.get(key).map(|v| (key.clone(), v.clone())))
+            .collect();
+        
+        Ok(json!({
+            "rules": rules,
+            "values": values,
+        }))
+    }
+    
+    fn exec(&self, prep_res: &Value) -> Result<Value> {
+        let rules: BTreeMap<String, Constraint> = serde_json::from_value(prep_res["rules"].clone())
+            .map_err(|e| Error::NodeExecution(format!("Invalid rules: {}", e)))?;
+        
+        let mut violations = Vec::new();
+        for (key, constraint) in &rules {
+            match prep_res["values"].get(key) {
+                Some(value) => constraint.check(key, value, &mut violations)?,
+                None => violations.push(Violation {
+                    key: key.clone(),
+                    pointer: String::new(),
+                    constraint: "required".to_string(),
+                    message: format!("missing key '{}'", key),
+                }),
+            }
+        }
+        
+        serde_json::to_value(violations).map_err(|e| Error::NodeExecution(e.to_string()))
+    }
+    
+    fn post(&self, shared: &mut SharedState, _prep_res: Value, exec_res: Value) -> Result<Action> {
+        let valid = exec_res.as_array().map(|v| v.is_empty()).unwrap_or(true);
+        shared.insert(self.report_key.clone(), exec_res);
+        Ok(Some(ActionName::new(if valid { "valid" } else { "invalid" })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// The (key, pointer, constraint) of every violation in the report
+    fn failures(shared: &SharedState) -> Vec<(String, String, String)> {
+        let violations: Vec<Violation> = serde_json::from_value(shared["violations"].clone()).unwrap();
+        violations.into_iter().map(|v| (v.key, v.pointer, v.constraint)).collect()
+    }
+    
+    fn store() -> SharedState {
+        SharedState::from([
+            ("title".to_string(), json!("Release notes")),
+            ("score".to_string(), json!(7)),
+            ("tags".to_string(), json!([])),
+        ])
+    }
+    
+    #[test]
+    fn passes_when_every_constraint_holds() {
+        let node = ValidateNode::new()
+            .rule("title", Constraint::of_type("string").non_empty().pattern("^[A-Z]"))
+            .rule("score", Constraint::of_type("integer").range(Some(0.0), Some(10.0)));
+        let mut shared = store();
+        assert_eq!(node.run(&mut shared).unwrap().as_deref(), Some("valid"));
+        assert_eq!(shared["violations"], json!([]));
+    }
+    
+    #[test]
+    fn aggregates_every_violation() {
+        let node = ValidateNode::new()
+            .rule("title", Constraint::of_type("string").pattern("^[0-9]+$"))
+            .rule("score", Constraint::of_type("string").range(Some(8.0), None))
+            .rule("tags", Constraint::of_type("array").non_empty())
+            .report_key("problems");
+        let mut shared = store();
+        assert_eq!(node.run(&mut shared).unwrap().as_deref(), Some("invalid"));
+        shared.insert("violations".to_string(), shared["problems"].clone());
+        let expected = [("score", "type"), ("score", "min"), ("tags", "non_empty"), ("title", "pattern")];
+        let expected: Vec<_> = expected.iter().map(|(key, constraint)| (key.to_string(), String::new(), constraint.to_string())).collect();
+        assert_eq!(failures(&shared), expected);
+    }
+    
+    #[test]
+    fn reports_a_missing_key() {
+        let node = ValidateNode::new().rule("summary", Constraint::of_type("string"));
+        let mut shared = store();
+        assert_eq!(node.run(&mut shared).unwrap().as_deref(), Some("invalid"));
+        assert_eq!(failures(&shared), [("summary".to_string(), String::new(), "required".to_string())]);
+    }
+    
+    #[test]
+    fn loads_declarative_constraints_from_params() {
+        let node = ValidateNode::new();
+        node.set_params_map(ParamMap::from([(
+            "rules".to_string(),
+            json!({
+                "title": {"type": "string", "non_empty": true, "pattern": "notes$"},
+                "score": {"type": "number", "min": 0, "max": 5},
+            }),
+        )]));
+        let mut shared = store();
+        assert_eq!(node.run(&mut shared).unwrap().as_deref(), Some("invalid"));
+        assert_eq!(failures(&shared), [("score".to_string(), String::new(), "max".to_string())]);
+        
+        node.set_params_map(ParamMap::from([("rules".to_string(), json!({"title": {"type": 3}}))]));
+        assert!(matches!(node.run(&mut store()), Err(Error::InvalidOperation(_))));
+    }
+    
+    #[cfg(feature = "schema")]
+    #[test]
+    fn schema_violations_point_into_the_value() {
+        let schema = json!({"type": "object", "properties": {"items": {"type": "array", "items": {"type": "integer"}}}});
+        let node = ValidateNode::new().rule("order", Constraint::schema(schema));
+        let mut shared = SharedState::from([("order".to_string(), json!({"items": [1, "two", 3]}))]);
+        assert_eq!(node.run(&mut shared).unwrap().as_deref(), Some("invalid"));
+        let failures = failures(&shared);
+        assert_eq!(failures.len(), 1);
+        assert_eq!((failures[0].0.as_str(), failures[0].1.as_str()), ("order", "/items/1"));
+        assert!(failures[0].2.starts_with("schema"), "{}", failures[0].2);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,101 @@
+use std::sync::Arc;
+use serde_json::Value;
+
+use crate::base::{Node, ParamMap};
+use crate::error::{Error, Result};
+
+/// A parameter a node expects to be given
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParamSpec {
+    /// Name of the param
+    pub name: String,
+    
+    /// Expected JSON type: "string", "number", "integer", "boolean", "array", "object" or "null"
+    pub json_type: Option<String>,
+    
+    /// Value used when the param is not provided; without one the param is required
+    pub default: Option<Value>,
+}
+
+impl ParamSpec {
+    /// A required param of any type
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            json_type: None,
+            default: None,
+        }
+    }
+    
+    /// Also require the param to have a JSON type
+    pub fn of_type(mut self, json_type: &str) -> Self {
+        self.json_type = Some(json_type.to_string());
+        self
+    }
+    
+    /// Make the param optional, filling in `default` when it is missing
+    pub fn with_default(mut self, default: Value) -> Self {
+        self.default = Some(default);
+        self
+    }
+}
+
+/// Whether a value has a JSON type, by the names used in `ParamSpec` and `Constraint`
+pub(crate) fn matches_json_type(json_type: &str, value: &Value) -> Result<bool> {
+    Ok(match json_type {
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "boolean" => value.is_boolean(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        "null" => value.is_null(),
+        other => return Err(Error::InvalidOperation(format!("Unknown JSON type '{}'", other))),
+    })
+}
+
+/// Check `params` against the node's param specs, appending every problem to `problems`
+///
+/// Returns the params with the missing defaults filled in.
+pub(crate) fn check_params(node: &dyn Node, params: Arc<ParamMap>, problems: &mut Vec<String>) -> Result<Arc<ParamMap>> {
+    let specs = node.required_params();
+    let mut filled: Option<ParamMap> = None;
+    
+    for spec in specs {
+        match params.get(&spec.name) {
+            Some(value) => {
+                if let Some(json_type) = &spec.json_type {
+                    if !matches_json_type(json_type, value)? {
+                        problems.push(format!("'{}' should be {}, found {}", spec.name, json_type, value));
+                    }
+                }
+            },
+            None => match &spec.default {
+                Some(default) => {
+                    filled
+                        .get_or_insert_with(|| ParamMap::clone(&params))
+                        .insert(spec.name.clone(), default.clone());
+                },
+                None => {
+                    let expected = spec.json_type.as_deref().unwrap_or("any");
+                    problems.push(format!("missing '{}' ({})", spec.name, expected));
+                },
+            },
+        }
+    }
+    
+    Ok(filled.map(Arc::new).unwrap_or(params))
+}
+
+/// Check `params` against the node's param specs, failing with every problem found
+pub(crate) fn resolve_params(node: &dyn Node, params: Arc<ParamMap>) -> Result<Arc<ParamMap>> {
+    let mut problems = Vec::new();
+    let params = check_params(node, params, &mut problems)?;
+    if problems.is_empty() {
+        Ok(params)
+    } else {
+        let name = node.name();
+        let problems: Vec<String> = problems.iter().map(|problem| format!("{}: {}", name, problem)).collect();
+        Err(Error::FlowExecution(format!("Invalid params: {}", problems.join("; "))))
+    }
+}
\ No newline at end of file
@@ -2,13 +2,14 @@
 
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 use pyo3::prelude::*;
-use pyo3::types::{PyDict, PyTuple, PyList};
-use pyo3::exceptions::{PyRuntimeError, PyTypeError};
+use pyo3::types::{PyDict, PyList};
+use pyo3::exceptions::{PyRuntimeError, PyTypeError, PyValueError};
 use pyo3::PyResult;
 use serde_json::Value;
 
-use crate::base::{BaseNode as RustBaseNode, Node as RustNodeTrait, SharedState};
+use crate::base::{BaseNode as RustBaseNode, Node as RustNodeTrait, SharedState, Action, DEFAULT_ACTION};
 use crate::node::{Node as RustNode, BatchNode as RustBatchNode};
 use crate::flow::{Flow as RustFlow, BatchFlow as RustBatchFlow};
 use crate::async_node::{
@@ -22,107 +23,228 @@ use crate::async_flow::{
     AsyncBatchFlow as RustAsyncBatchFlow, 
     AsyncParallelBatchFlow as RustAsyncParallelBatchFlow
 };
+use crate::batch_policy::{BatchProgress, ProgressCallback};
+use crate::cancel::CancellationToken;
+use crate::retry::FallbackContext;
+use crate::step_guard::DEFAULT_MAX_STEPS;
+use crate::trace::FlowTrace;
+use crate::history::{StateEvent, StateOp, EntryMeta};
 use crate::error::Error;
+use crate::conversions::{self, py_to_value, value_to_py};
+use crate::runtime::{RuntimeGuard, RuntimeOptions};
 
-/// Convert Python object to serde_json Value
-fn py_to_value(py: Python, obj: &PyAny) -> PyResult<Value> {
-    if obj.is_none() {
-        return Ok(Value::Null);
-    }
-    
-    if let Ok(val) = obj.extract::<bool>() {
-        return Ok(Value::Bool(val));
-    }
-    
-    if let Ok(val) = obj.extract::<i64>() {
-        return Ok(Value::Number(val.into()));
-    }
-    
-    if let Ok(val) = obj.extract::<f64>() {
-        return match serde_json::Number::from_f64(val) {
-            Some(n) => Ok(Value::Number(n)),
-            None => Err(PyTypeError::new_err(format!("Cannot convert f64 to JSON Number: {}", val))),
-        };
-    }
-    
-    if let Ok(val) = obj.extract::<String>() {
-        return Ok(Value::String(val));
-    }
-    
-    if let Ok(list) = obj.downcast::<PyTuple>() {
-        let mut values = Vec::new();
-        for item in list.iter() {
-            values.push(py_to_value(py, item)?);
-        }
-        return Ok(Value::Array(values));
+/// Guards the configuration of the runtime every `run_async` call runs on
+static RUNTIME: RuntimeGuard = RuntimeGuard::new();
+
+/// Record that the shared tokio runtime is in use and can no longer be configured
+fn mark_runtime_started() {
+    RUNTIME.mark_started();
+}
+
+/// Configure the tokio runtime used by every `run_async` call
+///
+/// Must be called at most once, before any async node or flow runs.
+#[pyfunction]
+#[pyo3(signature = (worker_threads=None, thread_name_prefix=None, enable_io=true, enable_time=true))]
+fn configure_runtime(
+    worker_threads: Option<usize>,
+    thread_name_prefix: Option<String>,
+    enable_io: bool,
+    enable_time: bool,
+) -> PyResult<()> {
+    if worker_threads == Some(0) {
+        return Err(PyValueError::new_err("worker_threads must be greater than zero"));
     }
-    
-    if let Ok(list) = obj.extract::<Vec<&PyAny>>() {
-        let mut values = Vec::new();
-        for item in list {
-            values.push(py_to_value(py, item)?);
-        }
-        return Ok(Value::Array(values));
+    let options = RuntimeOptions { worker_threads, thread_name_prefix, enable_io, enable_time };
+    let builder = RUNTIME.configure(options).map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+    pyo3_asyncio::tokio::init(builder);
+    Ok(())
+}
+
+/// Forwards the crate's log records to Python's `logging`, using the record target as logger name
+struct PythonLogger;
+
+static PYTHON_LOGGER: PythonLogger = PythonLogger;
+
+impl log::Log for PythonLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.target().starts_with("minllm")
     }
     
-    if let Ok(dict) = obj.downcast::<PyDict>() {
-        let mut map = serde_json::Map::new();
-        for (key, value) in dict.iter() {
-            let key = key.extract::<String>()?;
-            let value = py_to_value(py, value)?;
-            map.insert(key, value);
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
         }
-        return Ok(Value::Object(map));
+        let level = match record.level() {
+            log::Level::Error => 40,
+            log::Level::Warn => 30,
+            log::Level::Info => 20,
+            log::Level::Debug => 10,
+            log::Level::Trace => 5,
+        };
+        let message = record.args().to_string();
+        Python::with_gil(|py| {
+            let _ = py
+                .import("logging")
+                .and_then(|logging| logging.call_method1("getLogger", (record.target(),)))
+                .and_then(|logger| logger.call_method1("log", (level, message)));
+        });
     }
     
-    Err(PyTypeError::new_err(format!("Cannot convert Python object to JSON: {:?}", obj)))
+    fn flush(&self) {}
 }
 
-/// Convert serde_json Value to Python object
-fn value_to_py(py: Python, value: Value) -> PyResult<PyObject> {
-    match value {
-        Value::Null => Ok(py.None()),
-        Value::Bool(b) => Ok(b.to_object(py)),
-        Value::Number(n) => {
-            if let Some(i) = n.as_i64() {
-                Ok(i.to_object(py))
-            } else if let Some(f) = n.as_f64() {
-                Ok(f.to_object(py))
-            } else {
-                Err(PyTypeError::new_err("Unsupported number type"))
-            }
-        },
-        Value::String(s) => Ok(s.to_object(py)),
-        Value::Array(arr) => {
-            let py_list = PyList::empty(py);
-            for item in arr {
-                py_list.append(value_to_py(py, item)?)?;
+/// Wrap a Python callable as a batch progress callback, called with a dict of the progress
+///
+/// Batches run without the GIL, so it is only taken for the duration of each call.
+fn py_progress_callback(callback: PyObject) -> ProgressCallback {
+    Arc::new(move |progress: BatchProgress| {
+        Python::with_gil(|py| {
+            let dict = PyDict::new(py);
+            let res = dict.set_item("completed", progress.completed)
+                .and_then(|_| dict.set_item("total", progress.total))
+                .and_then(|_| dict.set_item("failed", progress.failed))
+                .and_then(|_| dict.set_item("last_index", progress.last_index))
+                .and_then(|_| dict.set_item("elapsed", progress.elapsed.as_secs_f64()))
+                .and_then(|_| callback.call1(py, (dict,)));
+            if let Err(e) = res {
+                e.print(py);
             }
-            Ok(py_list.to_object(py))
-        },
-        Value::Object(obj) => {
-            let py_dict = PyDict::new(py);
-            for (key, value) in obj {
-                py_dict.set_item(key, value_to_py(py, value)?)?;
-            }
-            Ok(py_dict.to_object(py))
-        }
-    }
+        });
+    })
+}
+
+/// Convert a Python object to JSON and back, for checking what a conversion preserves
+#[pyfunction]
+#[pyo3(name = "_roundtrip_json")]
+fn roundtrip_json(py: Python, obj: &PyAny) -> PyResult<PyObject> {
+    conversions::testing::roundtrip_json(py, obj)
 }
 
 /// Convert Python dict to Rust SharedState
 fn py_dict_to_shared_state(py: Python, dict: &PyAny) -> PyResult<SharedState> {
     let dict = dict.downcast::<PyDict>()?;
-    let mut shared = HashMap::new();
+    let mut shared = HashMap::with_capacity(dict.len());
     for (key, value) in dict.iter() {
         let key = key.extract::<String>()?;
         let value = py_to_value(py, value)?;
         shared.insert(key, value);
     }
-    Ok(shared)
+    Ok(shared.into())
+}
+
+/// Convert a flow trace to a list of dicts, with times in seconds
+fn trace_to_py(py: Python, trace: FlowTrace) -> PyResult<PyObject> {
+    let list = PyList::empty(py);
+    for entry in trace {
+        let dict = PyDict::new(py);
+        dict.set_item("node_name", entry.node_name)?;
+        dict.set_item("action_taken", entry.action_taken.as_deref())?;
+        dict.set_item("started_at", entry.started_at.as_secs_f64())?;
+        dict.set_item("duration", entry.duration.as_secs_f64())?;
+        dict.set_item("error", entry.error)?;
+        dict.set_item("writes", entry.writes)?;
+        let batch_params = match entry.batch_params {
+            Some(params) => value_to_py(py, Value::Object(params.into_iter().collect()))?,
+            None => py.None(),
+        };
+        dict.set_item("batch_params", batch_params)?;
+        list.append(dict)?;
+    }
+    Ok(list.to_object(py))
+}
+
+/// Seconds since the Unix epoch
+fn epoch_seconds(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_secs_f64()).unwrap_or_default()
+}
+
+/// Convert recorded state changes to a list of dicts, with `op` as "set" or "remove" and timestamps in seconds since the epoch
+fn history_to_py(py: Python, events: Vec<StateEvent>) -> PyResult<PyObject> {
+    let list = PyList::empty(py);
+    for event in events {
+        let dict = PyDict::new(py);
+        dict.set_item("key", event.key)?;
+        match event.op {
+            StateOp::Set(value) => {
+                dict.set_item("op", "set")?;
+                dict.set_item("value", value_to_py(py, value)?)?;
+            },
+            StateOp::Remove => dict.set_item("op", "remove")?,
+        }
+        dict.set_item("writer", event.writer)?;
+        dict.set_item("step", event.step)?;
+        dict.set_item("timestamp", epoch_seconds(event.timestamp))?;
+        list.append(dict)?;
+    }
+    Ok(list.to_object(py))
+}
+
+/// Convert the metadata of a key to a dict with `created_at`, `updated_at`, `writer` and `step`, or `None` without any
+fn metadata_to_py(py: Python, meta: Option<EntryMeta>) -> PyResult<PyObject> {
+    let Some(meta) = meta else {
+        return Ok(py.None());
+    };
+    let dict = PyDict::new(py);
+    dict.set_item("created_at", epoch_seconds(meta.created_at))?;
+    dict.set_item("updated_at", epoch_seconds(meta.updated_at))?;
+    dict.set_item("writer", meta.writer)?;
+    dict.set_item("step", meta.step)?;
+    Ok(dict.to_object(py))
+}
+
+/// Convert the error of a flow run, raising `ValueError` with the report when a strict flow failed validation
+fn flow_error_to_py(e: Error) -> PyErr {
+    match e {
+        Error::InvalidFlow(report) => PyValueError::new_err(format!("Invalid flow:\n{}", report)),
+        e => PyRuntimeError::new_err(format!("{}", e)),
+    }
+}
+
+/// Return a traced run to Python as `(action, trace)`, or raise its error with the trace as the `trace` attribute
+fn traced_to_py(py: Python, result: Result<Action, Error>, trace: FlowTrace) -> PyResult<PyObject> {
+    let trace = trace_to_py(py, trace)?;
+    match result {
+        Ok(action) => Ok((action.map(String::from), trace).to_object(py)),
+        Err(e) => {
+            let err = flow_error_to_py(e);
+            err.value(py).setattr("trace", trace)?;
+            Err(err)
+        },
+    }
 }
 
 /// Python wrapper for BaseNode
+/// Get the Rust node behind any of the Python node and flow classes
+fn extract_rust_node(obj: &PyAny) -> PyResult<Arc<dyn RustNodeTrait>> {
+    let node: Arc<dyn RustNodeTrait> = if let Ok(py_node) = obj.extract::<PyRef<PyBaseNode>>() {
+        py_node.node.clone()
+    } else if let Ok(py_node) = obj.extract::<PyRef<PyNode>>() {
+        py_node.node.clone()
+    } else if let Ok(py_node) = obj.extract::<PyRef<PyBatchNode>>() {
+        py_node.node.clone()
+    } else if let Ok(py_node) = obj.extract::<PyRef<PyFlow>>() {
+        py_node.flow.clone()
+    } else if let Ok(py_node) = obj.extract::<PyRef<PyBatchFlow>>() {
+        py_node.flow.clone()
+    } else if let Ok(py_node) = obj.extract::<PyRef<PyAsyncNode>>() {
+        py_node.node.clone()
+    } else if let Ok(py_node) = obj.extract::<PyRef<PyAsyncBatchNode>>() {
+        py_node.node.clone()
+    } else if let Ok(py_node) = obj.extract::<PyRef<PyAsyncParallelBatchNode>>() {
+        py_node.node.clone()
+    } else if let Ok(py_node) = obj.extract::<PyRef<PyAsyncFlow>>() {
+        py_node.flow.clone()
+    } else if let Ok(py_node) = obj.extract::<PyRef<PyAsyncBatchFlow>>() {
+        py_node.flow.clone()
+    } else if let Ok(py_node) = obj.extract::<PyRef<PyAsyncParallelBatchFlow>>() {
+        py_node.flow.clone()
+    } else {
+        return Err(PyTypeError::new_err("Invalid node type"));
+    };
+    Ok(node)
+}
+
 #[pyclass(name = "BaseNode")]
 struct PyBaseNode {
     node: Arc<RustBaseNode>,
@@ -131,9 +253,29 @@ struct PyBaseNode {
 #[pymethods]
 impl PyBaseNode {
     #[new]
-    fn new() -> Self {
-        Self {
-            node: Arc::new(RustBaseNode::new()),
+    #[pyo3(signature = (name=None))]
+    fn new(name: Option<&str>) -> Self {
+        let node = Arc::new(RustBaseNode::new());
+        if let Some(name) = name {
+            node.set_name(name);
+        }
+        Self { node }
+    }
+    
+    #[getter]
+    fn name(&self) -> String {
+        self.node.name()
+    }
+    
+    fn __repr__(&self) -> String {
+        format!("<BaseNode '{}'>", self.node.name())
+    }
+    
+    #[pyo3(signature = (key, default=None))]
+    fn get_param(&self, py: Python, key: &str, default: Option<PyObject>) -> PyResult<PyObject> {
+        match self.node.params().get(key) {
+            Some(value) => value_to_py(py, value.clone()),
+            None => Ok(default.unwrap_or_else(|| py.None())),
         }
     }
     
@@ -144,40 +286,15 @@ impl PyBaseNode {
             let value = py_to_value(py, value)?;
             rust_params.insert(key, value);
         }
-        self.node.set_params(rust_params);
+        self.node.set_params_map(rust_params);
         Ok(())
     }
     
     fn add_successor(&self, py: Python, node: PyObject, action: Option<&str>) -> PyResult<PyObject> {
-        let action = action.unwrap_or("default");
+        let action = action.unwrap_or(DEFAULT_ACTION);
         let successor: &PyAny = node.extract(py)?;
         
-        // Extract the Rust node from the Python object
-        let successor_node: Arc<dyn RustNodeTrait> = if let Ok(py_node) = successor.extract::<PyRef<PyBaseNode>>() {
-            py_node.node.clone()
-        } else if let Ok(py_node) = successor.extract::<PyRef<PyNode>>() {
-            py_node.node.clone()
-        } else if let Ok(py_node) = successor.extract::<PyRef<PyBatchNode>>() {
-            py_node.node.clone()
-        } else if let Ok(py_node) = successor.extract::<PyRef<PyFlow>>() {
-            py_node.flow.clone()
-        } else if let Ok(py_node) = successor.extract::<PyRef<PyBatchFlow>>() {
-            py_node.flow.clone()
-        } else if let Ok(py_node) = successor.extract::<PyRef<PyAsyncNode>>() {
-            py_node.node.clone()
-        } else if let Ok(py_node) = successor.extract::<PyRef<PyAsyncBatchNode>>() {
-            py_node.node.clone()
-        } else if let Ok(py_node) = successor.extract::<PyRef<PyAsyncParallelBatchNode>>() {
-            py_node.node.clone()
-        } else if let Ok(py_node) = successor.extract::<PyRef<PyAsyncFlow>>() {
-            py_node.flow.clone()
-        } else if let Ok(py_node) = successor.extract::<PyRef<PyAsyncBatchFlow>>() {
-            py_node.flow.clone()
-        } else if let Ok(py_node) = successor.extract::<PyRef<PyAsyncParallelBatchFlow>>() {
-            py_node.flow.clone()
-        } else {
-            return Err(PyTypeError::new_err("Invalid node type"));
-        };
+        let successor_node = extract_rust_node(successor)?;
         
         self.node.add_successor(successor_node, action).map_err(|e| {
             PyRuntimeError::new_err(format!("{}", e))
@@ -186,6 +303,21 @@ impl PyBaseNode {
         Ok(node)
     }
     
+    fn remove_successor(&self, action: Option<&str>) -> bool {
+        self.node.remove_successor(action.unwrap_or(DEFAULT_ACTION)).is_some()
+    }
+    
+    fn replace_successor(&self, py: Python, node: PyObject, action: Option<&str>) -> PyResult<PyObject> {
+        let action = action.unwrap_or(DEFAULT_ACTION);
+        let successor_node = extract_rust_node(node.extract(py)?)?;
+        
+        self.node.replace_successor(action, successor_node).map_err(|e| {
+            PyRuntimeError::new_err(format!("{}", e))
+        })?;
+        
+        Ok(node)
+    }
+    
     #[pyo3(text_signature = "($self, shared)")]
     fn prep(&self, py: Python, shared: &PyAny) -> PyResult<PyObject> {
         let mut shared_state = py_dict_to_shared_state(py, shared)?;
@@ -198,7 +330,7 @@ impl PyBaseNode {
     #[pyo3(text_signature = "($self, prep_res)")]
     fn exec(&self, py: Python, prep_res: &PyAny) -> PyResult<PyObject> {
         let prep_value = py_to_value(py, prep_res)?;
-        let result = self.node.exec(prep_value).map_err(|e| {
+        let result = self.node.exec(&prep_value).map_err(|e| {
             PyRuntimeError::new_err(format!("{}", e))
         })?;
         value_to_py(py, result)
@@ -214,7 +346,7 @@ impl PyBaseNode {
             PyRuntimeError::new_err(format!("{}", e))
         })?;
         
-        Ok(result)
+        Ok(result.map(String::from))
     }
     
     #[pyo3(text_signature = "($self, shared)")]
@@ -231,7 +363,7 @@ impl PyBaseNode {
             shared_dict.set_item(key, value_to_py(py, value)?)?;
         }
         
-        Ok(result)
+        Ok(result.map(String::from))
     }
     
     fn __rshift__(&self, py: Python, other: PyObject) -> PyResult<PyObject> {
@@ -261,33 +393,7 @@ struct PyConditionalTransition {
 impl PyConditionalTransition {
     fn __rshift__(&self, py: Python, other: PyObject) -> PyResult<PyObject> {
         let tgt: &PyAny = other.extract(py)?;
-        
-        // Extract the Rust node from the Python object based on its type
-        let tgt_node: Arc<dyn RustNodeTrait> = if let Ok(py_node) = tgt.extract::<PyRef<PyBaseNode>>() {
-            py_node.node.clone()
-        } else if let Ok(py_node) = tgt.extract::<PyRef<PyNode>>() {
-            py_node.node.clone()
-        } else if let Ok(py_node) = tgt.extract::<PyRef<PyBatchNode>>() {
-            py_node.node.clone()
-        } else if let Ok(py_node) = tgt.extract::<PyRef<PyFlow>>() {
-            py_node.flow.clone()
-        } else if let Ok(py_node) = tgt.extract::<PyRef<PyBatchFlow>>() {
-            py_node.flow.clone()
-        } else if let Ok(py_node) = tgt.extract::<PyRef<PyAsyncNode>>() {
-            py_node.node.clone()
-        } else if let Ok(py_node) = tgt.extract::<PyRef<PyAsyncBatchNode>>() {
-            py_node.node.clone()
-        } else if let Ok(py_node) = tgt.extract::<PyRef<PyAsyncParallelBatchNode>>() {
-            py_node.node.clone()
-        } else if let Ok(py_node) = tgt.extract::<PyRef<PyAsyncFlow>>() {
-            py_node.flow.clone()
-        } else if let Ok(py_node) = tgt.extract::<PyRef<PyAsyncBatchFlow>>() {
-            py_node.flow.clone()
-        } else if let Ok(py_node) = tgt.extract::<PyRef<PyAsyncParallelBatchFlow>>() {
-            py_node.flow.clone()
-        } else {
-            return Err(PyTypeError::new_err("Invalid node type"));
-        };
+        let tgt_node = extract_rust_node(tgt)?;
         
         self.src.add_successor(tgt_node, &self.action).map_err(|e| {
             PyRuntimeError::new_err(format!("{}", e))
@@ -306,10 +412,29 @@ pub struct PyNode {
 #[pymethods]
 impl PyNode {
     #[new]
-    #[pyo3(signature = (max_retries=1, wait=0))]
-    fn new(max_retries: usize, wait: u64) -> Self {
-        Self {
-            node: Arc::new(RustNode::new(max_retries, wait)),
+    #[pyo3(signature = (max_retries=1, wait=0, name=None))]
+    fn new(max_retries: usize, wait: u64, name: Option<&str>) -> Self {
+        let node = Arc::new(RustNode::new(max_retries, wait));
+        if let Some(name) = name {
+            node.set_name(name);
+        }
+        Self { node }
+    }
+    
+    #[getter]
+    fn name(&self) -> String {
+        self.node.name()
+    }
+    
+    fn __repr__(&self) -> String {
+        format!("<Node '{}'>", self.node.name())
+    }
+    
+    #[pyo3(signature = (key, default=None))]
+    fn get_param(&self, py: Python, key: &str, default: Option<PyObject>) -> PyResult<PyObject> {
+        match self.node.params().get(key) {
+            Some(value) => value_to_py(py, value.clone()),
+            None => Ok(default.unwrap_or_else(|| py.None())),
         }
     }
     
@@ -320,40 +445,15 @@ impl PyNode {
             let value = py_to_value(py, value)?;
             rust_params.insert(key, value);
         }
-        self.node.set_params(rust_params);
+        self.node.set_params_map(rust_params);
         Ok(())
     }
     
     fn add_successor(&self, py: Python, node: PyObject, action: Option<&str>) -> PyResult<PyObject> {
-        let action = action.unwrap_or("default");
+        let action = action.unwrap_or(DEFAULT_ACTION);
         let successor: &PyAny = node.extract(py)?;
         
-        // Extract the Rust node from the Python object
-        let successor_node: Arc<dyn RustNodeTrait> = if let Ok(py_node) = successor.extract::<PyRef<PyBaseNode>>() {
-            py_node.node.clone()
-        } else if let Ok(py_node) = successor.extract::<PyRef<PyNode>>() {
-            py_node.node.clone()
-        } else if let Ok(py_node) = successor.extract::<PyRef<PyBatchNode>>() {
-            py_node.node.clone()
-        } else if let Ok(py_node) = successor.extract::<PyRef<PyFlow>>() {
-            py_node.flow.clone()
-        } else if let Ok(py_node) = successor.extract::<PyRef<PyBatchFlow>>() {
-            py_node.flow.clone()
-        } else if let Ok(py_node) = successor.extract::<PyRef<PyAsyncNode>>() {
-            py_node.node.clone()
-        } else if let Ok(py_node) = successor.extract::<PyRef<PyAsyncBatchNode>>() {
-            py_node.node.clone()
-        } else if let Ok(py_node) = successor.extract::<PyRef<PyAsyncParallelBatchNode>>() {
-            py_node.node.clone()
-        } else if let Ok(py_node) = successor.extract::<PyRef<PyAsyncFlow>>() {
-            py_node.flow.clone()
-        } else if let Ok(py_node) = successor.extract::<PyRef<PyAsyncBatchFlow>>() {
-            py_node.flow.clone()
-        } else if let Ok(py_node) = successor.extract::<PyRef<PyAsyncParallelBatchFlow>>() {
-            py_node.flow.clone()
-        } else {
-            return Err(PyTypeError::new_err("Invalid node type"));
-        };
+        let successor_node = extract_rust_node(successor)?;
         
         self.node.add_successor(successor_node, action).map_err(|e| {
             PyRuntimeError::new_err(format!("{}", e))
@@ -362,6 +462,21 @@ impl PyNode {
         Ok(node)
     }
     
+    fn remove_successor(&self, action: Option<&str>) -> bool {
+        self.node.remove_successor(action.unwrap_or(DEFAULT_ACTION)).is_some()
+    }
+    
+    fn replace_successor(&self, py: Python, node: PyObject, action: Option<&str>) -> PyResult<PyObject> {
+        let action = action.unwrap_or(DEFAULT_ACTION);
+        let successor_node = extract_rust_node(node.extract(py)?)?;
+        
+        self.node.replace_successor(action, successor_node).map_err(|e| {
+            PyRuntimeError::new_err(format!("{}", e))
+        })?;
+        
+        Ok(node)
+    }
+    
     #[pyo3(text_signature = "($self, shared)")]
     fn prep(&self, py: Python, shared: &PyAny) -> PyResult<PyObject> {
         let mut shared_state = py_dict_to_shared_state(py, shared)?;
@@ -374,7 +489,7 @@ impl PyNode {
     #[pyo3(text_signature = "($self, prep_res)")]
     fn exec(&self, py: Python, prep_res: &PyAny) -> PyResult<PyObject> {
         let prep_value = py_to_value(py, prep_res)?;
-        let result = self.node.exec(prep_value).map_err(|e| {
+        let result = self.node.exec(&prep_value).map_err(|e| {
             PyRuntimeError::new_err(format!("{}", e))
         })?;
         value_to_py(py, result)
@@ -385,7 +500,7 @@ impl PyNode {
         let prep_value = py_to_value(py, prep_res)?;
         let error = Error::NodeExecution(format!("Python exception: {}", exc));
         
-        let result = self.node.exec_fallback(prep_value, error).map_err(|e| {
+        let result = self.node.exec_fallback(&prep_value, error).map_err(|e| {
             PyRuntimeError::new_err(format!("{}", e))
         })?;
         
@@ -402,7 +517,7 @@ impl PyNode {
             PyRuntimeError::new_err(format!("{}", e))
         })?;
         
-        Ok(result)
+        Ok(result.map(String::from))
     }
     
     #[pyo3(text_signature = "($self, shared)")]
@@ -419,7 +534,7 @@ impl PyNode {
             shared_dict.set_item(key, value_to_py(py, value)?)?;
         }
         
-        Ok(result)
+        Ok(result.map(String::from))
     }
     
     fn __rshift__(&self, py: Python, other: PyObject) -> PyResult<PyObject> {
@@ -447,11 +562,26 @@ struct PyBatchNode {
 #[pymethods]
 impl PyBatchNode {
     #[new]
-    #[pyo3(signature = (max_retries=1, wait=0))]
-    fn new(max_retries: usize, wait: u64) -> Self {
-        Self {
-            node: Arc::new(RustBatchNode::new(max_retries, wait)),
+    #[pyo3(signature = (max_retries=1, wait=0, chunk_size=None, name=None))]
+    fn new(max_retries: usize, wait: u64, chunk_size: Option<usize>, name: Option<&str>) -> Self {
+        let mut node = RustBatchNode::new(max_retries, wait);
+        if let Some(size) = chunk_size {
+            node = node.with_chunk_size(size);
+        }
+        let node = Arc::new(node);
+        if let Some(name) = name {
+            node.set_name(name);
         }
+        Self { node }
+    }
+    
+    #[getter]
+    fn name(&self) -> String {
+        self.node.name()
+    }
+    
+    fn __repr__(&self) -> String {
+        format!("<BatchNode '{}'>", self.node.name())
     }
     
     // Define the same methods as PyNode, but for BatchNode
@@ -469,7 +599,8 @@ pub struct PyFlow {
 #[pymethods]
 impl PyFlow {
     #[new]
-    fn new(py: Python, start: PyObject) -> PyResult<Self> {
+    #[pyo3(signature = (start, name=None, max_steps=Some(DEFAULT_MAX_STEPS), strict=false, history=None))]
+    fn new(py: Python, start: PyObject, name: Option<&str>, max_steps: Option<usize>, strict: bool, history: Option<usize>) -> PyResult<Self> {
         let start_node: &PyAny = start.extract(py)?;
         
         // Extract the Rust node from the Python object
@@ -489,9 +620,93 @@ impl PyFlow {
             return Err(PyTypeError::new_err("Invalid start node type"));
         };
         
-        Ok(Self {
-            flow: Arc::new(RustFlow::new(start_node)),
-        })
+        let mut flow = RustFlow::new(start_node).with_max_steps(max_steps).with_strict(strict);
+        if let Some(capacity) = history {
+            flow = flow.with_history(capacity);
+        }
+        let flow = Arc::new(flow);
+        if let Some(name) = name {
+            flow.set_name(name);
+        }
+        Ok(Self { flow })
+    }
+    
+    #[getter]
+    fn name(&self) -> String {
+        self.flow.name()
+    }
+    
+    fn __repr__(&self) -> String {
+        format!("<Flow '{}'>", self.flow.name())
+    }
+    
+    /// Groups of reachable nodes that can loop back to each other, as lists of node names
+    fn detect_cycles(&self) -> Vec<Vec<String>> {
+        self.flow.detect_cycles()
+    }
+    
+    /// The flow's graph in Graphviz DOT
+    fn to_dot(&self) -> String {
+        self.flow.to_dot()
+    }
+    
+    /// Recorded state changes, oldest first, as dicts with `key`, `op`, `value` for sets, `writer`, `step` and `timestamp`
+    ///
+    /// Only changes to `key` when given. Empty unless the flow was created with `history`, the number of changes kept.
+    #[pyo3(signature = (key=None))]
+    fn history(&self, py: Python, key: Option<&str>) -> PyResult<PyObject> {
+        let events = match key {
+            Some(key) => self.flow.history_for(key),
+            None => self.flow.history(),
+        };
+        history_to_py(py, events)
+    }
+    
+    /// When `key` was written and by which step, as a dict with `created_at` and `updated_at` in seconds since the epoch,
+    /// `writer` and `step`; `None` for keys no recorded step set, or unless the flow was created with `history`
+    fn metadata(&self, py: Python, key: &str) -> PyResult<PyObject> {
+        metadata_to_py(py, self.flow.metadata(key))
+    }
+    
+    /// Check the flow's graph, returning a dict with lists of `errors` and `warnings`, each a dict with `node` and `message`
+    fn validate(&self, py: Python) -> PyResult<PyObject> {
+        let report = self.flow.validate().map_err(|e| PyRuntimeError::new_err(format!("{}", e)))?;
+        let report = serde_json::to_value(report).map_err(|e| PyRuntimeError::new_err(format!("{}", e)))?;
+        value_to_py(py, report)
+    }
+    
+    /// Run the flow, raising `ValueError` with the validation report when a strict flow is invalid
+    #[pyo3(text_signature = "($self, shared)")]
+    fn run(&self, py: Python, shared: &PyAny) -> PyResult<Option<String>> {
+        let mut shared_state = py_dict_to_shared_state(py, shared)?;
+        
+        let result = self.flow.run(&mut shared_state).map_err(flow_error_to_py)?;
+        
+        // Update the Python shared dictionary with the values from SharedState
+        let shared_dict = shared.downcast::<PyDict>()?;
+        for (key, value) in shared_state {
+            shared_dict.set_item(key, value_to_py(py, value)?)?;
+        }
+        
+        Ok(result.map(String::from))
+    }
+    
+    /// Run the flow, returning its action and a list of dicts, one per node step and batch iteration
+    ///
+    /// A failed run raises with the trace as the exception's `trace` attribute.
+    #[pyo3(text_signature = "($self, shared)")]
+    fn run_traced(&self, py: Python, shared: &PyAny) -> PyResult<PyObject> {
+        let mut shared_state = py_dict_to_shared_state(py, shared)?;
+        
+        let (result, trace) = self.flow.run_traced(&mut shared_state);
+        
+        // Update the Python shared dictionary, also after a failure so it can be inspected
+        let shared_dict = shared.downcast::<PyDict>()?;
+        for (key, value) in shared_state {
+            shared_dict.set_item(key, value_to_py(py, value)?)?;
+        }
+        
+        traced_to_py(py, result, trace)
     }
     
     // Define similar methods as PyNode, but adapted for Flow
@@ -507,7 +722,8 @@ struct PyBatchFlow {
 #[pymethods]
 impl PyBatchFlow {
     #[new]
-    fn new(py: Python, start: PyObject) -> PyResult<Self> {
+    #[pyo3(signature = (start, name=None))]
+    fn new(py: Python, start: PyObject, name: Option<&str>) -> PyResult<Self> {
         let start_node: &PyAny = start.extract(py)?;
         
         // Extract the Rust node from the Python object
@@ -527,9 +743,20 @@ impl PyBatchFlow {
             return Err(PyTypeError::new_err("Invalid start node type"));
         };
         
-        Ok(Self {
-            flow: Arc::new(RustBatchFlow::new(start_node)),
-        })
+        let flow = Arc::new(RustBatchFlow::new(start_node));
+        if let Some(name) = name {
+            flow.set_name(name);
+        }
+        Ok(Self { flow })
+    }
+    
+    #[getter]
+    fn name(&self) -> String {
+        self.flow.name()
+    }
+    
+    fn __repr__(&self) -> String {
+        format!("<BatchFlow '{}'>", self.flow.name())
     }
     
     // Define similar methods as PyNode, but adapted for BatchFlow
@@ -545,22 +772,66 @@ pub struct PyAsyncNode {
 #[pymethods]
 impl PyAsyncNode {
     #[new]
-    #[pyo3(signature = (max_retries=1, wait=0))]
-    fn new(max_retries: usize, wait: u64) -> Self {
-        Self {
-            node: Arc::new(RustAsyncNode::new(max_retries, wait)),
+    #[pyo3(signature = (max_retries=1, wait=0, name=None))]
+    fn new(max_retries: usize, wait: u64, name: Option<&str>) -> Self {
+        let node = Arc::new(RustAsyncNode::new(max_retries, wait));
+        if let Some(name) = name {
+            node.set_name(name);
         }
+        Self { node }
+    }
+    
+    #[getter]
+    fn name(&self) -> String {
+        self.node.name()
+    }
+    
+    fn __repr__(&self) -> String {
+        format!("<AsyncNode '{}'>", self.node.name())
     }
     
     // Define similar methods as PyNode, but for async operations
     // Implementation details are omitted for brevity
     
+    #[pyo3(signature = (prep_res, exc, attempt=0, max_retries=1, item_index=None, node_name=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn exec_fallback_async<'p>(
+        &self,
+        py: Python<'p>,
+        prep_res: &PyAny,
+        exc: &PyAny,
+        attempt: usize,
+        max_retries: usize,
+        item_index: Option<usize>,
+        node_name: Option<String>,
+    ) -> PyResult<&'p PyAny> {
+        let prep_value = py_to_value(py, prep_res)?;
+        let error = Error::NodeExecution(format!("Python exception: {}", exc));
+        let ctx = FallbackContext {
+            attempt,
+            max_retries,
+            item_index,
+            node_name: node_name.unwrap_or_else(|| self.node.name()),
+        };
+        let node = self.node.clone();
+        
+        mark_runtime_started();
+        pyo3_asyncio::tokio::future_into_py(py, async move {
+            let result = node.exec_fallback_async_ctx(&prep_value, error, ctx).await.map_err(|e| {
+                PyRuntimeError::new_err(format!("{}", e))
+            })?;
+            
+            Python::with_gil(|py| value_to_py(py, result))
+        })
+    }
+    
     #[pyo3(text_signature = "($self, shared)")]
     fn run_async<'p>(&self, py: Python<'p>, shared: &'p PyAny) -> PyResult<&'p PyAny> {
         // Clone the shared state before the async block
         let mut shared_state = py_dict_to_shared_state(py, shared)?;
         let node = self.node.clone();
         
+        mark_runtime_started();
         let future = pyo3_asyncio::tokio::future_into_py(py, async move {
             let result = node.run_async(&mut shared_state).await.map_err(|e| {
                 PyRuntimeError::new_err(format!("{}", e))
@@ -589,11 +860,29 @@ pub struct PyAsyncBatchNode {
 #[pymethods]
 impl PyAsyncBatchNode {
     #[new]
-    #[pyo3(signature = (max_retries=1, wait=0))]
-    fn new(max_retries: usize, wait: u64) -> Self {
-        Self {
-            node: Arc::new(RustAsyncBatchNode::new(max_retries, wait)),
+    #[pyo3(signature = (max_retries=1, wait=0, chunk_size=None, name=None, progress=None))]
+    fn new(max_retries: usize, wait: u64, chunk_size: Option<usize>, name: Option<&str>, progress: Option<PyObject>) -> Self {
+        let mut node = RustAsyncBatchNode::new(max_retries, wait);
+        if let Some(size) = chunk_size {
+            node = node.with_chunk_size(size);
+        }
+        let node = Arc::new(node);
+        if let Some(name) = name {
+            node.set_name(name);
         }
+        if let Some(progress) = progress {
+            node.set_progress_callback(py_progress_callback(progress));
+        }
+        Self { node }
+    }
+    
+    #[getter]
+    fn name(&self) -> String {
+        self.node.name()
+    }
+    
+    fn __repr__(&self) -> String {
+        format!("<AsyncBatchNode '{}'>", self.node.name())
     }
     
     // Define similar methods as PyAsyncNode
@@ -609,11 +898,29 @@ pub struct PyAsyncParallelBatchNode {
 #[pymethods]
 impl PyAsyncParallelBatchNode {
     #[new]
-    #[pyo3(signature = (max_retries=1, wait=0))]
-    fn new(max_retries: usize, wait: u64) -> Self {
-        Self {
-            node: Arc::new(RustAsyncParallelBatchNode::new(max_retries, wait)),
+    #[pyo3(signature = (max_retries=1, wait=0, chunk_size=None, name=None, progress=None))]
+    fn new(max_retries: usize, wait: u64, chunk_size: Option<usize>, name: Option<&str>, progress: Option<PyObject>) -> Self {
+        let mut node = RustAsyncParallelBatchNode::new(max_retries, wait);
+        if let Some(size) = chunk_size {
+            node = node.with_chunk_size(size);
+        }
+        let node = Arc::new(node);
+        if let Some(name) = name {
+            node.set_name(name);
+        }
+        if let Some(progress) = progress {
+            node.set_progress_callback(py_progress_callback(progress));
         }
+        Self { node }
+    }
+    
+    #[getter]
+    fn name(&self) -> String {
+        self.node.name()
+    }
+    
+    fn __repr__(&self) -> String {
+        format!("<AsyncParallelBatchNode '{}'>", self.node.name())
     }
     
     // Define similar methods as PyAsyncNode
@@ -629,7 +936,8 @@ pub struct PyAsyncFlow {
 #[pymethods]
 impl PyAsyncFlow {
     #[new]
-    fn new(py: Python, start: PyObject) -> PyResult<Self> {
+    #[pyo3(signature = (start, name=None, max_steps=Some(DEFAULT_MAX_STEPS), strict=false, history=None))]
+    fn new(py: Python, start: PyObject, name: Option<&str>, max_steps: Option<usize>, strict: bool, history: Option<usize>) -> PyResult<Self> {
         let start_node: &PyAny = start.extract(py)?;
         
         // Extract the Rust node from the Python object
@@ -649,24 +957,93 @@ impl PyAsyncFlow {
             return Err(PyTypeError::new_err("Invalid start node type"));
         };
         
-        Ok(Self {
-            flow: Arc::new(RustAsyncFlow::new(start_node)),
+        let mut flow = RustAsyncFlow::new(start_node).with_max_steps(max_steps).with_strict(strict);
+        if let Some(capacity) = history {
+            flow = flow.with_history(capacity);
+        }
+        let flow = Arc::new(flow);
+        if let Some(name) = name {
+            flow.set_name(name);
+        }
+        Ok(Self { flow })
+    }
+    
+    #[getter]
+    fn name(&self) -> String {
+        self.flow.name()
+    }
+    
+    fn __repr__(&self) -> String {
+        format!("<AsyncFlow '{}'>", self.flow.name())
+    }
+    
+    /// Groups of reachable nodes that can loop back to each other, as lists of node names
+    fn detect_cycles(&self) -> Vec<Vec<String>> {
+        self.flow.detect_cycles()
+    }
+    
+    /// The flow's graph in Graphviz DOT
+    fn to_dot(&self) -> String {
+        self.flow.to_dot()
+    }
+    
+    /// Recorded state changes, oldest first, as dicts with `key`, `op`, `value` for sets, `writer`, `step` and `timestamp`
+    ///
+    /// Only changes to `key` when given. Empty unless the flow was created with `history`, the number of changes kept.
+    #[pyo3(signature = (key=None))]
+    fn history(&self, py: Python, key: Option<&str>) -> PyResult<PyObject> {
+        let events = match key {
+            Some(key) => self.flow.history_for(key),
+            None => self.flow.history(),
+        };
+        history_to_py(py, events)
+    }
+    
+    /// When `key` was written and by which step, as a dict with `created_at` and `updated_at` in seconds since the epoch,
+    /// `writer` and `step`; `None` for keys no recorded step set, or unless the flow was created with `history`
+    fn metadata(&self, py: Python, key: &str) -> PyResult<PyObject> {
+        metadata_to_py(py, self.flow.metadata(key))
+    }
+    
+    /// Check the flow's graph, returning a dict with lists of `errors` and `warnings`, each a dict with `node` and `message`
+    fn validate(&self, py: Python) -> PyResult<PyObject> {
+        let report = self.flow.validate().map_err(|e| PyRuntimeError::new_err(format!("{}", e)))?;
+        let report = serde_json::to_value(report).map_err(|e| PyRuntimeError::new_err(format!("{}", e)))?;
+        value_to_py(py, report)
+    }
+    
+    /// Run the flow, resolving to its action and a list of dicts, one per node step and batch iteration
+    ///
+    /// A failed run raises with the trace as the exception's `trace` attribute.
+    #[pyo3(text_signature = "($self, shared)")]
+    fn run_async_traced<'p>(&self, py: Python<'p>, shared: &'p PyAny) -> PyResult<&'p PyAny> {
+        let mut shared_state = py_dict_to_shared_state(py, shared)?;
+        let flow = self.flow.clone();
+        
+        mark_runtime_started();
+        pyo3_asyncio::tokio::future_into_py(py, async move {
+            let (result, trace) = flow.run_async_traced(&mut shared_state).await;
+            Python::with_gil(|py| traced_to_py(py, result, trace))
         })
     }
     
     // Define similar methods as PyFlow, but for async operations
     // Implementation details are omitted for brevity
     
-    #[pyo3(text_signature = "($self, shared)")]
-    fn run_async<'p>(&self, py: Python<'p>, shared: &'p PyAny) -> PyResult<&'p PyAny> {
+    #[pyo3(signature = (shared, token=None))]
+    fn run_async<'p>(&self, py: Python<'p>, shared: &'p PyAny, token: Option<PyRef<PyCancellationToken>>) -> PyResult<&'p PyAny> {
         // Clone the shared state before the async block
         let mut shared_state = py_dict_to_shared_state(py, shared)?;
         let flow = self.flow.clone();
+        let token = token.map(|token| token.token.clone());
         
+        mark_runtime_started();
         let future = pyo3_asyncio::tokio::future_into_py(py, async move {
-            let result = flow.run_async(&mut shared_state).await.map_err(|e| {
-                PyRuntimeError::new_err(format!("{}", e))
-            })?;
+            let result = match token {
+                Some(token) => flow.run_cancellable(&mut shared_state, token).await,
+                None => flow.run_async(&mut shared_state).await,
+            };
+            let result = result.map_err(flow_error_to_py)?;
             
             // Convert the result to a JSON string to avoid lifetime issues
             let result_str = match &result {
@@ -682,6 +1059,29 @@ impl PyAsyncFlow {
     }
 }
 
+/// Python handle for cancelling an `AsyncFlow.run_async` call
+#[pyclass(name = "CancellationToken")]
+pub struct PyCancellationToken {
+    token: CancellationToken,
+}
+
+#[pymethods]
+impl PyCancellationToken {
+    #[new]
+    fn new() -> Self {
+        Self { token: CancellationToken::new() }
+    }
+    
+    fn cancel(&self) {
+        self.token.cancel();
+    }
+    
+    #[getter]
+    fn cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+}
+
 /// Python wrapper for AsyncBatchFlow
 #[pyclass(name = "AsyncBatchFlow")]
 pub struct PyAsyncBatchFlow {
@@ -691,7 +1091,8 @@ pub struct PyAsyncBatchFlow {
 #[pymethods]
 impl PyAsyncBatchFlow {
     #[new]
-    fn new(py: Python, start: PyObject) -> PyResult<Self> {
+    #[pyo3(signature = (start, name=None))]
+    fn new(py: Python, start: PyObject, name: Option<&str>) -> PyResult<Self> {
         let start_node: &PyAny = start.extract(py)?;
         
         // Extract the Rust node from the Python object
@@ -711,9 +1112,20 @@ impl PyAsyncBatchFlow {
             return Err(PyTypeError::new_err("Invalid start node type"));
         };
         
-        Ok(Self {
-            flow: Arc::new(RustAsyncBatchFlow::new(start_node)),
-        })
+        let flow = Arc::new(RustAsyncBatchFlow::new(start_node));
+        if let Some(name) = name {
+            flow.set_name(name);
+        }
+        Ok(Self { flow })
+    }
+    
+    #[getter]
+    fn name(&self) -> String {
+        self.flow.name()
+    }
+    
+    fn __repr__(&self) -> String {
+        format!("<AsyncBatchFlow '{}'>", self.flow.name())
     }
     
     // Define similar methods as PyAsyncFlow but adapted for AsyncBatchFlow
@@ -729,7 +1141,8 @@ pub struct PyAsyncParallelBatchFlow {
 #[pymethods]
 impl PyAsyncParallelBatchFlow {
     #[new]
-    fn new(py: Python, start: PyObject) -> PyResult<Self> {
+    #[pyo3(signature = (start, name=None))]
+    fn new(py: Python, start: PyObject, name: Option<&str>) -> PyResult<Self> {
         let start_node: &PyAny = start.extract(py)?;
         
         // Extract the Rust node from the Python object
@@ -749,9 +1162,20 @@ impl PyAsyncParallelBatchFlow {
             return Err(PyTypeError::new_err("Invalid start node type"));
         };
         
-        Ok(Self {
-            flow: Arc::new(RustAsyncParallelBatchFlow::new(start_node)),
-        })
+        let flow = Arc::new(RustAsyncParallelBatchFlow::new(start_node));
+        if let Some(name) = name {
+            flow.set_name(name);
+        }
+        Ok(Self { flow })
+    }
+    
+    #[getter]
+    fn name(&self) -> String {
+        self.flow.name()
+    }
+    
+    fn __repr__(&self) -> String {
+        format!("<AsyncParallelBatchFlow '{}'>", self.flow.name())
     }
     
     // Define similar methods as PyAsyncFlow but adapted for AsyncParallelBatchFlow
@@ -773,6 +1197,14 @@ fn _minllm(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<PyAsyncFlow>()?;
     m.add_class::<PyAsyncBatchFlow>()?;
     m.add_class::<PyAsyncParallelBatchFlow>()?;
+    m.add_class::<PyCancellationToken>()?;
+    m.add_function(wrap_pyfunction!(configure_runtime, m)?)?;
+    m.add_function(wrap_pyfunction!(roundtrip_json, m)?)?;
+    
+    // Leave any logger the embedding application installed in place
+    if log::set_logger(&PYTHON_LOGGER).is_ok() {
+        log::set_max_level(log::LevelFilter::Debug);
+    }
     
     Ok(())
 } 
\ No newline at end of file
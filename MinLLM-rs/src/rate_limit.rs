@@ -0,0 +1,112 @@
+use std::time::Duration;
+use parking_lot::Mutex;
+use tokio::time::{sleep, Instant};
+
+use crate::sleeper::{RealSleeper, Sleeper};
+
+/// A token bucket that can be shared between nodes to enforce a rate limit
+pub struct RateLimiter {
+    /// Number of permits granted per window
+    permits: usize,
+    
+    /// Length of the window
+    window: Duration,
+    
+    /// Current bucket state
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    /// Tokens currently available
+    tokens: f64,
+    
+    /// Last time the bucket was refilled
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    /// Create a limiter granting `permits` acquisitions per `window`
+    pub fn new(permits: usize, window: Duration) -> Self {
+        let permits = permits.max(1);
+        Self {
+            permits,
+            window,
+            bucket: Mutex::new(Bucket {
+                tokens: permits as f64,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+    
+    /// Number of permits granted per window
+    pub fn permits(&self) -> usize {
+        self.permits
+    }
+    
+    /// Length of the window
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+    
+    /// Take a permit if one is available, otherwise return how long to wait for the next one
+    pub fn try_acquire(&self) -> std::result::Result<(), Duration> {
+        let mut bucket = self.refill();
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(self.wait_for(bucket.tokens))
+        }
+    }
+    
+    /// Take the next permit, available or not, returning how long to wait before it may be used
+    ///
+    /// Later callers queue behind the reservation, so the wait must be honored.
+    pub fn reserve(&self) -> Duration {
+        let mut bucket = self.refill();
+        let wait = if bucket.tokens >= 1.0 { Duration::ZERO } else { self.wait_for(bucket.tokens) };
+        bucket.tokens -= 1.0;
+        wait
+    }
+    
+    /// Block the current thread until a permit is available
+    pub fn acquire_blocking(&self) {
+        self.acquire_with(&RealSleeper);
+    }
+    
+    /// Reserve a permit and wait for it through `sleeper`
+    pub fn acquire_with(&self, sleeper: &dyn Sleeper) {
+        let wait = self.reserve();
+        if !wait.is_zero() {
+            sleeper.sleep(wait);
+        }
+    }
+    
+    /// Wait asynchronously until a permit is available
+    pub async fn acquire(&self) {
+        while let Err(wait) = self.try_acquire() {
+            sleep(wait).await;
+        }
+    }
+    
+    /// The bucket, refilled for the time since it was last refilled
+    fn refill(&self) -> parking_lot::MutexGuard<'_, Bucket> {
+        let mut bucket = self.bucket.lock();
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.last_refill);
+        bucket.last_refill = now;
+        
+        if !self.window.is_zero() {
+            let refill = elapsed.as_secs_f64() / self.window.as_secs_f64() * self.permits as f64;
+            bucket.tokens = (bucket.tokens + refill).min(self.permits as f64);
+        } else {
+            bucket.tokens = self.permits as f64;
+        }
+        bucket
+    }
+    
+    /// Time until a bucket holding `tokens` holds a whole token
+    fn wait_for(&self, tokens: f64) -> Duration {
+        self.window.mul_f64((1.0 - tokens) / self.permits as f64)
+    }
+}
\ No newline at end of file
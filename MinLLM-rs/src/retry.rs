@@ -0,0 +1,478 @@
+use std::any::Any;
+use std::cell::OnceCell;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+use futures::FutureExt;
+use serde_json::Value;
+
+use crate::base::Node;
+use crate::backoff::Backoff;
+use crate::sleeper::Sleeper;
+use crate::rate_limit::RateLimiter;
+use crate::heartbeat::{self, HeartbeatConfig};
+use crate::cancel;
+use crate::deadline;
+use crate::error::{Error, Result};
+
+tokio::task_local! {
+    /// Attempt of the exec or fallback currently running, counting from 0
+    static ATTEMPT: usize;
+    
+    /// Position of the batch item the current exec belongs to
+    static ITEM: usize;
+}
+
+/// The attempt the calling exec or fallback belongs to, counting from 0
+///
+/// Every run of a node, and every item of a batch, counts its own attempts, even when the items
+/// of a parallel batch run concurrently. A fallback sees the attempt that failed last.
+pub fn current_attempt() -> Option<usize> {
+    ATTEMPT.try_with(|attempt| *attempt).ok()
+}
+
+/// Run `f` as part of attempt `attempt`
+pub(crate) fn with_attempt<R>(attempt: usize, f: impl FnOnce() -> R) -> R {
+    ATTEMPT.sync_scope(attempt, f)
+}
+
+/// Await `fut` as part of attempt `attempt`
+pub(crate) async fn with_attempt_async<F: Future>(attempt: usize, fut: F) -> F::Output {
+    ATTEMPT.scope(attempt, fut).await
+}
+
+/// Await `fut` as the exec of batch item `index`
+pub(crate) async fn with_item_async<F: Future>(index: usize, fut: F) -> F::Output {
+    ITEM.scope(index, fut).await
+}
+
+/// What an async fallback is told about the failure it handles
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FallbackContext {
+    /// The attempt that failed last, counting from 0
+    pub attempt: usize,
+    
+    /// Most attempts the retry policy allows, or the attempts made when it does not say
+    pub max_retries: usize,
+    
+    /// Position of the batch item that failed, when the node runs as part of a batch
+    pub item_index: Option<usize>,
+    
+    /// Name of the failing node
+    pub node_name: String,
+}
+
+impl FallbackContext {
+    /// Context for a fallback after attempt `attempt` of at most `max_retries` failed
+    pub(crate) fn new(attempt: usize, max_retries: usize, node_name: String) -> Self {
+        Self {
+            attempt,
+            max_retries,
+            item_index: ITEM.try_with(|index| *index).ok(),
+            node_name,
+        }
+    }
+}
+
+/// Decides whether a failed exec attempt is retried and how long to wait first
+pub trait RetryPolicy: Send + Sync {
+    /// Wait before the next attempt after attempt `attempt` (counting from 0) failed with `error`,
+    /// or `None` to stop retrying and hand the error to the fallback
+    fn should_retry(&self, attempt: usize, error: &Error) -> Option<Duration>;
+    
+    /// Total attempts the policy allows, counting the first, when it has a fixed limit
+    fn max_attempts(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// Retry every error with the same wait, up to a total number of attempts
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FixedRetry {
+    /// Total attempts, counting the first
+    max_attempts: usize,
+    
+    /// Wait before each retry
+    wait: Duration,
+}
+
+impl FixedRetry {
+    /// Allow `max_attempts` attempts with `wait` between them
+    pub fn new(max_attempts: usize, wait: Duration) -> Self {
+        Self { max_attempts, wait }
+    }
+}
+
+impl RetryPolicy for FixedRetry {
+    fn should_retry(&self, attempt: usize, _error: &Error) -> Option<Duration> {
+        (attempt + 1 < self.max_attempts).then_some(self.wait)
+    }
+    
+    fn max_attempts(&self) -> Option<usize> {
+        Some(self.max_attempts)
+    }
+}
+
+/// Retry every error with waits taken from a `Backoff`, up to a total number of attempts
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BackoffRetry {
+    /// Total attempts, counting the first
+    max_attempts: usize,
+    
+    /// Wait before each retry
+    backoff: Backoff,
+}
+
+impl BackoffRetry {
+    /// Allow `max_attempts` attempts, waiting according to `backoff`
+    pub fn new(max_attempts: usize, backoff: Backoff) -> Self {
+        Self { max_attempts, backoff }
+    }
+    
+    /// Exponential waits starting at `base`, growing by `factor` and capped at `max`, without jitter
+    pub fn exponential(max_attempts: usize, base: Duration, factor: f64, max: Duration) -> Self {
+        Self::new(max_attempts, Backoff::Exponential { base, factor, max, jitter: false })
+    }
+}
+
+impl RetryPolicy for BackoffRetry {
+    fn should_retry(&self, attempt: usize, _error: &Error) -> Option<Duration> {
+        (attempt + 1 < self.max_attempts).then(|| self.backoff.delay(attempt))
+    }
+    
+    fn max_attempts(&self) -> Option<usize> {
+        Some(self.max_attempts)
+    }
+}
+
+/// Never retry; the first failure goes straight to the fallback
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NoRetry;
+
+impl RetryPolicy for NoRetry {
+    fn should_retry(&self, _attempt: usize, _error: &Error) -> Option<Duration> {
+        None
+    }
+    
+    fn max_attempts(&self) -> Option<usize> {
+        Some(1)
+    }
+}
+
+/// Run one exec attempt, turning a panic into a failed attempt that can be retried
+pub(crate) fn guard_attempt(attempt: impl FnOnce() -> Result<Value>) -> Result<Value> {
+    panic::catch_unwind(AssertUnwindSafe(attempt)).unwrap_or_else(|payload| Err(panic_error(payload)))
+}
+
+/// Await one exec attempt, turning a panic into a failed attempt that can be retried
+pub(crate) async fn guard_attempt_async(attempt: impl Future<Output = Result<Value>>) -> Result<Value> {
+    AssertUnwindSafe(attempt).catch_unwind().await.unwrap_or_else(|payload| Err(panic_error(payload)))
+}
+
+/// The error an exec attempt panicking with `payload` fails with
+pub(crate) fn panic_error(payload: Box<dyn Any + Send>) -> Error {
+    let message = payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string());
+    Error::NodeExecution(format!("Exec attempt panicked: {}", message))
+}
+
+/// How the retry drivers run the exec attempts of a node
+pub(crate) struct Attempts<'a> {
+    /// Decides which failures are retried and the wait before each retry
+    policy: &'a dyn RetryPolicy,
+    
+    /// Source of the waits between retries
+    sleeper: &'a dyn Sleeper,
+    
+    /// Limiter every attempt acquires a permit from, when set
+    limiter: Option<&'a RateLimiter>,
+    
+    /// Longest a single attempt may take
+    timeout: Option<Duration>,
+    
+    /// Heartbeat fired while an async attempt runs, in place of the run's default
+    heartbeat: Option<HeartbeatConfig>,
+}
+
+impl<'a> Attempts<'a> {
+    /// Attempts retried according to `policy`, waiting on `sleeper`
+    pub(crate) fn new(policy: &'a dyn RetryPolicy, sleeper: &'a dyn Sleeper) -> Self {
+        Self {
+            policy,
+            sleeper,
+            limiter: None,
+            timeout: None,
+            heartbeat: None,
+        }
+    }
+    
+    /// Acquire a permit from `limiter`, when set, before every attempt
+    pub(crate) fn limiter(mut self, limiter: Option<&'a RateLimiter>) -> Self {
+        self.limiter = limiter;
+        self
+    }
+    
+    /// Fail every attempt running longer than `timeout`, when set, with `Error::Timeout`
+    pub(crate) fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+    
+    /// Fire `heartbeat`, when set, while an async attempt runs
+    pub(crate) fn heartbeat(mut self, heartbeat: Option<HeartbeatConfig>) -> Self {
+        self.heartbeat = heartbeat;
+        self
+    }
+    
+    /// Wait before the attempt after `attempt`, or the context to hand `error` to the fallback with
+    fn after_failure(&self, attempt: usize, error: &Error, node: impl FnOnce() -> String) -> std::result::Result<Duration, FallbackContext> {
+        match self.policy.should_retry(attempt, error) {
+            Some(delay) => Ok(delay),
+            None => Err(FallbackContext::new(attempt, self.policy.max_attempts().unwrap_or(attempt + 1), node())),
+        }
+    }
+    
+    /// Run `exec` on `node` once as attempt `attempt`, on its own thread when bounded by a timeout
+    ///
+    /// Threaded attempts share the copy of `node` and `prep_res` made into `moved` by the first of them.
+    fn exec<N: Node + Clone + 'static>(
+        &self,
+        node: &N,
+        exec: fn(&N, &Value) -> Result<Value>,
+        prep_res: &Value,
+        moved: &OnceCell<Arc<(N, Value)>>,
+        attempt: usize,
+    ) -> Result<Value> {
+        if let Some(limiter) = self.limiter {
+            limiter.acquire_with(self.sleeper);
+        }
+        let Some(timeout) = self.timeout else {
+            return with_attempt(attempt, || guard_attempt(|| exec(node, prep_res)));
+        };
+        
+        let (tx, rx) = mpsc::channel();
+        let moved = moved.get_or_init(|| Arc::new((node.clone(), prep_res.clone()))).clone();
+        thread::spawn(move || {
+            let (node, prep_res) = &*moved;
+            let _ = tx.send(with_attempt(attempt, || guard_attempt(|| exec(node, prep_res))));
+        });
+        
+        match rx.recv_timeout(timeout) {
+            Ok(res) => res,
+            Err(mpsc::RecvTimeoutError::Timeout) => Err(Error::Timeout(timeout)),
+            Err(mpsc::RecvTimeoutError::Disconnected) => Err(Error::NodeExecution("Exec attempt panicked".into())),
+        }
+    }
+}
+
+/// Run `exec` on `node` until an attempt succeeds or the policy gives up, then hand the last error to `fallback`
+///
+/// Panicking attempts fail like any other. An attempt bounded by a timeout runs on its own
+/// thread, which is left to finish in the background once the attempt times out. Those threads
+/// share one copy of the node and the prep result across all attempts.
+pub(crate) fn drive<N: Node + Clone + 'static>(
+    node: &N,
+    exec: fn(&N, &Value) -> Result<Value>,
+    attempts: Attempts<'_>,
+    prep_res: &Value,
+    fallback: impl FnOnce(Error, FallbackContext) -> Result<Value>,
+) -> Result<Value> {
+    let moved = OnceCell::new();
+    let mut attempt = 0;
+    loop {
+        let error = match attempts.exec(node, exec, prep_res, &moved, attempt) {
+            Ok(res) => return Ok(res),
+            Err(e) => e,
+        };
+        match attempts.after_failure(attempt, &error, || node.name()) {
+            Ok(delay) => {
+                if !delay.is_zero() {
+                    attempts.sleeper.sleep(delay);
+                }
+                attempt += 1;
+            },
+            Err(ctx) => return with_attempt(attempt, || fallback(error, ctx)),
+        }
+    }
+}
+
+/// Await the attempts made by `exec` until one succeeds or the policy gives up, then await `fallback` with the last error
+///
+/// Every attempt races the run's cancellation and is bounded by the timeout and the run's
+/// deadline; cancelled and out-of-time runs fail right away without reaching the fallback.
+/// Panicking attempts fail like any other.
+pub(crate) async fn drive_async<E, F>(
+    attempts: Attempts<'_>,
+    node: impl Fn() -> String,
+    mut exec: impl FnMut(usize) -> E,
+    fallback: impl FnOnce(Error, FallbackContext) -> F,
+) -> Result<Value>
+where
+    E: Future<Output = Result<Value>>,
+    F: Future<Output = Result<Value>>,
+{
+    let mut attempt = 0;
+    loop {
+        if let Some(limiter) = attempts.limiter {
+            limiter.acquire().await;
+        }
+        let exec = with_attempt_async(attempt, guard_attempt_async(exec(attempt)));
+        let exec = heartbeat::watch(attempts.heartbeat.clone(), &node, attempt, exec);
+        let error = match cancel::race(deadline::limit(&node, attempts.timeout, exec)).await {
+            Ok(res) => return Ok(res),
+            Err(e @ (Error::Cancelled | Error::DeadlineExceeded { .. })) => return Err(e),
+            Err(e) => e,
+        };
+        match attempts.after_failure(attempt, &error, &node) {
+            Ok(delay) => {
+                if !delay.is_zero() {
+                    attempts.sleeper.sleep_async(delay).await;
+                }
+                attempt += 1;
+            },
+            Err(ctx) => return with_attempt_async(attempt, fallback(error, ctx)).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use parking_lot::Mutex;
+    use serde_json::json;
+    
+    use crate::cancel::{self, CancellationToken};
+    use crate::nodes::FnNode;
+    use crate::sleeper::TestSleeper;
+    
+    use super::*;
+    
+    #[test]
+    fn every_attempt_sees_the_original_prep_result() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let node = FnNode::new().retries(3, 0).with_exec({
+            let seen = seen.clone();
+            move |prep| {
+                seen.lock().push((current_attempt(), prep.clone()));
+                match current_attempt() {
+                    Some(0) => Err(Error::NodeExecution("flaky".into())),
+                    _ => Ok(json!(prep["n"].as_i64().unwrap() * 2)),
+                }
+            }
+        });
+        
+        assert_eq!(node._exec(&json!({"n": 21})).unwrap(), json!(42));
+        assert_eq!(*seen.lock(), [(Some(0), json!({"n": 21})), (Some(1), json!({"n": 21}))]);
+    }
+    
+    #[test]
+    fn gives_the_last_error_to_the_fallback_after_the_policy_stops() {
+        let sleeper = TestSleeper::new();
+        let policy = FixedRetry::new(3, Duration::from_millis(5));
+        let node = FnNode::new().with_exec(|_| panic!("boom"));
+        let res = drive(&node, FnNode::exec, Attempts::new(&policy, &sleeper), &json!(1), |e, ctx| {
+            assert_eq!(current_attempt(), Some(2));
+            assert_eq!((ctx.attempt, ctx.max_retries), (2, 3));
+            Err(e)
+        });
+        assert!(matches!(res, Err(Error::NodeExecution(msg)) if msg == "Exec attempt panicked: boom"));
+        assert_eq!(sleeper.requested(), [Duration::from_millis(5); 2]);
+    }
+    
+    #[test]
+    fn times_out_slow_attempts() {
+        let node = FnNode::new().with_exec(|_| {
+            thread::sleep(Duration::from_millis(200));
+            Ok(json!(1))
+        });
+        let sleeper = TestSleeper::new();
+        let attempts = Attempts::new(&NoRetry, &sleeper).timeout(Some(Duration::from_millis(10)));
+        let res = drive(&node, FnNode::exec, attempts, &Value::Null, |e, _| Err(e));
+        assert!(matches!(res, Err(Error::Timeout(_))));
+    }
+    
+    #[tokio::test(start_paused = true)]
+    async fn async_attempts_retry_timeouts_but_not_cancellation() {
+        let sleeper = TestSleeper::new();
+        let policy = FixedRetry::new(3, Duration::ZERO);
+        let attempts = Attempts::new(&policy, &sleeper).timeout(Some(Duration::from_secs(1)));
+        let res = drive_async(
+            attempts,
+            || "slow".to_string(),
+            |attempt| async move {
+                if attempt < 2 {
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                }
+                Ok(json!(attempt))
+            },
+            |e, _| async { Err(e) },
+        ).await;
+        assert_eq!(res.unwrap(), json!(2));
+        
+        let token = CancellationToken::new();
+        token.cancel();
+        let fallbacks = Mutex::new(0);
+        let res = cancel::scope(token, drive_async(
+            Attempts::new(&policy, &sleeper),
+            || "cancelled".to_string(),
+            |_| std::future::pending(),
+            |e, _| {
+                *fallbacks.lock() += 1;
+                async { Err(e) }
+            },
+        )).await;
+        assert!(matches!(res, Err(Error::Cancelled)));
+        assert_eq!(*fallbacks.lock(), 0);
+    }
+    
+    #[test]
+    fn shipped_policies_stop_after_their_attempts() {
+        let error = Error::NodeExecution("down".into());
+        let fixed = FixedRetry::new(3, Duration::from_millis(5));
+        let waits: Vec<_> = (0..3).map(|attempt| fixed.should_retry(attempt, &error)).collect();
+        assert_eq!(waits, [Some(Duration::from_millis(5)), Some(Duration::from_millis(5)), None]);
+        
+        let backoff = BackoffRetry::exponential(4, Duration::from_secs(1), 2.0, Duration::from_secs(3));
+        let waits: Vec<_> = (0..4).map(|attempt| backoff.should_retry(attempt, &error)).collect();
+        assert_eq!(waits, [Some(Duration::from_secs(1)), Some(Duration::from_secs(2)), Some(Duration::from_secs(3)), None]);
+        assert_eq!(backoff.max_attempts(), Some(4));
+        
+        assert_eq!(NoRetry.should_retry(0, &error), None);
+        assert_eq!(NoRetry.max_attempts(), Some(1));
+    }
+    
+    /// Retries timeouts up to five attempts but gives up on anything else
+    struct RetryTimeouts;
+    
+    impl RetryPolicy for RetryTimeouts {
+        fn should_retry(&self, attempt: usize, error: &Error) -> Option<Duration> {
+            (matches!(error, Error::Timeout(_)) && attempt < 4).then_some(Duration::from_millis(1))
+        }
+    }
+    
+    #[test]
+    fn custom_policies_choose_which_errors_to_retry() {
+        let errors = Arc::new(Mutex::new(vec![
+            Error::Timeout(Duration::from_secs(1)),
+            Error::Timeout(Duration::from_secs(1)),
+            Error::NodeExecution("unauthorized".into()),
+            Error::Timeout(Duration::from_secs(1)),
+        ]));
+        let sleeper = Arc::new(TestSleeper::new());
+        let node = FnNode::new()
+            .with_exec({
+                let errors = errors.clone();
+                move |_| Err(errors.lock().remove(0))
+            })
+            .with_retry_policy(Arc::new(RetryTimeouts))
+            .with_sleeper(sleeper.clone());
+        assert!(matches!(node._exec(&Value::Null), Err(Error::NodeExecution(msg)) if msg == "unauthorized"));
+        assert_eq!(errors.lock().len(), 1, "the authentication error is not retried");
+        assert_eq!(sleeper.requested(), [Duration::from_millis(1); 2]);
+    }
+}
\ No newline at end of file
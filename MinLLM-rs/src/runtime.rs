@@ -0,0 +1,111 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::runtime::Builder;
+
+use crate::error::{Error, Result};
+
+/// Settings of the tokio runtime the Python bindings run async calls on
+#[derive(Clone, Debug)]
+pub(crate) struct RuntimeOptions {
+    /// Number of worker threads, or tokio's default of one per core
+    pub worker_threads: Option<usize>,
+    
+    /// Name given to the worker threads
+    pub thread_name_prefix: Option<String>,
+    
+    /// Whether the IO driver is enabled
+    pub enable_io: bool,
+    
+    /// Whether the time driver is enabled
+    pub enable_time: bool,
+}
+
+/// Allows the shared runtime to be configured once, and only before it is first used
+pub(crate) struct RuntimeGuard {
+    /// Set once the first async call has handed work to the runtime
+    started: AtomicBool,
+    
+    /// Set once a runtime builder has been handed out
+    configured: AtomicBool,
+}
+
+impl RuntimeGuard {
+    /// A guard for a runtime that is neither configured nor started
+    pub const fn new() -> Self {
+        Self {
+            started: AtomicBool::new(false),
+            configured: AtomicBool::new(false),
+        }
+    }
+    
+    /// Record that the runtime is in use and can no longer be configured
+    pub fn mark_started(&self) {
+        self.started.store(true, Ordering::SeqCst);
+    }
+    
+    /// A builder for the runtime described by `options`, failing once the runtime is configured or started
+    pub fn configure(&self, options: RuntimeOptions) -> Result<Builder> {
+        if options.worker_threads == Some(0) {
+            return Err(Error::InvalidOperation("worker_threads must be greater than zero".into()));
+        }
+        if self.started.load(Ordering::SeqCst) {
+            return Err(Error::InvalidOperation("configure_runtime must be called before any async node or flow runs".into()));
+        }
+        if self.configured.swap(true, Ordering::SeqCst) {
+            return Err(Error::InvalidOperation("The runtime has already been configured".into()));
+        }
+        
+        let mut builder = Builder::new_multi_thread();
+        if let Some(worker_threads) = options.worker_threads {
+            builder.worker_threads(worker_threads);
+        }
+        if let Some(prefix) = options.thread_name_prefix {
+            builder.thread_name(prefix);
+        }
+        if options.enable_io {
+            builder.enable_io();
+        }
+        if options.enable_time {
+            builder.enable_time();
+        }
+        Ok(builder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    
+    use super::*;
+    
+    fn options(worker_threads: Option<usize>) -> RuntimeOptions {
+        RuntimeOptions {
+            worker_threads,
+            thread_name_prefix: Some("minllm-worker".to_string()),
+            enable_io: true,
+            enable_time: true,
+        }
+    }
+    
+    #[test]
+    fn the_configured_runtime_has_the_requested_threads() {
+        let guard = RuntimeGuard::new();
+        let runtime = guard.configure(options(Some(3))).unwrap().build().unwrap();
+        assert_eq!(runtime.metrics().num_workers(), 3);
+        let name = runtime.block_on(async { tokio::spawn(async { thread::current().name().map(str::to_string) }).await.unwrap() });
+        assert_eq!(name.as_deref(), Some("minllm-worker"));
+    }
+    
+    #[test]
+    fn the_runtime_is_configured_once_and_before_it_starts() {
+        let guard = RuntimeGuard::new();
+        assert!(guard.configure(options(Some(0))).is_err());
+        guard.configure(options(None)).unwrap();
+        let err = guard.configure(options(None)).unwrap_err();
+        assert_eq!(err.to_string(), Error::InvalidOperation("The runtime has already been configured".into()).to_string());
+        
+        let started = RuntimeGuard::new();
+        started.mark_started();
+        let err = started.configure(options(Some(2))).unwrap_err();
+        assert!(err.to_string().contains("before any async node or flow runs"), "{}", err);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,117 @@
+use std::future::Future;
+use std::sync::Arc;
+use parking_lot::Mutex;
+use tokio::sync::watch;
+
+use crate::base::Action;
+use crate::error::{Error, Result};
+
+tokio::task_local! {
+    /// Shutdown state of the run the current task belongs to
+    static SHUTDOWN: Arc<ShutdownRun>;
+}
+
+/// A handle asking async runs to stop cleanly once the node in flight has finished
+///
+/// Clones share the same signal, so one clone can be handed to the run and another kept to trigger it.
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    /// Whether shutdown was triggered
+    signal: Arc<watch::Sender<bool>>,
+}
+
+impl ShutdownHandle {
+    /// Create a handle that is not triggered
+    pub fn new() -> Self {
+        Self {
+            signal: Arc::new(watch::Sender::new(false)),
+        }
+    }
+    
+    /// Ask the run to stop before its next node or batch item
+    pub fn trigger(&self) {
+        self.signal.send_replace(true);
+    }
+    
+    /// Whether `trigger` was called
+    pub fn is_triggered(&self) -> bool {
+        *self.signal.borrow()
+    }
+    
+    /// Wait until the handle is triggered
+    pub async fn triggered(&self) {
+        let mut receiver = self.signal.subscribe();
+        // The sender lives as long as `self`, so the wait can't fail
+        let _ = receiver.wait_for(|triggered| *triggered).await;
+    }
+    
+    /// The handle of the run the calling task belongs to, for execs that want to stop early
+    ///
+    /// An exec that stops early should fail with `Error::Cancelled`, which ends the run as interrupted.
+    pub fn current() -> Option<ShutdownHandle> {
+        SHUTDOWN.try_with(|run| run.handle.clone()).ok()
+    }
+}
+
+impl Default for ShutdownHandle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// How a run that could be shut down ended
+#[derive(Clone, Debug, PartialEq)]
+pub enum RunOutcome {
+    /// The run went to the end, returning the action of the flow
+    Completed(Action),
+    
+    /// The run stopped early because shutdown was triggered
+    Interrupted {
+        /// Name of the last node that finished, if any did
+        last_node: Option<String>,
+        
+        /// Action that node returned
+        action: Action,
+    },
+}
+
+/// Shutdown state of one run
+struct ShutdownRun {
+    /// Handle the run watches
+    handle: ShutdownHandle,
+    
+    /// Last node that finished and the action it returned
+    last: Mutex<Option<(String, Action)>>,
+}
+
+/// Await `fut` as a run that stops cleanly once `handle` is triggered
+pub(crate) async fn scope(handle: ShutdownHandle, fut: impl Future<Output = Result<Action>>) -> Result<RunOutcome> {
+    let run = Arc::new(ShutdownRun {
+        handle,
+        last: Mutex::new(None),
+    });
+    match SHUTDOWN.scope(run.clone(), fut).await {
+        Ok(action) => Ok(RunOutcome::Completed(action)),
+        Err(e) if e.is_cancelled() && run.handle.is_triggered() => {
+            let (last_node, action) = match run.last.lock().take() {
+                Some((node, action)) => (Some(node), action),
+                None => (None, None),
+            };
+            Ok(RunOutcome::Interrupted { last_node, action })
+        },
+        Err(e) => Err(e),
+    }
+}
+
+/// Stop the current run with `Error::Cancelled` when shutdown was triggered
+pub(crate) fn checkpoint() -> Result<()> {
+    match SHUTDOWN.try_with(|run| run.handle.is_triggered()) {
+        Ok(true) => Err(Error::Cancelled),
+        _ => Ok(()),
+    }
+}
+
+/// Record a node step that finished, as the point a shutdown would leave the run at
+pub(crate) fn completed(step: impl FnOnce() -> (String, Action)) {
+    let _ = SHUTDOWN.try_with(|run| *run.last.lock() = Some(step()));
+}
\ No newline at end of file
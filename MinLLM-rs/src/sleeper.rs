@@ -0,0 +1,70 @@
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use async_trait::async_trait;
+use parking_lot::Mutex;
+
+/// Source of the waits between retries, replaceable for deterministic tests
+#[async_trait]
+pub trait Sleeper: Send + Sync {
+    /// Block the current thread for `duration`
+    fn sleep(&self, duration: Duration);
+    
+    /// Wait asynchronously for `duration`
+    async fn sleep_async(&self, duration: Duration);
+}
+
+/// Sleeper backed by `thread::sleep` and `tokio::time::sleep`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RealSleeper;
+
+#[async_trait]
+impl Sleeper for RealSleeper {
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+    
+    async fn sleep_async(&self, duration: Duration) {
+        tokio::time::sleep(duration).await;
+    }
+}
+
+/// Sleeper recording every requested duration and returning immediately
+#[derive(Debug, Default)]
+pub struct TestSleeper {
+    /// Requested durations, in order
+    requested: Mutex<Vec<Duration>>,
+}
+
+impl TestSleeper {
+    /// Create a sleeper with an empty record
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Every duration requested so far, in order
+    pub fn requested(&self) -> Vec<Duration> {
+        self.requested.lock().clone()
+    }
+    
+    /// Sum of the requested durations
+    pub fn total(&self) -> Duration {
+        self.requested.lock().iter().sum()
+    }
+}
+
+#[async_trait]
+impl Sleeper for TestSleeper {
+    fn sleep(&self, duration: Duration) {
+        self.requested.lock().push(duration);
+    }
+    
+    async fn sleep_async(&self, duration: Duration) {
+        self.requested.lock().push(duration);
+    }
+}
+
+/// The default sleeper used by nodes
+pub(crate) fn real() -> Arc<dyn Sleeper> {
+    Arc::new(RealSleeper)
+}
\ No newline at end of file
@@ -0,0 +1,111 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use parking_lot::Mutex;
+
+use crate::base::SharedState;
+use crate::error::{Error, Result};
+
+/// What a flow does when a node step leaves the shared state over its entry limit
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    /// Undo the keys the step added and fail the flow
+    #[default]
+    RejectNew,
+    
+    /// Remove the unpinned keys written longest ago until the state fits
+    ///
+    /// Reads are not observable, so recency is measured by the node steps that wrote a key.
+    LeastRecentlyWritten,
+}
+
+/// Bound on the number of shared state entries, checked by a flow after every node step
+#[derive(Debug)]
+pub struct StateLimit {
+    /// Maximum number of entries
+    max_entries: usize,
+    
+    /// What happens when the limit is exceeded
+    policy: EvictionPolicy,
+    
+    /// Keys that are never evicted
+    pinned: HashSet<String>,
+    
+    /// Step count at each key's last write
+    written: Mutex<HashMap<String, u64>>,
+    
+    /// Steps checked so far
+    steps: AtomicU64,
+}
+
+impl StateLimit {
+    /// Limit the state to `max_entries` entries
+    pub fn new(max_entries: usize, policy: EvictionPolicy) -> Self {
+        Self {
+            max_entries,
+            policy,
+            pinned: HashSet::new(),
+            written: Mutex::new(HashMap::new()),
+            steps: AtomicU64::new(0),
+        }
+    }
+    
+    /// Never evict `key`
+    pub fn pin(mut self, key: &str) -> Self {
+        self.pinned.insert(key.to_string());
+        self
+    }
+    
+    /// Maximum number of entries
+    pub fn max_entries(&self) -> usize {
+        self.max_entries
+    }
+    
+    /// Apply the limit to the state left by one node step
+    pub(crate) fn enforce(&self, before: &SharedState, shared: &mut SharedState) -> Result<()> {
+        let tick = self.steps.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut written = self.written.lock();
+        
+        written.retain(|key, _| shared.contains_key(key));
+        for (key, value) in shared.iter() {
+            if before.get(key) != Some(value) {
+                written.insert(key.clone(), tick);
+            }
+        }
+        
+        if shared.len() <= self.max_entries {
+            return Ok(());
+        }
+        
+        match self.policy {
+            EvictionPolicy::RejectNew => {
+                let size = shared.len();
+                let added: Vec<String> = shared.keys().filter(|key| !before.contains_key(key)).cloned().collect();
+                for key in &added {
+                    shared.remove(key);
+                    written.remove(key);
+                }
+                Err(Error::CapacityExceeded(format!(
+                    "Node step grew the state to {} entries, over the limit of {}; removed its new keys {:?}",
+                    size,
+                    self.max_entries,
+                    added
+                )))
+            },
+            EvictionPolicy::LeastRecentlyWritten => {
+                let mut candidates: Vec<(u64, String)> = shared
+                    .keys()
+                    .filter(|key| !self.pinned.contains(*key))
+                    .map(|key| (written.get(key).copied().unwrap_or(0), key.clone()))
+                    .collect();
+                candidates.sort();
+                
+                let excess = shared.len() - self.max_entries;
+                for (_, key) in candidates.into_iter().take(excess) {
+                    shared.remove(&key);
+                    written.remove(&key);
+                }
+                Ok(())
+            },
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,83 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::Arc;
+use parking_lot::Mutex;
+
+use crate::base::Node;
+use crate::error::{Error, Result};
+
+/// Most node steps a flow run takes before it is stopped, unless set otherwise
+pub const DEFAULT_MAX_STEPS: usize = 1000;
+
+/// Number of recent steps kept to describe the loop of a run that hit its limit
+const RECENT_STEPS: usize = 64;
+
+tokio::task_local! {
+    /// Steps taken by the flow run the current task belongs to
+    static STEPS: Mutex<StepCount>;
+}
+
+/// Steps taken by one flow run
+struct StepCount {
+    /// Most steps the run may take
+    limit: Option<usize>,
+    
+    /// Steps taken so far
+    taken: usize,
+    
+    /// Nodes of the most recent steps, oldest first, named only when the run hits its limit
+    recent: VecDeque<Arc<dyn Node>>,
+}
+
+impl StepCount {
+    fn new(limit: Option<usize>) -> Self {
+        Self {
+            limit,
+            taken: 0,
+            recent: VecDeque::new(),
+        }
+    }
+    
+    /// The shortest sequence of nodes the recent steps keep repeating, or the recent steps themselves
+    fn repeating(&self) -> Vec<String> {
+        let mut recent: Vec<String> = self.recent.iter().map(|node| node.name()).collect();
+        let n = recent.len();
+        let period = (1..=n / 2).find(|&p| (0..p).all(|k| recent[n - 1 - k] == recent[n - 1 - k - p]));
+        recent.split_off(n - period.unwrap_or(n.min(10)))
+    }
+}
+
+/// Run `f` as a flow run that may take at most `limit` node steps
+pub(crate) fn scope_sync<R>(limit: Option<usize>, f: impl FnOnce() -> R) -> R {
+    STEPS.sync_scope(Mutex::new(StepCount::new(limit)), f)
+}
+
+/// Await `fut` as a flow run that may take at most `limit` node steps
+pub(crate) async fn scope<F: Future>(limit: Option<usize>, fut: F) -> F::Output {
+    STEPS.scope(Mutex::new(StepCount::new(limit)), fut).await
+}
+
+/// Count a step of the current run about to run `node`, failing once the run is over its limit
+///
+/// The error gives the step count and the sequence of nodes the run keeps repeating.
+pub(crate) fn step(node: &Arc<dyn Node>) -> Result<()> {
+    STEPS.try_with(|steps| {
+        let mut steps = steps.lock();
+        let Some(limit) = steps.limit else {
+            return Ok(());
+        };
+        if steps.taken >= limit {
+            return Err(Error::FlowExecution(format!(
+                "Stopped after {} steps, the flow's max_steps; it keeps repeating {}",
+                steps.taken,
+                steps.repeating().join(" -> "),
+            )));
+        }
+        steps.taken += 1;
+        if steps.recent.len() == RECENT_STEPS {
+            steps.recent.pop_front();
+        }
+        steps.recent.push_back(node.clone());
+        Ok(())
+    }).unwrap_or(Ok(()))
+}
\ No newline at end of file
@@ -0,0 +1,135 @@
+use std::future::Future;
+use futures::future::BoxFuture;
+use futures::stream::{self, BoxStream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::mpsc;
+
+use crate::base::Action;
+use crate::error::Result;
+
+tokio::task_local! {
+    /// Sender of the streaming run the current task belongs to
+    static EVENTS: mpsc::Sender<FlowEvent>;
+}
+
+/// Something that happened during a streaming run, yielded in the order it happened
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum FlowEvent {
+    /// A node step is about to run
+    NodeStarted {
+        /// Name of the node
+        node: String,
+    },
+    
+    /// A node's exec produced part of its result
+    Chunk {
+        /// Name of the node
+        node: String,
+        
+        /// The chunk, as yielded by `exec_stream`
+        value: Value,
+    },
+    
+    /// A node step ended
+    NodeFinished {
+        /// Name of the node
+        node: String,
+        
+        /// Action post returned
+        action: Action,
+    },
+    
+    /// The run ended; always the last event of a successful run
+    Finished {
+        /// Action the flow returned
+        action: Action,
+    },
+}
+
+/// Whether the current node runs as part of a streaming run
+pub(crate) fn is_active() -> bool {
+    EVENTS.try_with(|_| ()).is_ok()
+}
+
+/// Hand an event to the caller of the current streaming run, if any
+///
+/// Waits while the caller has not taken the previous event, so the run never gets ahead of it.
+pub(crate) async fn emit(event: impl FnOnce() -> FlowEvent) {
+    if let Ok(events) = EVENTS.try_with(|events| events.clone()) {
+        // A caller that dropped the stream no longer wants events
+        let _ = events.send(event()).await;
+    }
+}
+
+/// Drain an exec stream, emitting each chunk, then combine the chunks with `collect`
+pub(crate) async fn collect(
+    node: String,
+    mut chunks: BoxStream<'_, Result<Value>>,
+    collect: impl FnOnce(Vec<Value>) -> Value,
+) -> Result<Value> {
+    let mut values = Vec::new();
+    while let Some(chunk) = chunks.next().await {
+        let chunk = chunk?;
+        emit(|| FlowEvent::Chunk { node: node.clone(), value: chunk.clone() }).await;
+        values.push(chunk);
+    }
+    Ok(collect(values))
+}
+
+/// Default combination of exec chunks: a lone chunk as is, strings concatenated, anything else as an array
+pub(crate) fn concat(mut chunks: Vec<Value>) -> Value {
+    if chunks.len() == 1 {
+        return chunks.pop().unwrap_or(Value::Null);
+    }
+    if chunks.is_empty() {
+        return Value::Null;
+    }
+    if chunks.iter().all(Value::is_string) {
+        let joined: String = chunks.iter().filter_map(Value::as_str).collect();
+        return Value::String(joined);
+    }
+    Value::Array(chunks)
+}
+
+/// Outcome of one wait on a streaming run
+enum Step {
+    /// The run emitted an event
+    Event(FlowEvent),
+    
+    /// The run ended
+    Done(Result<Action>),
+}
+
+/// Drive `fut` as a streaming run, yielding its events and then `FlowEvent::Finished`
+///
+/// The run only makes progress while the stream is polled, and events are taken before the run
+/// is resumed, so a caller sees each event before the node that emitted it moves on.
+/// A failed run yields its error as the last item.
+pub(crate) fn run<'a>(fut: impl Future<Output = Result<Action>> + Send + 'a) -> BoxStream<'a, Result<FlowEvent>> {
+    let (events, rx) = mpsc::channel(1);
+    let run: BoxFuture<'a, Result<Action>> = Box::pin(EVENTS.scope(events, fut));
+    
+    Box::pin(stream::unfold((Some(run), rx, None), |(mut run, mut rx, mut outcome)| async move {
+        if let Some(fut) = run.as_mut() {
+            let step = tokio::select! {
+                biased;
+                Some(event) = rx.recv() => Step::Event(event),
+                res = fut => Step::Done(res),
+            };
+            match step {
+                Step::Event(event) => return Some((Ok(event), (run, rx, outcome))),
+                Step::Done(res) => {
+                    // Dropping the run drops its sender, so the receiver ends once drained
+                    run = None;
+                    outcome = Some(res);
+                },
+            }
+        }
+        if let Some(event) = rx.recv().await {
+            return Some((Ok(event), (run, rx, outcome)));
+        }
+        let res = outcome.take()?;
+        Some((res.map(|action| FlowEvent::Finished { action }), (run, rx, None)))
+    }))
+}
\ No newline at end of file
@@ -0,0 +1,290 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use serde_json::Value;
+
+use crate::action::ActionName;
+use crate::base::{Node, SharedState};
+
+/// Number of successors kept in a flat list before switching to a map
+const INLINE_CAPACITY: usize = 8;
+
+/// Decides whether a conditional successor is taken, from the shared state and the exec result
+pub type EdgePredicate = Arc<dyn Fn(&SharedState, &Value) -> bool + Send + Sync>;
+
+/// A successor taken when its predicate holds
+#[derive(Clone)]
+pub struct ConditionalEdge {
+    /// Condition checked after the node runs
+    pub predicate: EdgePredicate,
+    
+    /// Node the flow continues with when the condition holds
+    pub node: Arc<dyn Node>,
+    
+    /// Label shown in graph descriptions
+    pub description: Option<String>,
+}
+
+/// Successors of a node, keyed by action
+///
+/// Nodes almost always have one or two successors, so they are kept in a small list searched
+/// linearly and only moved into a hash map once the list grows past `INLINE_CAPACITY`.
+/// An action can have several successors, kept in the order they were added.
+#[derive(Clone, Default)]
+pub struct Successors {
+    /// Successors stored as (action, node) pairs
+    inline: Vec<(ActionName, Arc<dyn Node>)>,
+    
+    /// Successors stored in a map once there are too many for the list
+    map: Option<HashMap<ActionName, Vec<Arc<dyn Node>>>>,
+    
+    /// Conditional successors, in insertion order
+    conditional: Vec<ConditionalEdge>,
+}
+
+impl Successors {
+    /// Create an empty successor set
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Get the first successor for an action
+    pub fn get(&self, action: &str) -> Option<&Arc<dyn Node>> {
+        match &self.map {
+            Some(map) => map.get(action).and_then(|nodes| nodes.first()),
+            None => self.inline.iter().find(|(a, _)| *a == *action).map(|(_, node)| node),
+        }
+    }
+    
+    /// Get every successor for an action, in the order they were added
+    pub fn get_all(&self, action: &str) -> Vec<Arc<dyn Node>> {
+        self.iter_for(action).cloned().collect()
+    }
+    
+    /// Iterate over every successor for an action, in the order they were added, without collecting them
+    pub fn iter_for<'a>(&'a self, action: &'a str) -> impl Iterator<Item = &'a Arc<dyn Node>> + 'a {
+        // The list is emptied when the successors move into the map, so at most one of these yields
+        let mapped = self.map.as_ref().and_then(|map| map.get(action)).into_iter().flatten();
+        let inline = self.inline.iter().filter(move |(a, _)| *a == *action).map(|(_, node)| node);
+        mapped.chain(inline)
+    }
+    
+    /// Whether a successor is registered for an action
+    pub fn contains_key(&self, action: &str) -> bool {
+        self.get(action).is_some()
+    }
+    
+    /// Add a successor after the ones already registered for the action
+    pub fn push(&mut self, action: impl Into<ActionName>, node: Arc<dyn Node>) {
+        let action = action.into();
+        if let Some(map) = &mut self.map {
+            map.entry(action).or_default().push(node);
+            return;
+        }
+        
+        if self.inline.len() < INLINE_CAPACITY {
+            self.inline.push((action, node));
+        } else {
+            let mut map: HashMap<ActionName, Vec<Arc<dyn Node>>> = HashMap::new();
+            for (a, n) in self.inline.drain(..) {
+                map.entry(a).or_default().push(n);
+            }
+            map.entry(action).or_default().push(node);
+            self.map = Some(map);
+        }
+    }
+    
+    /// Make `node` the only successor for an action, returning the first one it replaced
+    pub fn insert(&mut self, action: impl Into<ActionName>, node: Arc<dyn Node>) -> Option<Arc<dyn Node>> {
+        let action = action.into();
+        let replaced = self.remove(&action);
+        self.push(action, node);
+        replaced
+    }
+    
+    /// Remove every successor for an action, returning the first
+    pub fn remove(&mut self, action: &str) -> Option<Arc<dyn Node>> {
+        match &mut self.map {
+            Some(map) => map.remove(action).and_then(|nodes| nodes.into_iter().next()),
+            None => {
+                let mut removed = None;
+                self.inline.retain(|(a, node)| {
+                    if *a != *action {
+                        return true;
+                    }
+                    removed.get_or_insert_with(|| node.clone());
+                    false
+                });
+                removed
+            },
+        }
+    }
+    
+    /// Register a conditional successor, checked after the ones registered before it
+    pub fn push_conditional(&mut self, edge: ConditionalEdge) {
+        self.conditional.push(edge);
+    }
+    
+    /// Conditional successors, in insertion order
+    pub fn conditional(&self) -> &[ConditionalEdge] {
+        &self.conditional
+    }
+    
+    /// Whether any conditional successor is registered
+    pub fn has_conditional(&self) -> bool {
+        !self.conditional.is_empty()
+    }
+    
+    /// The first conditional successor whose predicate holds
+    pub fn route(&self, shared: &SharedState, exec_res: &Value) -> Option<&Arc<dyn Node>> {
+        self.conditional
+            .iter()
+            .find(|edge| (edge.predicate)(shared, exec_res))
+            .map(|edge| &edge.node)
+    }
+    
+    /// Number of successors registered for actions
+    pub fn len(&self) -> usize {
+        match &self.map {
+            Some(map) => map.values().map(Vec::len).sum(),
+            None => self.inline.len(),
+        }
+    }
+    
+    /// Whether no successors are registered, for actions or conditions
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0 && self.conditional.is_empty()
+    }
+    
+    /// Iterate over (action, node) pairs, one per successor
+    pub fn iter(&self) -> Box<dyn Iterator<Item = (&ActionName, &Arc<dyn Node>)> + '_> {
+        match &self.map {
+            Some(map) => Box::new(map.iter().flat_map(|(a, nodes)| nodes.iter().map(move |node| (a, node)))),
+            None => Box::new(self.inline.iter().map(|(a, node)| (a, node))),
+        }
+    }
+    
+    /// Iterate over the registered actions, each once
+    pub fn keys(&self) -> impl Iterator<Item = &ActionName> + '_ {
+        let mut seen = HashSet::new();
+        self.iter().map(|(action, _)| action).filter(move |action| seen.insert(*action))
+    }
+}
+
+/// A node reachable in a flow, with its outgoing edges
+///
+/// Edges point at other entries by their index in the list returned by `Flow::nodes`.
+#[derive(Clone)]
+pub struct GraphNode {
+    /// The node itself
+    pub node: Arc<dyn Node>,
+    
+    /// Action edges as (action, target index), sorted by action
+    pub edges: Vec<(ActionName, usize)>,
+    
+    /// Conditional edges as (description, target index), in insertion order
+    pub conditional: Vec<(Option<String>, usize)>,
+}
+
+/// Every node reachable from `start`, breadth-first, each listed once by pointer identity
+pub(crate) fn reachable(start: Arc<dyn Node>) -> Vec<GraphNode> {
+    reachable_with_predicates(start).0
+}
+
+/// `reachable`, along with the predicates of each node's conditional edges in the same order
+pub(crate) fn reachable_with_predicates(start: Arc<dyn Node>) -> (Vec<GraphNode>, Vec<Vec<EdgePredicate>>) {
+    let mut index: HashMap<*const (), usize> = HashMap::new();
+    index.insert(Arc::as_ptr(&start) as *const (), 0);
+    let mut nodes = vec![start];
+    let mut graph = Vec::new();
+    let mut predicates = Vec::new();
+    
+    while graph.len() < nodes.len() {
+        let node = nodes[graph.len()].clone();
+        let successors_lock = node.successors();
+        let successors = successors_lock.read();
+        let mut index_of = |next: &Arc<dyn Node>| {
+            let key = Arc::as_ptr(next) as *const ();
+            *index.entry(key).or_insert_with(|| {
+                nodes.push(next.clone());
+                nodes.len() - 1
+            })
+        };
+        
+        let mut actions: Vec<(&ActionName, &Arc<dyn Node>)> = successors.iter().collect();
+        actions.sort_by(|a, b| a.0.cmp(b.0));
+        let edges = actions
+            .into_iter()
+            .map(|(action, next)| (action.clone(), index_of(next)))
+            .collect();
+        let conditional = successors
+            .conditional()
+            .iter()
+            .map(|edge| (edge.description.clone(), index_of(&edge.node)))
+            .collect();
+        predicates.push(successors.conditional().iter().map(|edge| edge.predicate.clone()).collect());
+        drop(successors);
+        
+        graph.push(GraphNode { node, edges, conditional });
+    }
+    
+    (graph, predicates)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::node::Node as PlainNode;
+    
+    use super::*;
+    
+    fn node() -> Arc<dyn Node> {
+        Arc::new(PlainNode::new(1, 0))
+    }
+    
+    fn same(a: &Arc<dyn Node>, b: &Arc<dyn Node>) -> bool {
+        Arc::ptr_eq(a, b)
+    }
+    
+    #[test]
+    fn behaves_the_same_inline_and_as_a_map() {
+        for count in [2, INLINE_CAPACITY, INLINE_CAPACITY * 3] {
+            let nodes: Vec<_> = (0..count).map(|_| node()).collect();
+            let mut successors = Successors::new();
+            for (i, node) in nodes.iter().enumerate() {
+                successors.push(format!("a{}", i % (count / 2)), node.clone());
+            }
+            assert_eq!(successors.map.is_some(), count > INLINE_CAPACITY);
+            assert_eq!(successors.len(), count);
+            assert_eq!(successors.keys().count(), count / 2);
+            
+            let first = successors.get_all("a0");
+            assert_eq!(first.len(), 2);
+            assert!(same(&first[0], &nodes[0]) && same(&first[1], &nodes[count / 2]));
+            assert!(same(successors.get("a0").unwrap(), &nodes[0]));
+            
+            let replacement = node();
+            assert!(same(&successors.insert("a0", replacement.clone()).unwrap(), &nodes[0]));
+            assert_eq!(successors.get_all("a0").len(), 1);
+            assert!(same(successors.get("a0").unwrap(), &replacement));
+            
+            assert!(successors.remove("a0").is_some());
+            assert!(!successors.contains_key("a0"));
+            assert!(successors.remove("a0").is_none());
+            assert_eq!(successors.len(), count - 2);
+        }
+    }
+    
+    #[test]
+    fn reaches_each_node_once() {
+        let (first, second, third) = (node(), node(), node());
+        first.add_successor(second.clone(), "next").unwrap();
+        first.add_successor(third.clone(), "skip").unwrap();
+        second.add_successor(third.clone(), "next").unwrap();
+        third.add_successor(first.clone(), "again").unwrap();
+        
+        let graph = reachable(first);
+        assert_eq!(graph.len(), 3);
+        assert_eq!(graph[0].edges, [(ActionName::new("next"), 1), (ActionName::new("skip"), 2)]);
+        assert_eq!(graph[1].edges, [(ActionName::new("next"), 2)]);
+        assert_eq!(graph[2].edges, [(ActionName::new("again"), 0)]);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,218 @@
+use std::sync::Arc;
+use serde_json::json;
+
+use crate::base::Node as NodeTrait;
+use crate::node::BatchNode;
+use crate::flow::Flow;
+use crate::async_flow::AsyncFlow;
+use crate::error::Error;
+use super::{MockNode, FlowTestHarness};
+
+/// Trace of `linear(3, None)`
+pub const LINEAR_TRACE: &[&str] = &["step1", "step2", "step3"];
+
+/// Trace of `branching(false)`
+pub const BRANCHING_OK_TRACE: &[&str] = &["classify", "handle", "finish"];
+
+/// Trace of `branching(true)`
+pub const BRANCHING_ERROR_TRACE: &[&str] = &["classify", "recover", "finish"];
+
+/// Trace of `approval_loop(1)`
+pub const APPROVAL_TRACE: &[&str] = &["draft", "review", "draft", "review", "publish"];
+
+/// Trace of `batch(n, None)` for any `n`
+pub const BATCH_TRACE: &[&str] = &["load", "process", "save"];
+
+/// A canonical flow built from test nodes, with the names its trace uses
+///
+/// The same graph can be run through `Flow`, `AsyncFlow`, or a `FlowTestHarness` with every node named.
+/// Each of those rewinds the scripted nodes first, so every run follows `expected_trace`.
+pub struct Fixture {
+    /// First node of the graph
+    pub start: Arc<dyn NodeTrait>,
+    
+    /// Every node with its trace name, in construction order
+    pub nodes: Vec<(String, Arc<dyn NodeTrait>)>,
+    
+    /// Node names the run is expected to visit, in order
+    pub expected_trace: Vec<String>,
+    
+    /// The scripted nodes, rewound before every run
+    mocks: Vec<MockNode>,
+}
+
+impl Fixture {
+    /// Start a fixture from its first node
+    fn new(name: &str, start: MockNode) -> Self {
+        let node: Arc<dyn NodeTrait> = Arc::new(start.clone());
+        Self {
+            start: node.clone(),
+            nodes: vec![(name.to_string(), node)],
+            expected_trace: Vec::new(),
+            mocks: vec![start],
+        }
+    }
+    
+    /// Register another node under its trace name
+    fn add(&mut self, name: &str, node: Arc<dyn NodeTrait>) -> Arc<dyn NodeTrait> {
+        self.nodes.push((name.to_string(), node.clone()));
+        node
+    }
+    
+    /// Register a scripted node under its trace name
+    fn add_mock(&mut self, name: &str, node: MockNode) -> Arc<dyn NodeTrait> {
+        self.mocks.push(node.clone());
+        self.add(name, Arc::new(node))
+    }
+    
+    /// Replay the scripts of every scripted node from the start
+    pub fn rewind(&self) {
+        for mock in &self.mocks {
+            mock.rewind();
+        }
+    }
+    
+    /// Look up a node by its trace name
+    pub fn node(&self, name: &str) -> Option<&Arc<dyn NodeTrait>> {
+        self.nodes.iter().find(|(n, _)| n == name).map(|(_, node)| node)
+    }
+    
+    /// The graph as a sync flow
+    pub fn flow(&self) -> Flow {
+        self.rewind();
+        Flow::new(self.start.clone())
+    }
+    
+    /// The graph as an async flow
+    pub fn async_flow(&self) -> AsyncFlow {
+        self.rewind();
+        AsyncFlow::new(self.start.clone())
+    }
+    
+    /// A harness for the sync flow with every node named
+    pub fn harness(&self) -> FlowTestHarness {
+        self.named(FlowTestHarness::new(self.flow()))
+    }
+    
+    /// A harness for the async flow with every node named
+    pub fn async_harness(&self) -> FlowTestHarness {
+        self.named(FlowTestHarness::new_async(self.async_flow()))
+    }
+    
+    fn named(&self, harness: FlowTestHarness) -> FlowTestHarness {
+        self.nodes.iter().fold(harness, |harness, (name, node)| harness.name(node, name))
+    }
+    
+    /// The expected trace as borrowed names, for `assert_visited`
+    pub fn expected(&self) -> Vec<&str> {
+        self.expected_trace.iter().map(|s| s.as_str()).collect()
+    }
+}
+
+/// Add `to` as the successor of `from` for `action`
+fn wire(from: &Arc<dyn NodeTrait>, to: Arc<dyn NodeTrait>, action: &str) {
+    from.add_successor(to, action).expect("fixture nodes accept successors");
+}
+
+/// Node scripted to fail with a message naming it
+fn failing(name: &str) -> MockNode {
+    MockNode::new().fails(&format!("injected failure at {}", name))
+}
+
+/// `len` nodes named "step1".."stepN" run one after another
+///
+/// With `fail_at` set, the node at that zero-based index fails and the trace ends there.
+pub fn linear(len: usize, fail_at: Option<usize>) -> Fixture {
+    let names: Vec<String> = (1..=len.max(1)).map(|i| format!("step{}", i)).collect();
+    let make = |i: usize| {
+        if fail_at == Some(i) {
+            failing(&names[i])
+        } else {
+            MockNode::new().returns(json!(names[i]))
+        }
+    };
+    
+    let mut fixture = Fixture::new(&names[0], make(0));
+    let mut prev = fixture.start.clone();
+    for (i, name) in names.iter().enumerate().skip(1) {
+        let node = fixture.add_mock(name, make(i));
+        wire(&prev, node.clone(), "default");
+        prev = node;
+    }
+    
+    let visited = fail_at.map(|i| i + 1).unwrap_or(names.len()).min(names.len());
+    fixture.expected_trace = names[..visited].to_vec();
+    fixture
+}
+
+/// "classify" routes to "handle" on "ok" or "recover" on "error", and both rejoin at "finish"
+pub fn branching(take_error_path: bool) -> Fixture {
+    let action = if take_error_path { "error" } else { "ok" };
+    let mut fixture = Fixture::new("classify", MockNode::new().then_action(action));
+    let classify = fixture.start.clone();
+    let handle = fixture.add_mock("handle", MockNode::new().returns(json!("handled")));
+    let recover = fixture.add_mock("recover", MockNode::new().returns(json!("recovered")));
+    let finish = fixture.add_mock("finish", MockNode::new());
+    
+    wire(&classify, handle.clone(), "ok");
+    wire(&classify, recover.clone(), "error");
+    wire(&handle, finish.clone(), "default");
+    wire(&recover, finish, "default");
+    
+    let middle = if take_error_path { "recover" } else { "handle" };
+    fixture.expected_trace = vec!["classify".into(), middle.into(), "finish".into()];
+    fixture
+}
+
+/// "draft" and "review" loop on "revise" `rejections` times before "review" approves into "publish"
+///
+/// The loop is a reference cycle, so the nodes are never freed; that is fine for tests.
+pub fn approval_loop(rejections: usize) -> Fixture {
+    let review = (0..rejections).fold(MockNode::new(), |node, _| node.then_action("revise"));
+    
+    let mut fixture = Fixture::new("draft", MockNode::new().returns(json!("draft")));
+    let draft = fixture.start.clone();
+    let review = fixture.add_mock("review", review.default_action(Some("approved".into())));
+    let publish = fixture.add_mock("publish", MockNode::new());
+    
+    wire(&draft, review.clone(), "default");
+    wire(&review, draft, "revise");
+    wire(&review, publish, "approved");
+    
+    for _ in 0..=rejections {
+        fixture.expected_trace.push("draft".into());
+        fixture.expected_trace.push("review".into());
+    }
+    fixture.expected_trace.push("publish".into());
+    fixture
+}
+
+/// "load", then the `BatchNode` "process" doubling the items 0..`items` into "results", then "save"
+///
+/// With `fail_at` set, processing fails on the item at that index and "save" is not reached.
+pub fn batch(items: usize, fail_at: Option<usize>) -> Fixture {
+    let process = BatchNode::new(1, 0)
+        .with_prep(move |_| Ok(json!((0..items).collect::<Vec<_>>())))
+        .with_exec(move |item| {
+            let item = item.as_u64().unwrap_or_default();
+            if fail_at == Some(item as usize) {
+                return Err(Error::NodeExecution(format!("injected failure at item {}", item)));
+            }
+            Ok(json!(item * 2))
+        })
+        .with_post(|shared, _, results| {
+            shared.insert("results".to_string(), results);
+            Ok(None)
+        });
+    
+    let mut fixture = Fixture::new("load", MockNode::new());
+    let process = fixture.add("process", Arc::new(process));
+    let save = fixture.add_mock("save", MockNode::new());
+    
+    wire(&fixture.start, process.clone(), "default");
+    wire(&process, save, "default");
+    
+    let visited = if fail_at.is_some_and(|i| i < items) { 2 } else { 3 };
+    fixture.expected_trace = BATCH_TRACE[..visited].iter().map(|s| s.to_string()).collect();
+    fixture
+}
\ No newline at end of file
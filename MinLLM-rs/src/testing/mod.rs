@@ -0,0 +1,1433 @@
+pub mod fixtures;
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use parking_lot::{Mutex, RwLock};
+use async_trait::async_trait;
+use serde_json::Value;
+
+use crate::base::{BaseNode, short_type_name, Node as NodeTrait, SharedState, Action, ParamMap};
+use crate::action::ActionName;
+use crate::successors::Successors;
+use crate::hooks::NodeHooks;
+use crate::dry_run;
+use crate::trace::{self, FlowTrace, TraceEntry, StateAccess};
+use crate::param_spec::ParamSpec;
+use crate::dataflow::KeySpec;
+use crate::flow::Flow;
+use crate::async_flow::AsyncFlow;
+use crate::async_node::AsyncNodeTrait;
+use crate::sleeper::{self, Sleeper};
+use crate::retry::{self, FallbackContext};
+use crate::error::{Error, Result};
+
+/// The outcome of one scripted exec attempt
+#[derive(Clone, Debug, PartialEq)]
+pub enum MockExec {
+    /// Return the value
+    Return(Value),
+    
+    /// Fail the attempt with the message
+    Fail(String),
+}
+
+/// A call recorded by a `MockNode`
+#[derive(Clone, Debug, PartialEq)]
+pub enum MockCall {
+    /// `prep` ran against this shared state
+    Prep(SharedState),
+    
+    /// An exec attempt (starting at 0) ran with this prep result
+    Exec { prep_res: Value, attempt: usize },
+    
+    /// `post` ran with these results
+    Post { prep_res: Value, exec_res: Value },
+}
+
+/// Scripted outcomes taken one at a time, which can be replayed from the start
+#[derive(Debug)]
+struct Script<T> {
+    /// Every scripted outcome, in order
+    items: Vec<T>,
+    
+    /// Index of the next outcome to take
+    next: usize,
+}
+
+impl<T: Clone> Script<T> {
+    fn new() -> Self {
+        Self { items: Vec::new(), next: 0 }
+    }
+    
+    /// Take the next outcome, or `None` once the script has run out
+    fn take(&mut self) -> Option<T> {
+        let item = self.items.get(self.next).cloned();
+        self.next += item.is_some() as usize;
+        item
+    }
+}
+
+/// A node whose behavior is scripted per invocation and whose calls are recorded
+///
+/// Each exec attempt takes the next scripted `MockExec`, returning null once the script runs
+/// out, and each `post` takes the next scripted action, falling back to the default action.
+/// `rewind` replays both scripts from the start, e.g. before running the same graph again.
+/// Failed attempts are retried up to `retries` times, like `Node`.
+/// Implements both the sync and async node traits, so it works in `Flow` and `AsyncFlow`.
+#[derive(Clone)]
+pub struct MockNode {
+    /// Base node implementation
+    base: BaseNode,
+    
+    /// Value returned from prep
+    prep_res: Value,
+    
+    /// Scripted exec outcomes
+    execs: Arc<Mutex<Script<MockExec>>>,
+    
+    /// Scripted post actions
+    actions: Arc<Mutex<Script<Action>>>,
+    
+    /// Action returned once the scripted actions run out
+    default_action: Action,
+    
+    /// Simulated duration of each exec attempt
+    delay: Duration,
+    
+    /// Maximum number of exec attempts per run
+    max_retries: usize,
+    
+    /// Every call made to the node, in order
+    calls: Arc<Mutex<Vec<MockCall>>>,
+    
+    /// Source of the simulated delays
+    sleeper: Arc<dyn Sleeper>,
+}
+
+impl MockNode {
+    /// Create a mock returning null from every step and "default" from post
+    pub fn new() -> Self {
+        Self {
+            base: BaseNode::new(),
+            prep_res: Value::Null,
+            execs: Arc::new(Mutex::new(Script::new())),
+            actions: Arc::new(Mutex::new(Script::new())),
+            default_action: Some(ActionName::default_action()),
+            delay: Duration::ZERO,
+            max_retries: 1,
+            calls: Arc::new(Mutex::new(Vec::new())),
+            sleeper: sleeper::real(),
+        }
+    }
+    
+    /// Set the value returned from prep
+    pub fn with_prep(mut self, prep_res: Value) -> Self {
+        self.prep_res = prep_res;
+        self
+    }
+    
+    /// Script the next exec attempt to return a value
+    pub fn returns(self, value: Value) -> Self {
+        self.execs.lock().items.push(MockExec::Return(value));
+        self
+    }
+    
+    /// Script the next exec attempt to fail
+    pub fn fails(self, message: &str) -> Self {
+        self.execs.lock().items.push(MockExec::Fail(message.to_string()));
+        self
+    }
+    
+    /// Script the action returned by the next post
+    pub fn then_action(self, action: &str) -> Self {
+        self.actions.lock().items.push(Some(ActionName::from(action)));
+        self
+    }
+    
+    /// Set the action returned once the scripted actions run out
+    pub fn default_action(mut self, action: Action) -> Self {
+        self.default_action = action;
+        self
+    }
+    
+    /// Simulate each exec attempt taking `delay`
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+    
+    /// Route the simulated delays through `sleeper`
+    pub fn with_sleeper(mut self, sleeper: Arc<dyn Sleeper>) -> Self {
+        self.sleeper = sleeper;
+        self
+    }
+    
+    /// Allow up to `max_retries` exec attempts per run
+    pub fn retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries.max(1);
+        self
+    }
+    
+    /// Replay the scripted execs and actions from the start, keeping the recorded calls
+    ///
+    /// Clones share their scripts, so rewinding one rewinds them all.
+    pub fn rewind(&self) {
+        self.execs.lock().next = 0;
+        self.actions.lock().next = 0;
+    }
+    
+    /// Every call recorded so far
+    pub fn calls(&self) -> Vec<MockCall> {
+        self.calls.lock().clone()
+    }
+    
+    /// Number of exec attempts recorded so far
+    pub fn exec_attempts(&self) -> usize {
+        self.calls.lock().iter().filter(|c| matches!(c, MockCall::Exec { .. })).count()
+    }
+    
+    /// Record an attempt and take its scripted outcome
+    fn attempt(&self, prep_res: &Value, attempt: usize) -> Result<Value> {
+        self.calls.lock().push(MockCall::Exec { prep_res: prep_res.clone(), attempt });
+        match self.execs.lock().take() {
+            Some(MockExec::Return(value)) => Ok(value),
+            Some(MockExec::Fail(message)) => Err(Error::NodeExecution(message)),
+            None => Ok(Value::Null),
+        }
+    }
+}
+
+impl Default for MockNode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NodeTrait for MockNode {
+    fn params(&self) -> Arc<ParamMap> {
+        self.base.params()
+    }
+    
+    fn name(&self) -> String {
+        self.base.assigned_name().unwrap_or_else(short_type_name::<Self>)
+    }
+    
+    fn set_name(&self, name: &str) {
+        self.base.set_name(name);
+    }
+    
+    fn hooks(&self) -> NodeHooks {
+        self.base.hooks()
+    }
+    
+    fn set_hooks(&self, hooks: NodeHooks) {
+        self.base.set_hooks(hooks);
+    }
+    
+    fn successors(&self) -> Arc<RwLock<Successors>> {
+        self.base.successors()
+    }
+    
+    fn set_params(&self, params: Arc<ParamMap>) {
+        self.base.set_params(params);
+    }
+    
+    fn add_successor(&self, node: Arc<dyn NodeTrait>, action: &str) -> Result<Arc<dyn NodeTrait>> {
+        self.base.add_successor(node, action)
+    }
+    
+    fn prep(&self, shared: &mut SharedState) -> Result<Value> {
+        self.calls.lock().push(MockCall::Prep(shared.clone()));
+        Ok(self.prep_res.clone())
+    }
+    
+    fn exec(&self, prep_res: &Value) -> Result<Value> {
+        self.attempt(prep_res, 0)
+    }
+    
+    fn post(&self, _shared: &mut SharedState, prep_res: Value, exec_res: Value) -> Result<Action> {
+        self.calls.lock().push(MockCall::Post { prep_res, exec_res });
+        Ok(self.actions.lock().take().unwrap_or_else(|| self.default_action.clone()))
+    }
+    
+    fn _exec(&self, prep_res: &Value) -> Result<Value> {
+        let mut attempt = 0;
+        loop {
+            if !self.delay.is_zero() {
+                self.sleeper.sleep(self.delay);
+            }
+            match self.attempt(prep_res, attempt) {
+                Err(e) if attempt + 1 >= self.max_retries => return Err(e),
+                Err(_) => attempt += 1,
+                res => return res,
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl AsyncNodeTrait for MockNode {
+    async fn prep_async(&self, shared: &mut SharedState) -> Result<Value> {
+        self.prep(shared)
+    }
+    
+    async fn exec_async(&self, prep_res: &Value) -> Result<Value> {
+        self.exec(prep_res)
+    }
+    
+    async fn post_async(&self, shared: &mut SharedState, prep_res: Value, exec_res: Value) -> Result<Action> {
+        self.post(shared, prep_res, exec_res)
+    }
+    
+    async fn _exec_async(&self, prep_res: &Value) -> Result<Value> {
+        let mut attempt = 0;
+        loop {
+            if !self.delay.is_zero() {
+                self.sleeper.sleep_async(self.delay).await;
+            }
+            match self.attempt(prep_res, attempt) {
+                Err(e) if attempt + 1 >= self.max_retries => return Err(e),
+                Err(_) => attempt += 1,
+                res => return res,
+            }
+        }
+    }
+}
+
+/// One node execution recorded by `FlowTestHarness`
+#[derive(Clone, Debug)]
+pub struct TraceStep {
+    /// Name registered for the node, or the node's own name
+    pub node: String,
+    
+    /// Action the node returned
+    pub action: Action,
+    
+    /// Wall time spent in the node
+    pub duration: Duration,
+    
+    /// Error the node failed with
+    pub error: Option<String>,
+    
+    /// Shared state keys the node added, changed or removed, sorted
+    pub writes: Vec<String>,
+}
+
+/// The flow a `FlowTestHarness` runs
+enum Subject {
+    /// A flow run with `run`
+    Sync(Flow),
+    
+    /// An async flow run with `run_async`
+    Async(AsyncFlow),
+}
+
+/// Runs a flow through its traced run, for use in tests
+///
+/// Nodes are reported in the trace under the names registered with `name`. Each step also
+/// records the shared state keys the node changed, found by diffing the state around it, and
+/// every read, write and removal a node makes is logged in the result's `accesses`.
+/// Steps of nested flows are included; batch iterations are not.
+pub struct FlowTestHarness {
+    /// Flow under test
+    subject: Subject,
+    
+    /// Names of registered nodes, keyed by node address
+    names: HashMap<*const (), String>,
+    
+    /// Initial shared state
+    state: SharedState,
+    
+    /// Params overriding the flow's own
+    params: Option<ParamMap>,
+}
+
+impl FlowTestHarness {
+    /// Create a harness for a flow with an empty initial state
+    pub fn new(flow: Flow) -> Self {
+        Self::with_subject(Subject::Sync(flow))
+    }
+    
+    /// Create a harness for an async flow with an empty initial state, to be run with `run_async`
+    pub fn new_async(flow: AsyncFlow) -> Self {
+        Self::with_subject(Subject::Async(flow))
+    }
+    
+    fn with_subject(subject: Subject) -> Self {
+        Self {
+            subject,
+            names: HashMap::new(),
+            state: SharedState::new(),
+            params: None,
+        }
+    }
+    
+    /// Register the name a node is reported under, naming the node itself
+    pub fn name(mut self, node: &Arc<dyn NodeTrait>, name: &str) -> Self {
+        node.set_name(name);
+        self.names.insert(Arc::as_ptr(node) as *const (), name.to_string());
+        self
+    }
+    
+    /// Set the initial shared state from a JSON object
+    pub fn with_state(mut self, state: Value) -> Self {
+        self.state = object_entries(state, "with_state").into();
+        self
+    }
+    
+    /// Set the params handed to the start node from a JSON object
+    pub fn with_params(mut self, params: Value) -> Self {
+        self.params = Some(object_entries(params, "with_params"));
+        self
+    }
+    
+    /// Run the flow, capturing its trace, final state and error
+    ///
+    /// Panics for a harness made with `new_async`; use `run_async` for those.
+    pub fn run(self) -> FlowTestResult {
+        let Subject::Sync(flow) = &self.subject else {
+            panic!("FlowTestHarness::run can't run an AsyncFlow; use run_async");
+        };
+        let mut state = self.state.clone();
+        let params = self.params.clone().map(Arc::new).unwrap_or_else(|| flow.params());
+        let (outcome, trace, accesses) = trace::run_recording(true, || flow.run_with_params(params, &mut state));
+        self.finish(outcome, trace, accesses, state)
+    }
+    
+    /// Run the flow asynchronously, capturing its trace, final state and error
+    pub async fn run_async(self) -> FlowTestResult {
+        let mut state = self.state.clone();
+        let (outcome, trace, accesses) = match &self.subject {
+            Subject::Sync(flow) => {
+                let params = self.params.clone().map(Arc::new).unwrap_or_else(|| flow.params());
+                trace::run_recording(true, || flow.run_with_params(params, &mut state))
+            },
+            Subject::Async(flow) => {
+                let params = self.params.clone().map(Arc::new).unwrap_or_else(|| flow.params());
+                trace::run_async_recording(true, flow.run_async_with_params(params, &mut state)).await
+            },
+        };
+        self.finish(outcome, trace, accesses, state)
+    }
+    
+    /// Turn a traced run into its result, taking the final action from the last top-level step
+    fn finish(self, outcome: Result<Action>, trace: FlowTrace, accesses: Vec<StateAccess>, state: SharedState) -> FlowTestResult {
+        let steps: Vec<TraceEntry> = trace.into_iter().filter(|entry| entry.batch_params.is_none()).collect();
+        
+        // Steps of nested flows start and end within the step of their flow, so the last step
+        // to end after every step before it belongs to the outermost flow
+        let mut last: Option<&TraceEntry> = None;
+        for step in &steps {
+            if last.is_none_or(|last| step.started_at + step.duration > last.started_at + last.duration) {
+                last = Some(step);
+            }
+        }
+        let action = last.and_then(|step| step.action_taken.clone());
+        
+        FlowTestResult {
+            trace: steps
+                .into_iter()
+                .map(|entry| TraceStep {
+                    node: entry.node_name,
+                    action: entry.action_taken,
+                    duration: entry.duration,
+                    error: entry.error,
+                    writes: entry.writes,
+                })
+                .collect(),
+            accesses,
+            initial: self.state,
+            state,
+            action: if outcome.is_ok() { action } else { None },
+            error: outcome.err().map(|e| e.to_string()),
+        }
+    }
+    
+    /// Describe the flow's wiring as one `from --action--> to` line per edge
+    ///
+    /// Conditional edges follow the action edges of their node as `from --if description--> to`.
+    /// Unregistered nodes are labeled `#n`, numbered breadth-first from the start node.
+    pub fn topology(&self) -> String {
+        let graph = match &self.subject {
+            Subject::Sync(flow) => flow.nodes(),
+            Subject::Async(flow) => flow.nodes(),
+        };
+        let mut lines = Vec::new();
+        for (i, entry) in graph.iter().enumerate() {
+            let conditional = entry.conditional.iter().map(|(description, next)| {
+                let label = match description {
+                    Some(description) => format!("if {}", description),
+                    None => "if".to_string(),
+                };
+                (label, *next)
+            });
+            for (action, next) in entry.edges.iter().map(|(action, next)| (action.to_string(), *next)).chain(conditional) {
+                lines.push(format!("{} --{}--> {}", self.label(&entry.node, i), action, self.label(&graph[next].node, next)));
+            }
+        }
+        
+        if lines.is_empty() {
+            lines.push(self.label(&graph[0].node, 0));
+        }
+        lines.join("\n")
+    }
+    
+    fn label(&self, node: &Arc<dyn NodeTrait>, idx: usize) -> String {
+        self.names
+            .get(&(Arc::as_ptr(node) as *const ()))
+            .cloned()
+            .unwrap_or_else(|| format!("{}#{}", node.name(), idx))
+    }
+}
+
+/// Convert a JSON object into a map, panicking on anything else
+fn object_entries(value: Value, caller: &str) -> HashMap<String, Value> {
+    match value {
+        Value::Object(map) => map.into_iter().collect(),
+        other => panic!("{} expects a JSON object, got {}", caller, other),
+    }
+}
+
+/// The outcome of a `FlowTestHarness` run, with fluent assertions
+///
+/// A failed assertion panics with the full trace and the shared state diff.
+#[derive(Debug)]
+pub struct FlowTestResult {
+    /// Every node execution, in order
+    pub trace: Vec<TraceStep>,
+    
+    /// Every shared state access made by a node, in order
+    pub accesses: Vec<StateAccess>,
+    
+    /// Shared state before the run
+    pub initial: SharedState,
+    
+    /// Shared state after the run
+    pub state: SharedState,
+    
+    /// Action returned by the last node
+    pub action: Action,
+    
+    /// Error the run failed with
+    pub error: Option<String>,
+}
+
+impl FlowTestResult {
+    /// Names of the visited nodes, in order
+    pub fn visited(&self) -> Vec<&str> {
+        self.trace.iter().map(|step| step.node.as_str()).collect()
+    }
+    
+    /// Names of the nodes that wrote `key`, once per write, in order
+    ///
+    /// Storing a value equal to the one already there, updating in place and removing are all writes.
+    pub fn writes_to(&self, key: &str) -> Vec<&str> {
+        self.accesses
+            .iter()
+            .filter(|access| access.kind.is_write() && access.key == key)
+            .map(|access| access.node.as_str())
+            .collect()
+    }
+    
+    /// Keys written by every execution of a node, sorted
+    pub fn writes_by(&self, node: &str) -> Vec<&str> {
+        self.keys_accessed_by(node, true)
+    }
+    
+    /// Keys read by every execution of a node, sorted
+    pub fn reads_by(&self, node: &str) -> Vec<&str> {
+        self.keys_accessed_by(node, false)
+    }
+    
+    /// Keys a node wrote, or read when `writes` is unset, sorted
+    fn keys_accessed_by(&self, node: &str, writes: bool) -> Vec<&str> {
+        let mut keys: Vec<&str> = self
+            .accesses
+            .iter()
+            .filter(|access| access.node == node && access.kind.is_write() == writes)
+            .map(|access| access.key.as_str())
+            .collect();
+        keys.sort();
+        keys.dedup();
+        keys
+    }
+    
+    /// Assert `key` was written exactly once, by `node`
+    pub fn assert_written_once_by(&self, key: &str, node: &str) -> &Self {
+        let writers = self.writes_to(key);
+        if writers != [node] {
+            self.fail(&format!("expected '{}' to be written once by '{}', writers were {:?}", key, node, writers));
+        }
+        self
+    }
+    
+    /// Assert no node wrote a key starting with `prefix`
+    pub fn assert_no_writes_to_prefix(&self, prefix: &str) -> &Self {
+        let offending: Vec<String> = self
+            .accesses
+            .iter()
+            .filter(|access| access.kind.is_write() && access.key.starts_with(prefix))
+            .map(|access| format!("{} by {}", access.key, access.node))
+            .collect();
+        if !offending.is_empty() {
+            self.fail(&format!("expected no writes under '{}', got {:?}", prefix, offending));
+        }
+        self
+    }
+    
+    /// Assert the run finished without error
+    pub fn assert_ok(&self) -> &Self {
+        if let Some(error) = &self.error {
+            self.fail(&format!("expected the flow to succeed, but it failed: {}", error));
+        }
+        self
+    }
+    
+    /// Assert the run failed with an error containing `needle`
+    pub fn assert_error_contains(&self, needle: &str) -> &Self {
+        match &self.error {
+            Some(error) if error.contains(needle) => {},
+            Some(error) => self.fail(&format!("expected an error containing '{}', got: {}", needle, error)),
+            None => self.fail(&format!("expected an error containing '{}', but the flow succeeded", needle)),
+        }
+        self
+    }
+    
+    /// Assert the exact sequence of visited nodes
+    pub fn assert_visited(&self, expected: &[&str]) -> &Self {
+        if self.visited() != expected {
+            self.fail(&format!("expected visits {:?}, got {:?}", expected, self.visited()));
+        }
+        self
+    }
+    
+    /// Assert a node never ran
+    pub fn assert_not_visited(&self, node: &str) -> &Self {
+        if self.visited().contains(&node) {
+            self.fail(&format!("expected '{}' not to be visited", node));
+        }
+        self
+    }
+    
+    /// Assert the action returned by the last node
+    pub fn assert_action(&self, expected: &str) -> &Self {
+        if self.action.as_deref() != Some(expected) {
+            self.fail(&format!("expected final action '{}', got {:?}", expected, self.action));
+        }
+        self
+    }
+    
+    /// Assert the final value of a shared state key
+    pub fn assert_store_eq(&self, key: &str, expected: Value) -> &Self {
+        if self.state.get(key) != Some(&expected) {
+            self.fail(&format!("expected '{}' to be {}, got {:?}", key, expected, self.state.get(key)));
+        }
+        self
+    }
+    
+    /// Assert every execution of a node took less than `ms` milliseconds
+    pub fn assert_node_duration_under(&self, node: &str, ms: u64) -> &Self {
+        let limit = Duration::from_millis(ms);
+        let steps: Vec<&TraceStep> = self.trace.iter().filter(|step| step.node == node).collect();
+        if steps.is_empty() {
+            self.fail(&format!("expected '{}' to be visited", node));
+        }
+        if let Some(step) = steps.iter().find(|step| step.duration >= limit) {
+            self.fail(&format!("expected '{}' to take under {}ms, took {:?}", node, ms, step.duration));
+        }
+        self
+    }
+    
+    /// Render the trace and final state without volatile fields, for snapshot comparison
+    pub fn normalized(&self, options: &SnapshotOptions) -> String {
+        let mut out = String::from("--- trace ---\n");
+        for (i, step) in self.trace.iter().enumerate() {
+            let _ = match &step.error {
+                Some(error) => writeln!(out, "{}. {} failed: {} wrote {:?}", i + 1, step.node, error, step.writes),
+                None => writeln!(out, "{}. {} -> {:?} wrote {:?}", i + 1, step.node, step.action, step.writes),
+            };
+        }
+        
+        out.push_str("--- state ---\n");
+        let mut keys: Vec<&String> = self.state.keys().collect();
+        keys.sort();
+        for key in keys {
+            let _ = if options.redacts(key) {
+                writeln!(out, "{}: \"[redacted]\"", key)
+            } else {
+                writeln!(out, "{}: {}", key, self.state[key])
+            };
+        }
+        out
+    }
+    
+    /// Render the trace and the shared state diff
+    pub fn report(&self) -> String {
+        let mut out = String::from("--- trace ---\n");
+        for (i, step) in self.trace.iter().enumerate() {
+            let _ = match &step.error {
+                Some(error) => writeln!(out, "{}. {} failed: {} ({:?})", i + 1, step.node, error, step.duration),
+                None => writeln!(out, "{}. {} -> {:?} ({:?}) wrote {:?}", i + 1, step.node, step.action, step.duration, step.writes),
+            };
+        }
+        
+        out.push_str("--- store diff ---\n");
+        let mut keys: Vec<&String> = self.initial.keys().chain(self.state.keys()).collect();
+        keys.sort();
+        keys.dedup();
+        for key in keys {
+            let _ = match (self.initial.get(key), self.state.get(key)) {
+                (None, Some(after)) => writeln!(out, "+ {}: {}", key, after),
+                (Some(_), None) => writeln!(out, "- {}", key),
+                (Some(before), Some(after)) if before != after => writeln!(out, "~ {}: {} -> {}", key, before, after),
+                _ => Ok(()),
+            };
+        }
+        out
+    }
+    
+    fn fail(&self, message: &str) -> ! {
+        panic!("{}\n{}", message, self.report())
+    }
+}
+
+/// Which failures a `FaultyNode` injects into exec attempts
+#[derive(Clone, Debug)]
+pub struct FaultPlan {
+    /// Number of leading attempts that fail
+    fail_first: usize,
+    
+    /// Fail every k-th attempt
+    fail_every: Option<usize>,
+    
+    /// Latency added before every attempt
+    latency: Duration,
+    
+    /// Panic on the first attempt that is not otherwise failed
+    panic_once: bool,
+    
+    /// Message of injected errors
+    message: String,
+    
+    /// Keep counting attempts across runs instead of restarting each run
+    persist: bool,
+}
+
+impl FaultPlan {
+    /// A plan that injects nothing
+    pub fn new() -> Self {
+        Self {
+            fail_first: 0,
+            fail_every: None,
+            latency: Duration::ZERO,
+            panic_once: false,
+            message: "Injected fault".to_string(),
+            persist: false,
+        }
+    }
+    
+    /// Fail the first `n` attempts
+    pub fn fail_first(mut self, n: usize) -> Self {
+        self.fail_first = n;
+        self
+    }
+    
+    /// Fail every `k`-th attempt
+    pub fn fail_every(mut self, k: usize) -> Self {
+        self.fail_every = Some(k.max(1));
+        self
+    }
+    
+    /// Add `latency` before every attempt, whether it fails or not
+    pub fn latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+    
+    /// Panic once, on the first attempt the plan would otherwise let through
+    pub fn panic_once(mut self) -> Self {
+        self.panic_once = true;
+        self
+    }
+    
+    /// Set the message of injected errors
+    pub fn message(mut self, message: &str) -> Self {
+        self.message = message.to_string();
+        self
+    }
+    
+    /// Keep counting attempts across runs instead of restarting each run
+    pub fn persist_across_runs(mut self) -> Self {
+        self.persist = true;
+        self
+    }
+}
+
+impl Default for FaultPlan {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A fault injected by a `FaultyNode`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    /// The attempt returned an error
+    Error { attempt: usize },
+    
+    /// The attempt panicked
+    Panic { attempt: usize },
+}
+
+/// A wrapper injecting the failures described by a `FaultPlan` into a node's exec attempts
+///
+/// The wrapper drives the attempts itself so that every retry can be injected. Set the retry
+/// budget with `retries`; the inner node's own retry loop is not used.
+pub struct FaultyNode<N: ?Sized = dyn NodeTrait> {
+    /// The wrapped node
+    inner: Arc<N>,
+    
+    /// Faults to inject
+    plan: FaultPlan,
+    
+    /// Base node holding the wrapper's successors
+    base: BaseNode,
+    
+    /// Maximum number of attempts
+    max_retries: usize,
+    
+    /// Wait time between attempts in milliseconds
+    wait: u64,
+    
+    /// Attempts counted against the plan
+    attempts: Arc<AtomicUsize>,
+    
+    /// Whether the planned panic has happened
+    panicked: Arc<AtomicBool>,
+    
+    /// Every fault injected so far
+    injected: Arc<Mutex<Vec<Fault>>>,
+    
+    /// Source of the latency and the waits between attempts
+    sleeper: Arc<dyn Sleeper>,
+}
+
+impl<N: ?Sized> FaultyNode<N> {
+    /// Wrap a node so its exec attempts follow the plan
+    pub fn wrap(inner: Arc<N>, plan: FaultPlan) -> Self {
+        Self {
+            inner,
+            plan,
+            base: BaseNode::new(),
+            max_retries: 1,
+            wait: 0,
+            attempts: Arc::new(AtomicUsize::new(0)),
+            panicked: Arc::new(AtomicBool::new(false)),
+            injected: Arc::new(Mutex::new(Vec::new())),
+            sleeper: sleeper::real(),
+        }
+    }
+    
+    /// Allow up to `max_retries` attempts, waiting `wait` milliseconds between them
+    pub fn retries(mut self, max_retries: usize, wait: u64) -> Self {
+        self.max_retries = max_retries.max(1);
+        self.wait = wait;
+        self
+    }
+    
+    /// Route the latency and the waits between attempts through `sleeper`
+    pub fn with_sleeper(mut self, sleeper: Arc<dyn Sleeper>) -> Self {
+        self.sleeper = sleeper;
+        self
+    }
+    
+    /// The wrapped node
+    pub fn inner(&self) -> &Arc<N> {
+        &self.inner
+    }
+    
+    /// Every fault injected so far
+    pub fn injected(&self) -> Vec<Fault> {
+        self.injected.lock().clone()
+    }
+    
+    /// Number of attempts counted against the plan
+    pub fn attempts(&self) -> usize {
+        self.attempts.load(Ordering::SeqCst)
+    }
+    
+    /// Restart the plan's counters unless it persists across runs
+    fn start_run(&self) {
+        if !self.plan.persist {
+            self.attempts.store(0, Ordering::SeqCst);
+            self.panicked.store(false, Ordering::SeqCst);
+        }
+    }
+    
+    /// The async interface of the wrapped node
+    fn inner_async(&self) -> &dyn AsyncNodeTrait
+    where
+        N: NodeTrait,
+    {
+        self.inner.as_async().expect("FaultyNode runs asynchronously only around an async node")
+    }
+    
+    /// Count an attempt and apply the plan to it
+    fn inject(&self) -> Result<()> {
+        let attempt = self.attempts.fetch_add(1, Ordering::SeqCst);
+        let every = self.plan.fail_every.map(|k| (attempt + 1).is_multiple_of(k)).unwrap_or(false);
+        
+        if attempt < self.plan.fail_first || every {
+            self.injected.lock().push(Fault::Error { attempt });
+            return Err(Error::NodeExecution(self.plan.message.clone()));
+        }
+        if self.plan.panic_once && !self.panicked.swap(true, Ordering::SeqCst) {
+            self.injected.lock().push(Fault::Panic { attempt });
+            panic!("{}", self.plan.message);
+        }
+        Ok(())
+    }
+}
+
+impl<N: NodeTrait + ?Sized> NodeTrait for FaultyNode<N> {
+    fn params(&self) -> Arc<ParamMap> {
+        self.inner.params()
+    }
+    
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+    
+    fn set_name(&self, name: &str) {
+        self.inner.set_name(name);
+    }
+    
+    fn hooks(&self) -> NodeHooks {
+        self.inner.hooks()
+    }
+    
+    fn set_hooks(&self, hooks: NodeHooks) {
+        self.inner.set_hooks(hooks);
+    }
+    
+    fn required_params(&self) -> &[ParamSpec] {
+        self.inner.required_params()
+    }
+    
+    fn declared_actions(&self) -> &[String] {
+        self.inner.declared_actions()
+    }
+    
+    fn reads(&self) -> &[KeySpec] {
+        self.inner.reads()
+    }
+    
+    fn writes(&self) -> &[KeySpec] {
+        self.inner.writes()
+    }
+    
+    fn exec_dry_run(&self, prep_res: &Value) -> Result<Value> {
+        self.inner.exec_dry_run(prep_res)
+    }
+    
+    fn successors(&self) -> Arc<RwLock<Successors>> {
+        self.base.successors()
+    }
+    
+    fn set_params(&self, params: Arc<ParamMap>) {
+        self.inner.set_params(params);
+    }
+    
+    fn add_successor(&self, node: Arc<dyn NodeTrait>, action: &str) -> Result<Arc<dyn NodeTrait>> {
+        self.base.add_successor(node, action)
+    }
+    
+    fn prep(&self, shared: &mut SharedState) -> Result<Value> {
+        self.inner.prep(shared)
+    }
+    
+    fn exec(&self, prep_res: &Value) -> Result<Value> {
+        self.inner.exec(prep_res)
+    }
+    
+    fn post(&self, shared: &mut SharedState, prep_res: Value, exec_res: Value) -> Result<Action> {
+        self.inner.post(shared, prep_res, exec_res)
+    }
+    
+    fn branch_actions(&self) -> &[String] {
+        self.inner.branch_actions()
+    }
+    
+    fn as_async(&self) -> Option<&dyn AsyncNodeTrait> {
+        self.inner.as_async().map(|_| self as &dyn AsyncNodeTrait)
+    }
+    
+    fn _exec(&self, prep_res: &Value) -> Result<Value> {
+        for retry in 0..self.max_retries {
+            if !self.plan.latency.is_zero() {
+                self.sleeper.sleep(self.plan.latency);
+            }
+            
+            match retry::guard_attempt(|| self.inject().and_then(|_| self.inner.exec(prep_res))) {
+                Ok(res) => return Ok(res),
+                Err(e) => {
+                    if retry == self.max_retries - 1 {
+                        return Err(e);
+                    }
+                    
+                    if self.wait > 0 {
+                        self.sleeper.sleep(Duration::from_millis(self.wait));
+                    }
+                }
+            }
+        }
+        
+        Err(Error::NodeExecution("Max retries exceeded".into()))
+    }
+    
+    fn _run(&self, shared: &mut SharedState) -> Result<Action> {
+        self.start_run();
+        let prep_res = self.prep(shared)?;
+        let hooks = NodeHooks::effective(self.hooks());
+        if dry_run::is_active() {
+            let exec_res = hooks.observe(|| self.name(), &prep_res, || self.exec_dry_run(&prep_res))?;
+            let action = self.post(shared, prep_res.clone(), exec_res)?;
+            dry_run::record(self.name(), prep_res, &action);
+            return Ok(action);
+        }
+        let exec_res = hooks.observe(|| self.name(), &prep_res, || self._exec(&prep_res))?;
+        self.post(shared, prep_res, exec_res)
+    }
+}
+
+/// The async path of a wrapper around an async node, which `as_async` hands to an `AsyncFlow`
+///
+/// Panics when the wrapped node has no async interface.
+#[async_trait]
+impl<N: NodeTrait + ?Sized> AsyncNodeTrait for FaultyNode<N> {
+    async fn prep_async(&self, shared: &mut SharedState) -> Result<Value> {
+        self.inner_async().prep_async(shared).await
+    }
+    
+    async fn exec_async(&self, prep_res: &Value) -> Result<Value> {
+        self.inner_async().exec_async(prep_res).await
+    }
+    
+    async fn post_async(&self, shared: &mut SharedState, prep_res: Value, exec_res: Value) -> Result<Action> {
+        self.inner_async().post_async(shared, prep_res, exec_res).await
+    }
+    
+    async fn exec_fallback_async(&self, prep_res: &Value, error: Error) -> Result<Value> {
+        self.inner_async().exec_fallback_async(prep_res, error).await
+    }
+    
+    async fn exec_fallback_async_ctx(&self, prep_res: &Value, error: Error, ctx: FallbackContext) -> Result<Value> {
+        self.inner_async().exec_fallback_async_ctx(prep_res, error, ctx).await
+    }
+    
+    async fn _exec_async(&self, prep_res: &Value) -> Result<Value> {
+        for retry in 0..self.max_retries {
+            if !self.plan.latency.is_zero() {
+                self.sleeper.sleep_async(self.plan.latency).await;
+            }
+            
+            let result = retry::guard_attempt_async(async {
+                self.inject()?;
+                self.inner_async().exec_async(prep_res).await
+            }).await;
+            
+            match result {
+                Ok(res) => return Ok(res),
+                Err(e) => {
+                    if retry == self.max_retries - 1 {
+                        let ctx = FallbackContext::new(retry, self.max_retries, self.name());
+                        return self.inner_async().exec_fallback_async_ctx(prep_res, e, ctx).await;
+                    }
+                    
+                    if self.wait > 0 {
+                        self.sleeper.sleep_async(Duration::from_millis(self.wait)).await;
+                    }
+                }
+            }
+        }
+        
+        Err(Error::NodeExecution("Max retries exceeded".into()))
+    }
+    
+    async fn _run_async(&self, shared: &mut SharedState) -> Result<Action> {
+        self.start_run();
+        let prep_res = self.prep_async(shared).await?;
+        let hooks = NodeHooks::effective(self.hooks());
+        if dry_run::is_active() {
+            let exec_res = hooks.observe(|| self.name(), &prep_res, || self.exec_dry_run(&prep_res))?;
+            let action = self.post_async(shared, prep_res.clone(), exec_res).await?;
+            dry_run::record(self.name(), prep_res, &action);
+            return Ok(action);
+        }
+        let exec_res = hooks.observe_async(|| self.name(), &prep_res, self._exec_async(&prep_res)).await?;
+        self.post_async(shared, prep_res, exec_res).await
+    }
+}
+
+/// Environment variable that rewrites snapshots instead of comparing them
+pub const UPDATE_SNAPSHOTS_ENV: &str = "MINLLM_UPDATE_SNAPSHOTS";
+
+/// Environment variable overriding the snapshot directory
+pub const SNAPSHOT_DIR_ENV: &str = "MINLLM_SNAPSHOT_DIR";
+
+/// Normalization applied to a trace before it is compared with a snapshot
+///
+/// Durations are always dropped. Steps keep their node, action, error and written keys,
+/// and the final state is listed with sorted keys. Redacted keys keep their name, but their
+/// value is replaced by `"[redacted]"`.
+#[derive(Clone, Debug, Default)]
+pub struct SnapshotOptions {
+    /// Keys whose values are redacted
+    redact_keys: Vec<String>,
+    
+    /// Key prefixes whose values are redacted
+    redact_prefixes: Vec<String>,
+}
+
+impl SnapshotOptions {
+    /// Options with no redactions
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Redact the value of a shared state key
+    pub fn redact_key(mut self, key: &str) -> Self {
+        self.redact_keys.push(key.to_string());
+        self
+    }
+    
+    /// Redact the values of every key starting with `prefix`
+    pub fn redact_prefix(mut self, prefix: &str) -> Self {
+        self.redact_prefixes.push(prefix.to_string());
+        self
+    }
+    
+    fn redacts(&self, key: &str) -> bool {
+        self.redact_keys.iter().any(|k| k == key) || self.redact_prefixes.iter().any(|p| key.starts_with(p.as_str()))
+    }
+}
+
+/// Compare a run's normalized trace with the snapshot `{name}.trace.snap`
+pub fn assert_trace_snapshot(name: &str, result: &FlowTestResult) {
+    assert_trace_snapshot_with(name, result, &SnapshotOptions::default());
+}
+
+/// Compare a run's trace, normalized with `options`, with the snapshot `{name}.trace.snap`
+pub fn assert_trace_snapshot_with(name: &str, result: &FlowTestResult, options: &SnapshotOptions) {
+    assert_snapshot(&format!("{}.trace", name), &result.normalized(options));
+}
+
+/// Compare a flow's wiring with the snapshot `{name}.topology.snap`
+pub fn assert_topology_snapshot(name: &str, harness: &FlowTestHarness) {
+    assert_snapshot(&format!("{}.topology", name), &harness.topology());
+}
+
+/// Directory holding snapshots: `MINLLM_SNAPSHOT_DIR`, else `tests/snapshots` in the crate under test
+fn snapshot_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os(SNAPSHOT_DIR_ENV) {
+        return PathBuf::from(dir);
+    }
+    let root = std::env::var_os("CARGO_MANIFEST_DIR").map(PathBuf::from).unwrap_or_default();
+    root.join("tests").join("snapshots")
+}
+
+/// Compare `actual` with a stored snapshot, writing it when missing or when updating
+fn assert_snapshot(name: &str, actual: &str) {
+    let path = snapshot_dir().join(format!("{}.snap", name));
+    let update = std::env::var_os(UPDATE_SNAPSHOTS_ENV).is_some();
+    
+    match fs::read_to_string(&path) {
+        Ok(expected) if !update => {
+            if expected != actual {
+                panic!(
+                    "Snapshot '{}' does not match {} (set {}=1 to update)\n{}",
+                    name,
+                    path.display(),
+                    UPDATE_SNAPSHOTS_ENV,
+                    line_diff(&expected, actual)
+                );
+            }
+        },
+        _ => {
+            if let Some(parent) = path.parent() {
+                let _ = fs::create_dir_all(parent);
+            }
+            fs::write(&path, actual).unwrap_or_else(|e| panic!("Failed to write snapshot {}: {}", path.display(), e));
+        },
+    }
+}
+
+/// A line diff marking lines only in `expected` with `-` and lines only in `actual` with `+`
+fn line_diff(expected: &str, actual: &str) -> String {
+    let old: Vec<&str> = expected.lines().collect();
+    let new: Vec<&str> = actual.lines().collect();
+    
+    // Longest common subsequence table, filled from the end
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    
+    let mut out = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            let _ = writeln!(out, "  {}", old[i]);
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            let _ = writeln!(out, "- {}", old[i]);
+            i += 1;
+        } else {
+            let _ = writeln!(out, "+ {}", new[j]);
+            j += 1;
+        }
+    }
+    out
+}
+
+/// Sleeper on tokio's clock that tracks its pending wakes, for paused-clock tests
+///
+/// Sync sleeps are recorded but return immediately, since a blocking thread cannot wait on a paused clock.
+#[derive(Debug, Default)]
+pub struct VirtualSleeper {
+    /// Requested durations, in order
+    requested: Mutex<Vec<Duration>>,
+    
+    /// Deadlines of the async sleeps still waiting
+    pending: Mutex<Vec<tokio::time::Instant>>,
+}
+
+impl VirtualSleeper {
+    /// Create a sleeper with an empty record
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Every duration requested so far, in order
+    pub fn requested(&self) -> Vec<Duration> {
+        self.requested.lock().clone()
+    }
+    
+    /// Sum of the requested durations
+    pub fn total(&self) -> Duration {
+        self.requested.lock().iter().sum()
+    }
+    
+    /// Earliest deadline among the async sleeps still waiting
+    pub fn next_wake(&self) -> Option<tokio::time::Instant> {
+        self.pending.lock().iter().min().copied()
+    }
+}
+
+#[async_trait]
+impl Sleeper for VirtualSleeper {
+    fn sleep(&self, duration: Duration) {
+        self.requested.lock().push(duration);
+    }
+    
+    async fn sleep_async(&self, duration: Duration) {
+        let deadline = tokio::time::Instant::now() + duration;
+        self.requested.lock().push(duration);
+        self.pending.lock().push(deadline);
+        tokio::time::sleep_until(deadline).await;
+        
+        let mut pending = self.pending.lock();
+        if let Some(index) = pending.iter().position(|d| *d == deadline) {
+            pending.swap_remove(index);
+        }
+    }
+}
+
+/// Create a harness whose runtime starts with tokio's clock paused
+pub fn async_harness() -> AsyncHarness {
+    AsyncHarness::new()
+}
+
+/// Runs async flows on a paused clock that jumps ahead whenever every task is idle
+///
+/// Timeouts, backoff and throttling complete in order but without waiting in real time.
+/// Hand `sleeper()` to nodes through `with_sleeper` so their retry waits use the same clock.
+pub struct AsyncHarness {
+    /// Single-threaded runtime with the clock paused
+    runtime: tokio::runtime::Runtime,
+    
+    /// Sleeper shared with the nodes under test
+    sleeper: Arc<VirtualSleeper>,
+}
+
+impl AsyncHarness {
+    /// Create a harness with a fresh paused runtime
+    pub fn new() -> Self {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .start_paused(true)
+            .build()
+            .expect("failed to build paused runtime");
+        
+        Self {
+            runtime,
+            sleeper: Arc::new(VirtualSleeper::new()),
+        }
+    }
+    
+    /// The sleeper to give nodes under test
+    pub fn sleeper(&self) -> Arc<dyn Sleeper> {
+        self.sleeper.clone()
+    }
+    
+    /// The sleeper with its recorded durations
+    pub fn virtual_sleeper(&self) -> &Arc<VirtualSleeper> {
+        &self.sleeper
+    }
+    
+    /// Run a future on the paused runtime
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+    
+    /// Run a flow to completion on the paused runtime
+    pub fn run_flow(&self, flow: &AsyncFlow, shared: &mut SharedState) -> AsyncRunResult {
+        let slept_before = self.sleeper.total();
+        let real_start = Instant::now();
+        
+        let (result, elapsed) = self.block_on(async {
+            let start = tokio::time::Instant::now();
+            let result = flow.run_async(shared).await;
+            (result, start.elapsed())
+        });
+        
+        AsyncRunResult {
+            result,
+            elapsed,
+            slept: self.sleeper.total() - slept_before,
+            real: real_start.elapsed(),
+        }
+    }
+    
+    /// Manual control over the paused clock, for use inside `block_on`
+    pub fn time_travel(&self) -> TimeTravel {
+        TimeTravel {
+            sleeper: self.sleeper.clone(),
+            start: self.block_on(async { tokio::time::Instant::now() }),
+        }
+    }
+}
+
+impl Default for AsyncHarness {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Outcome of a flow run on a paused clock
+#[derive(Debug)]
+pub struct AsyncRunResult {
+    /// Final action, or the error that stopped the flow
+    pub result: Result<Action>,
+    
+    /// Time that passed on the paused clock
+    pub elapsed: Duration,
+    
+    /// Sum of the waits requested through the harness sleeper
+    pub slept: Duration,
+    
+    /// Wall-clock time the run took
+    pub real: Duration,
+}
+
+impl AsyncRunResult {
+    /// Assert the flow finished without an error
+    pub fn assert_ok(&self) -> &Self {
+        if let Err(e) = &self.result {
+            panic!("expected the flow to succeed, got: {}", e);
+        }
+        self
+    }
+    
+    /// Assert the flow failed with an error containing `needle`
+    pub fn assert_error_contains(&self, needle: &str) -> &Self {
+        match &self.result {
+            Ok(action) => panic!("expected an error containing '{}', flow returned {:?}", needle, action),
+            Err(e) if !e.to_string().contains(needle) => {
+                panic!("expected an error containing '{}', got: {}", needle, e)
+            }
+            Err(_) => self,
+        }
+    }
+    
+    /// Assert the waits requested through the harness sleeper sum to `expected`
+    pub fn assert_slept_total(&self, expected: Duration) -> &Self {
+        assert_eq!(self.slept, expected, "flow slept {:?}, expected {:?}", self.slept, expected);
+        self
+    }
+    
+    /// Assert the paused clock advanced by exactly `expected`
+    pub fn assert_elapsed(&self, expected: Duration) -> &Self {
+        assert_eq!(self.elapsed, expected, "clock advanced {:?}, expected {:?}", self.elapsed, expected);
+        self
+    }
+    
+    /// Assert the run took less than `ms` milliseconds of real time
+    pub fn assert_real_under(&self, ms: u64) -> &Self {
+        assert!(
+            self.real < Duration::from_millis(ms),
+            "run took {:?} of real time, expected under {}ms",
+            self.real,
+            ms
+        );
+        self
+    }
+}
+
+/// Steps a paused clock forward by hand
+///
+/// Obtained from `AsyncHarness::time_travel` and used inside `block_on`, alongside the tasks under test.
+pub struct TimeTravel {
+    /// Sleeper whose pending wakes can be jumped to
+    sleeper: Arc<VirtualSleeper>,
+    
+    /// Clock reading when the guard was created
+    start: tokio::time::Instant,
+}
+
+impl TimeTravel {
+    /// Advance the clock by `duration`, waking every timer that falls due
+    pub async fn advance(&self, duration: Duration) {
+        tokio::time::advance(duration).await;
+    }
+    
+    /// Advance to the earliest pending sleeper wake, returning how far the clock moved
+    ///
+    /// Yields first so freshly spawned tasks can register their sleeps.
+    pub async fn next_wake(&self) -> Option<Duration> {
+        tokio::task::yield_now().await;
+        let now = tokio::time::Instant::now();
+        let wake = self.sleeper.next_wake()?;
+        let step = wake.saturating_duration_since(now);
+        tokio::time::advance(step).await;
+        Some(step)
+    }
+    
+    /// Time the clock has moved since the guard was created
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
\ No newline at end of file
@@ -0,0 +1,225 @@
+use std::future::Future;
+use std::mem;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::base::{Action, ParamMap, SharedState};
+use crate::error::{Error, Result};
+
+tokio::task_local! {
+    /// Trace of the flow run the current task belongs to, if it is traced
+    static TRACE: Trace;
+}
+
+/// State of a traced run
+struct Trace {
+    /// When the run started
+    started: Instant,
+    
+    /// Entries recorded so far, in the order they finished
+    entries: Arc<Mutex<Vec<TraceEntry>>>,
+    
+    /// Whether each step records the keys it changed, and nodes the state accesses they make
+    writes: bool,
+    
+    /// Shared state accesses recorded so far, in the order they were made
+    accesses: Arc<Mutex<Vec<StateAccess>>>,
+}
+
+impl Trace {
+    /// A run starting now
+    fn new(writes: bool) -> Self {
+        Self {
+            started: Instant::now(),
+            entries: Arc::new(Mutex::new(Vec::new())),
+            writes,
+            accesses: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+}
+
+/// The start of a traced step
+pub(crate) struct Started {
+    /// When the step started
+    at: Instant,
+    
+    /// Shared state before the step, when the run records writes
+    before: Option<SharedState>,
+}
+
+/// A node step or batch iteration of a traced run
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TraceEntry {
+    /// Name of the node, or of the batch flow for a batch iteration
+    pub node_name: String,
+    
+    /// Action the step returned, `None` for failed steps and batch iterations
+    pub action_taken: Action,
+    
+    /// Time from the start of the run to the start of the step
+    pub started_at: Duration,
+    
+    /// Time the step took
+    pub duration: Duration,
+    
+    /// Error the step failed with, if it failed
+    pub error: Option<String>,
+    
+    /// Params a batch iteration ran with, `None` for node steps
+    pub batch_params: Option<ParamMap>,
+    
+    /// Shared state keys the step added, changed or removed, sorted; empty unless the run recorded writes
+    #[serde(default)]
+    pub writes: Vec<String>,
+}
+
+/// Node steps and batch iterations of a traced run, in the order they started
+pub type FlowTrace = Vec<TraceEntry>;
+
+/// How a node accessed a shared state key
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AccessKind {
+    /// Read with `get`, `contains_key` or indexing
+    Read,
+    
+    /// Stored with `insert` or `extend`, even when the value didn't change
+    Write,
+    
+    /// Handed out for in-place changes with `get_mut` or `entry`
+    Update,
+    
+    /// Removed with `remove`
+    Remove,
+}
+
+impl AccessKind {
+    /// Whether the access may have changed the key
+    pub fn is_write(self) -> bool {
+        self != AccessKind::Read
+    }
+}
+
+/// A shared state access a node made during a traced run
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StateAccess {
+    /// Name of the node whose step made the access
+    pub node: String,
+    
+    /// Key accessed
+    pub key: String,
+    
+    /// How the key was accessed
+    pub kind: AccessKind,
+    
+    /// Time from the start of the run to the access
+    pub at: Duration,
+}
+
+/// Start a step of the current run over `shared`, when the run is traced
+pub(crate) fn start(shared: &SharedState) -> Option<Started> {
+    TRACE
+        .try_with(|trace| Started {
+            at: Instant::now(),
+            before: trace.writes.then(|| shared.clone()),
+        })
+        .ok()
+}
+
+/// Record a step of the current run that started with `started` and left `shared` behind, doing nothing when the run isn't traced
+pub(crate) fn record(
+    started: Option<Started>,
+    node: impl FnOnce() -> String,
+    outcome: std::result::Result<&Action, &Error>,
+    batch_params: Option<&ParamMap>,
+    shared: &SharedState,
+) {
+    let Some(Started { at: started, before }) = started else {
+        return;
+    };
+    let _ = TRACE.try_with(|trace| {
+        let (action_taken, error) = match outcome {
+            Ok(action) => (action.clone(), None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        trace.entries.lock().push(TraceEntry {
+            node_name: node(),
+            action_taken,
+            started_at: started.saturating_duration_since(trace.started),
+            duration: started.elapsed(),
+            error,
+            batch_params: batch_params.cloned(),
+            writes: before.map(|before| changed_keys(&before, shared)).unwrap_or_default(),
+        });
+    });
+}
+
+/// The node to attribute the state accesses of a step to, when the run records them
+pub(crate) fn accessor(node: impl FnOnce() -> String) -> Option<Arc<str>> {
+    TRACE.try_with(|trace| trace.writes).unwrap_or(false).then(|| node().into())
+}
+
+/// Record an access `node` made to `key`, doing nothing when the run doesn't record accesses
+pub(crate) fn access(node: &str, key: &str, kind: AccessKind) {
+    let _ = TRACE.try_with(|trace| {
+        if trace.writes {
+            trace.accesses.lock().push(StateAccess {
+                node: node.to_string(),
+                key: key.to_string(),
+                kind,
+                at: trace.started.elapsed(),
+            });
+        }
+    });
+}
+
+/// The entries of a finished run, ordered by when they started
+fn finish(entries: &Mutex<Vec<TraceEntry>>) -> FlowTrace {
+    let mut entries = std::mem::take(&mut *entries.lock());
+    entries.sort_by_key(|entry| entry.started_at);
+    entries
+}
+
+/// Keys whose value differs between two states, sorted
+pub(crate) fn changed_keys(before: &SharedState, after: &SharedState) -> Vec<String> {
+    let mut keys: Vec<String> = before
+        .keys()
+        .chain(after.keys())
+        .filter(|key| before.get(key) != after.get(key))
+        .cloned()
+        .collect();
+    keys.sort();
+    keys.dedup();
+    keys
+}
+
+/// Run `f` as a traced run, returning its result along with what it recorded
+pub(crate) fn run(f: impl FnOnce() -> Result<Action>) -> (Result<Action>, FlowTrace) {
+    let (result, trace, _) = run_recording(false, f);
+    (result, trace)
+}
+
+/// Run `f` as a traced run, recording the keys each step writes and the state accesses nodes make when `writes` is set
+pub(crate) fn run_recording(writes: bool, f: impl FnOnce() -> Result<Action>) -> (Result<Action>, FlowTrace, Vec<StateAccess>) {
+    let trace = Trace::new(writes);
+    let (entries, accesses) = (trace.entries.clone(), trace.accesses.clone());
+    let result = TRACE.sync_scope(trace, f);
+    let accesses = mem::take(&mut *accesses.lock());
+    (result, finish(&entries), accesses)
+}
+
+/// Await `fut` as a traced run, returning its result along with what it recorded
+pub(crate) async fn run_async(fut: impl Future<Output = Result<Action>>) -> (Result<Action>, FlowTrace) {
+    let (result, trace, _) = run_async_recording(false, fut).await;
+    (result, trace)
+}
+
+/// Await `fut` as a traced run, recording the keys each step writes and the state accesses nodes make when `writes` is set
+pub(crate) async fn run_async_recording(writes: bool, fut: impl Future<Output = Result<Action>>) -> (Result<Action>, FlowTrace, Vec<StateAccess>) {
+    let trace = Trace::new(writes);
+    let (entries, accesses) = (trace.entries.clone(), trace.accesses.clone());
+    let result = TRACE.scope(trace, fut).await;
+    let accesses = mem::take(&mut *accesses.lock());
+    (result, finish(&entries), accesses)
+}
\ No newline at end of file
@@ -0,0 +1,72 @@
+use std::fmt;
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+/// A problem `Flow::validate` found with one node
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValidationIssue {
+    /// Name of the node
+    pub node: String,
+    
+    /// What is wrong with it
+    pub message: String,
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.node, self.message)
+    }
+}
+
+/// Outcome of `Flow::validate`
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValidationReport {
+    /// Problems that make the flow fail or misbehave when run, in graph order
+    pub errors: Vec<ValidationIssue>,
+    
+    /// Problems that are likely mistakes but leave the flow runnable, in graph order
+    pub warnings: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    /// Whether no errors were found; warnings are allowed
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+    
+    /// Record an error with `node`
+    pub(crate) fn error(&mut self, node: String, message: String) {
+        self.errors.push(ValidationIssue { node, message });
+    }
+    
+    /// Record a warning about `node`
+    pub(crate) fn warning(&mut self, node: String, message: String) {
+        self.warnings.push(ValidationIssue { node, message });
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let issues = self.errors.iter().map(|issue| ("error", issue)).chain(self.warnings.iter().map(|issue| ("warning", issue)));
+        for (i, (severity, issue)) in issues.enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}: {}", severity, issue)?;
+        }
+        Ok(())
+    }
+}
+
+/// Fail with `Error::InvalidFlow` when `report` has errors, logging its warnings otherwise
+pub(crate) fn enforce(report: ValidationReport) -> Result<()> {
+    if !report.is_ok() {
+        return Err(Error::InvalidFlow(report));
+    }
+    for issue in &report.warnings {
+        warn!(target: "minllm::flow", "{}", issue);
+    }
+    Ok(())
+}
\ No newline at end of file
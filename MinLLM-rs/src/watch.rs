@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use parking_lot::Mutex;
+use serde_json::Value;
+use tokio::sync::watch;
+
+use crate::base::SharedState;
+
+/// Subscriptions to shared state keys, checked by a flow after every node step
+#[derive(Clone, Default)]
+pub(crate) struct StateWatchers {
+    /// Sender per watched key, holding the last value the flow saw
+    senders: Arc<Mutex<HashMap<String, watch::Sender<Option<Value>>>>>,
+}
+
+impl StateWatchers {
+    /// Subscribe to changes of `key`, sharing the sender with earlier subscribers
+    pub(crate) fn subscribe(&self, key: &str) -> watch::Receiver<Option<Value>> {
+        self.senders
+            .lock()
+            .entry(key.to_string())
+            .or_insert_with(|| watch::channel(None).0)
+            .subscribe()
+    }
+    
+    /// Record the current values without notifying, so only changes made by the run are reported
+    pub(crate) fn prime(&self, shared: &SharedState) {
+        self.update(shared, false);
+    }
+    
+    /// Notify subscribers of every watched key whose value changed since the last check
+    pub(crate) fn notify(&self, shared: &SharedState) {
+        self.update(shared, true);
+    }
+    
+    fn update(&self, shared: &SharedState, notify: bool) {
+        let mut senders = self.senders.lock();
+        if senders.is_empty() {
+            return;
+        }
+        
+        // Drop senders whose receivers are all gone
+        senders.retain(|_, sender| !sender.is_closed());
+        
+        for (key, sender) in senders.iter() {
+            let current = shared.get(key);
+            sender.send_if_modified(|last| {
+                if last.as_ref() == current {
+                    return false;
+                }
+                *last = current.cloned();
+                notify
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    
+    use super::*;
+    
+    #[test]
+    fn only_changes_after_priming_notify() {
+        let watchers = StateWatchers::default();
+        let mut progress = watchers.subscribe("progress");
+        let mut shared = SharedState::from([("progress".to_string(), json!(0))]);
+        watchers.prime(&shared);
+        assert!(!progress.has_changed().unwrap());
+        
+        shared.insert("other".to_string(), json!(1));
+        watchers.notify(&shared);
+        assert!(!progress.has_changed().unwrap());
+        
+        shared.insert("progress".to_string(), json!(50));
+        watchers.notify(&shared);
+        assert_eq!(*progress.borrow_and_update(), Some(json!(50)));
+        
+        shared.remove("progress");
+        watchers.notify(&shared);
+        assert_eq!(*progress.borrow_and_update(), None);
+    }
+    
+    #[test]
+    fn dropped_subscriptions_are_removed() {
+        let watchers = StateWatchers::default();
+        let first = watchers.subscribe("progress");
+        let second = watchers.subscribe("progress");
+        drop(first);
+        watchers.notify(&SharedState::new());
+        assert_eq!(watchers.senders.lock().len(), 1);
+        
+        drop(second);
+        watchers.notify(&SharedState::new());
+        assert!(watchers.senders.lock().is_empty());
+    }
+}
\ No newline at end of file
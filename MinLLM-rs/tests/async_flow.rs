@@ -0,0 +1,44 @@
+use serde_json::json;
+use minllm::testing::fixtures::{self, APPROVAL_TRACE, BATCH_TRACE, BRANCHING_ERROR_TRACE, LINEAR_TRACE};
+use minllm::{AsyncNodeTrait, SharedState};
+
+#[tokio::test]
+async fn linear_flows_visit_every_step() {
+    fixtures::linear(3, None).async_harness().run_async().await.assert_ok().assert_visited(LINEAR_TRACE);
+}
+
+#[tokio::test]
+async fn a_failing_step_ends_the_flow() {
+    let fixture = fixtures::linear(4, Some(0));
+    fixture
+        .async_harness()
+        .run_async()
+        .await
+        .assert_error_contains("injected failure at step1")
+        .assert_visited(&fixture.expected());
+    assert!(fixture.async_flow().run_async(&mut SharedState::new()).await.is_err());
+}
+
+#[tokio::test]
+async fn branching_flows_follow_the_returned_action() {
+    fixtures::branching(true).async_harness().run_async().await.assert_ok().assert_visited(BRANCHING_ERROR_TRACE);
+}
+
+#[tokio::test]
+async fn sync_and_async_runs_of_a_fixture_agree() {
+    let fixture = fixtures::approval_loop(1);
+    let sync = fixture.harness().run();
+    let run_async = fixture.async_harness().run_async().await;
+    sync.assert_ok().assert_visited(APPROVAL_TRACE);
+    run_async.assert_ok().assert_visited(APPROVAL_TRACE);
+    assert_eq!(sync.action, run_async.action);
+}
+
+#[tokio::test]
+async fn batch_nodes_run_in_async_flows() {
+    let fixture = fixtures::batch(10, None);
+    let mut shared = SharedState::new();
+    fixture.async_flow().run_async(&mut shared).await.unwrap();
+    assert_eq!(shared["results"], json!([0, 2, 4, 6, 8, 10, 12, 14, 16, 18]));
+    fixture.async_harness().run_async().await.assert_ok().assert_visited(BATCH_TRACE);
+}
\ No newline at end of file
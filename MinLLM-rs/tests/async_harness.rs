@@ -0,0 +1,80 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use serde_json::json;
+use minllm::testing::async_harness;
+use minllm::{current_attempt, AsyncFlow, AsyncNode, Backoff, Error, SharedState};
+
+/// Waits of 2s doubling up to a minute
+const BACKOFF: Backoff = Backoff::Exponential {
+    base: Duration::from_secs(2),
+    factor: 2.0,
+    max: Duration::from_secs(60),
+    jitter: false,
+};
+
+#[test]
+fn backoff_completes_in_order_without_waiting() {
+    let harness = async_harness();
+    let node = AsyncNode::new(5, 0)
+        .with_exec(|_| async {
+            match current_attempt() {
+                Some(4) => Ok(json!("up")),
+                _ => Err(Error::NodeExecution("down".into())),
+            }
+        })
+        .with_backoff(BACKOFF)
+        .with_sleeper(harness.sleeper());
+    let flow = AsyncFlow::new(Arc::new(node));
+    
+    let run = harness.run_flow(&flow, &mut SharedState::new());
+    run.assert_ok()
+        .assert_slept_total(Duration::from_secs(30))
+        .assert_elapsed(Duration::from_secs(30))
+        .assert_real_under(1_000);
+    let waits: Vec<_> = [2, 4, 8, 16].into_iter().map(Duration::from_secs).collect();
+    assert_eq!(harness.virtual_sleeper().requested(), waits);
+}
+
+#[test]
+fn a_30s_deadline_stops_the_backoff() {
+    let harness = async_harness();
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let counter = attempts.clone();
+    let node = AsyncNode::new(10, 0)
+        .with_exec(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async { Err(Error::NodeExecution("down".into())) }
+        })
+        .with_backoff(BACKOFF)
+        .with_sleeper(harness.sleeper());
+    let flow = AsyncFlow::new(Arc::new(node));
+    
+    let real = std::time::Instant::now();
+    let (result, elapsed) = harness.block_on(async {
+        let start = tokio::time::Instant::now();
+        let result = flow.run_async_with_deadline(&mut SharedState::new(), start + Duration::from_secs(30)).await;
+        (result, start.elapsed())
+    });
+    let err = result.unwrap_err();
+    assert!(err.is_deadline_exceeded(), "{}", err);
+    assert_eq!(elapsed, Duration::from_secs(30));
+    // Waits of 2s, 4s and 8s leave the fourth failure 16s before the deadline, which cuts the next wait short
+    assert_eq!(attempts.load(Ordering::SeqCst), 4);
+    assert!(real.elapsed() < Duration::from_secs(1), "{:?}", real.elapsed());
+}
+
+#[test]
+fn time_travel_jumps_to_the_next_wake() {
+    let harness = async_harness();
+    let sleeper = harness.sleeper();
+    let travel = harness.time_travel();
+    harness.block_on(async {
+        let sleeping = tokio::spawn(async move { sleeper.sleep_async(Duration::from_secs(45)).await });
+        assert_eq!(travel.next_wake().await, Some(Duration::from_secs(45)));
+        sleeping.await.unwrap();
+        assert_eq!(travel.next_wake().await, None);
+        travel.advance(Duration::from_secs(5)).await;
+        assert_eq!(travel.elapsed(), Duration::from_secs(50));
+    });
+}
\ No newline at end of file
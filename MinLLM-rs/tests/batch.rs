@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use minllm::{ActionName, BatchFlow, BatchNode, BatchReport, Error, ErrorPolicy, Flow, NodeTrait, ParamMap, SharedState};
+
+mod common;
+use common::appender;
+
+/// One batch item per value of "item"
+fn items(values: &[&str]) -> Vec<ParamMap> {
+    values.iter().map(|value| ParamMap::from([("item".to_string(), json!(value))])).collect()
+}
+
+#[test]
+fn every_node_of_a_chain_runs_for_every_item() {
+    let first = appender("first", "next");
+    let second = appender("second", "next");
+    let third = appender("third", "done");
+    first.add_successor(second.clone(), "next").unwrap();
+    second.add_successor(third, "next").unwrap();
+    let flow = BatchFlow::new(first.clone()).with_items(items(&["a", "b"])).with_post(|shared, items, _| {
+        shared.insert("items".to_string(), json!(items.as_array().map_or(0, |items| items.len())));
+        Ok(Some(ActionName::new("finished")))
+    });
+    
+    let mut shared = SharedState::new();
+    assert_eq!(flow.run(&mut shared).unwrap().as_deref(), Some("finished"));
+    assert_eq!(shared["log"], json!(["first", "second", "third", "first", "second", "third"]));
+    assert_eq!(shared["items"], json!(2));
+    assert_eq!(first.params().get("item"), Some(&json!("b")));
+    
+    let (result, trace) = Flow::new(Arc::new(flow.with_name("batch"))).run_traced(&mut SharedState::new());
+    result.unwrap();
+    let thirds: Vec<_> = trace.iter().filter(|entry| entry.node_name == "third").collect();
+    assert_eq!(thirds.len(), 2);
+    assert!(thirds.iter().all(|entry| entry.action_taken.as_deref() == Some("done")));
+    let batch = trace.iter().find(|entry| entry.node_name == "batch" && entry.batch_params.is_none()).unwrap();
+    assert_eq!(batch.action_taken.as_deref(), Some("finished"));
+    assert_eq!(trace.iter().filter(|entry| entry.batch_params.is_some()).count(), 2);
+}
+
+#[test]
+fn items_come_from_the_shared_state() {
+    let first = appender("first", "next");
+    let flow = BatchFlow::new(first).with_prep(|shared| Ok(shared["pending"].clone()));
+    
+    let mut shared = SharedState::new();
+    shared.insert("pending".to_string(), json!([{"item": 1}, {"item": 2}, {"item": 3}]));
+    flow.run(&mut shared).unwrap();
+    assert_eq!(shared["log"], json!(["first", "first", "first"]));
+}
+
+#[test]
+fn reset_items_start_from_the_state_after_prep() {
+    let flow = BatchFlow::new(appender("first", "next")).with_items(items(&["a", "b", "c"]));
+    let mut shared = SharedState::from([("log".to_string(), json!(["prep"]))]);
+    flow.clone().reset_state_between_items().run(&mut shared).unwrap();
+    assert_eq!(shared["log"], json!(["prep", "first"]), "the last item's writes are kept");
+    
+    let mut shared = SharedState::from([("log".to_string(), json!(["prep"]))]);
+    flow.run(&mut shared).unwrap();
+    assert_eq!(shared["log"], json!(["prep", "first", "first", "first"]));
+}
+
+/// A batch node doubling numbers, whose first attempt at each item in `flaky` fails
+fn doubler(flaky: &'static [i64], attempts: Arc<Mutex<HashMap<i64, usize>>>) -> BatchNode {
+    BatchNode::new(2, 0).with_exec(move |item| {
+        let n = item.as_i64().unwrap();
+        let mut attempts = attempts.lock();
+        let attempt = attempts.entry(n).or_default();
+        *attempt += 1;
+        if flaky.contains(&n) && *attempt == 1 {
+            return Err(Error::NodeExecution(format!("item {} failed", n)));
+        }
+        Ok(json!(n * 2))
+    })
+}
+
+#[test]
+fn batch_nodes_retry_each_item_and_keep_the_batch_order() {
+    let attempts = Arc::new(Mutex::new(HashMap::new()));
+    let node = doubler(&[2, 4], attempts.clone());
+    assert_eq!(node._exec(&json!([1, 2, 3, 4, 5])).unwrap(), json!([2, 4, 6, 8, 10]));
+    assert_eq!(*attempts.lock(), HashMap::from([(1, 1), (2, 2), (3, 1), (4, 2), (5, 1)]));
+}
+
+#[test]
+fn single_values_and_null_are_batches_too() {
+    let node = doubler(&[], Arc::new(Mutex::new(HashMap::new())));
+    assert_eq!(node._exec(&json!(7)).unwrap(), json!([14]));
+    assert_eq!(node._exec(&Value::Null).unwrap(), json!([]));
+}
+
+#[test]
+fn collected_errors_keep_the_other_items_and_let_post_branch() {
+    let node = BatchNode::new(1, 0)
+        .with_prep(|_| Ok(json!((0..10).collect::<Vec<_>>())))
+        .with_exec(|item| match item.as_i64().unwrap() {
+            3 | 7 => Err(Error::NodeExecution("blew up".into())),
+            n => Ok(json!(n)),
+        })
+        .with_error_policy(ErrorPolicy::CollectErrors)
+        .with_post(|shared, _, exec_res| {
+            let report = BatchReport::from_exec(&exec_res).unwrap();
+            shared.insert("results".to_string(), json!(report.results));
+            shared.insert("failed".to_string(), json!(report.errors.iter().map(|error| error.index).collect::<Vec<_>>()));
+            Ok(report.has_failures().then(|| ActionName::new("partial_failure")))
+        });
+    
+    let mut shared = SharedState::new();
+    assert_eq!(node.run(&mut shared).unwrap().as_deref(), Some("partial_failure"));
+    assert_eq!(shared["results"], json!([0, 1, 2, 4, 5, 6, 8, 9]));
+    assert_eq!(shared["failed"], json!([3, 7]));
+}
+
+#[test]
+fn skipped_items_are_only_counted() {
+    let node = BatchNode::new(1, 0)
+        .with_exec(|item| if item == &json!(2) { Err(Error::NodeExecution("no".into())) } else { Ok(item.clone()) })
+        .with_error_policy(ErrorPolicy::SkipFailed);
+    let report = BatchReport::from_exec(&node._exec(&json!([1, 2, 3])).unwrap()).unwrap();
+    assert_eq!(report.results, [json!(1), json!(3)]);
+    assert!(report.errors.is_empty());
+    assert_eq!(report.skipped, 1);
+}
+
+#[test]
+fn chunked_batches_hand_exec_arrays_and_flatten_the_results() {
+    let chunks = Arc::new(Mutex::new(Vec::new()));
+    let seen = chunks.clone();
+    let node = BatchNode::new(1, 0)
+        .with_chunk_size(3)
+        .with_exec(move |chunk| {
+            seen.lock().push(chunk.clone());
+            if chunk[0] == json!(4) {
+                return Err(Error::NodeExecution("chunk failed".into()));
+            }
+            Ok(json!(chunk.as_array().unwrap().iter().map(|n| n.as_i64().unwrap() * 2).collect::<Vec<_>>()))
+        })
+        .with_error_policy(ErrorPolicy::CollectErrors);
+    
+    let report = BatchReport::from_exec(&node._exec(&json!([1, 2, 3, 4, 5, 6, 7])).unwrap()).unwrap();
+    assert_eq!(*chunks.lock(), [json!([1, 2, 3]), json!([4, 5, 6]), json!([7])]);
+    assert_eq!(report.results, [json!(2), json!(4), json!(6), json!(14)]);
+    assert_eq!(report.errors.len(), 1);
+    assert_eq!(report.errors[0].index, 3, "a failed chunk is reported at its first item");
+}
+
+#[cfg(feature = "testing")]
+mod fixtures {
+    use serde_json::{json, Value};
+    use minllm::testing::fixtures::{self, BATCH_TRACE};
+    
+    #[test]
+    fn a_100_item_batch_processes_every_item() {
+        let result = fixtures::batch(100, None).harness().run();
+        let doubled: Vec<Value> = (0..100).map(|i| json!(i * 2)).collect();
+        result
+            .assert_ok()
+            .assert_visited(BATCH_TRACE)
+            .assert_store_eq("results", Value::Array(doubled))
+            .assert_written_once_by("results", "process");
+    }
+    
+    #[test]
+    fn a_failing_item_stops_the_batch() {
+        let fixture = fixtures::batch(100, Some(40));
+        fixture
+            .harness()
+            .run()
+            .assert_error_contains("injected failure at item 40")
+            .assert_visited(&fixture.expected())
+            .assert_not_visited("save");
+        assert_eq!(fixture.expected(), ["load", "process"]);
+    }
+    
+    #[test]
+    fn an_empty_batch_still_reaches_save() {
+        fixtures::batch(0, None).harness().run().assert_ok().assert_visited(BATCH_TRACE).assert_store_eq("results", json!([]));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,55 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use serde_json::{json, Value};
+use tokio::time::{self, Instant};
+use minllm::{ActionName, AsyncFlow, AsyncFnNode, CancellationToken, NodeTrait, SharedState};
+
+mod common;
+use common::step;
+
+#[tokio::test(start_paused = true)]
+async fn a_cancelled_exec_is_abandoned_without_retries_or_fallback() {
+    let fallbacks = Arc::new(AtomicUsize::new(0));
+    let counted = fallbacks.clone();
+    let first: Arc<dyn NodeTrait> = Arc::new(step("first"));
+    let second: Arc<dyn NodeTrait> = Arc::new(step("second").retries(3, 0).with_fallback(move |_, _| {
+        counted.fetch_add(1, Ordering::SeqCst);
+        async { Ok(Value::Null) }
+    }));
+    first.add_successor(second.clone(), "next").unwrap();
+    second.add_successor(Arc::new(step("third")), "next").unwrap();
+    
+    let token = CancellationToken::new();
+    let canceller = token.clone();
+    tokio::spawn(async move {
+        time::sleep(Duration::from_millis(1500)).await;
+        canceller.cancel();
+    });
+    let mut shared = SharedState::new();
+    let started = Instant::now();
+    let err = AsyncFlow::new(first).run_cancellable(&mut shared, token).await.unwrap_err();
+    assert!(err.is_cancelled(), "{}", err);
+    assert_eq!(started.elapsed(), Duration::from_millis(1500));
+    assert_eq!(shared.get("first"), Some(&json!(true)), "finished nodes keep their writes");
+    assert!(!shared.contains_key("second"));
+    assert!(!shared.contains_key("third"));
+    assert_eq!(fallbacks.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn a_run_cancelled_between_nodes_stops_before_the_next_one() {
+    let token = CancellationToken::new();
+    let canceller = token.clone();
+    let first: Arc<dyn NodeTrait> = Arc::new(AsyncFnNode::new().with_post(move |_, _, _| {
+        canceller.cancel();
+        Ok(Some(ActionName::new("next")))
+    }));
+    first.add_successor(Arc::new(step("second")), "next").unwrap();
+    
+    let mut shared = SharedState::new();
+    let err = AsyncFlow::new(first).run_cancellable(&mut shared, token.clone()).await.unwrap_err();
+    assert!(err.is_cancelled(), "{}", err);
+    assert!(token.is_cancelled());
+    assert!(!shared.contains_key("second"));
+}
\ No newline at end of file
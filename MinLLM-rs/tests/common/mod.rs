@@ -0,0 +1,99 @@
+//! Helpers shared by the integration tests
+
+// Each test crate uses only some of the helpers
+#![allow(dead_code)]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::time::Duration;
+use serde_json::{json, Value};
+use tokio::time;
+use minllm::{ActionName, AsyncFnNode, FnNode, NodeTrait};
+
+/// Allocator counting the bytes allocated by threads that are measuring
+struct CountingAllocator;
+
+thread_local! {
+    /// Bytes allocated by this thread while measuring, or `None` when not measuring
+    static ALLOCATED: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATED.try_with(|allocated| allocated.set(allocated.get().map(|n| n + layout.size())));
+        unsafe { System.alloc(layout) }
+    }
+    
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+    
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let _ = ALLOCATED.try_with(|allocated| allocated.set(allocated.get().map(|n| n + new_size)));
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Start counting the bytes allocated on this thread
+pub fn start_counting() {
+    ALLOCATED.with(|allocated| allocated.set(Some(0)));
+}
+
+/// Stop counting, returning the bytes allocated on this thread since `start_counting`
+pub fn stop_counting() -> usize {
+    ALLOCATED.with(|allocated| allocated.take()).expect("counting was started")
+}
+
+/// Bytes allocated on this thread while running `f`
+pub fn allocated_by(f: impl FnOnce()) -> usize {
+    start_counting();
+    f();
+    stop_counting()
+}
+
+/// An async node named `name` whose exec takes a second, storing `true` under its name
+pub fn step(name: &'static str) -> AsyncFnNode {
+    let node = AsyncFnNode::new()
+        .with_exec(|_| async {
+            time::sleep(Duration::from_secs(1)).await;
+            Ok(Value::Null)
+        })
+        .with_post(move |shared, _, _| {
+            shared.insert(name.to_string(), json!(true));
+            Ok(Some(ActionName::new("next")))
+        });
+    node.set_name(name);
+    node
+}
+
+/// A sync node named `name`
+pub fn named(name: &str) -> Arc<dyn NodeTrait> {
+    let node: Arc<dyn NodeTrait> = Arc::new(FnNode::new());
+    node.set_name(name);
+    node
+}
+
+/// A node named `name` that appends its name to the "log" list and returns `action`
+pub fn appender(name: &'static str, action: &'static str) -> Arc<dyn NodeTrait> {
+    let node: Arc<dyn NodeTrait> = Arc::new(FnNode::new().with_post(move |shared, _, _| {
+        let log = shared.entry("log".to_string()).or_insert_with(|| json!([]));
+        log.as_array_mut().unwrap().push(json!(name));
+        Ok(Some(ActionName::new(action)))
+    }));
+    node.set_name(name);
+    node
+}
+
+/// The message `assertion` panics with
+pub fn panic_message(assertion: impl FnOnce()) -> String {
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let payload = panic::catch_unwind(AssertUnwindSafe(assertion)).expect_err("the assertion should fail");
+    panic::set_hook(hook);
+    *payload.downcast::<String>().expect("assertions panic with a formatted message")
+}
\ No newline at end of file
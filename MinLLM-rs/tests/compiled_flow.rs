@@ -0,0 +1,195 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use serde_json::json;
+use minllm::{
+    actions, Action, ActionName, AsyncFlow, AsyncFnNode, Error, Flow, FlowTrace, FnNode, NodeTrait, SharedState,
+};
+
+mod common;
+use common::allocated_by;
+
+actions! {
+    enum Review {
+        Approve => "approve",
+        Reject => "reject",
+    }
+}
+
+/// A node named `name` that appends its name to the "visited" list and returns `action(count)`,
+/// where `count` is how many times it has run
+fn step(name: &str, action: impl Fn(u64) -> Action + Send + Sync + 'static) -> Arc<dyn NodeTrait> {
+    let key = format!("runs_{}", name);
+    let label = name.to_string();
+    let node: Arc<dyn NodeTrait> = Arc::new(FnNode::new().with_exec(|prep| Ok(prep.clone())).with_prep(|shared| {
+        Ok(shared.get("score").cloned().unwrap_or(json!(0)))
+    }).with_post(move |shared, _, _| {
+        let count = shared.get(&key).and_then(|v| v.as_u64()).unwrap_or(0) + 1;
+        shared.insert(key.clone(), json!(count));
+        let visited = shared.entry("visited".to_string()).or_insert_with(|| json!([]));
+        visited.as_array_mut().unwrap().push(json!(label));
+        Ok(action(count))
+    }));
+    node.set_name(name);
+    node
+}
+
+/// The parts of a trace that don't depend on timing
+fn shape(trace: &FlowTrace) -> Vec<(String, Action, Option<String>)> {
+    trace.iter().map(|entry| (entry.node_name.clone(), entry.action_taken.clone(), entry.error.clone())).collect()
+}
+
+/// A flow that branches on an action, on a condition and into a fan-out
+fn branching_flow() -> Flow {
+    let review = step("review", |_| Some(ActionName::new("approve")));
+    let approve = step("approve", |_| None);
+    let reject = step("reject", |_| None);
+    let audit = step("audit", |_| None);
+    let urgent = step("urgent", |_| None);
+    let done = step("done", |_| None);
+    review.add_successor(reject, "reject").unwrap();
+    review.add_successor(approve.clone(), "approve").unwrap();
+    approve.add_described_successor_if(urgent.clone(), "score > 5", Arc::new(|_, exec_res| exec_res.as_u64() > Some(5))).unwrap();
+    approve.add_successors("default", vec![audit, done.clone()]).unwrap();
+    urgent.add_successor(done, "default").unwrap();
+    Flow::new(review)
+}
+
+/// A flow that loops between two nodes three times before leaving
+fn looping_flow() -> Flow {
+    let draft = step("draft", |_| None);
+    let check = step("check", |count| Some(ActionName::new(if count < 3 { "retry" } else { "ok" })));
+    let publish = step("publish", |_| None);
+    draft.add_successor(check.clone(), "default").unwrap();
+    check.add_successor(draft.clone(), "retry").unwrap();
+    check.add_successor(publish, "ok").unwrap();
+    Flow::new(draft)
+}
+
+/// Run `flow` interpreted and compiled on copies of `shared`, checking both take the same steps
+fn assert_equivalent(flow: &Flow, shared: SharedState) {
+    let compiled = flow.compile().unwrap();
+    let (mut interpreted_state, mut compiled_state) = (shared.clone(), shared);
+    let (interpreted, interpreted_trace) = flow.run_traced(&mut interpreted_state);
+    let (result, compiled_trace) = compiled.run_traced(&mut compiled_state);
+    assert_eq!(interpreted.unwrap(), result.unwrap());
+    assert!(!interpreted_trace.is_empty());
+    assert_eq!(shape(&interpreted_trace), shape(&compiled_trace));
+    assert_eq!(interpreted_state, compiled_state);
+}
+
+#[test]
+fn compiled_branching_flows_take_the_same_steps() {
+    assert_equivalent(&branching_flow(), SharedState::new());
+    
+    let mut urgent = SharedState::new();
+    urgent.insert("score".to_string(), json!(9));
+    assert_equivalent(&branching_flow(), urgent);
+}
+
+#[test]
+fn compiled_looping_flows_take_the_same_steps() {
+    let flow = looping_flow();
+    assert_equivalent(&flow, SharedState::new());
+    
+    let mut shared = SharedState::new();
+    flow.compile().unwrap().run(&mut shared).unwrap();
+    assert_eq!(shared["visited"], json!(["draft", "check", "draft", "check", "draft", "check", "publish"]));
+}
+
+#[tokio::test]
+async fn compiled_async_flows_take_the_same_steps() {
+    let fetch: Arc<dyn NodeTrait> = Arc::new(AsyncFnNode::new().with_exec(|_| async { Ok(json!(7)) }).with_post(|shared, _, exec_res| {
+        shared.insert("fetched".to_string(), exec_res);
+        Ok(None)
+    }));
+    fetch.set_name("fetch");
+    fetch.add_successor(looping_flow().start.clone(), "default").unwrap();
+    let flow = AsyncFlow::new(fetch);
+    let compiled = flow.compile().unwrap();
+    
+    let (mut interpreted_state, mut compiled_state) = (SharedState::new(), SharedState::new());
+    let (interpreted, interpreted_trace) = flow.run_async_traced(&mut interpreted_state).await;
+    let (result, compiled_trace) = compiled.run_async_traced(&mut compiled_state).await;
+    assert_eq!(interpreted.unwrap(), result.unwrap());
+    assert_eq!(shape(&interpreted_trace), shape(&compiled_trace));
+    assert_eq!(interpreted_state, compiled_state);
+    assert_eq!(compiled_state["fetched"], json!(7));
+}
+
+#[test]
+fn compiling_validates_the_flow() {
+    let review: Arc<dyn NodeTrait> = Arc::new(FnNode::new().with_actions::<Review>());
+    review.add_successor(step("approve", |_| None), Review::Approve.as_str()).unwrap();
+    let flow = Flow::new(review.clone());
+    match flow.compile() {
+        Err(Error::InvalidFlow(report)) => assert!(report.to_string().contains("no successor for action 'reject'")),
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("compiled a flow with a missing successor"),
+    }
+    
+    review.add_successor(step("reject", |_| None), Review::Reject.as_str()).unwrap();
+    assert!(flow.compile().is_ok());
+}
+
+#[test]
+fn successors_added_after_compiling_are_not_seen() {
+    let first = step("first", |_| None);
+    let flow = Flow::new(first.clone());
+    let compiled = flow.compile().unwrap();
+    first.add_successor(step("second", |_| None), "default").unwrap();
+    
+    let mut shared = SharedState::new();
+    compiled.run(&mut shared).unwrap();
+    assert_eq!(shared["visited"], json!(["first"]));
+    
+    let mut shared = SharedState::new();
+    flow.run(&mut shared).unwrap();
+    assert_eq!(shared["visited"], json!(["first", "second"]));
+}
+
+#[test]
+fn compiled_flows_keep_the_step_limit() {
+    let spin = step("spin", |_| None);
+    spin.add_successor(spin.clone(), "default").unwrap();
+    let compiled = Flow::new(spin).with_max_steps(Some(10)).compile().unwrap();
+    
+    let mut shared = SharedState::new();
+    assert!(matches!(compiled.run(&mut shared), Err(Error::FlowExecution(_))));
+    assert_eq!(shared["runs_spin"], json!(10));
+}
+
+#[test]
+fn compiled_flows_dry_run_like_the_flow() {
+    let flow = looping_flow();
+    let report = flow.dry_run(&mut SharedState::new()).unwrap();
+    let compiled = flow.compile().unwrap().dry_run(&mut SharedState::new()).unwrap();
+    assert_eq!(report.nodes(), compiled.nodes());
+}
+
+/// Number of nodes in the chain benchmark
+const CHAIN_LENGTH: usize = 500;
+
+#[test]
+fn a_500_node_chain_runs_compiled_with_no_more_allocations_than_interpreted() {
+    let nodes: Vec<Arc<dyn NodeTrait>> = (0..CHAIN_LENGTH)
+        .map(|_| Arc::new(FnNode::new().with_post(|_, _, _| Ok(Some(ActionName::new("next"))))) as Arc<dyn NodeTrait>)
+        .collect();
+    for pair in nodes.windows(2) {
+        pair[0].add_successor(pair[1].clone(), "next").unwrap();
+        // Unused edges make the interpreted lookup scan past them
+        for other in ["skip", "retry", "error"] {
+            pair[0].add_successor(pair[1].clone(), other).unwrap();
+        }
+    }
+    let flow = Flow::new(nodes[0].clone());
+    let compiled = flow.compile().unwrap();
+    
+    let interpreted = allocated_by(|| flow.run(&mut SharedState::new()).map(drop).unwrap());
+    let started = Instant::now();
+    let allocated = allocated_by(|| compiled.run(&mut SharedState::new()).map(drop).unwrap());
+    let elapsed = started.elapsed();
+    assert!(allocated <= interpreted, "compiled allocated {} bytes, interpreted {}", allocated, interpreted);
+    // The run itself sets up a few buffers; no step allocates
+    assert!(allocated < CHAIN_LENGTH * 8, "{} steps allocated {} bytes", CHAIN_LENGTH, allocated);
+    assert!(elapsed < Duration::from_secs(5), "{} steps took {:?}", CHAIN_LENGTH, elapsed);
+}
\ No newline at end of file
@@ -0,0 +1,52 @@
+mod common;
+
+use std::sync::Arc;
+use serde_json::json;
+use minllm::{AsyncNodeTrait, AsyncParallelBatchFlow, BatchFlow, FnNode, NodeTrait, ParamMap, SharedState};
+use common::{allocated_by, start_counting, stop_counting};
+
+/// Size of the large value in the base state
+const MB: usize = 1 << 20;
+
+/// Number of items in the batch
+const ITEMS: usize = 100;
+
+/// A base state holding a 1MB corpus next to a scratch key
+fn large_state() -> SharedState {
+    SharedState::from([("corpus".to_string(), json!("x".repeat(MB))), ("scratch".to_string(), json!(true))])
+}
+
+/// A node storing the corpus length under "length" and removing "scratch"
+fn measure() -> Arc<dyn NodeTrait> {
+    Arc::new(FnNode::new().with_post(|shared, _, _| {
+        let length = shared["corpus"].as_str().unwrap().len();
+        shared.insert("length".to_string(), json!(length));
+        shared.remove("scratch");
+        Ok(None)
+    }))
+}
+
+#[tokio::test]
+async fn parallel_items_share_the_base_state_and_merge_their_removals() {
+    let flow = AsyncParallelBatchFlow::new(measure()).with_items(vec![ParamMap::new(); ITEMS]);
+    let mut shared = large_state();
+    
+    start_counting();
+    flow.run_async(&mut shared).await.unwrap();
+    let allocated = stop_counting();
+    // Copying the state per item would allocate ITEMS times the corpus
+    assert!(allocated < MB, "a {}-item batch allocated {} bytes", ITEMS, allocated);
+    assert_eq!(shared["length"], json!(MB));
+    assert!(!shared.contains_key("scratch"), "a removal by an item is merged back");
+}
+
+#[test]
+fn items_reset_between_runs_without_copying_the_state() {
+    let flow = BatchFlow::new(measure()).with_items(vec![ParamMap::new(); ITEMS]).reset_state_between_items();
+    let mut shared = large_state();
+    
+    let allocated = allocated_by(|| flow.run(&mut shared).map(drop).unwrap());
+    assert!(allocated < MB / 4, "a {}-item batch allocated {} bytes", ITEMS, allocated);
+    assert_eq!(shared["length"], json!(MB));
+    assert!(!shared.contains_key("scratch"));
+}
\ No newline at end of file
@@ -0,0 +1,39 @@
+use std::sync::Arc;
+use std::time::Duration;
+use serde_json::{json, Value};
+use tokio::time::{self, Instant};
+use minllm::{current_deadline, AsyncFlow, AsyncFnNode, Error, NodeTrait, SharedState};
+
+mod common;
+use common::step;
+
+#[tokio::test(start_paused = true)]
+async fn a_flow_stops_after_the_node_that_used_up_the_budget() {
+    let [first, second, third] = ["first", "second", "third"].map(|name| Arc::new(step(name)) as Arc<dyn NodeTrait>);
+    first.add_successor(second.clone(), "next").unwrap();
+    second.add_successor(third, "next").unwrap();
+    let flow = AsyncFlow::new(first);
+    
+    let mut shared = SharedState::new();
+    let started = Instant::now();
+    let err = flow.run_async_with_deadline(&mut shared, started + Duration::from_secs(2)).await.unwrap_err();
+    assert!(matches!(err.root(), Error::DeadlineExceeded { remaining_nodes } if remaining_nodes == &["third"]), "{}", err);
+    assert_eq!(started.elapsed(), Duration::from_secs(2));
+    assert_eq!(shared.get("second"), Some(&json!(true)), "the second node finished in time");
+    assert!(!shared.contains_key("third"));
+}
+
+#[tokio::test(start_paused = true)]
+async fn execs_see_the_deadline_and_are_cut_short_at_it() {
+    let node = AsyncFnNode::new().with_exec(|_| async {
+        let deadline = current_deadline().unwrap();
+        time::sleep_until(deadline + Duration::from_secs(5)).await;
+        Ok(Value::Null)
+    });
+    let flow = AsyncFlow::new(Arc::new(node));
+    
+    let started = Instant::now();
+    let err = flow.run_async_with_deadline(&mut SharedState::new(), started + Duration::from_secs(3)).await.unwrap_err();
+    assert!(err.is_deadline_exceeded(), "{}", err);
+    assert_eq!(started.elapsed(), Duration::from_secs(3));
+}
\ No newline at end of file
@@ -0,0 +1,73 @@
+use std::sync::Arc;
+use std::time::Duration;
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use minllm::{AsyncNodeTrait, AsyncParallelBatchNode, Determinism, Error, ErrorPolicy, ResultOrder};
+
+/// Number of items in each batch
+const ITEMS: u64 = 12;
+
+/// Run a batch of `ITEMS` items under `determinism`, returning the order the items started in and the exec result
+///
+/// Items wait less the later they are in the batch, so concurrent runs finish out of order,
+/// and every third item fails. The wall-clock `elapsed_ms` is left out of the result.
+async fn run(determinism: Determinism) -> (Vec<u64>, Value) {
+    let started = Arc::new(Mutex::new(Vec::new()));
+    let log = started.clone();
+    let node = AsyncParallelBatchNode::new(1, 0)
+        .with_determinism(determinism)
+        .with_result_order(ResultOrder::CompletionOrder)
+        .with_error_policy(ErrorPolicy::CollectErrors)
+        .with_exec(move |item: Value| {
+            let log = log.clone();
+            async move {
+                let n = item.as_u64().unwrap();
+                log.lock().push(n);
+                tokio::time::sleep(Duration::from_millis(10 * (ITEMS - n))).await;
+                match n % 3 {
+                    0 => Err(Error::NodeExecution(format!("item {} failed", n))),
+                    _ => Ok(json!(n * 10)),
+                }
+            }
+        });
+    let items: Vec<u64> = (0..ITEMS).collect();
+    let mut exec_res = node._exec_async(&json!(items)).await.unwrap();
+    // Measured on the wall clock, so it differs between runs
+    exec_res.as_object_mut().unwrap().remove("elapsed_ms");
+    let started = started.lock().clone();
+    (started, exec_res)
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_seeded_batch_runs_the_same_way_every_time() {
+    let (order, exec_res) = run(Determinism::Seeded(7)).await;
+    let expected: Vec<u64> = Determinism::Seeded(7).order(ITEMS as usize).into_iter().map(|i| i as u64).collect();
+    assert_eq!(order, expected);
+    
+    for _ in 0..3 {
+        let (again, again_res) = run(Determinism::Seeded(7)).await;
+        assert_eq!(again, order);
+        assert_eq!(serde_json::to_string(&again_res).unwrap(), serde_json::to_string(&exec_res).unwrap());
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn different_seeds_give_different_orders() {
+    let (first, _) = run(Determinism::Seeded(1)).await;
+    let (second, _) = run(Determinism::Seeded(2)).await;
+    assert_ne!(first, second);
+    assert_eq!(run(Determinism::Seeded(2)).await.0, second);
+    
+    let mut sorted = first.clone();
+    sorted.sort_unstable();
+    assert_eq!(sorted, (0..ITEMS).collect::<Vec<_>>());
+}
+
+#[tokio::test(start_paused = true)]
+async fn concurrent_batches_start_every_item_at_once() {
+    let (order, exec_res) = run(Determinism::Concurrent).await;
+    assert_eq!(order, (0..ITEMS).collect::<Vec<_>>());
+    let finished: Vec<u64> = exec_res["results"].as_array().unwrap().iter().map(|r| r["index"].as_u64().unwrap()).collect();
+    let expected: Vec<u64> = (0..ITEMS).rev().filter(|n| n % 3 != 0).collect();
+    assert_eq!(finished, expected, "the quickest items finish first");
+}
\ No newline at end of file
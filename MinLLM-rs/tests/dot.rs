@@ -0,0 +1,27 @@
+use std::sync::Arc;
+use minllm::Flow;
+
+mod common;
+use common::named;
+
+/// Committed rendering of the diamond flow
+const DIAMOND: &str = include_str!("snapshots/diamond.dot");
+
+/// classify -> (handle | escalate when urgent) -> finish
+fn diamond() -> Flow {
+    let classify = named("classify");
+    let finish = named("finish");
+    let handle = named("handle");
+    let escalate = named("escalate");
+    classify.add_successor(handle.clone(), "ok").unwrap();
+    classify.add_described_successor_if(escalate.clone(), "urgent", Arc::new(|shared, _| shared.contains_key("urgent"))).unwrap();
+    handle.add_successor(finish.clone(), "default").unwrap();
+    escalate.add_successor(finish, "default").unwrap();
+    Flow::new(classify).with_name("diamond")
+}
+
+#[test]
+fn a_diamond_flow_renders_as_committed() {
+    let dot = diamond().to_dot();
+    assert_eq!(dot, DIAMOND.trim_end(), "rendered:\n{}", dot);
+}
\ No newline at end of file
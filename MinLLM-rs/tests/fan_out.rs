@@ -0,0 +1,133 @@
+use std::sync::Arc;
+use std::time::Duration;
+use serde_json::{json, Value};
+use minllm::{ActionName, AsyncFlow, AsyncFnNode, AsyncNodeTrait, Error, FanOutNode, Flow, FnNode, MergePolicy, NodeTrait, SharedState};
+
+/// A node that reads its branch's copy of the input, stores it tagged under "seen/{branch}" and logs the branch
+fn reader(branch: &'static str) -> Arc<dyn NodeTrait> {
+    Arc::new(FnNode::new().with_prep(move |shared| Ok(shared.get(&format!("fan_out/{}", branch)).cloned().unwrap_or(Value::Null))).with_post(move |shared, prep_res, _| {
+        shared.insert(format!("seen/{}", branch), json!({"branch": branch, "input": prep_res}));
+        let log = shared.entry("log".to_string()).or_insert_with(|| json!([]));
+        log.as_array_mut().unwrap().push(json!(branch));
+        Ok(None)
+    }))
+}
+
+/// A fan-out of "draft" to "summary", "keywords" and "title", continuing to a node logging "done"
+fn fan_out(branches: [Arc<dyn NodeTrait>; 3]) -> Arc<dyn NodeTrait> {
+    let split: Arc<dyn NodeTrait> = Arc::new(FanOutNode::new("draft", &["summary", "keywords", "title"]).continue_with("joined"));
+    for (branch, node) in ["summary", "keywords", "title"].into_iter().zip(branches) {
+        split.add_successor(node, branch).unwrap();
+    }
+    split.add_successor(reader("done"), "joined").unwrap();
+    split
+}
+
+fn draft() -> SharedState {
+    SharedState::from([("draft".to_string(), json!("a long draft"))])
+}
+
+#[test]
+fn every_branch_reads_its_own_copy() {
+    let flow = Flow::new(fan_out([reader("summary"), reader("keywords"), reader("title")]));
+    let mut shared = draft();
+    flow.run(&mut shared).unwrap();
+    for branch in ["summary", "keywords", "title"] {
+        assert_eq!(shared[&format!("fan_out/{}", branch)], json!("a long draft"));
+        assert_eq!(shared[&format!("seen/{}", branch)], json!({"branch": branch, "input": "a long draft"}));
+    }
+}
+
+#[test]
+fn sync_flows_run_branches_in_order_before_continuing() {
+    let summary = reader("summary");
+    summary.add_successor(reader("summary_checked"), "default").unwrap();
+    let flow = Flow::new(fan_out([summary, reader("keywords"), reader("title")]));
+    let mut shared = draft();
+    flow.run(&mut shared).unwrap();
+    assert_eq!(shared["log"], json!(["summary", "summary_checked", "keywords", "title", "done"]));
+}
+
+#[test]
+fn successors_of_one_action_run_as_branches_before_the_last_continues() {
+    let fetch: Arc<dyn NodeTrait> = Arc::new(FnNode::new());
+    let summarize = reader("summarize");
+    summarize.add_successor(reader("summary_saved"), "default").unwrap();
+    let classify: Arc<dyn NodeTrait> = Arc::new(FnNode::new().with_post(|shared, _, _| {
+        shared.entry("log".to_string()).or_insert_with(|| json!([])).as_array_mut().unwrap().push(json!("classify"));
+        Ok(Some(ActionName::new("unrouted")))
+    }));
+    let report: Arc<dyn NodeTrait> = Arc::new(FnNode::new().with_post(|shared, _, _| {
+        shared.entry("log".to_string()).or_insert_with(|| json!([])).as_array_mut().unwrap().push(json!("report"));
+        Ok(None)
+    }));
+    fetch.add_successors("default", vec![summarize, classify, report]).unwrap();
+    
+    let mut shared = SharedState::new();
+    Flow::new(fetch).run(&mut shared).unwrap();
+    assert_eq!(shared["log"], json!(["summarize", "summary_saved", "classify", "report"]));
+}
+
+/// A branch that waits `secs` seconds before storing its copy of the input
+fn slow(branch: &'static str, secs: u64) -> Arc<dyn NodeTrait> {
+    Arc::new(AsyncFnNode::new().with_prep(move |shared| Ok(shared[&format!("fan_out/{}", branch)].clone())).with_exec(move |prep_res| async move {
+        tokio::time::sleep(Duration::from_secs(secs)).await;
+        Ok(prep_res)
+    }).with_post(move |shared, _, exec_res: Value| {
+        shared.insert(format!("seen/{}", branch), exec_res);
+        Ok(Some(ActionName::default_action()))
+    }))
+}
+
+#[tokio::test(start_paused = true)]
+async fn async_flows_run_branches_concurrently() {
+    let flow = AsyncFlow::new(fan_out([slow("summary", 3), slow("keywords", 2), slow("title", 1)])).with_concurrent_fan_out(MergePolicy::Fail);
+    let mut shared = draft();
+    let started = tokio::time::Instant::now();
+    flow.run_async(&mut shared).await.unwrap();
+    assert_eq!(started.elapsed(), Duration::from_secs(3));
+    for branch in ["summary", "keywords", "title"] {
+        assert_eq!(shared[&format!("seen/{}", branch)], json!("a long draft"));
+    }
+    assert_eq!(shared["log"], json!(["done"]));
+    
+    let sequential = AsyncFlow::new(fan_out([slow("summary", 3), slow("keywords", 2), slow("title", 1)]));
+    let started = tokio::time::Instant::now();
+    sequential.run_async(&mut draft()).await.unwrap();
+    assert_eq!(started.elapsed(), Duration::from_secs(6));
+}
+
+#[test]
+fn a_failing_branch_fails_the_flow() {
+    let failing: Arc<dyn NodeTrait> = Arc::new(FnNode::new().with_exec(|_| Err(Error::NodeExecution("no keywords".into()))));
+    let flow = Flow::new(fan_out([reader("summary"), failing, reader("title")]));
+    let mut shared = draft();
+    let err = flow.run(&mut shared).unwrap_err();
+    assert!(err.to_string().contains("no keywords"), "{}", err);
+    assert_eq!(shared["log"], json!(["summary"]));
+}
+
+/// A node that waits `secs` seconds and then stores `value` under "winner"
+fn claim(value: &'static str, secs: u64) -> Arc<dyn NodeTrait> {
+    Arc::new(AsyncFnNode::new().with_exec(move |_| async move {
+        tokio::time::sleep(Duration::from_secs(secs)).await;
+        Ok(json!(value))
+    }).with_post(|shared, _, exec_res| {
+        shared.insert("winner".to_string(), exec_res);
+        Ok(None)
+    }))
+}
+
+#[tokio::test(start_paused = true)]
+async fn concurrent_writes_merge_in_successor_order_not_finish_order() {
+    for (policy, winner) in [(MergePolicy::LastWins, "late"), (MergePolicy::FirstWins, "early")] {
+        let start: Arc<dyn NodeTrait> = Arc::new(FnNode::new());
+        // The last successor is where the flow continues once the branches before it are merged
+        let rest: Arc<dyn NodeTrait> = Arc::new(FnNode::new());
+        start.add_successors("default", vec![claim("early", 3), claim("middle", 2), claim("late", 1), rest]).unwrap();
+        let flow = AsyncFlow::new(start).with_concurrent_fan_out(policy);
+        let mut shared = SharedState::new();
+        flow.run_async(&mut shared).await.unwrap();
+        assert_eq!(shared["winner"], json!(winner));
+    }
+}
\ No newline at end of file
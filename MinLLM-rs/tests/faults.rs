@@ -0,0 +1,89 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use serde_json::json;
+use minllm::testing::{Fault, FaultPlan, FaultyNode};
+use minllm::{AsyncFnNode, AsyncNodeTrait, AsyncParallelBatchFlow, Error, Flow, FnNode, NodeTrait, ParamMap, SharedState, TestSleeper};
+
+/// A node whose exec succeeds with "done", wrapped with `plan`
+fn faulty(plan: FaultPlan) -> FaultyNode {
+    let inner: Arc<dyn NodeTrait> = Arc::new(FnNode::new().with_exec(|_| Ok(json!("done"))).with_post(|shared, _, exec_res| {
+        shared.insert("result".to_string(), exec_res);
+        Ok(None)
+    }));
+    FaultyNode::wrap(inner, plan)
+}
+
+#[test]
+fn a_node_with_three_attempts_recovers_from_two_failures() {
+    let node = Arc::new(faulty(FaultPlan::new().fail_first(2).message("flaky")).retries(3, 0));
+    let flow = Flow::new(node.clone());
+    let mut shared = SharedState::new();
+    flow.run(&mut shared).unwrap();
+    assert_eq!(shared["result"], json!("done"));
+    assert_eq!(node.injected(), [Fault::Error { attempt: 0 }, Fault::Error { attempt: 1 }]);
+    assert_eq!(node.attempts(), 3);
+    
+    // Counters restart with every run
+    flow.run(&mut SharedState::new()).unwrap();
+    assert_eq!(node.injected().len(), 4);
+    
+    let node = Arc::new(faulty(FaultPlan::new().fail_first(2)).retries(2, 0));
+    let err = Flow::new(node.clone()).run(&mut SharedState::new()).unwrap_err();
+    assert!(err.to_string().contains("Injected fault"), "{}", err);
+}
+
+#[test]
+fn persistent_plans_count_attempts_across_runs() {
+    let node = faulty(FaultPlan::new().fail_first(1).persist_across_runs());
+    assert!(node.run(&mut SharedState::new()).is_err());
+    node.run(&mut SharedState::new()).unwrap();
+    assert_eq!(node.injected(), [Fault::Error { attempt: 0 }]);
+    assert_eq!(node.attempts(), 2);
+}
+
+#[test]
+fn plans_fail_every_kth_attempt_panic_once_and_add_latency() {
+    let sleeper = Arc::new(TestSleeper::new());
+    let node = faulty(FaultPlan::new().fail_every(3).panic_once().latency(Duration::from_millis(50)).persist_across_runs())
+        .retries(2, 10)
+        .with_sleeper(sleeper.clone());
+    for _ in 0..3 {
+        node.run(&mut SharedState::new()).unwrap();
+    }
+    assert_eq!(node.injected(), [Fault::Panic { attempt: 0 }, Fault::Error { attempt: 2 }]);
+    let (latency, wait) = (Duration::from_millis(50), Duration::from_millis(10));
+    assert_eq!(sleeper.requested(), [latency, wait, latency, latency, wait, latency, latency]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_failing_item_drops_its_running_siblings() {
+    let finished = Arc::new(AtomicUsize::new(0));
+    let done = finished.clone();
+    let slow = AsyncFnNode::new().with_exec(move |_| {
+        let done = done.clone();
+        async move {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            done.fetch_add(1, Ordering::SeqCst);
+            Ok(json!("done"))
+        }
+    });
+    // The first attempt of the whole batch fails at once; the other items sleep
+    let failing = Arc::new(FaultyNode::wrap(Arc::new(slow), FaultPlan::new().fail_first(1).persist_across_runs().message("item failed")));
+    let flow = AsyncParallelBatchFlow::new(failing.clone()).with_items(vec![ParamMap::new(); 4]);
+    
+    let err = flow.run_async(&mut SharedState::new()).await.unwrap_err();
+    match &err {
+        Error::BatchFailed { failures, results } => {
+            assert_eq!(failures.len(), 1);
+            assert!(failures[0].1.to_string().contains("item failed"), "{}", failures[0].1);
+            assert!(results.is_empty(), "{:?}", results);
+        },
+        other => panic!("unexpected error: {}", other),
+    }
+    assert_eq!(failing.injected(), [Fault::Error { attempt: 0 }]);
+    assert_eq!(failing.attempts(), 4, "every item was running when the first one failed");
+    
+    tokio::time::advance(Duration::from_secs(2)).await;
+    assert_eq!(finished.load(Ordering::SeqCst), 0, "the items still running were dropped");
+}
\ No newline at end of file
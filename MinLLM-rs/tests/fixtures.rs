@@ -0,0 +1,42 @@
+use minllm::testing::assert_topology_snapshot;
+use minllm::testing::fixtures::{self, Fixture, APPROVAL_TRACE, BATCH_TRACE, BRANCHING_ERROR_TRACE, BRANCHING_OK_TRACE, LINEAR_TRACE};
+
+/// Every fixture with the name of its snapshot
+fn all() -> Vec<(&'static str, Fixture)> {
+    vec![
+        ("fixture_linear", fixtures::linear(3, None)),
+        ("fixture_branching", fixtures::branching(false)),
+        ("fixture_approval_loop", fixtures::approval_loop(1)),
+        ("fixture_batch", fixtures::batch(100, None)),
+    ]
+}
+
+#[test]
+fn fixture_topologies_match_their_snapshots() {
+    for (name, fixture) in all() {
+        assert_topology_snapshot(name, &fixture.harness());
+    }
+}
+
+#[test]
+fn sync_and_async_fixtures_share_their_topology() {
+    for (_, fixture) in all() {
+        assert_eq!(fixture.harness().topology(), fixture.async_harness().topology());
+    }
+}
+
+#[test]
+fn expected_traces_match_the_constants() {
+    assert_eq!(fixtures::linear(3, None).expected(), LINEAR_TRACE);
+    assert_eq!(fixtures::branching(false).expected(), BRANCHING_OK_TRACE);
+    assert_eq!(fixtures::branching(true).expected(), BRANCHING_ERROR_TRACE);
+    assert_eq!(fixtures::approval_loop(1).expected(), APPROVAL_TRACE);
+    assert_eq!(fixtures::batch(100, None).expected(), BATCH_TRACE);
+}
+
+#[test]
+fn every_fixture_runs_its_expected_trace() {
+    for (_, fixture) in all() {
+        fixture.harness().run().assert_ok().assert_visited(&fixture.expected());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,406 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use serde_json::json;
+use minllm::{
+    actions, ActionName, AsyncFnNode, Error, EvictionPolicy, Flow, FnNode, JoinNode, KeySpec, NodeTrait, ParamMap, SharedState, StateLimit, StateOp, TypeConflict,
+    UnsatisfiedRead, ValidationIssue,
+};
+
+mod common;
+use common::{allocated_by, appender, named};
+
+/// Number of node steps in the loop benchmark
+const LOOP_STEPS: usize = 100_000;
+
+#[test]
+fn a_two_node_loop_runs_100k_steps_quickly() {
+    let ping: Arc<dyn NodeTrait> = Arc::new(FnNode::new().with_post(|shared, _, _| {
+        let count = shared["count"].as_u64().unwrap() + 1;
+        shared.insert("count".to_string(), json!(count));
+        Ok(Some(ActionName::new("pong")))
+    }));
+    let pong: Arc<dyn NodeTrait> = Arc::new(FnNode::new().with_post(|shared, _, _| {
+        let count = shared["count"].as_u64().unwrap() + 1;
+        shared.insert("count".to_string(), json!(count));
+        if count as usize >= LOOP_STEPS {
+            Ok(Some(ActionName::new("done")))
+        } else {
+            Ok(Some(ActionName::new("ping")))
+        }
+    }));
+    ping.add_successor(pong.clone(), "pong").unwrap();
+    pong.add_successor(ping.clone(), "ping").unwrap();
+    let flow = Flow::new(ping).with_max_steps(Some(LOOP_STEPS));
+    
+    let mut shared = SharedState::new();
+    shared.insert("count".to_string(), json!(0));
+    let started = Instant::now();
+    let allocated = allocated_by(|| flow.run(&mut shared).map(drop).unwrap());
+    let elapsed = started.elapsed();
+    
+    assert_eq!(shared["count"], json!(LOOP_STEPS));
+    // Generous enough for unoptimized builds on slow machines
+    assert!(elapsed < Duration::from_secs(30), "100k steps took {:?}", elapsed);
+    // The posts allocate the "count" key; finding the next node allocates nothing
+    assert!(allocated <= LOOP_STEPS * 8, "100k steps allocated {} bytes", allocated);
+}
+
+#[test]
+fn every_node_of_a_chain_runs() {
+    let first = appender("first", "next");
+    let second = appender("second", "next");
+    let third = appender("third", "done");
+    first.add_successor(second.clone(), "next").unwrap();
+    second.add_successor(third, "next").unwrap();
+    
+    let mut shared = SharedState::new();
+    let (result, trace) = Flow::new(first).run_traced(&mut shared);
+    result.unwrap();
+    assert_eq!(shared["log"], json!(["first", "second", "third"]));
+    assert_eq!(trace.last().map(|entry| entry.node_name.as_str()), Some("third"));
+    assert_eq!(trace.last().and_then(|entry| entry.action_taken.as_deref()), Some("done"));
+}
+
+#[test]
+fn a_cloned_flow_runs_the_same_nodes() {
+    let execs = Arc::new(AtomicUsize::new(0));
+    let counting = |action: &'static str| -> Arc<dyn NodeTrait> {
+        let execs = execs.clone();
+        Arc::new(
+            FnNode::new()
+                .with_exec(move |_| Ok(json!(execs.fetch_add(1, Ordering::SeqCst))))
+                .with_post(move |_, _, _| Ok(Some(ActionName::new(action)))),
+        )
+    };
+    let (first, second, third) = (counting("next"), counting("next"), counting("done"));
+    first.add_successor(second.clone(), "next").unwrap();
+    second.add_successor(third, "next").unwrap();
+    let flow = Flow::new(first);
+    
+    flow.run(&mut SharedState::new()).unwrap();
+    assert_eq!(execs.load(Ordering::SeqCst), 3);
+    let copy = flow.clone();
+    copy.set_params_map(ParamMap::from([("run".to_string(), json!(2))]));
+    copy.run(&mut SharedState::new()).unwrap();
+    assert_eq!(execs.load(Ordering::SeqCst), 6);
+    assert_eq!(flow.params().get("run"), Some(&json!(2)), "clones share their params");
+}
+
+#[test]
+fn rewired_successors_change_the_run_and_the_graph() {
+    let first = appender("first", "next");
+    first.add_successor(appender("mock", "done"), "next").unwrap();
+    first.add_successor(appender("audit", "done"), "audit").unwrap();
+    
+    first.replace_successor("next", appender("real", "done")).unwrap();
+    assert!(first.remove_successor("audit").is_some());
+    assert!(first.remove_successor("audit").is_none());
+    assert!(matches!(first.replace_successor("audit", appender("audit", "done")), Err(Error::MissingSuccessor(_))));
+    assert_eq!(first.successor_actions(), ["next"]);
+    
+    let flow = Flow::new(first);
+    let names: Vec<_> = flow.nodes().iter().map(|graph_node| graph_node.node.name()).collect();
+    assert_eq!(names, ["first", "real"]);
+    let mut shared = SharedState::new();
+    flow.run(&mut shared).unwrap();
+    assert_eq!(shared["log"], json!(["first", "real"]));
+}
+
+#[test]
+fn errors_name_the_nodes_they_come_from() {
+    let parse: Arc<dyn NodeTrait> = Arc::new(FnNode::new().with_exec(|_| Err(Error::NodeExecution("bad input".into()))));
+    assert_eq!(parse.name(), "FnNode");
+    parse.set_name("parse");
+    let inner: Arc<dyn NodeTrait> = Arc::new(Flow::new(parse).with_name("ingest"));
+    let start = appender("start", "next");
+    start.add_successor(inner, "next").unwrap();
+    
+    let err = Flow::new(start).run(&mut SharedState::new()).unwrap_err();
+    assert!(matches!(&err, Error::InNode { node, .. } if node == "ingest"), "{:?}", err);
+    assert_eq!(err.to_string(), "Node 'ingest' failed: Node 'parse' failed: Node execution error: bad input");
+    assert!(matches!(err.root(), Error::NodeExecution(message) if message == "bad input"));
+}
+
+/// Number of nodes in the generated flow benchmark
+const GENERATED_NODES: usize = 1_000;
+
+#[test]
+fn a_generated_1000_node_flow_builds_and_runs_quickly() {
+    let started = Instant::now();
+    let nodes: Vec<Arc<dyn NodeTrait>> = (0..GENERATED_NODES)
+        .map(|_| Arc::new(FnNode::new().with_post(|_, _, _| Ok(Some(ActionName::new("next"))))) as Arc<dyn NodeTrait>)
+        .collect();
+    let recovery = appender("recovery", "done");
+    for pair in nodes.windows(2) {
+        pair[0].add_successor(pair[1].clone(), "next").unwrap();
+        pair[0].add_successor(recovery.clone(), "error").unwrap();
+    }
+    let flow = Flow::new(nodes[0].clone()).with_max_steps(Some(GENERATED_NODES));
+    assert_eq!(flow.nodes().len(), GENERATED_NODES + 1);
+    let built = started.elapsed();
+    
+    let started = Instant::now();
+    let mut shared = SharedState::new();
+    let (result, trace) = flow.run_traced(&mut shared);
+    result.unwrap();
+    let ran = started.elapsed();
+    
+    assert_eq!(trace.len(), GENERATED_NODES);
+    assert!(!shared.contains_key("log"));
+    // Generous enough for unoptimized builds on slow machines
+    assert!(built + ran < Duration::from_secs(10), "building took {:?}, running took {:?}", built, ran);
+}
+
+#[test]
+fn a_flow_runs_again_after_a_node_panicked() {
+    // The first run panics in exec, which fails the flow; the second panics in post, which escapes it
+    let runs = Arc::new(AtomicUsize::new(0));
+    let (exec_runs, post_runs) = (runs.clone(), runs.clone());
+    let flaky: Arc<dyn NodeTrait> = Arc::new(FnNode::new().with_exec(move |_| {
+        if exec_runs.load(Ordering::SeqCst) == 0 {
+            panic!("exec blows up");
+        }
+        Ok(json!("ok"))
+    }).with_post(move |_, _, _| {
+        if post_runs.fetch_add(1, Ordering::SeqCst) == 1 {
+            panic!("post blows up");
+        }
+        Ok(None)
+    }));
+    flaky.add_successor(appender("after", "done"), "default").unwrap();
+    let flow = Flow::new(flaky.clone());
+    flow.set_params_map(ParamMap::from([("run".to_string(), json!(1))]));
+    
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let err = flow.run(&mut SharedState::new()).unwrap_err();
+    runs.store(1, Ordering::SeqCst);
+    let escaped = panic::catch_unwind(AssertUnwindSafe(|| flow.run(&mut SharedState::new())));
+    panic::set_hook(hook);
+    assert!(err.to_string().contains("exec blows up"), "{}", err);
+    assert!(escaped.is_err());
+    
+    let mut shared = SharedState::new();
+    flow.run(&mut shared).unwrap();
+    assert_eq!(shared["log"], json!(["after"]));
+    assert_eq!(flaky.params().get("run"), Some(&json!(1)));
+    assert_eq!(flaky.successor_actions(), ["default"]);
+}
+
+/// A node storing `value` under "result" and copying what it sees under `read` to "seen"
+fn writer(value: &'static str, read: &'static str) -> Arc<dyn NodeTrait> {
+    Arc::new(FnNode::new().with_post(move |shared, _, _| {
+        let seen = shared.get(read).cloned().unwrap_or_default();
+        shared.insert("seen".to_string(), seen);
+        shared.insert("result".to_string(), json!(value));
+        Ok(None)
+    }))
+}
+
+#[test]
+fn a_namespaced_sub_flow_keeps_its_keys_apart() {
+    let sub: Arc<dyn NodeTrait> = Arc::new(Flow::new(writer("inner", "../result")).with_namespace("sub"));
+    let outer = writer("outer", "result");
+    outer.add_successor(sub, "default").unwrap();
+    
+    let mut shared = SharedState::new();
+    Flow::new(outer).run(&mut shared).unwrap();
+    assert_eq!(shared["result"], json!("outer"));
+    assert_eq!(shared["sub/result"], json!("inner"));
+    assert_eq!(shared["sub/seen"], json!("outer"), "sub-flows reach the parent through ../");
+    assert_eq!(shared["seen"], json!(null));
+}
+
+#[test]
+fn history_names_the_node_behind_every_write() {
+    let first = appender("first", "next");
+    first.add_successor(appender("second", "done"), "next").unwrap();
+    let flow = Flow::new(first).with_history(16);
+    flow.run(&mut SharedState::new()).unwrap();
+    let writers: Vec<_> = flow.history_for("log").into_iter().map(|event| (event.writer, event.op)).collect();
+    assert_eq!(writers, [
+        (Some("first".to_string()), StateOp::Set(json!(["first"]))),
+        (Some("second".to_string()), StateOp::Set(json!(["first", "second"]))),
+    ]);
+    assert!(Flow::new(appender("first", "done")).history().is_empty());
+}
+
+/// A node writing "item/{n}" for n = 1, 2, ... and looping until it has written `items` keys
+fn filler(items: u64) -> Arc<dyn NodeTrait> {
+    let node: Arc<dyn NodeTrait> = Arc::new(FnNode::new().with_post(move |shared, _, _| {
+        let n = shared.get("written").and_then(|n| n.as_u64()).unwrap_or(0) + 1;
+        shared.insert("written".to_string(), json!(n));
+        shared.insert(format!("item/{}", n), json!(n));
+        Ok(Some(ActionName::new(if n < items { "next" } else { "done" })))
+    }));
+    node.add_successor(node.clone(), "next").unwrap();
+    node
+}
+
+#[test]
+fn a_state_limit_bounds_long_runs_and_keeps_pinned_keys() {
+    let limit = StateLimit::new(100, EvictionPolicy::LeastRecentlyWritten).pin("config");
+    let flow = Flow::new(filler(5_000)).with_max_steps(None).with_state_limit(limit);
+    let mut shared = SharedState::from([("config".to_string(), json!("fast"))]);
+    flow.run(&mut shared).unwrap();
+    assert_eq!(shared.len(), 100);
+    assert_eq!(shared["config"], json!("fast"));
+    assert_eq!(shared["written"], json!(5_000));
+    assert!((4_903..=5_000).all(|n| shared.contains_key(&format!("item/{}", n))));
+    
+    let flow = Flow::new(filler(5_000)).with_max_steps(None).with_state_limit(StateLimit::new(10, EvictionPolicy::RejectNew));
+    let mut shared = SharedState::new();
+    let err = flow.run(&mut shared).unwrap_err();
+    assert!(err.to_string().contains("over the limit of 10"), "{}", err);
+    assert_eq!(shared.len(), 10);
+    assert!(!shared.contains_key("item/10"));
+}
+
+#[cfg(feature = "testing")]
+mod fixtures {
+    use minllm::testing::fixtures::{self, APPROVAL_TRACE, BRANCHING_ERROR_TRACE, BRANCHING_OK_TRACE, LINEAR_TRACE};
+    
+    use super::*;
+    
+    #[test]
+    fn linear_flows_visit_every_step() {
+        fixtures::linear(3, None).harness().run().assert_ok().assert_visited(LINEAR_TRACE).assert_action("default");
+    }
+    
+    #[test]
+    fn a_failing_step_ends_the_flow() {
+        let fixture = fixtures::linear(5, Some(2));
+        fixture
+            .harness()
+            .run()
+            .assert_error_contains("injected failure at step3")
+            .assert_visited(&["step1", "step2", "step3"])
+            .assert_not_visited("step4");
+    }
+    
+    #[test]
+    fn branching_flows_follow_the_returned_action() {
+        fixtures::branching(false).harness().run().assert_ok().assert_visited(BRANCHING_OK_TRACE);
+        fixtures::branching(true).harness().run().assert_ok().assert_visited(BRANCHING_ERROR_TRACE).assert_not_visited("handle");
+    }
+    
+    #[test]
+    fn fixtures_replay_their_scripts_on_every_run() {
+        let fixture = fixtures::approval_loop(1);
+        fixture.harness().run().assert_ok().assert_visited(APPROVAL_TRACE);
+        fixture.flow().run(&mut SharedState::new()).unwrap();
+        fixture.harness().run().assert_ok().assert_visited(APPROVAL_TRACE);
+    }
+    
+    #[test]
+    fn loops_run_until_approved() {
+        let fixture = fixtures::approval_loop(3);
+        let result = fixture.harness().run();
+        result.assert_ok().assert_visited(&fixture.expected());
+        assert_eq!(result.trace.iter().filter(|step| step.node == "draft").count(), 4);
+    }
+}
+
+/// A node named `name` declaring `reads` and `writes`, returning "next"
+fn declared(name: &str, reads: Vec<KeySpec>, writes: Vec<KeySpec>) -> Arc<dyn NodeTrait> {
+    let node = reads.into_iter().fold(FnNode::new(), FnNode::declare_read);
+    let node = writes.into_iter().fold(node, FnNode::declare_write);
+    let node: Arc<dyn NodeTrait> = Arc::new(node.with_post(|_, _, _| Ok(Some(ActionName::new("next")))));
+    node.set_name(name);
+    node
+}
+
+#[test]
+fn dataflow_checks_reads_against_upstream_writes() {
+    let load = declared("load", vec![KeySpec::new("path")], vec![KeySpec::new("doc").of_type("string"), KeySpec::new("pages").of_type("integer")]);
+    let summarize = declared(
+        "summarize",
+        vec![KeySpec::new("doc").of_type("object"), KeySpec::new("pages").of_type("number"), KeySpec::new("notes")],
+        vec![KeySpec::new("summary")],
+    );
+    let review = declared("review", vec![KeySpec::new("summary")], vec![KeySpec::new("notes")]);
+    load.add_successor(summarize.clone(), "next").unwrap();
+    summarize.add_successor(review, "next").unwrap();
+    let flow = Flow::new(load);
+    
+    let report = flow.check_dataflow(&["path"]);
+    assert!(!report.is_ok());
+    assert_eq!(
+        report.unsatisfied,
+        [UnsatisfiedRead { node: "summarize".to_string(), key: "notes".to_string(), writers: vec!["review".to_string()] }]
+    );
+    assert_eq!(
+        report.type_conflicts,
+        [TypeConflict {
+            key: "doc".to_string(),
+            reader: "summarize".to_string(),
+            expected: "object".to_string(),
+            writer: "load".to_string(),
+            found: "string".to_string(),
+        }],
+        "integer pages satisfy a number read"
+    );
+    assert_eq!(flow.check_dataflow(&[]).unsatisfied[0].key, "path");
+}
+
+actions! {
+    enum Review {
+        Approve => "approve",
+        Reject => "reject",
+    }
+}
+
+#[test]
+fn validation_reports_errors_and_warnings_by_node() {
+    let review: Arc<dyn NodeTrait> = Arc::new(FnNode::new().with_actions::<Review>());
+    review.set_name("review");
+    let (publish, notify): (_, Arc<dyn NodeTrait>) = (named("publish"), Arc::new(AsyncFnNode::new()));
+    notify.set_name("notify");
+    review.add_successor(publish.clone(), "approve").unwrap();
+    review.add_successor(named("again"), "retry").unwrap();
+    publish.add_successor(notify.clone(), "default").unwrap();
+    publish.add_successor(notify, "default").unwrap();
+    let flow = Flow::new(review);
+    
+    let report = flow.validate().unwrap();
+    let issues = |issues: &[ValidationIssue]| issues.iter().map(ToString::to_string).collect::<Vec<_>>();
+    assert_eq!(
+        issues(&report.errors),
+        [
+            "review: no successor for action 'reject'",
+            "publish: 'notify' is a successor for 'default' more than once",
+            "notify: only runs asynchronously, so it needs an AsyncFlow",
+        ]
+    );
+    assert_eq!(
+        issues(&report.warnings),
+        [
+            "review: has a successor for 'retry', an action it never returns",
+            "again: is unreachable from the start node through the actions nodes return",
+        ]
+    );
+    
+    let strict = flow.with_strict(true);
+    match strict.run(&mut SharedState::new()) {
+        Err(Error::InvalidFlow(found)) => assert_eq!(found, report),
+        other => panic!("a strict run of an invalid flow gave {:?}", other),
+    }
+}
+#[test]
+fn a_join_on_a_cycle_fails_validation() {
+    let (start, a, b, check) = (named("start"), named("a"), named("b"), named("check"));
+    let join: Arc<dyn NodeTrait> = Arc::new(JoinNode::new(&["a", "b"]));
+    join.set_name("join");
+    start.add_successor(a.clone(), "default").unwrap();
+    start.add_successor(b.clone(), "default").unwrap();
+    a.add_successor(join.clone(), "default").unwrap();
+    b.add_successor(join.clone(), "default").unwrap();
+    join.add_successor(check.clone(), "default").unwrap();
+    check.add_successor(start.clone(), "again").unwrap();
+    
+    let report = Flow::new(start).validate().unwrap();
+    let errors: Vec<String> = report.errors.iter().map(ToString::to_string).collect();
+    assert_eq!(errors, ["join: join is part of a cycle"]);
+}
\ No newline at end of file
@@ -0,0 +1,233 @@
+use std::sync::Arc;
+use serde_json::{json, Value};
+use minllm::testing::{FlowTestHarness, FlowTestResult};
+use minllm::{AccessKind, ActionName, AsyncFlow, AsyncFnNode, Flow, FnNode, NodeTrait};
+
+mod common;
+use common::panic_message;
+
+/// A node that stores its exec result under `key` and returns `action`
+fn writer(key: &'static str, value: Value, action: Option<&'static str>) -> Arc<dyn NodeTrait> {
+    Arc::new(FnNode::new().with_exec(move |_| Ok(value.clone())).with_post(move |shared, _, exec_res| {
+        shared.insert(key.to_string(), exec_res);
+        Ok(action.map(ActionName::new))
+    }))
+}
+
+/// Nodes with the names the harness reports them under
+type Named = Vec<(&'static str, Arc<dyn NodeTrait>)>;
+
+/// fetch -> summarize -> publish, with an error handler summarize never routes to
+fn pipeline() -> (Flow, Named) {
+    let fetch = writer("page", json!("<html>"), None);
+    let summarize = writer("summary", json!("short"), Some("publish"));
+    let publish = writer("published", json!(true), Some("done"));
+    let handler = writer("error", json!("handled"), None);
+    fetch.add_successor(summarize.clone(), "default").unwrap();
+    summarize.add_successor(publish.clone(), "publish").unwrap();
+    summarize.add_successor(handler.clone(), "error").unwrap();
+    let nodes = vec![("fetch", fetch.clone()), ("summarize", summarize), ("publish", publish), ("error_handler", handler)];
+    (Flow::new(fetch), nodes)
+}
+
+fn harness(flow: Flow, nodes: &[(&str, Arc<dyn NodeTrait>)]) -> FlowTestHarness {
+    nodes.iter().fold(FlowTestHarness::new(flow), |harness, (name, node)| harness.name(node, name))
+}
+
+/// The message a failed assertion panics with, with durations replaced by `<duration>`
+fn failure_message(assertion: impl FnOnce()) -> String {
+    let message = panic_message(assertion);
+    let mut normalized = String::new();
+    for line in message.lines() {
+        let duration = line.find(" (").and_then(|open| line[open..].find(')').map(|len| (open, open + len)));
+        let line = match duration {
+            Some((open, close)) => format!("{} (<duration>{}", &line[..open], &line[close..]),
+            None => line.to_string(),
+        };
+        normalized.push_str(&line);
+        normalized.push('\n');
+    }
+    normalized
+}
+
+#[test]
+fn runs_through_the_traced_run() {
+    let (flow, nodes) = pipeline();
+    let result = harness(flow, &nodes).with_state(json!({"url": "https://example.com"})).run();
+    result
+        .assert_ok()
+        .assert_visited(&["fetch", "summarize", "publish"])
+        .assert_not_visited("error_handler")
+        .assert_action("done")
+        .assert_store_eq("summary", json!("short"))
+        .assert_store_eq("url", json!("https://example.com"))
+        .assert_written_once_by("summary", "summarize")
+        .assert_node_duration_under("fetch", 5_000);
+    assert_eq!(result.writes_by("publish"), ["published"]);
+}
+
+#[test]
+fn hands_params_to_the_start_node() {
+    let start: Arc<dyn NodeTrait> = Arc::new(FnNode::new());
+    let result = FlowTestHarness::new(Flow::new(start.clone())).name(&start, "start").with_params(json!({"topic": "rust"})).run();
+    result.assert_ok().assert_visited(&["start"]);
+    assert_eq!(start.params().get("topic"), Some(&json!("rust")));
+}
+
+#[test]
+fn includes_the_steps_of_nested_flows() {
+    let (inner, nodes) = pipeline();
+    let inner: Arc<dyn NodeTrait> = Arc::new(inner.with_name("pipeline"));
+    let archive = writer("archived", json!(true), Some("archived"));
+    inner.add_successor(archive.clone(), "default").unwrap();
+    let result = harness(Flow::new(inner), &nodes).name(&archive, "archive").run();
+    result
+        .assert_ok()
+        .assert_visited(&["pipeline", "fetch", "summarize", "publish", "archive"])
+        .assert_action("archived");
+}
+
+#[test]
+fn reports_the_failing_node() {
+    let fetch: Arc<dyn NodeTrait> = Arc::new(FnNode::new().with_exec(|_| Err(minllm::Error::NodeExecution("offline".into()))));
+    let result = FlowTestHarness::new(Flow::new(fetch.clone())).name(&fetch, "fetch").run();
+    result.assert_error_contains("offline").assert_visited(&["fetch"]);
+    assert_eq!(result.action, None);
+    assert_eq!(result.trace[0].error.as_deref(), Some("Node 'fetch' failed: Node execution error: offline"));
+}
+
+#[tokio::test]
+async fn runs_async_flows() {
+    let fetch: Arc<dyn NodeTrait> = Arc::new(AsyncFnNode::new().with_exec(|_| async { Ok(json!("<html>")) }).with_post(|shared, _, exec_res| {
+        shared.insert("page".to_string(), exec_res);
+        Ok(Some(ActionName::new("parse")))
+    }));
+    let parse = writer("title", json!("Example"), Some("done"));
+    fetch.add_successor(parse.clone(), "parse").unwrap();
+    let result = FlowTestHarness::new_async(AsyncFlow::new(fetch.clone()))
+        .name(&fetch, "fetch")
+        .name(&parse, "parse")
+        .run_async()
+        .await;
+    result
+        .assert_ok()
+        .assert_visited(&["fetch", "parse"])
+        .assert_action("done")
+        .assert_store_eq("title", json!("Example"))
+        .assert_written_once_by("page", "fetch");
+}
+
+#[test]
+#[should_panic(expected = "use run_async")]
+fn async_harnesses_need_run_async() {
+    let node: Arc<dyn NodeTrait> = Arc::new(FnNode::new());
+    FlowTestHarness::new_async(AsyncFlow::new(node)).run();
+}
+
+#[test]
+fn failed_assertions_print_the_trace_and_store_diff() {
+    let (flow, nodes) = pipeline();
+    let result: FlowTestResult = harness(flow, &nodes).with_state(json!({"url": "https://example.com", "stale": 1})).run();
+    
+    let message = failure_message(|| {
+        result.assert_visited(&["fetch", "error_handler"]);
+    });
+    assert_eq!(message, r#"expected visits ["fetch", "error_handler"], got ["fetch", "summarize", "publish"]
+--- trace ---
+1. fetch -> None (<duration>) wrote ["page"]
+2. summarize -> Some("publish") (<duration>) wrote ["summary"]
+3. publish -> Some("done") (<duration>) wrote ["published"]
+--- store diff ---
++ page: "<html>"
++ published: true
++ summary: "short"
+"#);
+    
+    let message = failure_message(|| {
+        result.assert_store_eq("summary", json!("long"));
+    });
+    assert!(message.starts_with("expected 'summary' to be \"long\", got Some(String(\"short\"))\n--- trace ---\n"), "{}", message);
+}
+/// draft writes "summary" and "draft", edit rewrites "summary" and removes "draft",
+/// and check writes "summary" back unchanged and stores a key under "secret/"
+fn editing() -> (Flow, Named) {
+    let draft: Arc<dyn NodeTrait> = Arc::new(FnNode::new().with_post(|shared, _, _| {
+        shared.insert("summary".to_string(), json!("rough"));
+        shared.insert("draft".to_string(), json!(1));
+        Ok(None)
+    }));
+    let edit: Arc<dyn NodeTrait> = Arc::new(FnNode::new().with_post(|shared, _, _| {
+        shared.insert("summary".to_string(), json!("polished"));
+        shared.remove("draft");
+        Ok(None)
+    }));
+    let check: Arc<dyn NodeTrait> = Arc::new(FnNode::new().with_post(|shared, _, _| {
+        let summary = shared["summary"].clone();
+        shared.insert("summary".to_string(), summary);
+        shared.insert("secret/token".to_string(), json!("abc"));
+        Ok(None)
+    }));
+    draft.add_successor(edit.clone(), "default").unwrap();
+    edit.add_successor(check.clone(), "default").unwrap();
+    (Flow::new(draft.clone()), vec![("draft", draft), ("edit", edit), ("check", check)])
+}
+
+#[test]
+fn attributes_writes_to_the_nodes_that_made_them() {
+    let (flow, nodes) = editing();
+    let result = harness(flow, &nodes).run();
+    result.assert_ok().assert_no_writes_to_prefix("cache/");
+    assert_eq!(result.writes_to("summary"), ["draft", "edit", "check"], "writing a value back unchanged is a write");
+    assert_eq!(result.writes_to("draft"), ["draft", "edit"], "removing a key writes it");
+    assert_eq!(result.writes_by("draft"), ["draft", "summary"]);
+    assert_eq!(result.writes_by("check"), ["secret/token", "summary"]);
+    assert_eq!(result.reads_by("check"), ["summary"]);
+    assert!(result.reads_by("draft").is_empty());
+    assert!(result.writes_to("missing").is_empty());
+}
+
+#[test]
+fn failed_write_assertions_name_the_writers() {
+    let (flow, nodes) = editing();
+    let result = harness(flow, &nodes).run();
+    
+    let message = failure_message(|| {
+        result.assert_written_once_by("summary", "edit");
+    });
+    assert!(message.starts_with("expected 'summary' to be written once by 'edit', writers were [\"draft\", \"edit\", \"check\"]\n"), "{}", message);
+    
+    let message = failure_message(|| {
+        result.assert_no_writes_to_prefix("secret/");
+    });
+    assert!(message.starts_with("expected no writes under 'secret/', got [\"secret/token by check\"]\n"), "{}", message);
+}
+
+#[test]
+fn logs_every_access_including_unchanged_rewrites() {
+    let stamp: Arc<dyn NodeTrait> = Arc::new(FnNode::new().with_post(|shared, _, _| {
+        let count = shared["count"].clone();
+        shared.insert("count".to_string(), count.clone());
+        shared.insert("count".to_string(), count);
+        Ok(None)
+    }));
+    let inner: Arc<dyn NodeTrait> = Arc::new(Flow::new(stamp.clone()));
+    let result = FlowTestHarness::new(Flow::new(inner.clone()))
+        .name(&stamp, "stamp")
+        .name(&inner, "inner")
+        .with_state(json!({"count": 1}))
+        .run();
+    
+    result.assert_ok().assert_store_eq("count", json!(1));
+    assert!(result.trace.iter().all(|step| step.writes.is_empty()), "the state never changed");
+    assert_eq!(result.writes_to("count"), ["stamp", "stamp"], "each write counts, even within one step");
+    assert_eq!(result.reads_by("stamp"), ["count"]);
+    assert!(result.reads_by("inner").is_empty() && result.writes_by("inner").is_empty(), "accesses belong to the innermost node");
+    let kinds: Vec<AccessKind> = result.accesses.iter().map(|access| access.kind).collect();
+    assert_eq!(kinds, [AccessKind::Read, AccessKind::Write, AccessKind::Write]);
+    assert!(result.accesses.windows(2).all(|pair| pair[0].at <= pair[1].at));
+    
+    let message = failure_message(|| {
+        result.assert_written_once_by("count", "stamp");
+    });
+    assert!(message.starts_with("expected 'count' to be written once by 'stamp', writers were [\"stamp\", \"stamp\"]\n"), "{}", message);
+}
\ No newline at end of file
@@ -0,0 +1,69 @@
+use std::sync::Arc;
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use minllm::{current_attempt, ActionName, Error, Flow, FnNode, NodeHooks, NodeTrait, SharedState};
+
+/// Hooks appending "before", "after" or "error" events with the node name to `events`
+fn recording(events: &Arc<Mutex<Vec<String>>>) -> NodeHooks {
+    let (before, after, failed) = (events.clone(), events.clone(), events.clone());
+    NodeHooks::new()
+        .before(move |node, prep_res| before.lock().push(format!("before {} {}", node, prep_res)))
+        .after(move |node, exec_res| after.lock().push(format!("after {} {}", node, exec_res)))
+        .on_error(move |node, error| failed.lock().push(format!("error {} {}", node, error.root())))
+}
+
+/// A node named `name` whose exec doubles its prep result of 21
+fn doubler(name: &str) -> Arc<dyn NodeTrait> {
+    let node: Arc<dyn NodeTrait> = Arc::new(
+        FnNode::new()
+            .with_prep(|_| Ok(json!(21)))
+            .with_exec(|prep| Ok(json!(prep.as_i64().unwrap() * 2)))
+            .with_post(|_, _, _| Ok(Some(ActionName::new("next")))),
+    );
+    node.set_name(name);
+    node
+}
+
+#[test]
+fn flow_default_hooks_observe_every_node() {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let first = doubler("first");
+    first.add_successor(doubler("second"), "next").unwrap();
+    let flow = Flow::new(first).with_default_hooks(recording(&events));
+    
+    flow.run(&mut SharedState::new()).unwrap();
+    assert_eq!(*events.lock(), ["before first 21", "after first 42", "before second 21", "after second 42"]);
+}
+
+#[test]
+fn a_node_own_hooks_take_precedence() {
+    let (defaults, own) = (Arc::new(Mutex::new(Vec::new())), Arc::new(Mutex::new(Vec::new())));
+    let first = doubler("first");
+    let seen = own.clone();
+    first.set_hooks(NodeHooks::new().after(move |node, _| seen.lock().push(format!("own {}", node))));
+    let flow = Flow::new(first).with_default_hooks(recording(&defaults));
+    
+    flow.run(&mut SharedState::new()).unwrap();
+    assert_eq!(*own.lock(), ["own first"]);
+    assert_eq!(*defaults.lock(), ["before first 21"], "the defaults fill only the hooks left unset");
+}
+
+#[test]
+fn hooks_fire_once_per_run_whatever_the_retries() {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let flaky = FnNode::new()
+        .with_exec(|_| match current_attempt() {
+            Some(0) => Err(Error::NodeExecution("first attempt fails".into())),
+            _ => Ok(Value::Null),
+        })
+        .retries(3, 0);
+    flaky.set_hooks(recording(&events));
+    flaky.run(&mut SharedState::new()).unwrap();
+    assert_eq!(*events.lock(), ["before FnNode null", "after FnNode null"]);
+    
+    events.lock().clear();
+    let failing = FnNode::new().with_exec(|_| Err(Error::NodeExecution("down".into()))).retries(3, 0);
+    failing.set_hooks(recording(&events));
+    assert!(failing.run(&mut SharedState::new()).is_err());
+    assert_eq!(*events.lock(), ["before FnNode null", "error FnNode Node execution error: down"]);
+}
\ No newline at end of file
@@ -0,0 +1,102 @@
+use std::panic;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use serde_json::{json, Value};
+use minllm::{
+    current_attempt, ActionName, AsyncFlow, AsyncFnNode, AsyncNodeTrait, BlockingNode, Error, FnNode, MergePolicy, NodeTrait, ParamMap, SharedState,
+    SyncAdapter,
+};
+
+/// Append `entry` to the "log" list
+fn log(shared: &mut SharedState, entry: Value) {
+    shared.entry("log".to_string()).or_insert_with(|| json!([])).as_array_mut().unwrap().push(entry);
+}
+
+/// A sync node logging `name`, wrapped for async flows
+fn sync_step(name: &'static str, blocking: bool) -> Arc<dyn NodeTrait> {
+    let node: Arc<dyn NodeTrait> = Arc::new(FnNode::new().with_post(move |shared, _, _| {
+        log(shared, json!(name));
+        Ok(Some(ActionName::new("next")))
+    }));
+    node.set_name(name);
+    Arc::new(SyncAdapter::wrap(node).with_blocking(blocking))
+}
+
+/// An async node logging `name`
+fn async_step(name: &'static str) -> Arc<dyn NodeTrait> {
+    Arc::new(AsyncFnNode::new().with_exec(|_| async { Ok(Value::Null) }).with_post(move |shared, _, _| {
+        log(shared, json!(name));
+        Ok(Some(ActionName::new("next")))
+    }))
+}
+
+/// Chain `nodes` on the "next" action
+fn chain(nodes: &[Arc<dyn NodeTrait>]) -> Arc<dyn NodeTrait> {
+    for pair in nodes.windows(2) {
+        pair[0].add_successor(pair[1].clone(), "next").unwrap();
+    }
+    nodes[0].clone()
+}
+
+#[tokio::test]
+async fn sync_and_async_nodes_alternate_in_one_flow() {
+    let start = chain(&[async_step("fetch"), sync_step("parse", false), async_step("enrich"), sync_step("score", true)]);
+    let mut shared = SharedState::new();
+    AsyncFlow::new(start).run_async(&mut shared).await.unwrap();
+    assert_eq!(shared["log"], json!(["fetch", "parse", "enrich", "score"]));
+}
+
+#[tokio::test]
+async fn adapters_pass_on_params_successors_and_errors() {
+    let inner: Arc<dyn NodeTrait> = Arc::new(FnNode::new().with_exec(|_| Err(Error::NodeExecution("parse failed".into()))));
+    inner.set_name("parse");
+    let adapter: Arc<dyn NodeTrait> = Arc::new(SyncAdapter::wrap(inner.clone()));
+    adapter.add_successor(async_step("after"), "next").unwrap();
+    adapter.set_params_map(ParamMap::from([("run".to_string(), json!(3))]));
+    
+    assert_eq!(adapter.name(), "parse");
+    assert_eq!(inner.successor_actions(), ["next"]);
+    assert_eq!(inner.params().get("run"), Some(&json!(3)));
+    let err = AsyncFlow::new(adapter).run_async(&mut SharedState::new()).await.unwrap_err();
+    assert!(err.to_string().contains("parse failed"), "{}", err);
+}
+
+#[tokio::test]
+async fn a_slow_blocking_node_does_not_delay_async_branches() {
+    let started = Instant::now();
+    let slow: Arc<dyn NodeTrait> = Arc::new(BlockingNode::new().with_exec(|_| {
+        std::thread::sleep(Duration::from_millis(300));
+        Ok(json!("parsed"))
+    }));
+    let fast = |name: &'static str| -> Arc<dyn NodeTrait> {
+        Arc::new(AsyncFnNode::new().with_post(move |shared, _, _| {
+            shared.insert(name.to_string(), json!(started.elapsed().as_millis() as u64));
+            Ok(None)
+        }))
+    };
+    let start: Arc<dyn NodeTrait> = Arc::new(AsyncFnNode::new());
+    start.add_successors("default", vec![slow, fast("first"), fast("second"), fast("rest")]).unwrap();
+    
+    let mut shared = SharedState::new();
+    AsyncFlow::new(start).with_concurrent_fan_out(MergePolicy::LastWins).run_async(&mut shared).await.unwrap();
+    for branch in ["first", "second"] {
+        assert!(shared[branch].as_u64().unwrap() < 100, "{} was held up for {}ms", branch, shared[branch]);
+    }
+    assert!(shared["rest"].as_u64().unwrap() >= 300, "the flow continues once every branch ended");
+}
+
+#[tokio::test]
+async fn blocking_attempts_that_panic_are_retried() {
+    let node = BlockingNode::new()
+        .with_exec(|_| match current_attempt() {
+            Some(0) => panic!("tokenizer crashed"),
+            _ => Ok(json!("tokens")),
+        })
+        .retries(2, 0);
+    
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let result = node._exec_async(&Value::Null).await;
+    panic::set_hook(hook);
+    assert_eq!(result.unwrap(), json!("tokens"));
+}
\ No newline at end of file
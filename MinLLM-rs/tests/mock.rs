@@ -0,0 +1,98 @@
+use std::sync::Arc;
+use std::time::Duration;
+use serde_json::json;
+use minllm::testing::{MockCall, MockNode};
+use minllm::{ActionName, AsyncFlow, AsyncNodeTrait, Flow, NodeTrait, SharedState, TestSleeper};
+
+#[test]
+fn execs_follow_the_script_then_return_null() {
+    let mock = MockNode::new().returns(json!(1)).returns(json!(2));
+    let node: Arc<dyn NodeTrait> = Arc::new(mock.clone());
+    let mut shared = SharedState::new();
+    for _ in 0..3 {
+        node.run(&mut shared).unwrap();
+    }
+    
+    let results: Vec<_> = mock
+        .calls()
+        .into_iter()
+        .filter_map(|call| match call {
+            MockCall::Post { exec_res, .. } => Some(exec_res),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(results, [json!(1), json!(2), json!(null)]);
+}
+
+#[test]
+fn failed_attempts_are_retried_and_recorded() {
+    let mock = MockNode::new().with_prep(json!("doc")).fails("rate limited").returns(json!("summary")).retries(2);
+    let node: Arc<dyn NodeTrait> = Arc::new(mock.clone());
+    let mut shared = SharedState::new();
+    shared.insert("url".to_string(), json!("https://example.com"));
+    node.run(&mut shared).unwrap();
+    
+    assert_eq!(mock.exec_attempts(), 2);
+    assert_eq!(mock.calls(), [
+        MockCall::Prep(shared.clone()),
+        MockCall::Exec { prep_res: json!("doc"), attempt: 0 },
+        MockCall::Exec { prep_res: json!("doc"), attempt: 1 },
+        MockCall::Post { prep_res: json!("doc"), exec_res: json!("summary") },
+    ]);
+}
+
+#[test]
+fn the_last_failure_fails_the_run() {
+    let mock = MockNode::new().fails("first").fails("second").retries(2);
+    let node: Arc<dyn NodeTrait> = Arc::new(mock.clone());
+    let error = node.run(&mut SharedState::new()).unwrap_err();
+    assert!(error.to_string().contains("second"), "{}", error);
+    assert!(!mock.calls().iter().any(|call| matches!(call, MockCall::Post { .. })));
+}
+
+#[test]
+fn actions_follow_the_script_then_the_default() {
+    let mock = MockNode::new().then_action("retry").default_action(Some(ActionName::new("done")));
+    let node: Arc<dyn NodeTrait> = Arc::new(mock.clone());
+    let mut shared = SharedState::new();
+    assert_eq!(node.run(&mut shared).unwrap().as_deref(), Some("retry"));
+    assert_eq!(node.run(&mut shared).unwrap().as_deref(), Some("done"));
+    
+    mock.rewind();
+    assert_eq!(node.run(&mut shared).unwrap().as_deref(), Some("retry"));
+}
+
+#[test]
+fn delays_go_through_the_sleeper() {
+    let sleeper = Arc::new(TestSleeper::new());
+    let mock = MockNode::new().fails("slow").with_delay(Duration::from_millis(250)).with_sleeper(sleeper.clone()).retries(2);
+    let node: Arc<dyn NodeTrait> = Arc::new(mock);
+    node.run(&mut SharedState::new()).unwrap();
+    assert_eq!(sleeper.requested(), [Duration::from_millis(250); 2]);
+}
+
+#[tokio::test]
+async fn mocks_run_in_async_flows() {
+    let mock = MockNode::new().fails("flaky").returns(json!("ok")).retries(2);
+    let flow = AsyncFlow::new(Arc::new(mock.clone()));
+    flow.run_async(&mut SharedState::new()).await.unwrap();
+    assert_eq!(mock.exec_attempts(), 2);
+}
+
+#[test]
+fn two_mocks_check_a_branching_flow_routes_by_action() {
+    let classify: Arc<dyn NodeTrait> = Arc::new(MockNode::new().then_action("urgent").then_action("routine"));
+    let urgent = MockNode::new();
+    let routine = MockNode::new();
+    classify.add_successor(Arc::new(urgent.clone()), "urgent").unwrap();
+    classify.add_successor(Arc::new(routine.clone()), "routine").unwrap();
+    let flow = Flow::new(classify);
+    
+    flow.run(&mut SharedState::new()).unwrap();
+    assert_eq!(urgent.calls().len(), 3);
+    assert!(routine.calls().is_empty());
+    
+    flow.run(&mut SharedState::new()).unwrap();
+    assert_eq!(urgent.calls().len(), 3);
+    assert_eq!(routine.calls().len(), 3);
+}
\ No newline at end of file
@@ -0,0 +1,177 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use futures::future::BoxFuture;
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use tokio::time::Instant;
+use minllm::{current_attempt, AsyncBatchNode, AsyncNodeTrait, AsyncParallelBatchNode, BatchProgress, Error, ErrorPolicy, Result, ResultOrder};
+
+/// An exec closure waiting `10 * (5 - n)` milliseconds for item `n`, failing item 2
+///
+/// Later items finish first when run concurrently.
+fn reversed(item: Value) -> BoxFuture<'static, Result<Value>> {
+    Box::pin(async move {
+        let n = item.as_u64().unwrap();
+        tokio::time::sleep(Duration::from_millis(10 * (5 - n))).await;
+        match n {
+            2 => Err(Error::NodeExecution("item 2 failed".into())),
+            n => Ok(json!(n)),
+        }
+    })
+}
+
+/// A progress callback appending every report to `reports`
+fn record(reports: &Arc<Mutex<Vec<BatchProgress>>>) -> minllm::ProgressCallback {
+    let reports = reports.clone();
+    Arc::new(move |progress| reports.lock().push(progress))
+}
+
+/// (completed, failed, last_index) of every report
+fn counts(reports: &Mutex<Vec<BatchProgress>>) -> Vec<(usize, usize, usize)> {
+    reports.lock().iter().map(|progress| (progress.completed, progress.failed, progress.last_index)).collect()
+}
+
+#[tokio::test(start_paused = true)]
+async fn batches_report_progress_after_every_item() {
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let node = AsyncBatchNode::new(1, 0).with_error_policy(ErrorPolicy::CollectErrors).with_exec(reversed);
+    node.set_progress_callback(record(&reports));
+    
+    node._exec_async(&json!([0, 1, 2, 3, 4])).await.unwrap();
+    assert_eq!(counts(&reports), [(1, 0, 0), (2, 0, 1), (3, 1, 2), (4, 1, 3), (5, 1, 4)]);
+    assert!(reports.lock().iter().all(|progress| progress.total == 5));
+}
+
+#[tokio::test(start_paused = true)]
+async fn parallel_batches_report_progress_in_completion_order() {
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let node = AsyncParallelBatchNode::new(1, 0).with_error_policy(ErrorPolicy::CollectErrors).with_exec(reversed);
+    node.set_progress_callback(record(&reports));
+    
+    node._exec_async(&json!([0, 1, 2, 3, 4])).await.unwrap();
+    assert_eq!(counts(&reports), [(1, 0, 4), (2, 0, 3), (3, 1, 2), (4, 1, 1), (5, 1, 0)]);
+    // Measured on the wall clock, which the paused test clock does not move
+    assert!(reports.lock().windows(2).all(|pair| pair[0].elapsed <= pair[1].elapsed));
+}
+
+#[tokio::test(start_paused = true)]
+async fn results_come_in_batch_or_completion_order() {
+    let preserve = AsyncParallelBatchNode::new(1, 0).with_error_policy(ErrorPolicy::SkipFailed).with_exec(reversed);
+    let exec_res = preserve._exec_async(&json!([0, 1, 2, 3, 4])).await.unwrap();
+    assert_eq!(exec_res["results"], json!([0, 1, 3, 4]));
+    
+    let completion = preserve.with_result_order(ResultOrder::CompletionOrder);
+    let started = Instant::now();
+    let exec_res = completion._exec_async(&json!([0, 1, 2, 3, 4])).await.unwrap();
+    assert_eq!(started.elapsed(), Duration::from_millis(50), "the items still run side by side");
+    assert_eq!(
+        exec_res["results"],
+        json!([{"index": 4, "value": 4}, {"index": 3, "value": 3}, {"index": 1, "value": 1}, {"index": 0, "value": 0}])
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn every_item_counts_its_own_attempts() {
+    let attempts = Arc::new(Mutex::new(Vec::new()));
+    let fallbacks = Arc::new(Mutex::new(Vec::new()));
+    let (exec_attempts, seen) = (attempts.clone(), fallbacks.clone());
+    let node = AsyncParallelBatchNode::new(2, 0)
+        .with_exec(move |item: Value| {
+            exec_attempts.lock().push((item.as_u64().unwrap(), current_attempt()));
+            async move {
+                // Items wait different times, so their attempts interleave
+                tokio::time::sleep(Duration::from_millis(10 * item.as_u64().unwrap())).await;
+                Err::<Value, _>(Error::NodeExecution(format!("item {} failed", item)))
+            }
+        })
+        .with_fallback(move |item, _| {
+            seen.lock().push((item.as_u64().unwrap(), current_attempt()));
+            async move { Ok(item) }
+        });
+    
+    let exec_res = node._exec_async(&json!([3, 1, 2, 4])).await.unwrap();
+    assert_eq!(exec_res, json!([3, 1, 2, 4]));
+    let mut fallbacks = fallbacks.lock().clone();
+    fallbacks.sort();
+    assert_eq!(fallbacks, [(1, Some(1)), (2, Some(1)), (3, Some(1)), (4, Some(1))]);
+    let mut attempts = attempts.lock().clone();
+    attempts.sort();
+    let expected: Vec<_> = (1..=4).flat_map(|n| [(n, Some(0)), (n, Some(1))]).collect();
+    assert_eq!(attempts, expected);
+}
+
+#[tokio::test(start_paused = true)]
+async fn failing_items_are_all_reported_with_the_other_results() {
+    let node = AsyncParallelBatchNode::new(1, 0).with_error_policy(ErrorPolicy::FailFast).with_exec(|item: Value| async move {
+        let n = item.as_u64().unwrap();
+        match n % 33 {
+            7 => Err(Error::NodeExecution(format!("item {} failed", n))),
+            _ => Ok(json!(n)),
+        }
+    });
+    
+    let items: Vec<u64> = (0..100).collect();
+    let Err(Error::BatchFailed { failures, results }) = node._exec_async(&json!(items)).await else {
+        panic!("the batch should fail with every failure");
+    };
+    let failed: Vec<usize> = failures.iter().map(|(index, _)| *index).collect();
+    assert_eq!(failed, [7, 40, 73]);
+    assert_eq!(failures[1].1.to_string(), Error::NodeExecution("item 40 failed".into()).to_string());
+    assert_eq!(results.len(), 97);
+    assert!(results.iter().all(|(index, value)| !failed.contains(index) && value == &json!(index)));
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_fail_fast_failure_drops_the_items_still_running() {
+    let finished = Arc::new(AtomicUsize::new(0));
+    let done = finished.clone();
+    let node = AsyncParallelBatchNode::new(1, 0).with_error_policy(ErrorPolicy::FailFast).with_exec(move |item: Value| {
+        let done = done.clone();
+        async move {
+            if item == json!(2) {
+                return Err(Error::NodeExecution("item 2 failed".into()));
+            }
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            done.fetch_add(1, Ordering::SeqCst);
+            Ok(item)
+        }
+    });
+    
+    let Err(Error::BatchFailed { failures, results }) = node._exec_async(&json!([0, 1, 2, 3])).await else {
+        panic!("the batch should fail");
+    };
+    assert_eq!(failures.iter().map(|(index, _)| *index).collect::<Vec<_>>(), [2]);
+    assert!(results.is_empty(), "{:?}", results);
+    tokio::time::advance(Duration::from_secs(2)).await;
+    assert_eq!(finished.load(Ordering::SeqCst), 0);
+}
+
+/// Number of items in the large batch
+const LARGE_BATCH: u64 = 50_000;
+
+#[tokio::test]
+async fn large_batches_keep_only_the_concurrency_limit_in_flight() {
+    for order in [ResultOrder::Preserve, ResultOrder::CompletionOrder] {
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let (running, highest) = (in_flight.clone(), peak.clone());
+        let node = AsyncParallelBatchNode::new(1, 0).with_concurrency(8).with_result_order(order).with_exec(move |item: Value| {
+            let (running, highest) = (running.clone(), highest.clone());
+            async move {
+                highest.fetch_max(running.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+                tokio::task::yield_now().await;
+                running.fetch_sub(1, Ordering::SeqCst);
+                Ok(item)
+            }
+        });
+        
+        let items: Vec<u64> = (0..LARGE_BATCH).collect();
+        let exec_res = node._exec_async(&json!(items)).await.unwrap();
+        assert_eq!(exec_res.as_array().unwrap().len(), LARGE_BATCH as usize);
+        assert_eq!(peak.load(Ordering::SeqCst), 8, "{:?} started more items than the limit", order);
+        if order == ResultOrder::Preserve {
+            assert_eq!(exec_res, json!(items));
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,93 @@
+mod common;
+
+use std::sync::Arc;
+use serde_json::{json, Value};
+use minllm::{BatchFlow, Error, Flow, FnNode, NodeTrait, ParamMap, ParamSpec, SharedState};
+use common::allocated_by;
+
+/// Size of the large param value
+const MB: usize = 1 << 20;
+
+/// Number of items in the batch
+const ITEMS: usize = 100;
+
+/// Three nodes run one after another, returning the start node
+fn chain() -> Arc<dyn NodeTrait> {
+    let nodes: Vec<Arc<dyn NodeTrait>> = (0..3).map(|_| Arc::new(FnNode::new()) as Arc<dyn NodeTrait>).collect();
+    for pair in nodes.windows(2) {
+        pair[0].add_successor(pair[1].clone(), "default").unwrap();
+    }
+    nodes[0].clone()
+}
+
+/// Params holding a 1MB list of few-shot examples
+fn large_params() -> ParamMap {
+    ParamMap::from([("examples".to_string(), json!("x".repeat(MB)))])
+}
+
+/// A node named "summarize" requiring an integer "limit" and taking a "style" defaulting to "short"
+fn summarize() -> Arc<dyn NodeTrait> {
+    let node: Arc<dyn NodeTrait> = Arc::new(
+        FnNode::new()
+            .require_param(ParamSpec::new("limit").of_type("integer"))
+            .require_param(ParamSpec::new("style").of_type("string").with_default(json!("short"))),
+    );
+    node.set_name("summarize");
+    node
+}
+
+#[test]
+fn flows_share_their_params_with_every_node() {
+    let start = chain();
+    let flow = Flow::new(start.clone());
+    flow.set_params_map(large_params());
+    
+    let allocated = allocated_by(|| flow.run(&mut SharedState::new()).map(drop).unwrap());
+    assert!(allocated < MB / 4, "a 3-node run allocated {} bytes", allocated);
+    assert!(Arc::ptr_eq(&start.params(), &flow.params()));
+}
+
+#[test]
+fn batch_items_without_params_share_the_flow_params() {
+    let flow = BatchFlow::new(chain()).with_items(vec![ParamMap::new(); ITEMS]);
+    flow.set_params_map(large_params());
+    
+    let allocated = allocated_by(|| flow.run(&mut SharedState::new()).map(drop).unwrap());
+    assert!(allocated < MB / 4, "a {}-item batch allocated {} bytes", ITEMS, allocated);
+}
+
+#[test]
+fn batch_items_copy_the_params_once_per_item() {
+    let items = (0..ITEMS).map(|i| ParamMap::from([("item".to_string(), json!(i))])).collect();
+    let start = chain();
+    let flow = BatchFlow::new(start.clone()).with_items(items);
+    flow.set_params_map(large_params());
+    
+    let allocated = allocated_by(|| flow.run(&mut SharedState::new()).map(drop).unwrap());
+    // Copying per node would allocate three times this
+    assert!(allocated < ITEMS * MB * 3 / 2, "a {}-item batch allocated {} bytes", ITEMS, allocated);
+    assert_eq!(start.params().get("item"), Some(&json!(ITEMS - 1)));
+    assert_eq!(start.params().get("examples").and_then(Value::as_str).map(str::len), Some(MB));
+}
+
+#[test]
+fn flows_check_the_start_node_params_before_running_it() {
+    let start = summarize();
+    let flow = Flow::new(start.clone());
+    flow.set_params_map(ParamMap::from([("style".to_string(), json!(3))]));
+    let Err(Error::FlowExecution(message)) = flow.run(&mut SharedState::new()) else {
+        panic!("the run should fail on the params");
+    };
+    assert_eq!(message, "Invalid params: summarize: missing 'limit' (integer); summarize: 'style' should be string, found 3");
+    
+    flow.set_params_map(ParamMap::from([("limit".to_string(), json!(200))]));
+    flow.run(&mut SharedState::new()).unwrap();
+    assert_eq!(start.params().get("style"), Some(&json!("short")), "the default is filled in");
+}
+
+#[test]
+fn batch_flows_check_every_item_params() {
+    let items = vec![ParamMap::from([("limit".to_string(), json!(100))]), ParamMap::from([("limit".to_string(), json!("all"))])];
+    let err = BatchFlow::new(summarize()).with_items(items).run(&mut SharedState::new()).unwrap_err();
+    assert!(err.to_string().contains("summarize: 'limit' should be integer, found \"all\""), "{}", err);
+}
\ No newline at end of file
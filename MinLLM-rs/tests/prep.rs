@@ -0,0 +1,167 @@
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+use async_trait::async_trait;
+use parking_lot::{Mutex, RwLock};
+use serde_json::{json, Value};
+use minllm::{
+    current_attempt, Action, AsyncFlow, AsyncNodeTrait, BaseNode, BlockingNode, Error, Flow, FnNode, NodeTrait, ParamMap, Result, SharedState,
+    Successors,
+};
+use common::allocated_by;
+
+/// A multi-megabyte prep result, as a batch of embeddings would be
+fn embeddings() -> Value {
+    json!((0..200_000).map(|i| i as f64 / 7.0).collect::<Vec<_>>())
+}
+
+/// Address of the buffer behind an array, which a copy of the array would move
+fn buffer(value: &Value) -> usize {
+    value.as_array().expect("the prep result is an array").as_ptr() as usize
+}
+
+/// A node handing out `prep` whose exec fails its first two attempts, recording the buffer exec and post see in `seen`
+fn failing_twice(prep: Value, seen: Arc<Mutex<Vec<usize>>>) -> FnNode {
+    let (exec_seen, post_seen) = (seen.clone(), seen);
+    FnNode::new()
+        .with_prep(move |_| Ok(prep.clone()))
+        .with_exec(move |prep| {
+            exec_seen.lock().push(buffer(prep));
+            match current_attempt() {
+                Some(attempt) if attempt < 2 => Err(Error::NodeExecution(format!("attempt {} failed", attempt))),
+                _ => Ok(json!("ok")),
+            }
+        })
+        .with_post(move |_, prep, _| {
+            post_seen.lock().push(buffer(&prep));
+            Ok(None)
+        })
+        .retries(3, 0)
+}
+
+#[test]
+fn retries_borrow_the_prep_result() {
+    let prep = embeddings();
+    let copy = allocated_by(|| drop(prep.clone()));
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let flow = Flow::new(Arc::new(failing_twice(prep, seen.clone())));
+    
+    let allocated = allocated_by(|| flow.run(&mut SharedState::new()).map(drop).unwrap());
+    let seen = seen.lock();
+    assert_eq!(seen.len(), 4, "three attempts and post");
+    assert!(seen.iter().all(|ptr| *ptr == seen[0]), "exec or post saw a copy");
+    // Prep itself makes the one copy; copying per attempt would make three more
+    assert!(allocated < copy * 3 / 2, "copy is {} bytes, the run allocated {}", copy, allocated);
+    
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let flow = Flow::new(Arc::new(failing_twice(embeddings(), seen.clone()).with_timeout(Duration::from_secs(5))));
+    let allocated = allocated_by(|| flow.run(&mut SharedState::new()).map(drop).unwrap());
+    let seen = seen.lock();
+    assert_eq!(seen.len(), 4, "three timed attempts and post");
+    assert!(seen[..3].iter().all(|ptr| *ptr == seen[0]), "a timed attempt saw its own copy");
+    // Timed attempts run on their own threads, which share one more copy between them
+    assert!(allocated < copy * 5 / 2, "copy is {} bytes, the timed run allocated {}", copy, allocated);
+}
+
+/// An async node recording the buffer of the prep result each step sees
+struct Recorder {
+    /// Base node implementation
+    base: BaseNode,
+    
+    /// The prep result handed out by prep
+    prep: Value,
+    
+    /// Buffer addresses seen by exec and post
+    seen: Mutex<Vec<usize>>,
+}
+
+impl NodeTrait for Recorder {
+    fn params(&self) -> Arc<ParamMap> {
+        self.base.params()
+    }
+    
+    fn successors(&self) -> Arc<RwLock<Successors>> {
+        self.base.successors()
+    }
+    
+    fn set_params(&self, params: Arc<ParamMap>) {
+        self.base.set_params(params);
+    }
+    
+    fn add_successor(&self, node: Arc<dyn NodeTrait>, action: &str) -> Result<Arc<dyn NodeTrait>> {
+        self.base.add_successor(node, action)
+    }
+    
+    fn as_async(&self) -> Option<&dyn AsyncNodeTrait> {
+        Some(self)
+    }
+}
+
+#[async_trait]
+impl AsyncNodeTrait for Recorder {
+    async fn prep_async(&self, _shared: &mut SharedState) -> Result<Value> {
+        Ok(self.prep.clone())
+    }
+    
+    async fn exec_async(&self, prep_res: &Value) -> Result<Value> {
+        self.seen.lock().push(buffer(prep_res));
+        Ok(Value::Null)
+    }
+    
+    async fn post_async(&self, _shared: &mut SharedState, prep_res: Value, _exec_res: Value) -> Result<Action> {
+        self.seen.lock().push(buffer(&prep_res));
+        Ok(None)
+    }
+    
+    async fn _exec_async(&self, prep_res: &Value) -> Result<Value> {
+        self.exec_async(prep_res).await
+    }
+}
+
+#[tokio::test]
+async fn async_runs_hand_post_the_prep_result_exec_saw() {
+    let prep = embeddings();
+    let copy = allocated_by(|| drop(prep.clone()));
+    let recorder = Arc::new(Recorder { base: BaseNode::new(), prep, seen: Mutex::new(Vec::new()) });
+    let flow = AsyncFlow::new(recorder.clone());
+    
+    let mut shared = SharedState::new();
+    common::start_counting();
+    flow.run_async(&mut shared).await.unwrap();
+    let allocated = common::stop_counting();
+    
+    let seen = recorder.seen.lock();
+    assert_eq!(seen.len(), 2);
+    assert_eq!(seen[0], seen[1], "post saw a copy of the prep result");
+    assert!(allocated < copy * 3 / 2, "copy is {} bytes, the run allocated {}", copy, allocated);
+}
+
+#[tokio::test]
+async fn blocking_retries_share_one_copy_of_the_prep_result() {
+    let prep = embeddings();
+    let copy = allocated_by(|| drop(prep.clone()));
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let exec_seen = seen.clone();
+    let node = BlockingNode::new()
+        .with_prep(move |_| Ok(prep.clone()))
+        .with_exec(move |prep| {
+            exec_seen.lock().push(buffer(prep));
+            match current_attempt() {
+                Some(attempt) if attempt < 2 => Err(Error::NodeExecution(format!("attempt {} failed", attempt))),
+                _ => Ok(json!("ok")),
+            }
+        })
+        .retries(3, 0);
+    let flow = AsyncFlow::new(Arc::new(node));
+    
+    common::start_counting();
+    flow.run_async(&mut SharedState::new()).await.unwrap();
+    let allocated = common::stop_counting();
+    
+    let seen = seen.lock();
+    assert_eq!(seen.len(), 3);
+    assert!(seen.iter().all(|ptr| *ptr == seen[0]), "an attempt saw its own copy");
+    // Prep makes one copy and the blocking threads share one more
+    assert!(allocated < copy * 5 / 2, "copy is {} bytes, the run allocated {}", copy, allocated);
+}
\ No newline at end of file
@@ -0,0 +1,58 @@
+use std::sync::Arc;
+use std::time::Duration;
+use parking_lot::Mutex;
+use serde_json::json;
+use tokio::time::Instant;
+use minllm::{AsyncNodeTrait, AsyncParallelBatchNode, BatchNode, NodeTrait, RateLimiter, TestSleeper};
+
+/// Whether `actual` is within a few milliseconds of `expected`
+fn close_to(actual: Duration, expected: Duration) -> bool {
+    actual.abs_diff(expected) < Duration::from_millis(20)
+}
+
+#[test]
+fn sync_batch_items_wait_for_the_interval() {
+    let sleeper = Arc::new(TestSleeper::new());
+    let node = BatchNode::new(1, 0)
+        .with_exec(|item| Ok(item.clone()))
+        .with_min_interval(Duration::from_millis(100))
+        .with_sleeper(sleeper.clone());
+    
+    assert_eq!(node._exec(&json!([1, 2, 3])).unwrap(), json!([1, 2, 3]));
+    let waits = sleeper.requested();
+    assert_eq!(waits.len(), 2, "the first item starts right away: {:?}", waits);
+    assert!(close_to(waits[0], Duration::from_millis(100)), "{:?}", waits);
+    assert!(close_to(waits[1], Duration::from_millis(200)), "the test sleeper does not pass time: {:?}", waits);
+}
+
+#[tokio::test(start_paused = true)]
+async fn parallel_items_share_one_limit() {
+    let starts = Arc::new(Mutex::new(Vec::new()));
+    let recorded = starts.clone();
+    let node = AsyncParallelBatchNode::new(1, 0).with_min_interval(Duration::from_millis(100)).with_exec(move |item| {
+        recorded.lock().push(Instant::now());
+        async move { Ok(item) }
+    });
+    
+    let started = Instant::now();
+    node._exec_async(&json!([1, 2, 3, 4])).await.unwrap();
+    let mut offsets: Vec<_> = starts.lock().iter().map(|start| start.duration_since(started)).collect();
+    offsets.sort();
+    assert_eq!(offsets.len(), 4);
+    for (i, offset) in offsets.iter().enumerate() {
+        assert!(close_to(*offset, Duration::from_millis(100) * i as u32), "{:?}", offsets);
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn nodes_sharing_a_limiter_share_its_budget() {
+    let limiter = Arc::new(RateLimiter::new(2, Duration::from_secs(1)));
+    let first = AsyncParallelBatchNode::new(1, 0).with_rate_limiter(limiter.clone()).with_exec(|item| async move { Ok(item) });
+    let second = AsyncParallelBatchNode::new(1, 0).with_rate_limiter(limiter).with_exec(|item| async move { Ok(item) });
+    
+    let started = Instant::now();
+    first._exec_async(&json!([1, 2])).await.unwrap();
+    assert!(close_to(started.elapsed(), Duration::ZERO), "{:?}", started.elapsed());
+    second._exec_async(&json!([3, 4])).await.unwrap();
+    assert!(close_to(started.elapsed(), Duration::from_secs(1)), "{:?}", started.elapsed());
+}
\ No newline at end of file
@@ -0,0 +1,106 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use minllm::{
+    current_attempt, AsyncFlow, AsyncFnNode, AsyncNode, AsyncNodeTrait, Backoff, Error, Flow, FnNode, Node, NodeTrait, SharedState,
+    TestSleeper,
+};
+
+/// Records the prep result every exec attempt sees, failing the first attempt
+fn fail_once(seen: &Arc<Mutex<Vec<Value>>>) -> impl Fn(&Value) -> minllm::Result<Value> + Send + Sync + 'static {
+    let seen = seen.clone();
+    move |prep| {
+        seen.lock().push(prep.clone());
+        match current_attempt() {
+            Some(0) => Err(Error::NodeExecution("first attempt fails".into())),
+            _ => Ok(json!(prep["text"].as_str().unwrap().to_uppercase())),
+        }
+    }
+}
+
+fn store_output(shared: &mut SharedState, _prep: Value, exec_res: Value) -> minllm::Result<minllm::Action> {
+    shared.insert("output".to_string(), exec_res);
+    Ok(None)
+}
+
+#[test]
+fn a_retried_attempt_sees_the_original_prep_result() {
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let node = FnNode::new()
+        .with_prep(|shared| Ok(json!({"text": shared["input"]})))
+        .with_exec(fail_once(&seen))
+        .with_post(store_output)
+        .retries(2, 0);
+    let flow = Flow::new(Arc::new(node));
+    
+    let mut shared = SharedState::from([("input".to_string(), json!("hello"))]);
+    flow.run(&mut shared).unwrap();
+    
+    assert_eq!(shared["output"], json!("HELLO"));
+    assert_eq!(*seen.lock(), [json!({"text": "hello"}), json!({"text": "hello"})]);
+}
+
+#[tokio::test]
+async fn a_retried_async_attempt_sees_the_original_prep_result() {
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let exec = fail_once(&seen);
+    let node = AsyncFnNode::new()
+        .with_prep(|shared| Ok(json!({"text": shared["input"]})))
+        .with_exec(move |prep| std::future::ready(exec(&prep)))
+        .with_post(store_output)
+        .retries(2, 0);
+    let flow = AsyncFlow::new(Arc::new(node));
+    
+    let mut shared = SharedState::from([("input".to_string(), json!("hello"))]);
+    flow.run_async(&mut shared).await.unwrap();
+    
+    assert_eq!(shared["output"], json!("HELLO"));
+    assert_eq!(*seen.lock(), [json!({"text": "hello"}), json!({"text": "hello"})]);
+}
+
+/// Waits of 1s doubling up to 5s
+const BACKOFF: Backoff = Backoff::Exponential {
+    base: Duration::from_secs(1),
+    factor: 2.0,
+    max: Duration::from_secs(5),
+    jitter: false,
+};
+
+fn secs(secs: &[u64]) -> Vec<Duration> {
+    secs.iter().copied().map(Duration::from_secs).collect()
+}
+
+#[test]
+fn exponential_backoff_requests_each_wait_without_sleeping() {
+    let sleeper = Arc::new(TestSleeper::new());
+    let node = Node::new(5, 0)
+        .with_exec(|_| Err(Error::NodeExecution("down".into())))
+        .with_backoff(BACKOFF)
+        .with_sleeper(sleeper.clone());
+    
+    let started = Instant::now();
+    assert!(node.run(&mut SharedState::new()).is_err());
+    assert!(started.elapsed() < Duration::from_secs(1), "{:?}", started.elapsed());
+    assert_eq!(sleeper.requested(), secs(&[1, 2, 4, 5]));
+    assert_eq!(sleeper.total(), Duration::from_secs(12));
+}
+
+#[tokio::test]
+async fn exponential_backoff_requests_each_async_wait_without_sleeping() {
+    let sleeper = Arc::new(TestSleeper::new());
+    let node = AsyncNode::new(4, 0)
+        .with_exec(|_| async {
+            match current_attempt() {
+                Some(3) => Ok(json!("up")),
+                _ => Err(Error::NodeExecution("down".into())),
+            }
+        })
+        .with_backoff(BACKOFF)
+        .with_sleeper(sleeper.clone());
+    
+    let started = Instant::now();
+    node._run_async(&mut SharedState::new()).await.unwrap();
+    assert!(started.elapsed() < Duration::from_secs(1), "{:?}", started.elapsed());
+    assert_eq!(sleeper.requested(), secs(&[1, 2, 4]));
+}
\ No newline at end of file
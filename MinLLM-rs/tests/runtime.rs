@@ -0,0 +1,40 @@
+use std::sync::Arc;
+use std::thread;
+use serde_json::json;
+use minllm::{AsyncFlow, AsyncFnNode, NodeTrait, SharedState};
+
+/// A flow whose one node stores the name of the runtime thread a task it spawns runs on
+fn flow() -> AsyncFlow {
+    let node: Arc<dyn NodeTrait> = Arc::new(AsyncFnNode::new().with_exec(|_| async {
+        let worker = tokio::spawn(async { thread::current().name().map(str::to_string) }).await.unwrap();
+        Ok(json!(worker))
+    }).with_post(|shared, _, exec_res| {
+        shared.insert("worker".to_string(), exec_res);
+        Ok(None)
+    }));
+    AsyncFlow::new(node)
+}
+
+#[test]
+fn flows_share_a_caller_provided_runtime() {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(2)
+        .thread_name("flows")
+        .enable_all()
+        .build()
+        .unwrap();
+    
+    let runs: Vec<_> = (0..2)
+        .map(|_| {
+            let handle = runtime.handle().clone();
+            thread::spawn(move || {
+                let mut shared = SharedState::new();
+                flow().run_on(&handle, &mut shared).unwrap();
+                shared
+            })
+        })
+        .collect();
+    for run in runs {
+        assert_eq!(run.join().unwrap()["worker"], json!("flows"));
+    }
+}
\ No newline at end of file
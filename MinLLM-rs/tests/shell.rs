@@ -0,0 +1,130 @@
+//! Runs `ShellCommandNode` against this test binary, which acts as the child command when
+//! `MINLLM_SHELL_CHILD` is set, so the tests need no platform-specific programs.
+
+use std::env;
+use std::panic;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+use serde_json::{json, Value};
+use minllm::{AsyncNodeTrait, Error, NodeTrait, ParamMap, SharedState, ShellCommandNode, DEFAULT_ACTION};
+
+/// Env var selecting how the binary behaves when run as the child command
+const CHILD: &str = "MINLLM_SHELL_CHILD";
+
+/// Behave as the child command selected by `mode`, with `args` as its arguments
+fn child(mode: &str, args: &[String]) -> ExitCode {
+    match mode {
+        "echo" => {
+            println!("{}", args.join(" "));
+            eprint!("to stderr");
+            ExitCode::SUCCESS
+        },
+        "exit" => ExitCode::from(args[0].parse::<u8>().unwrap()),
+        "json" => {
+            print!("{}", json!({"files": ["a.rs", "b.rs"], "count": 2}));
+            ExitCode::SUCCESS
+        },
+        "sleep" => {
+            std::thread::sleep(Duration::from_secs(30));
+            ExitCode::SUCCESS
+        },
+        other => panic!("unknown child mode {}", other),
+    }
+}
+
+/// A node running this binary as the child command `mode`, with `params` on top
+fn node(mode: &str, args: Value, params: Value) -> ShellCommandNode {
+    let node = ShellCommandNode::new();
+    let mut map = ParamMap::from([
+        ("program".to_string(), json!(env::current_exe().unwrap())),
+        ("args".to_string(), args),
+        ("env".to_string(), json!({CHILD: mode})),
+    ]);
+    if let Value::Object(params) = params {
+        map.extend(params);
+    }
+    node.set_params_map(map);
+    node
+}
+
+fn runtime() -> tokio::runtime::Runtime {
+    tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap()
+}
+
+fn stores_output_and_returns_the_default_action() {
+    let node = node("echo", json!(["${param:greeting}", "${store:name}"]), json!({"greeting": "hello"}));
+    let mut shared = SharedState::from([("name".to_string(), json!("world"))]);
+    
+    assert_eq!(node.run(&mut shared).unwrap().as_deref(), Some(DEFAULT_ACTION));
+    assert_eq!(shared["stdout"].as_str().unwrap().trim_end(), "hello world");
+    assert_eq!(shared["stderr"], json!("to stderr"));
+    assert_eq!(shared["exit_code"], json!(0));
+}
+
+fn stores_output_asynchronously() {
+    let node = node("echo", json!(["async"]), json!({"stdout_key": "out", "exit_code_key": "code"}));
+    let mut shared = SharedState::new();
+    
+    let action = runtime().block_on(node._run_async(&mut shared)).unwrap();
+    assert_eq!(action.as_deref(), Some(DEFAULT_ACTION));
+    assert_eq!(shared["out"].as_str().unwrap().trim_end(), "async");
+    assert_eq!(shared["code"], json!(0));
+}
+
+fn routes_non_zero_exits_to_the_failure_action() {
+    let mut shared = SharedState::new();
+    let action = node("exit", json!(["3"]), json!({})).run(&mut shared).unwrap();
+    assert_eq!(action.as_deref(), Some("failed"));
+    assert_eq!(shared["exit_code"], json!(3));
+    
+    let node = node("exit", json!(["1"]), json!({"failure_action": "repair"}));
+    let action = runtime().block_on(node._run_async(&mut SharedState::new())).unwrap();
+    assert_eq!(action.as_deref(), Some("repair"));
+}
+
+fn kills_the_child_on_timeout() {
+    let timeout = Duration::from_millis(200);
+    let node = node("sleep", json!([]), json!({"timeout_ms": 200}));
+    
+    let started = Instant::now();
+    let result = node.run(&mut SharedState::new());
+    assert!(matches!(result, Err(Error::Timeout(t)) if t == timeout), "{:?}", result);
+    assert!(started.elapsed() < Duration::from_secs(10));
+    
+    let started = Instant::now();
+    let result = runtime().block_on(node._run_async(&mut SharedState::new()));
+    assert!(matches!(result, Err(Error::Timeout(t)) if t == timeout), "{:?}", result);
+    assert!(started.elapsed() < Duration::from_secs(10));
+}
+
+fn parses_stdout_as_json_when_asked() {
+    let mut shared = SharedState::new();
+    node("json", json!([]), json!({"parse_json": true})).run(&mut shared).unwrap();
+    assert_eq!(shared["stdout"], json!({"files": ["a.rs", "b.rs"], "count": 2}));
+    
+    let result = node("echo", json!(["not json"]), json!({"parse_json": true})).run(&mut SharedState::new());
+    assert!(matches!(result, Err(Error::NodeExecution(msg)) if msg.starts_with("Command output is not valid JSON")));
+}
+
+fn main() -> ExitCode {
+    if let Ok(mode) = env::var(CHILD) {
+        return child(&mode, &env::args().skip(1).collect::<Vec<_>>());
+    }
+    
+    let tests: [(&str, fn()); 5] = [
+        ("stores_output_and_returns_the_default_action", stores_output_and_returns_the_default_action),
+        ("stores_output_asynchronously", stores_output_asynchronously),
+        ("routes_non_zero_exits_to_the_failure_action", routes_non_zero_exits_to_the_failure_action),
+        ("kills_the_child_on_timeout", kills_the_child_on_timeout),
+        ("parses_stdout_as_json_when_asked", parses_stdout_as_json_when_asked),
+    ];
+    let filter = env::args().skip(1).find(|arg| !arg.starts_with('-'));
+    let mut failed = 0;
+    for (name, test) in tests.iter().filter(|(name, _)| filter.as_deref().is_none_or(|f| name.contains(f))) {
+        let ok = panic::catch_unwind(test).is_ok();
+        println!("test {} ... {}", name, if ok { "ok" } else { "FAILED" });
+        failed += usize::from(!ok);
+    }
+    println!("\ntest result: {}. {} failed", if failed == 0 { "ok" } else { "FAILED" }, failed);
+    if failed == 0 { ExitCode::SUCCESS } else { ExitCode::FAILURE }
+}
\ No newline at end of file
@@ -0,0 +1,69 @@
+use std::sync::Arc;
+use std::time::Duration;
+use serde_json::{json, Value};
+use tokio::time::{self, Instant};
+use minllm::{ActionName, AsyncFlow, AsyncFnNode, Error, NodeTrait, RunOutcome, SharedState, ShutdownHandle};
+
+mod common;
+use common::step;
+
+#[tokio::test(start_paused = true)]
+async fn a_shutdown_after_the_second_node_keeps_its_writes_and_skips_the_rest() {
+    let handle = ShutdownHandle::new();
+    let trigger = handle.clone();
+    let second = step("second").with_post(move |shared, _, _| {
+        shared.insert("second".to_string(), json!(true));
+        trigger.trigger();
+        Ok(Some(ActionName::new("next")))
+    });
+    let nodes: [Arc<dyn NodeTrait>; 4] = [Arc::new(step("first")), Arc::new(second), Arc::new(step("third")), Arc::new(step("fourth"))];
+    for pair in nodes.windows(2) {
+        pair[0].add_successor(pair[1].clone(), "next").unwrap();
+    }
+    let flow = AsyncFlow::new(nodes[0].clone());
+    
+    let mut shared = SharedState::new();
+    let outcome = flow.run_with_shutdown(&mut shared, handle).await.unwrap();
+    assert_eq!(
+        outcome,
+        RunOutcome::Interrupted {
+            last_node: Some("second".to_string()),
+            action: Some(ActionName::new("next")),
+        }
+    );
+    assert_eq!(shared.get("second"), Some(&json!(true)));
+    assert!(!shared.contains_key("third"));
+    assert!(!shared.contains_key("fourth"));
+}
+
+#[tokio::test(start_paused = true)]
+async fn an_untriggered_run_completes() {
+    let first = Arc::new(step("first"));
+    first.add_successor(Arc::new(step("second")), "next").unwrap();
+    let mut shared = SharedState::new();
+    let outcome = AsyncFlow::new(first).run_with_shutdown(&mut shared, ShutdownHandle::new()).await.unwrap();
+    assert!(matches!(outcome, RunOutcome::Completed(_)));
+    assert_eq!(shared.get("second"), Some(&json!(true)));
+}
+
+#[tokio::test(start_paused = true)]
+async fn execs_can_stop_early_on_the_signal() {
+    let handle = ShutdownHandle::new();
+    let trigger = handle.clone();
+    tokio::spawn(async move {
+        time::sleep(Duration::from_secs(1)).await;
+        trigger.trigger();
+    });
+    let slow = AsyncFnNode::new().with_exec(|_| async {
+        let handle = ShutdownHandle::current().unwrap();
+        tokio::select! {
+            _ = time::sleep(Duration::from_secs(60)) => Ok(Value::Null),
+            _ = handle.triggered() => Err(Error::Cancelled),
+        }
+    });
+    
+    let started = Instant::now();
+    let outcome = AsyncFlow::new(Arc::new(slow)).run_with_shutdown(&mut SharedState::new(), handle).await.unwrap();
+    assert!(matches!(outcome, RunOutcome::Interrupted { last_node: None, .. }), "{:?}", outcome);
+    assert_eq!(started.elapsed(), Duration::from_secs(1));
+}
\ No newline at end of file
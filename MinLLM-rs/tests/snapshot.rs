@@ -0,0 +1,119 @@
+use std::env;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::sync::Arc;
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use minllm::testing::{
+    assert_topology_snapshot, assert_trace_snapshot, assert_trace_snapshot_with, FlowTestHarness, FlowTestResult, SnapshotOptions,
+    SNAPSHOT_DIR_ENV, UPDATE_SNAPSHOTS_ENV,
+};
+use minllm::{ActionName, Flow, FnNode, NodeTrait};
+
+mod common;
+use common::panic_message;
+
+/// Held by every test, since the snapshot settings are process-wide environment variables
+static ENV: Mutex<()> = Mutex::new(());
+
+/// A node that stores `value` under `key` and returns `action`
+fn writer(key: &'static str, value: Value, action: &'static str) -> Arc<dyn NodeTrait> {
+    Arc::new(FnNode::new().with_post(move |shared, _, _| {
+        shared.insert(key.to_string(), value.clone());
+        Ok(Some(ActionName::new(action)))
+    }))
+}
+
+/// fetch -> summarize, with the page stored under "page"
+fn summarizer(summary: &'static str) -> FlowTestHarness {
+    let fetch = writer("page", json!("<html>"), "summarize");
+    let summarize = writer("summary", json!(summary), "done");
+    fetch.add_successor(summarize.clone(), "summarize").unwrap();
+    FlowTestHarness::new(Flow::new(fetch.clone())).name(&fetch, "fetch").name(&summarize, "summarize")
+}
+
+/// A fresh snapshot directory for one test, set as `MINLLM_SNAPSHOT_DIR` while `f` runs
+fn in_snapshot_dir(test: &str, f: impl FnOnce(&PathBuf)) {
+    let dir = env::temp_dir().join(format!("minllm-snapshots-{}-{}", std::process::id(), test));
+    let _ = fs::remove_dir_all(&dir);
+    env::set_var(SNAPSHOT_DIR_ENV, &dir);
+    let outcome = panic::catch_unwind(AssertUnwindSafe(|| f(&dir)));
+    env::remove_var(SNAPSHOT_DIR_ENV);
+    let _ = fs::remove_dir_all(&dir);
+    if let Err(payload) = outcome {
+        panic::resume_unwind(payload);
+    }
+}
+
+#[test]
+fn runs_match_their_committed_snapshots() {
+    let _env = ENV.lock();
+    let harness = summarizer("short");
+    assert_topology_snapshot("summarizer", &harness);
+    assert_trace_snapshot("summarizer", &harness.run());
+}
+
+#[test]
+fn missing_snapshots_are_written() {
+    let _env = ENV.lock();
+    in_snapshot_dir("missing", |dir| {
+        let result: FlowTestResult = summarizer("short").run();
+        assert_trace_snapshot("new", &result);
+        let written = fs::read_to_string(dir.join("new.trace.snap")).unwrap();
+        assert_eq!(written, result.normalized(&SnapshotOptions::new()));
+        assert_trace_snapshot("new", &result);
+    });
+}
+
+#[test]
+fn mismatches_show_a_line_diff() {
+    let _env = ENV.lock();
+    in_snapshot_dir("mismatch", |_| {
+        assert_trace_snapshot("changed", &summarizer("short").run());
+        let changed = summarizer("long").run();
+        
+        let message = panic_message(|| assert_trace_snapshot("changed", &changed));
+        let (header, diff) = message.split_once('\n').unwrap();
+        assert!(header.starts_with("Snapshot 'changed.trace' does not match "), "{}", header);
+        assert!(header.ends_with("(set MINLLM_UPDATE_SNAPSHOTS=1 to update)"), "{}", header);
+        assert_eq!(diff, r#"  --- trace ---
+  1. fetch -> Some("summarize") wrote ["page"]
+  2. summarize -> Some("done") wrote ["summary"]
+  --- state ---
+  page: "<html>"
+- summary: "short"
++ summary: "long"
+"#);
+    });
+}
+
+#[test]
+fn updating_rewrites_snapshots() {
+    let _env = ENV.lock();
+    in_snapshot_dir("update", |dir| {
+        assert_trace_snapshot("blessed", &summarizer("short").run());
+        let changed = summarizer("long").run();
+        
+        env::set_var(UPDATE_SNAPSHOTS_ENV, "1");
+        assert_trace_snapshot("blessed", &changed);
+        env::remove_var(UPDATE_SNAPSHOTS_ENV);
+        
+        assert!(fs::read_to_string(dir.join("blessed.trace.snap")).unwrap().contains("summary: \"long\""));
+        assert_trace_snapshot("blessed", &changed);
+    });
+}
+
+#[test]
+fn redacted_keys_keep_their_name() {
+    let _env = ENV.lock();
+    in_snapshot_dir("redact", |dir| {
+        let options = SnapshotOptions::new().redact_key("page").redact_prefix("sum");
+        assert_trace_snapshot_with("redacted", &summarizer("short").run(), &options);
+        let written = fs::read_to_string(dir.join("redacted.trace.snap")).unwrap();
+        assert!(written.ends_with("--- state ---\npage: \"[redacted]\"\nsummary: \"[redacted]\"\n"), "{}", written);
+        
+        // Redacted values can change without failing the comparison
+        assert_trace_snapshot_with("redacted", &summarizer("long").run(), &options);
+    });
+}
\ No newline at end of file
@@ -0,0 +1,95 @@
+use std::sync::Arc;
+use async_trait::async_trait;
+use futures::stream::{self, BoxStream, StreamExt};
+use parking_lot::{Mutex, RwLock};
+use serde_json::{json, Value};
+use minllm::{Action, AsyncFlow, AsyncFnNode, AsyncNodeTrait, BaseNode, FlowEvent, NodeTrait, ParamMap, Result, SharedState, Successors};
+
+/// An async node whose exec yields "a", "b" and "c" as separate chunks
+struct Typist {
+    /// Base node implementation
+    base: BaseNode,
+}
+
+impl NodeTrait for Typist {
+    fn params(&self) -> Arc<ParamMap> {
+        self.base.params()
+    }
+    
+    fn name(&self) -> String {
+        "typist".to_string()
+    }
+    
+    fn successors(&self) -> Arc<RwLock<Successors>> {
+        self.base.successors()
+    }
+    
+    fn set_params(&self, params: Arc<ParamMap>) {
+        self.base.set_params(params);
+    }
+    
+    fn add_successor(&self, node: Arc<dyn NodeTrait>, action: &str) -> Result<Arc<dyn NodeTrait>> {
+        self.base.add_successor(node, action)
+    }
+    
+    fn as_async(&self) -> Option<&dyn AsyncNodeTrait> {
+        Some(self)
+    }
+}
+
+#[async_trait]
+impl AsyncNodeTrait for Typist {
+    async fn post_async(&self, shared: &mut SharedState, _prep_res: Value, exec_res: Value) -> Result<Action> {
+        shared.insert("typed".to_string(), exec_res);
+        Ok(None)
+    }
+    
+    async fn _exec_async(&self, _prep_res: &Value) -> Result<Value> {
+        Ok(json!("abc"))
+    }
+    
+    fn exec_stream<'a>(&'a self, _prep_res: &'a Value) -> BoxStream<'a, Result<Value>> {
+        Box::pin(stream::iter(["a", "b", "c"].map(|chunk| Ok(json!(chunk)))))
+    }
+}
+
+#[tokio::test]
+async fn chunks_arrive_in_order_before_the_flow_advances() {
+    let ran = Arc::new(Mutex::new(false));
+    let typist: Arc<dyn NodeTrait> = Arc::new(Typist { base: BaseNode::new() });
+    let next: Arc<dyn NodeTrait> = Arc::new(AsyncFnNode::new().with_exec({
+        let ran = ran.clone();
+        move |_| {
+            *ran.lock() = true;
+            async { Ok(Value::Null) }
+        }
+    }));
+    typist.add_successor(next, "default").unwrap();
+    let flow = AsyncFlow::new(typist);
+    
+    let mut shared = SharedState::new();
+    let mut chunks = Vec::new();
+    let mut events = flow.run_async_streaming(&mut shared);
+    while let Some(event) = events.next().await {
+        match event.unwrap() {
+            FlowEvent::Chunk { node, value } if node == "typist" => {
+                assert!(!*ran.lock(), "the next node ran before chunk {} was seen", value);
+                chunks.push(value);
+            },
+            FlowEvent::Finished { .. } => assert!(*ran.lock()),
+            _ => {},
+        }
+    }
+    drop(events);
+    
+    assert_eq!(chunks, [json!("a"), json!("b"), json!("c")]);
+    assert_eq!(shared["typed"], json!("abc"), "post receives the combined chunks");
+}
+
+#[tokio::test]
+async fn runs_without_a_stream_use_the_plain_exec() {
+    let flow = AsyncFlow::new(Arc::new(Typist { base: BaseNode::new() }));
+    let mut shared = SharedState::new();
+    flow.run_async(&mut shared).await.unwrap();
+    assert_eq!(shared["typed"], json!("abc"));
+}
\ No newline at end of file
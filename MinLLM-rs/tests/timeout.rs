@@ -0,0 +1,155 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use futures::future::BoxFuture;
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use tokio::time::{self, Instant};
+use minllm::{
+    AsyncBatchNode, AsyncFlow, AsyncFnNode, AsyncNode, AsyncNodeTrait, AsyncParallelBatchNode, BatchReport, Error, ErrorPolicy, Flow, FnNode, NodeTrait, Result,
+    SharedState,
+};
+
+/// An exec closure sleeping for the number of milliseconds it is given, counting its attempts
+fn sleeps(attempts: Arc<AtomicUsize>) -> impl Fn(Value) -> BoxFuture<'static, Result<Value>> + Send + Sync {
+    move |prep_res| {
+        attempts.fetch_add(1, Ordering::SeqCst);
+        Box::pin(async move {
+            time::sleep(Duration::from_millis(prep_res.as_u64().unwrap_or_default())).await;
+            Ok(prep_res)
+        })
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn timed_out_attempts_are_retried_then_handed_to_the_fallback() {
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let fallback_error = Arc::new(Mutex::new(None));
+    let seen = fallback_error.clone();
+    let node = AsyncNode::new(3, 0).with_timeout(Duration::from_secs(1)).with_exec(sleeps(attempts.clone())).with_fallback(move |_, error| {
+        *seen.lock() = Some(error);
+        async { Ok(json!("fallback")) }
+    });
+    
+    let started = Instant::now();
+    assert_eq!(node._exec_async(&json!(10_000)).await.unwrap(), json!("fallback"));
+    assert_eq!(started.elapsed(), Duration::from_secs(3));
+    assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    assert!(matches!(*fallback_error.lock(), Some(Error::Timeout(timeout)) if timeout == Duration::from_secs(1)));
+}
+
+#[tokio::test(start_paused = true)]
+async fn retries_after_a_timeout_wait_first() {
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let node = AsyncNode::new(2, 500).with_timeout(Duration::from_secs(1)).with_exec(sleeps(attempts.clone()));
+    
+    let started = Instant::now();
+    assert!(matches!(node._exec_async(&json!(10_000)).await, Err(Error::Timeout(_))));
+    assert_eq!(started.elapsed(), Duration::from_millis(2_500));
+    assert_eq!(attempts.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test(start_paused = true)]
+async fn fast_attempts_are_unaffected() {
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let node = AsyncNode::new(3, 0).with_timeout(Duration::from_secs(1)).with_exec(sleeps(attempts.clone()));
+    
+    let started = Instant::now();
+    assert_eq!(node._exec_async(&json!(100)).await.unwrap(), json!(100));
+    assert_eq!(started.elapsed(), Duration::from_millis(100));
+    assert_eq!(attempts.load(Ordering::SeqCst), 1);
+}
+
+/// The errors of a batch run under `CollectErrors`
+fn errors(exec_res: &Value) -> Vec<(usize, String)> {
+    let report = BatchReport::from_exec(exec_res).unwrap();
+    report.errors.into_iter().map(|error| (error.index, error.error)).collect()
+}
+
+#[tokio::test(start_paused = true)]
+async fn batch_nodes_time_out_each_item() {
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let node = AsyncBatchNode::new(1, 0)
+        .with_timeout(Duration::from_secs(1))
+        .with_error_policy(ErrorPolicy::CollectErrors)
+        .with_exec(sleeps(attempts.clone()));
+    
+    let started = Instant::now();
+    let exec_res = node._exec_async(&json!([100, 5_000, 200])).await.unwrap();
+    assert_eq!(started.elapsed(), Duration::from_millis(1_300));
+    assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    assert_eq!(errors(&exec_res), [(1, Error::Timeout(Duration::from_secs(1)).to_string())]);
+    assert_eq!(BatchReport::from_exec(&exec_res).unwrap().results, [json!(100), json!(200)]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn parallel_batch_nodes_time_out_each_item() {
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let node = AsyncParallelBatchNode::new(2, 0)
+        .with_timeout(Duration::from_secs(1))
+        .with_error_policy(ErrorPolicy::CollectErrors)
+        .with_exec(sleeps(attempts.clone()));
+    
+    let started = Instant::now();
+    let exec_res = node._exec_async(&json!([100, 5_000, 200])).await.unwrap();
+    assert_eq!(started.elapsed(), Duration::from_secs(2));
+    assert_eq!(attempts.load(Ordering::SeqCst), 4, "the slow item is tried twice");
+    assert_eq!(errors(&exec_res), [(1, Error::Timeout(Duration::from_secs(1)).to_string())]);
+}
+
+/// Follow `answer` with a node storing `true` under "continued"
+fn answer_then_continue(answer: Arc<dyn NodeTrait>) -> Arc<dyn NodeTrait> {
+    let next: Arc<dyn NodeTrait> = Arc::new(FnNode::new().with_post(|shared, _, _| {
+        shared.insert("continued".to_string(), json!(true));
+        Ok(None)
+    }));
+    answer.add_successor(next, "default").unwrap();
+    answer
+}
+
+fn store_answer(shared: &mut SharedState, _prep: Value, exec_res: Value) -> Result<minllm::Action> {
+    shared.insert("answer".to_string(), exec_res);
+    Ok(None)
+}
+
+#[test]
+fn a_sync_flow_continues_after_a_timed_out_exec_falls_back() {
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let counter = attempts.clone();
+    let slow = FnNode::new()
+        .with_exec(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            std::thread::sleep(Duration::from_millis(500));
+            Ok(json!("late"))
+        })
+        .retries(2, 0)
+        .with_timeout(Duration::from_millis(20))
+        .with_fallback(|_, error| Ok(json!(format!("fallback after {}", error))))
+        .with_post(store_answer);
+    
+    let mut shared = SharedState::new();
+    Flow::new(answer_then_continue(Arc::new(slow))).run(&mut shared).unwrap();
+    assert_eq!(shared["answer"], json!(format!("fallback after {}", Error::Timeout(Duration::from_millis(20)))));
+    assert_eq!(shared["continued"], json!(true));
+    assert_eq!(attempts.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test(start_paused = true)]
+async fn an_async_flow_continues_after_a_timed_out_exec_falls_back() {
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let slow = AsyncFnNode::new()
+        .with_prep(|_| Ok(json!(10_000)))
+        .with_exec(sleeps(attempts.clone()))
+        .retries(3, 0)
+        .with_timeout(Duration::from_secs(1))
+        .with_fallback(|_, _| async { Ok(json!("fallback")) })
+        .with_post(store_answer);
+    
+    let mut shared = SharedState::new();
+    let started = Instant::now();
+    AsyncFlow::new(answer_then_continue(Arc::new(slow))).run_async(&mut shared).await.unwrap();
+    assert_eq!(started.elapsed(), Duration::from_secs(3));
+    assert_eq!(shared["answer"], json!("fallback"));
+    assert_eq!(shared["continued"], json!(true));
+    assert_eq!(attempts.load(Ordering::SeqCst), 3);
+}
\ No newline at end of file
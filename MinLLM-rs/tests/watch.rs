@@ -0,0 +1,38 @@
+use std::sync::Arc;
+use std::time::Duration;
+use serde_json::json;
+use minllm::{AsyncFlow, AsyncFnNode, AsyncNodeTrait, NodeTrait, SharedState};
+
+/// A node that works for a second and then stores `percent` under "progress"
+fn step(percent: u64) -> Arc<dyn NodeTrait> {
+    Arc::new(AsyncFnNode::new().with_exec(move |_| async move {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        Ok(json!(percent))
+    }).with_post(|shared, _, exec_res| {
+        shared.insert("progress".to_string(), exec_res);
+        Ok(None)
+    }))
+}
+
+#[tokio::test(start_paused = true)]
+async fn watchers_see_every_step_of_progress() {
+    let start = step(25);
+    start.add_successor(step(50), "default").unwrap().add_successor(step(100), "default").unwrap();
+    let flow = AsyncFlow::new(start);
+    let mut progress = flow.watch("progress");
+    let bar = tokio::spawn(async move {
+        let mut seen = Vec::new();
+        while progress.changed().await.is_ok() {
+            let percent = progress.borrow_and_update().clone();
+            seen.push(percent);
+            if seen.last() == Some(&Some(json!(100))) {
+                break;
+            }
+        }
+        seen
+    });
+    
+    let mut shared = SharedState::from([("progress".to_string(), json!(0))]);
+    flow.run_async(&mut shared).await.unwrap();
+    assert_eq!(bar.await.unwrap(), [Some(json!(25)), Some(json!(50)), Some(json!(100))]);
+}
\ No newline at end of file